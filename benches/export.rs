@@ -0,0 +1,68 @@
+//! Export throughput on synthetic places: meshing on one thread against the
+//! whole pool, and writing a million vertices of blocks.
+//!
+//! Run with `cargo bench`; criterion keeps earlier runs in `target/criterion`
+//! to compare against.
+
+use std::io;
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use rbx_dom_weak::{InstanceBuilder, WeakDom};
+use rbx_obj::ExportOptions;
+use rbx_types::{CFrame, Color3uint8, Enum, Matrix3, Vector3};
+
+/// A place with `count` parts in a grid under its Workspace, cycling through
+/// the given `Shape`s.
+fn place(count: usize, shapes: &[u32]) -> WeakDom {
+    let mut dom = WeakDom::new(InstanceBuilder::new("DataModel"));
+    let workspace = dom.insert(dom.root_ref(), InstanceBuilder::new("Workspace"));
+    for i in 0..count {
+        let position = Vector3::new((i % 1000) as f32 * 4.0, 0.0, (i / 1000) as f32 * 4.0);
+        dom.insert(
+            workspace,
+            InstanceBuilder::new("Part")
+                .with_property("Size", Vector3::new(2.0, 2.0, 2.0))
+                .with_property("CFrame", CFrame::new(position, Matrix3::identity()))
+                .with_property("Color", Color3uint8::new(163, 162, 165))
+                .with_property("Shape", Enum::from_u32(shapes[i % shapes.len()])),
+        );
+    }
+    dom
+}
+
+fn export(dom: &WeakDom, opts: &ExportOptions) {
+    rbx_obj::export_dom(dom, &mut io::sink(), None, opts).unwrap();
+}
+
+/// Balls and cylinders, where generating the meshes dominates.
+fn meshing(c: &mut Criterion) {
+    let dom = place(20_000, &[0, 2]);
+    let opts = ExportOptions::builder().build().unwrap();
+    let one = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("meshing 20k balls and cylinders");
+    group.sample_size(10);
+    group.bench_function("1 thread", |b| {
+        b.iter(|| one.install(|| export(&dom, &opts)))
+    });
+    group.bench_function("all threads", |b| b.iter(|| export(&dom, &opts)));
+    group.finish();
+}
+
+/// Blocks, where formatting and writing the lines dominates.
+fn writing(c: &mut Criterion) {
+    let dom = place(125_000, &[1]);
+    let opts = ExportOptions::builder().build().unwrap();
+
+    let mut group = c.benchmark_group("writing");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(125_000 * 8));
+    group.bench_function("1M block vertices", |b| b.iter(|| export(&dom, &opts)));
+    group.finish();
+}
+
+criterion_group!(benches, meshing, writing);
+criterion_main!(benches);
@@ -0,0 +1,80 @@
+/*
+ * Converts a place through the C interface and checks what comes back.
+ *
+ *     cargo build --release --features ffi
+ *     cc examples/ffi/convert.c -Iinclude -Ltarget/release -lrbx_obj -o convert
+ *     LD_LIBRARY_PATH=target/release ./convert place.rbxl
+ *
+ * CI runs it on python/tests/two_blocks.rbxm.
+ *
+ * Exits with 0 when everything checks out, printing the OBJ's size.
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "rbx_obj.h"
+
+static unsigned char *read_file(const char *path, size_t *len) {
+    FILE *file = fopen(path, "rb");
+    if (!file) {
+        return NULL;
+    }
+    fseek(file, 0, SEEK_END);
+    long size = ftell(file);
+    fseek(file, 0, SEEK_SET);
+    unsigned char *data = malloc(size > 0 ? (size_t)size : 1);
+    *len = fread(data, 1, (size_t)size, file);
+    fclose(file);
+    return data;
+}
+
+static int check(int ok, const char *what) {
+    if (!ok) {
+        fprintf(stderr, "FAILED: %s\n", what);
+    }
+    return ok;
+}
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s <place.rbxl>\n", argv[0]);
+        return 2;
+    }
+    int ok = 1;
+    RbxlOutput out = {0};
+
+    /* Bad input is reported, not crashed on. */
+    ok &= check(rbxl_convert_buffer(NULL, 0, NULL, &out) == RBXL_INVALID_ARGUMENT,
+                "null data is an invalid argument");
+    ok &= check(rbxl_last_error() != NULL, "a failed call leaves a message");
+    const unsigned char junk[] = "not a place";
+    ok &= check(rbxl_convert_buffer(junk, sizeof junk, NULL, &out) == RBXL_EXPORT_FAILED,
+                "junk bytes fail to convert");
+    ok &= check(rbxl_convert_buffer(junk, sizeof junk, "{\"no_such_option\": true}", &out) ==
+                    RBXL_INVALID_ARGUMENT,
+                "unknown options are rejected");
+
+    size_t len = 0;
+    unsigned char *place = read_file(argv[1], &len);
+    if (!place) {
+        fprintf(stderr, "couldn't read %s\n", argv[1]);
+        return 2;
+    }
+    int code = rbxl_convert_buffer(place, len, "{\"weld\": 0.001, \"merge_boxes\": true}", &out);
+    free(place);
+    if (!check(code == RBXL_OK, "the place converts")) {
+        fprintf(stderr, "  %s\n", rbxl_last_error());
+        return 1;
+    }
+    ok &= check(out.obj_len > 0 && out.mtl_len > 0, "OBJ and MTL are both written");
+    ok &= check(out.obj_len > 6 && memcmp(out.obj, "# ", 2) == 0, "the OBJ starts with its header");
+    printf("OBJ %zu bytes, MTL %zu bytes\n", out.obj_len, out.mtl_len);
+
+    rbxl_free_output(&out);
+    ok &= check(out.obj == NULL && out.mtl == NULL, "freeing zeroes the output");
+    rbxl_free_output(&out);
+
+    return ok ? 0 : 1;
+}
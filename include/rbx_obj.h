@@ -0,0 +1,73 @@
+/* Generated by cbindgen from src/ffi.rs. Don't edit by hand. */
+
+#ifndef RBX_OBJ_H
+#define RBX_OBJ_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The conversion worked and the output holds the OBJ and MTL.
+#define RBXL_OK 0
+
+// A pointer was null, or the options weren't valid JSON, named an option
+// this doesn't know or didn't go together.
+#define RBXL_INVALID_ARGUMENT 1
+
+// The bytes didn't decode or the export failed.
+#define RBXL_EXPORT_FAILED 2
+
+// The converter panicked. That's a bug; the message says where.
+#define RBXL_PANIC 3
+
+// What `rbxl_convert_buffer` wrote. Neither buffer is NUL-terminated. Pass
+// it to `rbxl_free_output` once done with it.
+typedef struct RbxlOutput {
+  uint8_t *obj;
+  size_t obj_len;
+  uint8_t *mtl;
+  size_t mtl_len;
+} RbxlOutput;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Converts the `len` bytes of a binary place or model at `data`, filling
+// in `out` on success. `options_json` is a JSON object of option names (as
+// in `ExportOptions`) to values, like `{"weld": 0.001, "merge_boxes":
+// true, "services": ["Workspace", "Lighting"]}`, taking what
+// `ExportOptions::set` does, or null for the defaults. Returns one of the `RBXL_*` codes; on
+// anything but `RBXL_OK`, `out` is left alone and `rbxl_last_error` says
+// what went wrong.
+//
+// # Safety
+//
+// `data` must point to `len` readable bytes, `options_json` must be null
+// or a NUL-terminated string, and `out` must point to an `RbxlOutput` that
+// can be written.
+int rbxl_convert_buffer(const uint8_t *data,
+                        size_t len,
+                        const char *options_json,
+                        struct RbxlOutput *out);
+
+// Frees the buffers of an output filled in by `rbxl_convert_buffer` and
+// zeroes it, so freeing it twice does nothing. Null is ignored.
+//
+// # Safety
+//
+// `output` must be null or point to an `RbxlOutput` that's zeroed or was
+// filled in by `rbxl_convert_buffer`.
+void rbxl_free_output(struct RbxlOutput *output);
+
+// The message for the last call on this thread that failed, or null if
+// none has. It stays valid until the next failing call on the same thread.
+const char *rbxl_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RBX_OBJ_H */
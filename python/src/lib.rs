@@ -0,0 +1,112 @@
+//! The `rbxl_to_obj` Python module, for `maturin build` in this directory.
+//! Like the C interface, it converts bytes already in memory and takes the
+//! options by the names of `ExportOptions`' fields.
+
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyValueError},
+    prelude::*,
+    types::{PyBool, PyDict, PyList, PyTuple},
+};
+use rbx_obj::{ExportOptions, OptionValue, export_dom};
+
+create_exception!(
+    rbxl_to_obj,
+    ConvertError,
+    PyException,
+    "The bytes didn't decode or the export failed."
+);
+
+/// What `convert` wrote: the OBJ and MTL as strings, and `stats`, a dict of
+/// `parts`, `parts_by_class`, `vertices`, `faces` and `materials`.
+#[pyclass(frozen, module = "rbxl_to_obj")]
+struct ConvertResult {
+    /// The OBJ, whose `mtllib` line names `scene.mtl`.
+    #[pyo3(get)]
+    obj: String,
+    #[pyo3(get)]
+    mtl: String,
+    #[pyo3(get)]
+    stats: Py<PyDict>,
+}
+
+/// Converts the bytes of a binary place or model. `options` is a dict of
+/// option names to values, like `{"weld": 0.001, "merge_boxes": True,
+/// "services": ["Workspace"]}`, taking what `ExportOptions::set` does; it
+/// raises `ValueError` for one it doesn't know, a value of the wrong kind
+/// or options that don't go together, and `ConvertError` when the bytes
+/// don't decode or the export fails.
+#[pyfunction]
+#[pyo3(signature = (data, options = None))]
+fn convert(
+    py: Python<'_>,
+    data: &[u8],
+    options: Option<&Bound<'_, PyDict>>,
+) -> PyResult<ConvertResult> {
+    let opts = match options {
+        Some(options) => parse_options(options)?,
+        None => ExportOptions::default(),
+    };
+    let dom = rbx_binary::from_reader(data)
+        .map_err(|e| ConvertError::new_err(format!("couldn't decode the file: {}", e)))?;
+    let (mut obj, mut mtl) = (Vec::new(), Vec::new());
+    let report = export_dom(&dom, &mut obj, Some(&mut mtl), &opts)
+        .map_err(|e| ConvertError::new_err(e.to_string()))?;
+
+    let stats = PyDict::new(py);
+    stats.set_item("parts", report.parts())?;
+    stats.set_item("parts_by_class", &report.parts_by_class)?;
+    stats.set_item("vertices", report.vertices)?;
+    stats.set_item("faces", report.faces)?;
+    stats.set_item("materials", report.materials)?;
+    Ok(ConvertResult {
+        obj: String::from_utf8_lossy(&obj).into_owned(),
+        mtl: String::from_utf8_lossy(&mtl).into_owned(),
+        stats: stats.unbind(),
+    })
+}
+
+/// Sets each option with `ExportOptions::set`, as `rbxl_convert_buffer`
+/// does.
+fn parse_options(options: &Bound<'_, PyDict>) -> PyResult<ExportOptions> {
+    let mut opts = ExportOptions::default();
+    for (key, value) in options {
+        let key: String = key.extract()?;
+        let value = option_value(&value)
+            .ok_or_else(|| format!("{} can't be a {}", key, value.get_type()))
+            .and_then(|value| opts.set(&key, value).map_err(|e| e.to_string()));
+        value.map_err(|e| PyValueError::new_err(format!("bad options: {}", e)))?;
+    }
+    opts.validate()
+        .map_err(|e| PyValueError::new_err(format!("bad options: {}", e)))?;
+    Ok(opts)
+}
+
+/// `value` as an `OptionValue`: None, a bool, a number, a string, or a list
+/// or tuple of those.
+fn option_value(value: &Bound<'_, PyAny>) -> Option<OptionValue> {
+    if value.is_none() {
+        Some(OptionValue::Null)
+    } else if value.is_instance_of::<PyBool>() {
+        value.extract().ok().map(OptionValue::Bool)
+    } else if let Ok(s) = value.extract::<String>() {
+        Some(OptionValue::String(s))
+    } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+        value
+            .try_iter()
+            .ok()?
+            .map(|item| option_value(&item.ok()?))
+            .collect::<Option<_>>()
+            .map(OptionValue::List)
+    } else {
+        value.extract().ok().map(OptionValue::Number)
+    }
+}
+
+#[pymodule]
+fn rbxl_to_obj(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(convert, m)?)?;
+    m.add_class::<ConvertResult>()?;
+    m.add("ConvertError", m.py().get_type::<ConvertError>())?;
+    Ok(())
+}
@@ -0,0 +1,64 @@
+"""Run with `maturin develop && pytest` from python/."""
+
+from pathlib import Path
+
+import pytest
+
+import rbxl_to_obj
+
+# A Model holding two anchored blocks, an 8x1x8 grey floor and a 2x2x2 red crate on it.
+FIXTURE = Path(__file__).with_name("two_blocks.rbxm")
+
+
+def convert(options=None):
+    return rbxl_to_obj.convert(FIXTURE.read_bytes(), options=options)
+
+
+def count_lines(text, prefix):
+    return sum(1 for line in text.splitlines() if line.startswith(prefix))
+
+
+def test_converts_the_fixture():
+    result = convert()
+    assert result.stats["vertices"] == 16
+    assert count_lines(result.obj, "v ") == 16
+    assert result.stats["faces"] == count_lines(result.obj, "f ") == 24
+    assert result.stats["parts"] == 2
+    assert result.stats["parts_by_class"] == {"Part": 2}
+    assert result.stats["materials"] == count_lines(result.mtl, "newmtl ") == 2
+
+
+def test_takes_options():
+    result = convert({"weld": 0.001, "merge_boxes": True})
+    assert result.stats["vertices"] == count_lines(result.obj, "v ")
+    assert result.stats["parts"] == 2
+
+
+def test_unknown_option_is_a_value_error():
+    with pytest.raises(ValueError):
+        convert({"no_such_option": True})
+
+
+def test_bytes_that_dont_decode_are_a_convert_error():
+    with pytest.raises(rbxl_to_obj.ConvertError):
+        rbxl_to_obj.convert(b"not a place")
+
+
+def test_takes_every_kind_of_option():
+    result = convert(
+        {
+            "services": ["Workspace"],
+            "mirror": [True, False, False],
+            "normals": "flat",
+            "tag_materials": [["Lava", [255, 0, 0]]],
+            "region": (-10, -10, -10, 10, 10, 10),
+            "max_depth": 8,
+        }
+    )
+    assert result.stats["parts"] == 2
+
+
+def test_option_of_the_wrong_kind_is_a_value_error():
+    for options in [{"merge_boxes": 1}, {"weld": True}, {"normals": "shiny"}, {"mirror": [True]}]:
+        with pytest.raises(ValueError):
+            convert(options)
@@ -0,0 +1,661 @@
+//! A download cache for the assets places refer to, for the exports that
+//! need more than the place itself. Each asset is kept in one directory under
+//! its id and version with its MD5 beside it, so a download that was cut
+//! short or a copy that got damaged is fetched again instead of used.
+
+use std::{
+    error::Error,
+    fmt, fs,
+    io::{self, Read},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use md5::{Digest, Md5};
+
+/// Where assets come from unless [`AssetCache::base_url`] says otherwise.
+const ROBLOX_ASSET_DELIVERY: &str = "https://assetdelivery.roblox.com";
+
+/// The longest a server's `Retry-After` gets to hold up a download.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Tells apart the partial files of downloads running at the same time.
+static DOWNLOADS: AtomicUsize = AtomicUsize::new(0);
+
+/// One version of an asset, or its latest without a version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AssetKey {
+    pub id: u64,
+    pub version: Option<u32>,
+}
+
+impl AssetKey {
+    pub fn latest(id: u64) -> Self {
+        AssetKey { id, version: None }
+    }
+
+    fn file_name(&self) -> String {
+        match self.version {
+            Some(version) => format!("{}-v{}", self.id, version),
+            None => self.id.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for AssetKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.version {
+            Some(version) => write!(f, "asset {} version {}", self.id, version),
+            None => write!(f, "asset {}", self.id),
+        }
+    }
+}
+
+/// An asset that's in the cache.
+#[derive(Debug)]
+pub struct CachedAsset {
+    pub key: AssetKey,
+    pub path: PathBuf,
+    /// It wasn't in the cache, or wasn't intact, and was downloaded just now.
+    pub downloaded: bool,
+}
+
+/// Why an asset couldn't be had.
+#[derive(Debug)]
+pub enum AssetError {
+    /// The cache is offline and doesn't have the asset.
+    NotCached(AssetKey),
+    /// The server answered with `status`, the last time if it was worth
+    /// retrying.
+    Status { key: AssetKey, status: u16 },
+    /// The connection failed or the asset didn't arrive whole, the last time.
+    Download { key: AssetKey, message: String },
+    /// Reading or writing the cache directory failed.
+    Io { path: PathBuf, source: io::Error },
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::NotCached(key) => {
+                write!(f, "{} isn't in the cache, and downloading is off", key)
+            }
+            AssetError::Status { key, status } => {
+                write!(f, "couldn't download {}: HTTP {}", key, status)
+            }
+            AssetError::Download { key, message } => {
+                write!(f, "couldn't download {}: {}", key, message)
+            }
+            AssetError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+        }
+    }
+}
+
+impl Error for AssetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AssetError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Assets on disk in a directory, downloaded into it when they're missing.
+///
+/// Requests answered with 429 or a 5xx, and ones whose connection fails or
+/// whose body comes up short, are tried again after a wait that doubles each
+/// time, or as long as the server's `Retry-After` says. A server that can't
+/// be reached at all fails the downloads after it straight away.
+pub struct AssetCache {
+    dir: PathBuf,
+    /// `None` when offline.
+    agent: Option<ureq::Agent>,
+    base_url: String,
+    concurrency: NonZeroUsize,
+    retries: u32,
+    backoff: Duration,
+    /// Why the server couldn't be reached, once it couldn't.
+    unreachable: OnceLock<String>,
+}
+
+impl AssetCache {
+    /// A cache in `dir`, created along with the first asset put in it, that
+    /// downloads from Roblox four assets at a time and tries each request up
+    /// to five times, waiting half a second before the first retry.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(10))
+            .timeout_read(Duration::from_secs(30))
+            .user_agent(concat!("rbx_obj/", env!("CARGO_PKG_VERSION")))
+            .build();
+        AssetCache {
+            dir: dir.into(),
+            agent: Some(agent),
+            base_url: ROBLOX_ASSET_DELIVERY.to_string(),
+            concurrency: NonZeroUsize::new(4).unwrap(),
+            retries: 4,
+            backoff: Duration::from_millis(500),
+            unreachable: OnceLock::new(),
+        }
+    }
+
+    /// Only hands out what's already in the cache, failing with
+    /// [`AssetError::NotCached`] for the rest instead of downloading.
+    pub fn offline(mut self) -> Self {
+        self.agent = None;
+        self
+    }
+
+    /// How many assets [`AssetCache::get_all`] downloads at once.
+    pub fn concurrency(mut self, concurrency: NonZeroUsize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// How many times a failed request is tried again.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// How long to wait before the first retry.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// The server to download from, in place of Roblox's asset delivery.
+    /// It's asked for `<url>/v1/asset/?id=<id>`, with `&version=<version>`
+    /// for a versioned key.
+    pub fn base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = url.into();
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// `key`'s asset, from the cache if it's there and intact, downloaded
+    /// into it otherwise.
+    pub fn get(&self, key: AssetKey) -> Result<CachedAsset, AssetError> {
+        let path = self.dir.join(key.file_name());
+        if intact(&path)? {
+            return Ok(CachedAsset {
+                key,
+                path,
+                downloaded: false,
+            });
+        }
+        let Some(agent) = &self.agent else {
+            return Err(AssetError::NotCached(key));
+        };
+        if let Some(message) = self.unreachable.get() {
+            return Err(AssetError::Download {
+                key,
+                message: message.clone(),
+            });
+        }
+        let body = self.download(agent, key)?;
+        store(&self.dir, &path, &body)?;
+        Ok(CachedAsset {
+            key,
+            path,
+            downloaded: true,
+        })
+    }
+
+    /// [`AssetCache::get`] for each of `keys`, at most `concurrency` at a
+    /// time, in the order of `keys`.
+    pub fn get_all(&self, keys: &[AssetKey]) -> Vec<Result<CachedAsset, AssetError>> {
+        let next = AtomicUsize::new(0);
+        let results: Vec<Mutex<Option<_>>> = keys.iter().map(|_| Mutex::new(None)).collect();
+        thread::scope(|scope| {
+            for _ in 0..self.concurrency.get().min(keys.len()) {
+                scope.spawn(|| {
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(&key) = keys.get(i) else { break };
+                        *results[i].lock().unwrap() = Some(self.get(key));
+                    }
+                });
+            }
+        });
+        results
+            .into_iter()
+            .map(|result| result.into_inner().unwrap().unwrap())
+            .collect()
+    }
+
+    fn download(&self, agent: &ureq::Agent, key: AssetKey) -> Result<Vec<u8>, AssetError> {
+        let mut url = format!("{}/v1/asset/?id={}", self.base_url, key.id);
+        if let Some(version) = key.version {
+            url.push_str(&format!("&version={}", version));
+        }
+        let (mut attempt, mut wait) = (0, self.backoff);
+        loop {
+            let (error, retry_after) = match agent.get(&url).call() {
+                Ok(response) => match read_body(response) {
+                    Ok(body) => return Ok(body),
+                    Err(message) => (AssetError::Download { key, message }, None),
+                },
+                Err(ureq::Error::Status(status, response)) if status == 429 || status >= 500 => {
+                    let retry_after = response
+                        .header("Retry-After")
+                        .and_then(|seconds| seconds.trim().parse().ok())
+                        .map(|seconds| Duration::from_secs(seconds).min(MAX_RETRY_AFTER));
+                    (AssetError::Status { key, status }, retry_after)
+                }
+                Err(ureq::Error::Status(status, _)) => {
+                    return Err(AssetError::Status { key, status });
+                }
+                Err(ureq::Error::Transport(transport)) => {
+                    // Not `to_string`, which starts with the URL, for the
+                    // error to say only the key when other assets reuse it.
+                    let message = match transport.message() {
+                        Some(message) => format!("{}: {}", transport.kind(), message),
+                        None => transport.kind().to_string(),
+                    };
+                    let error = AssetError::Download {
+                        key,
+                        message: message.clone(),
+                    };
+                    // A name that didn't resolve won't on a retry either.
+                    let dns = matches!(transport.kind(), ureq::ErrorKind::Dns);
+                    let refused = matches!(transport.kind(), ureq::ErrorKind::ConnectionFailed);
+                    if dns || (refused && attempt == self.retries) {
+                        let _ = self.unreachable.set(message);
+                        return Err(error);
+                    }
+                    (error, None)
+                }
+            };
+            if attempt == self.retries {
+                return Err(error);
+            }
+            let pause = retry_after.unwrap_or(wait);
+            log::debug!("{}; trying again in {:.1}s", error, pause.as_secs_f64());
+            thread::sleep(pause);
+            attempt += 1;
+            wait = wait.saturating_mul(2);
+        }
+    }
+}
+
+/// The whole body of `response`, or why it's not to be trusted: it came up
+/// short of its `Content-Length`, or it doesn't match an ETag that's an MD5,
+/// which is what Roblox's CDN sends.
+fn read_body(response: ureq::Response) -> Result<Vec<u8>, String> {
+    let length: Option<usize> = response
+        .header("Content-Length")
+        .and_then(|length| length.trim().parse().ok());
+    let etag_md5 = response
+        .header("ETag")
+        .map(|etag| etag.trim_matches('"').to_ascii_lowercase())
+        .filter(|etag| etag.len() == 32 && etag.bytes().all(|b| b.is_ascii_hexdigit()));
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut body)
+        .map_err(|e| e.to_string())?;
+    if let Some(length) = length
+        && body.len() != length
+    {
+        return Err(format!("got {} of {} bytes", body.len(), length));
+    }
+    if let Some(etag_md5) = etag_md5
+        && etag_md5 != md5_hex(&body)
+    {
+        return Err(format!("the body doesn't match its ETag {}", etag_md5));
+    }
+    Ok(body)
+}
+
+/// Whether the asset at `path` is there and matches the MD5 stored with it.
+fn intact(path: &Path) -> Result<bool, AssetError> {
+    let read = |path: &Path| match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(AssetError::Io {
+            path: path.to_path_buf(),
+            source,
+        }),
+    };
+    let (Some(body), Some(md5)) = (read(path)?, read(&md5_path(path))?) else {
+        return Ok(false);
+    };
+    Ok(md5.trim_ascii() == md5_hex(&body).as_bytes())
+}
+
+/// Puts `body` at `path` whole or not at all, then its MD5 beside it. A copy
+/// left without its MD5 by a crash in between is downloaded again.
+fn store(dir: &Path, path: &Path, body: &[u8]) -> Result<(), AssetError> {
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| AssetError::Io { path, source }
+    };
+    fs::create_dir_all(dir).map_err(io_error(dir))?;
+    let partial = path.with_extension(format!(
+        "{}-{}.part",
+        process::id(),
+        DOWNLOADS.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&partial, body).map_err(io_error(&partial))?;
+    fs::rename(&partial, path).map_err(io_error(path))?;
+    let md5_path = md5_path(path);
+    fs::write(&md5_path, md5_hex(body)).map_err(io_error(&md5_path))
+}
+
+fn md5_path(path: &Path) -> PathBuf {
+    path.with_extension("md5")
+}
+
+fn md5_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Md5::digest(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::Arc,
+        time::Instant,
+    };
+
+    use super::*;
+
+    /// What the mock server answers a request with.
+    struct Reply {
+        status: u16,
+        headers: Vec<(&'static str, String)>,
+        body: Vec<u8>,
+        /// Hang up after this many bytes of the body.
+        cut_at: Option<usize>,
+    }
+
+    impl Reply {
+        /// A 200 with an ETag that's the body's MD5, like Roblox's CDN.
+        fn ok(body: &[u8]) -> Self {
+            Reply {
+                status: 200,
+                headers: vec![("ETag", format!("\"{}\"", md5_hex(body)))],
+                body: body.to_vec(),
+                cut_at: None,
+            }
+        }
+
+        fn status(status: u16) -> Self {
+            Reply {
+                status,
+                headers: Vec::new(),
+                body: Vec::new(),
+                cut_at: None,
+            }
+        }
+    }
+
+    /// An HTTP server on localhost answering every request on its own thread.
+    struct MockServer {
+        url: String,
+        /// The path and query of each request, in the order they came.
+        requests: Arc<Mutex<Vec<String>>>,
+        /// The most requests that were being answered at the same time.
+        most_at_once: Arc<AtomicUsize>,
+    }
+
+    impl MockServer {
+        /// Answers the `n`th request (from 0), asking for `path`, with
+        /// `reply(path, n)`.
+        fn start(reply: impl Fn(&str, usize) -> Reply + Send + Sync + 'static) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let most_at_once = Arc::new(AtomicUsize::new(0));
+            let at_once = Arc::new(AtomicUsize::new(0));
+            let reply = Arc::new(reply);
+            let server = MockServer {
+                url,
+                requests: requests.clone(),
+                most_at_once: most_at_once.clone(),
+            };
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let mut stream = stream.unwrap();
+                    let (requests, most_at_once, at_once, reply) = (
+                        requests.clone(),
+                        most_at_once.clone(),
+                        at_once.clone(),
+                        reply.clone(),
+                    );
+                    thread::spawn(move || {
+                        let mut head = BufReader::new(&stream).lines();
+                        let request_line = head.next().unwrap().unwrap();
+                        for line in head.by_ref() {
+                            if line.unwrap().is_empty() {
+                                break;
+                            }
+                        }
+                        let path = request_line.split(' ').nth(1).unwrap().to_string();
+                        let n = {
+                            let mut requests = requests.lock().unwrap();
+                            requests.push(path.clone());
+                            requests.len() - 1
+                        };
+                        let now = at_once.fetch_add(1, Ordering::SeqCst) + 1;
+                        most_at_once.fetch_max(now, Ordering::SeqCst);
+                        let reply = reply(&path, n);
+                        at_once.fetch_sub(1, Ordering::SeqCst);
+
+                        let mut response = format!(
+                            "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+                            reply.status,
+                            reply.body.len()
+                        );
+                        for (name, value) in &reply.headers {
+                            response.push_str(&format!("{}: {}\r\n", name, value));
+                        }
+                        response.push_str("\r\n");
+                        let sent = reply.cut_at.unwrap_or(reply.body.len());
+                        // The client may hang up first on an error status.
+                        let _ = stream
+                            .write_all(response.as_bytes())
+                            .and_then(|()| stream.write_all(&reply.body[..sent]));
+                    });
+                }
+            });
+            server
+        }
+
+        fn requests(&self) -> Vec<String> {
+            self.requests.lock().unwrap().clone()
+        }
+
+        /// A cache in a fresh directory named after the test, downloading
+        /// from this server without waiting between retries.
+        fn cache(&self, test: &str) -> AssetCache {
+            let dir = std::env::temp_dir().join(format!("rbx_obj-{}-{}", test, process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            AssetCache::new(dir)
+                .base_url(&self.url)
+                .backoff(Duration::from_millis(1))
+        }
+    }
+
+    const BODY: &[u8] = b"version 2.00\n8\n";
+
+    #[test]
+    fn downloads_once_then_reads_the_cache() {
+        let server = MockServer::start(|_, _| Reply::ok(BODY));
+        let cache = server.cache("downloads_once");
+        let key = AssetKey {
+            id: 7,
+            version: Some(2),
+        };
+        let first = cache.get(key).unwrap();
+        assert!(first.downloaded);
+        assert_eq!(first.path, cache.dir().join("7-v2"));
+        assert_eq!(fs::read(&first.path).unwrap(), BODY);
+        let second = cache.get(key).unwrap();
+        assert!(!second.downloaded);
+        assert_eq!(second.path, first.path);
+        assert_eq!(server.requests(), ["/v1/asset/?id=7&version=2"]);
+    }
+
+    #[test]
+    fn retries_rate_limits_and_server_errors() {
+        let server = MockServer::start(|_, n| match n {
+            0 => Reply {
+                headers: vec![("Retry-After", "0".to_string())],
+                ..Reply::status(429)
+            },
+            1 => Reply::status(503),
+            _ => Reply::ok(BODY),
+        });
+        let cache = server.cache("retries");
+        assert!(cache.get(AssetKey::latest(1)).unwrap().downloaded);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn gives_up_after_the_last_retry() {
+        let server = MockServer::start(|_, _| Reply::status(500));
+        let cache = server.cache("gives_up").retries(2);
+        let error = cache.get(AssetKey::latest(1)).unwrap_err();
+        assert!(matches!(error, AssetError::Status { status: 500, .. }));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn backs_off_twice_as_long_each_time() {
+        let server = MockServer::start(|_, _| Reply::status(503));
+        let cache = server
+            .cache("backs_off")
+            .retries(3)
+            .backoff(Duration::from_millis(40));
+        let started = Instant::now();
+        cache.get(AssetKey::latest(1)).unwrap_err();
+        // 40 + 80 + 160 ms.
+        assert!(started.elapsed() >= Duration::from_millis(280));
+        assert_eq!(server.requests().len(), 4);
+    }
+
+    #[test]
+    fn doesnt_retry_what_isnt_there() {
+        let server = MockServer::start(|_, _| Reply::status(404));
+        let cache = server.cache("not_there");
+        let error = cache.get(AssetKey::latest(1)).unwrap_err();
+        assert!(matches!(error, AssetError::Status { status: 404, .. }));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn downloads_again_when_one_is_cut_short() {
+        let server = MockServer::start(|_, n| Reply {
+            cut_at: (n == 0).then_some(4),
+            ..Reply::ok(BODY)
+        });
+        let cache = server.cache("cut_short");
+        let asset = cache.get(AssetKey::latest(1)).unwrap();
+        assert_eq!(fs::read(asset.path).unwrap(), BODY);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn downloads_again_when_the_body_doesnt_match_its_etag() {
+        let server = MockServer::start(|_, n| match n {
+            0 => Reply {
+                body: b"version 2.00\n9\n".to_vec(),
+                ..Reply::ok(BODY)
+            },
+            _ => Reply::ok(BODY),
+        });
+        let cache = server.cache("etag");
+        let asset = cache.get(AssetKey::latest(1)).unwrap();
+        assert_eq!(fs::read(asset.path).unwrap(), BODY);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn downloads_again_over_a_damaged_copy() {
+        let server = MockServer::start(|_, _| Reply::ok(BODY));
+        let cache = server.cache("damaged");
+        let key = AssetKey::latest(1);
+        let asset = cache.get(key).unwrap();
+
+        fs::write(&asset.path, &BODY[..4]).unwrap();
+        assert!(cache.get(key).unwrap().downloaded);
+        assert_eq!(fs::read(&asset.path).unwrap(), BODY);
+
+        // As a crash between writing the asset and its MD5 would leave it.
+        fs::remove_file(md5_path(&asset.path)).unwrap();
+        assert!(cache.get(key).unwrap().downloaded);
+        assert!(!cache.get(key).unwrap().downloaded);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn stops_trying_a_server_that_cant_be_reached() {
+        // Nothing listens on a port that was just given up.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let cache = AssetCache::new(std::env::temp_dir().join("rbx_obj-unreachable"))
+            .base_url(url)
+            .retries(2)
+            .backoff(Duration::from_millis(100));
+
+        let started = Instant::now();
+        let first = cache.get(AssetKey::latest(1)).unwrap_err();
+        assert!(matches!(first, AssetError::Download { .. }));
+        assert!(started.elapsed() >= Duration::from_millis(300));
+
+        // The rest don't wait through retries of their own.
+        let started = Instant::now();
+        let keys: Vec<AssetKey> = (2..=9).map(AssetKey::latest).collect();
+        let rest = cache.get_all(&keys);
+        assert!(rest.iter().all(Result::is_err));
+        assert!(started.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn offline_only_hands_out_whats_cached() {
+        let server = MockServer::start(|_, _| Reply::ok(BODY));
+        let cache = server.cache("offline");
+        let dir = cache.dir().to_path_buf();
+        let offline = AssetCache::new(&dir).base_url(&server.url).offline();
+
+        let error = offline.get(AssetKey::latest(1)).unwrap_err();
+        assert!(matches!(error, AssetError::NotCached(key) if key.id == 1));
+        assert!(server.requests().is_empty());
+
+        cache.get(AssetKey::latest(1)).unwrap();
+        assert!(!offline.get(AssetKey::latest(1)).unwrap().downloaded);
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn downloads_at_most_concurrency_at_once() {
+        let server = MockServer::start(|_, _| {
+            thread::sleep(Duration::from_millis(50));
+            Reply::ok(BODY)
+        });
+        let cache = server
+            .cache("concurrency")
+            .concurrency(NonZeroUsize::new(3).unwrap());
+        let keys: Vec<AssetKey> = (1..=8).map(AssetKey::latest).collect();
+        let assets = cache.get_all(&keys);
+        let ids: Vec<u64> = assets.iter().map(|a| a.as_ref().unwrap().key.id).collect();
+        assert_eq!(ids, (1..=8).collect::<Vec<_>>());
+        assert_eq!(server.requests().len(), 8);
+        let most = server.most_at_once.load(Ordering::SeqCst);
+        assert!((2..=3).contains(&most), "{} at once", most);
+    }
+}
@@ -0,0 +1,402 @@
+//! [`export_dom_async`] and [`convert_file_async`]: the export on Tokio's
+//! blocking threads, handing what it writes over to `AsyncWrite`s.
+
+use std::{
+    io::{self, Write},
+    panic,
+    path::Path,
+    sync::Arc,
+};
+
+use rbx_dom_weak::WeakDom;
+use tokio::{
+    fs::{self, File},
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+    task::{self, JoinError},
+};
+
+use crate::{
+    CancellationToken, ExportError, ExportOptions, ExportReport,
+    export::{self, AsyncRead},
+    export_dom,
+};
+
+/// How much the export writes before handing it over.
+const CHUNK: usize = 64 * 1024;
+
+/// Chunks handed over but not written yet before the export waits for the
+/// writers to catch up.
+const CHUNKS_IN_FLIGHT: usize = 16;
+
+enum Chunk {
+    Obj(Vec<u8>),
+    Mtl(Vec<u8>),
+}
+
+/// The `Write` the export writes into, sending what it gets to the async
+/// side a chunk at a time.
+struct ChunkWriter {
+    chunks: mpsc::Sender<Chunk>,
+    wrap: fn(Vec<u8>) -> Chunk,
+    buffer: Vec<u8>,
+}
+
+impl ChunkWriter {
+    fn new(chunks: mpsc::Sender<Chunk>, wrap: fn(Vec<u8>) -> Chunk) -> Self {
+        ChunkWriter {
+            chunks,
+            wrap,
+            buffer: Vec::with_capacity(CHUNK),
+        }
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(CHUNK));
+        self.chunks
+            .blocking_send((self.wrap)(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the async writer stopped"))
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(bytes);
+        if self.buffer.len() >= CHUNK {
+            self.send()?;
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+/// [`export_dom`] for async code. The export runs on Tokio's blocking
+/// threads, so it has to be awaited inside a Tokio runtime, while what it
+/// writes goes into `obj` and `mtl` on this task as it comes, at most about
+/// a megabyte ahead of them. Several exports can run at once, sharing the
+/// DOM and options.
+///
+/// The writers are flushed at the end but not shut down. If writing to one
+/// fails, the export stops and that's the error returned, as is dropping the
+/// future, which stops it at the next chunk.
+pub async fn export_dom_async(
+    dom: Arc<WeakDom>,
+    obj: impl AsyncWrite + Unpin,
+    mtl: Option<impl AsyncWrite + Unpin>,
+    opts: Arc<ExportOptions>,
+) -> Result<ExportReport, ExportError> {
+    write_async(move |obj, mtl| export_dom(&dom, obj, mtl, &opts), obj, mtl).await
+}
+
+/// [`convert_file_cancellable`] for async code, which is what `--async`
+/// runs. The file is read on Tokio's blocking threads, and the OBJ and MTL
+/// are written through Tokio's files as [`export_dom_async`] writes them;
+/// the files that go with them, like `part_info_csv`, are written directly.
+/// `lods`, `max_vertices_per_file` and the formats that don't write an OBJ
+/// are `Unsupported`.
+///
+/// [`convert_file_cancellable`]: crate::convert_file_cancellable
+pub async fn convert_file_async(
+    input: &Path,
+    output: &Path,
+    opts: Arc<ExportOptions>,
+    cancel: &CancellationToken,
+) -> Result<ExportReport, ExportError> {
+    let (input, output, cancel) = (input.to_path_buf(), output.to_path_buf(), cancel.clone());
+    let read = {
+        let (opts, input, output) = (opts.clone(), input.clone(), output.clone());
+        let read = task::spawn_blocking(move || {
+            export::read_for_async(&opts, &input, &output).map_err(ExportError::from)
+        });
+        joined(read.await)?
+    };
+    let read = match read {
+        AsyncRead::Done(report) => return Ok(report),
+        AsyncRead::Read(read) => read,
+    };
+
+    let mtl_path = export::mtl_path(&output, &opts);
+    let obj = File::create(&output)
+        .await
+        .map_err(|e| ExportError::io(&output, e))?;
+    let mtl = File::create(&mtl_path)
+        .await
+        .map_err(|e| ExportError::io(&mtl_path, e))?;
+    let result = write_async(
+        {
+            let output = output.clone();
+            move |obj, mtl| {
+                let mut discard = io::sink();
+                let mtl = mtl.unwrap_or(&mut discard);
+                export::convert_into(&opts, read, &input, &output, obj, mtl, Some(&cancel))
+                    .map_err(Into::into)
+            }
+        },
+        obj,
+        Some(mtl),
+    )
+    .await;
+    // Like `convert_file_cancellable`, don't leave half an export behind.
+    if let Err(ExportError::Cancelled) = result {
+        for path in [&output, &mtl_path] {
+            match fs::remove_file(path).await {
+                Ok(()) => log::info!("removed partial output {}", path.display()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => log::warn!("couldn't remove partial output {}: {}", path.display(), e),
+            }
+        }
+    }
+    result
+}
+
+/// What a blocking task returned, passing on its panic if it had one.
+fn joined<T>(joined: Result<Result<T, ExportError>, JoinError>) -> Result<T, ExportError> {
+    match joined {
+        Ok(result) => result,
+        Err(error) if error.is_panic() => panic::resume_unwind(error.into_panic()),
+        Err(error) => Err(ExportError::Other(error.into())),
+    }
+}
+
+/// Runs `export` on Tokio's blocking threads, writing what it writes into
+/// `obj` and `mtl` on this task as it comes.
+async fn write_async(
+    export: impl FnOnce(&mut dyn Write, Option<&mut dyn Write>) -> Result<ExportReport, ExportError>
+    + Send
+    + 'static,
+    mut obj: impl AsyncWrite + Unpin,
+    mut mtl: Option<impl AsyncWrite + Unpin>,
+) -> Result<ExportReport, ExportError> {
+    let (chunks, mut received) = mpsc::channel(CHUNKS_IN_FLIGHT);
+    let with_mtl = mtl.is_some();
+    let export = task::spawn_blocking(move || {
+        let mut obj = ChunkWriter::new(chunks.clone(), Chunk::Obj);
+        let mut mtl = with_mtl.then(|| ChunkWriter::new(chunks, Chunk::Mtl));
+        let report = export(&mut obj, mtl.as_mut().map(|mtl| mtl as &mut dyn Write))?;
+        obj.flush()?;
+        if let Some(mtl) = &mut mtl {
+            mtl.flush()?;
+        }
+        Ok(report)
+    });
+
+    let mut written = Ok(());
+    while let Some(chunk) = received.recv().await {
+        written = match (chunk, &mut mtl) {
+            (Chunk::Obj(bytes), _) => obj.write_all(&bytes).await,
+            (Chunk::Mtl(bytes), Some(mtl)) => mtl.write_all(&bytes).await,
+            (Chunk::Mtl(_), None) => Ok(()),
+        };
+        if written.is_err() {
+            break;
+        }
+    }
+    // The export gets an error at its next chunk instead of waiting forever.
+    drop(received);
+    let report = joined(export.await);
+    written?;
+    let report = report?;
+    obj.flush().await?;
+    if let Some(mtl) = &mut mtl {
+        mtl.flush().await?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use rbx_dom_weak::InstanceBuilder;
+    use rbx_types::{CFrame, Enum, Matrix3, Vector3};
+
+    use super::*;
+
+    /// `count` balls in a row, enough for the OBJ to take many chunks.
+    fn balls(count: usize) -> WeakDom {
+        let mut dom = WeakDom::new(InstanceBuilder::new("DataModel"));
+        let workspace = dom.insert(dom.root_ref(), InstanceBuilder::new("Workspace"));
+        for i in 0..count {
+            dom.insert(
+                workspace,
+                InstanceBuilder::new("Part")
+                    .with_property("Size", Vector3::new(2.0, 2.0, 2.0))
+                    .with_property(
+                        "CFrame",
+                        CFrame::new(Vector3::new(i as f32 * 3.0, 0.0, 0.0), Matrix3::identity()),
+                    )
+                    .with_property("Shape", Enum::from_u32(0)),
+            );
+        }
+        dom
+    }
+
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    /// Takes `left` bytes, then fails every write.
+    struct FailingWriter {
+        left: usize,
+    }
+
+    impl AsyncWrite for FailingWriter {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            bytes: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            if self.left == 0 {
+                return Poll::Ready(Err(io::Error::other("disk full")));
+            }
+            let taken = bytes.len().min(self.left);
+            self.left -= taken;
+            Poll::Ready(Ok(taken))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn writes_what_export_dom_writes() {
+        let dom = Arc::new(balls(200));
+        let opts = Arc::new(ExportOptions::default());
+        let (mut obj, mut mtl) = (Vec::new(), Vec::new());
+        let report = export_dom(&dom, &mut obj, Some(&mut mtl), &opts).unwrap();
+
+        let (mut async_obj, mut async_mtl) = (Vec::new(), Vec::new());
+        let async_report = block_on(export_dom_async(
+            dom,
+            &mut async_obj,
+            Some(&mut async_mtl),
+            opts,
+        ))
+        .unwrap();
+        assert!(obj.len() > 4 * CHUNK);
+        assert!(async_obj == obj && async_mtl == mtl);
+        assert_eq!(async_report.vertices, report.vertices);
+        assert_eq!(async_report.faces, report.faces);
+    }
+
+    #[test]
+    fn runs_exports_side_by_side() {
+        let dom = Arc::new(balls(50));
+        let opts = Arc::new(ExportOptions::default());
+        let objs = block_on(async {
+            let tasks = [(); 2].map(|()| {
+                let (dom, opts) = (dom.clone(), opts.clone());
+                tokio::spawn(async move {
+                    let mut obj = Vec::new();
+                    let report = export_dom_async(dom, &mut obj, None::<Vec<u8>>, opts).await;
+                    assert_eq!(report.unwrap().parts(), 50);
+                    obj
+                })
+            });
+            let mut objs = Vec::new();
+            for task in tasks {
+                objs.push(task.await.unwrap());
+            }
+            objs
+        });
+        assert!(!objs[0].is_empty() && objs[0] == objs[1]);
+    }
+
+    #[test]
+    fn stops_when_writing_fails() {
+        let writer = FailingWriter { left: CHUNK };
+        let error = block_on(export_dom_async(
+            Arc::new(balls(200)),
+            writer,
+            None::<Vec<u8>>,
+            Arc::new(ExportOptions::default()),
+        ))
+        .unwrap_err();
+        assert!(error.to_string().contains("disk full"), "{}", error);
+    }
+
+    /// A fresh directory named after the test, with `balls(count)` saved in
+    /// it as `place.rbxm`.
+    fn saved_balls(test: &str, count: usize) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("rbx_obj-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let dom = balls(count);
+        let file = std::fs::File::create(dir.join("place.rbxm")).unwrap();
+        rbx_binary::to_writer(file, &dom, dom.root().children()).unwrap();
+        dir
+    }
+
+    #[test]
+    fn converts_a_file_like_convert_file() {
+        let dir = saved_balls("convert-async", 200);
+        let input = dir.join("place.rbxm");
+        for side in ["sync", "async"] {
+            std::fs::create_dir(dir.join(side)).unwrap();
+        }
+        let opts = Arc::new(ExportOptions::default());
+        let report = crate::convert_file(&input, &dir.join("sync/scene.obj"), &opts).unwrap();
+        let output = dir.join("async/scene.obj");
+        let async_report = block_on(convert_file_async(
+            &input,
+            &output,
+            opts,
+            &CancellationToken::default(),
+        ))
+        .unwrap();
+
+        let read = |path: &str| std::fs::read(dir.join(path)).unwrap();
+        assert!(read("sync/scene.obj") == read("async/scene.obj"));
+        assert!(read("sync/scene.mtl") == read("async/scene.mtl"));
+        assert_eq!(async_report.parts(), report.parts());
+        assert_eq!(async_report.outputs, [output.with_extension("mtl"), output]);
+    }
+
+    #[test]
+    fn cancelled_conversion_leaves_no_files() {
+        let dir = saved_balls("convert-async-cancelled", 50);
+        let cancel = CancellationToken::default();
+        cancel.cancel();
+        let output = dir.join("scene.obj");
+        let result = block_on(convert_file_async(
+            &dir.join("place.rbxm"),
+            &output,
+            Arc::new(ExportOptions::default()),
+            &cancel,
+        ));
+        assert!(matches!(result, Err(ExportError::Cancelled)));
+        assert!(!output.exists() && !output.with_extension("mtl").exists());
+    }
+
+    #[test]
+    fn split_outputs_are_unsupported_before_any_file_is_made() {
+        let dir = saved_balls("convert-async-lods", 5);
+        let output = dir.join("scene.obj");
+        let opts = ExportOptions::builder().lods(Some(2)).build().unwrap();
+        let result = block_on(convert_file_async(
+            &dir.join("place.rbxm"),
+            &output,
+            Arc::new(opts),
+            &CancellationToken::default(),
+        ));
+        assert!(matches!(result, Err(ExportError::Unsupported(_))));
+        assert!(!output.exists());
+    }
+}
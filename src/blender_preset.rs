@@ -0,0 +1,127 @@
+//! `--blender-materials`: a Python script that sets up a node material in
+//! Blender for each material in the MTL, with the roughness and metalness
+//! its Roblox `Material` has, so an imported OBJ renders close to Studio in
+//! Cycles or EEVEE without redoing every material by hand.
+
+use std::io::{self, Write};
+
+use crate::scene::roblox_material_name;
+
+/// One material of the export: its MTL name, its color and alpha, and its
+/// `Enum.Material` value.
+pub struct PresetMaterial<'a> {
+    pub name: &'a str,
+    pub color: (u8, u8, u8, u8),
+    pub material: u32,
+}
+
+/// Roughness, metalness and emission strength for a Roblox material, going
+/// by how it looks in Studio.
+fn surface(material: u32) -> (f32, f32, f32) {
+    match roblox_material_name(material) {
+        "SmoothPlastic" => (0.2, 0.0, 0.0),
+        "Neon" => (0.5, 0.0, 5.0),
+        "Wood" | "WoodPlanks" | "Mud" | "Rubber" => (0.7, 0.0, 0.0),
+        "Marble" | "CeramicTiles" => (0.3, 0.0, 0.0),
+        "Granite" | "Leather" => (0.6, 0.0, 0.0),
+        "Basalt" | "Slate" | "Limestone" | "Pebble" | "Snow" | "Salt" | "ClayRoofTiles" => {
+            (0.8, 0.0, 0.0)
+        }
+        "CrackedLava" => (0.8, 0.0, 1.0),
+        "Cobblestone" | "Sandstone" | "Plaster" => (0.85, 0.0, 0.0),
+        "Concrete" | "Pavement" | "Brick" | "Rock" | "Grass" | "LeafyGrass" | "Asphalt"
+        | "Cardboard" | "RoofShingles" => (0.9, 0.0, 0.0),
+        "Sand" | "Fabric" | "Ground" | "Carpet" => (0.95, 0.0, 0.0),
+        "CorrodedMetal" => (0.8, 0.6, 0.0),
+        "DiamondPlate" => (0.4, 1.0, 0.0),
+        "Foil" => (0.2, 1.0, 0.0),
+        "Metal" => (0.35, 1.0, 0.0),
+        "Ice" => (0.1, 0.0, 0.0),
+        "Glacier" => (0.15, 0.0, 0.0),
+        "Glass" | "Water" => (0.05, 0.0, 0.0),
+        "ForceField" => (0.5, 0.0, 1.0),
+        _ => (0.5, 0.0, 0.0),
+    }
+}
+
+/// Roblox colors are sRGB, while Blender's node colors are linear.
+fn srgb_to_linear(channel: u8) -> f32 {
+    let c = channel as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+const SCRIPT: &str = r#"
+for name, color, alpha, roughness, metallic, emission in MATERIALS:
+    material = bpy.data.materials.get(name) or bpy.data.materials.new(name)
+    material.use_nodes = True
+    nodes = material.node_tree.nodes
+    nodes.clear()
+    bsdf = nodes.new("ShaderNodeBsdfPrincipled")
+    output = nodes.new("ShaderNodeOutputMaterial")
+    output.location = (300, 0)
+    material.node_tree.links.new(bsdf.outputs["BSDF"], output.inputs["Surface"])
+
+    bsdf.inputs["Base Color"].default_value = (*color, 1.0)
+    bsdf.inputs["Roughness"].default_value = roughness
+    bsdf.inputs["Metallic"].default_value = metallic
+    bsdf.inputs["Alpha"].default_value = alpha
+    if emission > 0:
+        # Blender 4 renamed Emission to Emission Color.
+        key = "Emission Color" if "Emission Color" in bsdf.inputs else "Emission"
+        bsdf.inputs[key].default_value = (*color, 1.0)
+        if "Emission Strength" in bsdf.inputs:
+            bsdf.inputs["Emission Strength"].default_value = emission
+    if alpha < 1 and hasattr(material, "blend_method"):
+        material.blend_method = "BLEND"
+    material.diffuse_color = (*color, alpha)
+"#;
+
+/// Writes the script. Run from Blender's text editor, or with
+/// `blender --python`, after importing the OBJ, it gives each material of
+/// the same name a Principled BSDF, creating the ones that don't exist yet
+/// and replacing the nodes of those that do.
+pub fn write_blender_materials(
+    py: &mut dyn Write,
+    obj_name: &str,
+    materials: &[PresetMaterial],
+) -> io::Result<()> {
+    writeln!(
+        py,
+        "# Blender materials for {}, written by rbx_obj.",
+        obj_name
+    )?;
+    writeln!(
+        py,
+        "# Run this after importing the OBJ, from the Text Editor or with blender --python."
+    )?;
+    writeln!(py, "import bpy")?;
+    writeln!(py)?;
+    writeln!(
+        py,
+        "# (name, linear RGB, alpha, roughness, metallic, emission strength)"
+    )?;
+    writeln!(py, "MATERIALS = [")?;
+    for material in materials {
+        let (r, g, b, a) = material.color;
+        let (roughness, metallic, emission) = surface(material.material);
+        writeln!(
+            py,
+            "    ({:?}, ({:?}, {:?}, {:?}), {:?}, {:?}, {:?}, {:?}),",
+            material.name,
+            srgb_to_linear(r),
+            srgb_to_linear(g),
+            srgb_to_linear(b),
+            a as f32 / 255.0,
+            roughness,
+            metallic,
+            emission
+        )?;
+    }
+    writeln!(py, "]")?;
+    py.write_all(SCRIPT.as_bytes())?;
+    py.flush()
+}
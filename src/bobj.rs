@@ -0,0 +1,278 @@
+//! `--format bobj`: the whole scene as one binary mesh, a quarter or so the
+//! size of the OBJ, and [`bobj_to_obj`] for turning it back. The layout is
+//! described in `BOBJ_FORMAT.md`.
+
+use std::io::{self, Read, Write};
+
+use rbx_types::Vector3;
+
+use crate::{
+    export::{FLUSH_THRESHOLD, push_vertex},
+    geometry_json::GeometryPart,
+};
+
+const MAGIC: &[u8; 4] = b"BOBJ";
+
+/// Bytes a material name takes up, padded with zeros.
+const NAME_LENGTH: usize = 64;
+
+/// Writes every part's vertices, then every part's faces with the indices
+/// running on across parts, then a range of faces for each run of parts
+/// sharing a material.
+pub fn write_bobj(out: &mut dyn Write, parts: &[GeometryPart]) -> io::Result<()> {
+    let vertex_count = parts.iter().map(|part| part.vertices.len()).sum();
+    let mut buf = Vec::new();
+    buf.extend_from_slice(MAGIC);
+    push_u32(&mut buf, vertex_count)?;
+    for part in parts {
+        for v in &part.vertices {
+            for value in [v.x, v.y, v.z] {
+                buf.extend(value.to_le_bytes());
+            }
+        }
+        out.write_all(&buf)?;
+        buf.clear();
+    }
+
+    let face_count = parts.iter().map(|part| part.faces.len()).sum();
+    push_u32(&mut buf, face_count)?;
+    let mut offset = 0;
+    let mut ranges: Vec<(usize, usize, &str)> = Vec::new();
+    let mut face_offset = 0;
+    for part in parts {
+        for &(a, b, c) in &part.faces {
+            for index in [a, b, c] {
+                push_u32(&mut buf, index + offset)?;
+            }
+        }
+        offset += part.vertices.len();
+        let end = face_offset + part.faces.len();
+        match ranges.last_mut() {
+            Some((_, last_end, material)) if *material == part.material => *last_end = end,
+            _ => ranges.push((face_offset, end, &part.material)),
+        }
+        face_offset = end;
+        out.write_all(&buf)?;
+        buf.clear();
+    }
+
+    push_u32(&mut buf, ranges.len())?;
+    for (start, end, material) in ranges {
+        push_u32(&mut buf, start)?;
+        push_u32(&mut buf, end)?;
+        // Names longer than the field lose their end, cut between
+        // characters so what's left is still UTF-8.
+        let mut length = material.len().min(NAME_LENGTH);
+        while !material.is_char_boundary(length) {
+            length -= 1;
+        }
+        let name = &material.as_bytes()[..length];
+        buf.extend_from_slice(name);
+        buf.resize(buf.len() + NAME_LENGTH - name.len(), 0);
+    }
+    out.write_all(&buf)
+}
+
+fn push_u32(buf: &mut Vec<u8>, value: usize) -> io::Result<()> {
+    let value = u32::try_from(value)
+        .map_err(|_| io::Error::other(format!("{} doesn't fit in a .bobj", value)))?;
+    buf.extend(value.to_le_bytes());
+    Ok(())
+}
+
+/// Turns a `.bobj` back into an OBJ: the vertices, then each material
+/// range's faces after a `usemtl` line, with a `mtllib` line naming
+/// `mtl_name` first if there is one. Faces outside every range are left
+/// out. A file that isn't a `.bobj`, or refers to vertices or faces it
+/// doesn't have, is `InvalidData`, and one that stops short `UnexpectedEof`.
+pub fn bobj_to_obj(
+    bobj: &mut dyn Read,
+    obj: &mut dyn Write,
+    mtl_name: Option<&str>,
+) -> io::Result<()> {
+    let mut magic = [0; 4];
+    bobj.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a .bobj file"));
+    }
+
+    let mut buf = String::new();
+    buf.push_str("# Converted from .bobj\n");
+    if let Some(mtl_name) = mtl_name {
+        buf.push_str("mtllib ");
+        buf.push_str(mtl_name);
+        buf.push('\n');
+    }
+    let vertex_count = read_u32(bobj)?;
+    for _ in 0..vertex_count {
+        let [x, y, z] = [read_f32(bobj)?, read_f32(bobj)?, read_f32(bobj)?];
+        push_vertex(&mut buf, Vector3::new(x, y, z));
+        flush_if_full(obj, &mut buf)?;
+    }
+    obj.write_all(buf.as_bytes())?;
+    buf.clear();
+
+    // Ranges come after the faces, so the faces have to be held on to.
+    let face_count = read_u32(bobj)?;
+    let mut faces = Vec::new();
+    for _ in 0..face_count {
+        let face = [read_u32(bobj)?, read_u32(bobj)?, read_u32(bobj)?];
+        if face.iter().any(|&index| index >= vertex_count) {
+            return Err(invalid("face refers to a vertex past the end"));
+        }
+        faces.push(face);
+    }
+
+    let mut ints = itoa::Buffer::new();
+    for _ in 0..read_u32(bobj)? {
+        let (start, end) = (read_u32(bobj)?, read_u32(bobj)?);
+        let mut name = [0; NAME_LENGTH];
+        bobj.read_exact(&mut name)?;
+        if start > end || end > face_count {
+            return Err(invalid("material range past the last face"));
+        }
+        let length = name.iter().position(|&b| b == 0).unwrap_or(NAME_LENGTH);
+        let name = std::str::from_utf8(&name[..length])
+            .map_err(|_| invalid("material name isn't UTF-8"))?;
+
+        buf.push_str("usemtl ");
+        buf.push_str(name);
+        buf.push('\n');
+        for face in &faces[start as usize..end as usize] {
+            buf.push('f');
+            for index in face {
+                buf.push(' ');
+                buf.push_str(ints.format(index + 1));
+            }
+            buf.push('\n');
+            flush_if_full(obj, &mut buf)?;
+        }
+    }
+    obj.write_all(buf.as_bytes())?;
+    obj.flush()
+}
+
+fn read_u32(bobj: &mut dyn Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    bobj.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f32(bobj: &mut dyn Read) -> io::Result<f32> {
+    read_u32(bobj).map(f32::from_bits)
+}
+
+/// Hands `buf` to `obj` once it's grown past `FLUSH_THRESHOLD`.
+fn flush_if_full(obj: &mut dyn Write, buf: &mut String) -> io::Result<()> {
+    if buf.len() >= FLUSH_THRESHOLD {
+        obj.write_all(buf.as_bytes())?;
+        buf.clear();
+    }
+    Ok(())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(material: &str, offset: f32) -> GeometryPart<'static> {
+        GeometryPart {
+            name: material.to_string(),
+            class: "Part",
+            vertices: vec![
+                Vector3::new(offset, 0.0, 0.0),
+                Vector3::new(offset + 1.0, 0.0, 0.0),
+                Vector3::new(offset, 1.0, 0.0),
+            ],
+            faces: vec![(0, 1, 2), (2, 1, 0)],
+            color: (255, 255, 255, 255),
+            material: material.to_string(),
+        }
+    }
+
+    fn bobj(parts: &[GeometryPart]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_bobj(&mut out, parts).unwrap();
+        out
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<String> {
+        let mut obj = Vec::new();
+        bobj_to_obj(&mut &bytes[..], &mut obj, Some("scene.mtl"))?;
+        Ok(String::from_utf8(obj).unwrap())
+    }
+
+    #[test]
+    fn round_trips_several_materials() {
+        let parts = [part("red", 0.0), part("red", 2.0), part("blue", 4.0)];
+        let obj = decode(&bobj(&parts)).unwrap();
+        let lines: Vec<&str> = obj.lines().collect();
+        assert_eq!(lines[1], "mtllib scene.mtl");
+        assert_eq!(lines.iter().filter(|l| l.starts_with("v ")).count(), 9);
+        let rest: Vec<&str> = lines
+            .iter()
+            .copied()
+            .filter(|l| !l.starts_with("v "))
+            .skip(2)
+            .collect();
+        assert_eq!(
+            rest,
+            [
+                "usemtl red",
+                "f 1 2 3",
+                "f 3 2 1",
+                "f 4 5 6",
+                "f 6 5 4",
+                "usemtl blue",
+                "f 7 8 9",
+                "f 9 8 7",
+            ]
+        );
+    }
+
+    #[test]
+    fn long_names_are_cut_between_characters() {
+        // 63 bytes of ASCII, then a two-byte character straddling the field.
+        let name = format!("{}é", "a".repeat(63));
+        let obj = decode(&bobj(&[part(&name, 0.0)])).unwrap();
+        assert!(obj.contains(&format!("usemtl {}\n", "a".repeat(63))));
+    }
+
+    #[test]
+    fn rejects_a_bad_magic() {
+        let mut bytes = bobj(&[part("red", 0.0)]);
+        bytes[..4].copy_from_slice(b"OBJB");
+        assert_eq!(
+            decode(&bytes).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn rejects_a_face_past_the_last_vertex() {
+        let mut bytes = bobj(&[part("red", 0.0)]);
+        // Magic, vertex count, three vertices, face count, then the first
+        // face's first index.
+        let index = 4 + 4 + 3 * 12 + 4;
+        bytes[index..index + 4].copy_from_slice(&3u32.to_le_bytes());
+        assert_eq!(
+            decode(&bytes).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn rejects_a_file_that_stops_short() {
+        let bytes = bobj(&[part("red", 0.0)]);
+        for length in [2, 20, bytes.len() - 1] {
+            assert_eq!(
+                decode(&bytes[..length]).unwrap_err().kind(),
+                io::ErrorKind::UnexpectedEof
+            );
+        }
+    }
+}
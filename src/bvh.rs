@@ -0,0 +1,230 @@
+//! Bounding volume hierarchy over axis-aligned boxes, for finding which parts
+//! a short ray runs into without testing every part in the place.
+
+use rbx_types::Vector3;
+
+/// Parts per leaf before a node gets split.
+const LEAF_SIZE: usize = 4;
+
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+impl Aabb {
+    /// The smallest box containing all of `points`.
+    pub fn from_points(points: &[Vector3]) -> Aabb {
+        let mut min = Vector3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+        for p in points {
+            min = Vector3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Vector3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+        Aabb { min, max }
+    }
+
+    /// Whether `p` is inside the box or on its surface.
+    pub fn contains(&self, p: Vector3) -> bool {
+        (self.min.x..=self.max.x).contains(&p.x)
+            && (self.min.y..=self.max.y).contains(&p.y)
+            && (self.min.z..=self.max.z).contains(&p.z)
+    }
+
+    fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vector3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Vector3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    fn axis(v: Vector3, axis: usize) -> f32 {
+        match axis {
+            0 => v.x,
+            1 => v.y,
+            _ => v.z,
+        }
+    }
+
+    fn center(&self, axis: usize) -> f32 {
+        (Self::axis(self.min, axis) + Self::axis(self.max, axis)) / 2.0
+    }
+
+    /// Slab test for the segment running from `from` to `to`.
+    fn hits_segment(&self, from: Vector3, to: Vector3) -> bool {
+        let (mut enter, mut exit) = (0.0f32, 1.0f32);
+        for axis in 0..3 {
+            let origin = Self::axis(from, axis);
+            let delta = Self::axis(to, axis) - origin;
+            let (lo, hi) = (Self::axis(self.min, axis), Self::axis(self.max, axis));
+            if delta.abs() < f32::EPSILON {
+                if origin < lo || origin > hi {
+                    return false;
+                }
+                continue;
+            }
+            let (t0, t1) = ((lo - origin) / delta, (hi - origin) / delta);
+            enter = enter.max(t0.min(t1));
+            exit = exit.min(t0.max(t1));
+            if enter > exit {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+enum Node {
+    Leaf {
+        bounds: Aabb,
+        items: Vec<usize>,
+    },
+    Branch {
+        bounds: Aabb,
+        left: Box<Node>,
+        right: Box<Node>,
+    },
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Branch { bounds, .. } => bounds,
+        }
+    }
+}
+
+pub struct Bvh {
+    boxes: Vec<Aabb>,
+    root: Option<Node>,
+}
+
+impl Bvh {
+    /// Builds the tree by splitting at the median along the longest axis.
+    pub fn build(boxes: Vec<Aabb>) -> Bvh {
+        let items: Vec<usize> = (0..boxes.len()).collect();
+        let root = (!items.is_empty()).then(|| Self::build_node(&boxes, items));
+        Bvh { boxes, root }
+    }
+
+    fn build_node(boxes: &[Aabb], mut items: Vec<usize>) -> Node {
+        let bounds = items[1..]
+            .iter()
+            .fold(boxes[items[0]], |acc, &i| acc.union(&boxes[i]));
+        if items.len() <= LEAF_SIZE {
+            return Node::Leaf { bounds, items };
+        }
+
+        let extent = [
+            bounds.max.x - bounds.min.x,
+            bounds.max.y - bounds.min.y,
+            bounds.max.z - bounds.min.z,
+        ];
+        let axis = (0..3)
+            .max_by(|&a, &b| extent[a].total_cmp(&extent[b]))
+            .unwrap();
+        items.sort_by(|&a, &b| boxes[a].center(axis).total_cmp(&boxes[b].center(axis)));
+
+        let right = items.split_off(items.len() / 2);
+        Node::Branch {
+            bounds,
+            left: Box::new(Self::build_node(boxes, items)),
+            right: Box::new(Self::build_node(boxes, right)),
+        }
+    }
+
+    /// Calls `visit` with every box the segment from `from` to `to` passes through.
+    pub fn query_segment(&self, from: Vector3, to: Vector3, visit: &mut dyn FnMut(usize)) {
+        let mut stack: Vec<&Node> = self.root.iter().collect();
+        while let Some(node) = stack.pop() {
+            if !node.bounds().hits_segment(from, to) {
+                continue;
+            }
+            match node {
+                Node::Leaf { items, .. } => {
+                    for &i in items {
+                        if self.boxes[i].hits_segment(from, to) {
+                            visit(i);
+                        }
+                    }
+                }
+                Node::Branch { left, right, .. } => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box(x: f32, y: f32, z: f32) -> Aabb {
+        Aabb {
+            min: Vector3::new(x, y, z),
+            max: Vector3::new(x + 1.0, y + 1.0, z + 1.0),
+        }
+    }
+
+    fn hits(bvh: &Bvh, from: Vector3, to: Vector3) -> Vec<usize> {
+        let mut hits = Vec::new();
+        bvh.query_segment(from, to, &mut |i| hits.push(i));
+        hits.sort();
+        hits
+    }
+
+    #[test]
+    fn finds_the_boxes_a_segment_passes_through() {
+        // A row of boxes with a gap between each, enough for several leaves.
+        let bvh = Bvh::build(
+            (0..20)
+                .map(|i| unit_box(i as f32 * 2.0, 0.0, 0.0))
+                .collect(),
+        );
+        let along = |x: f32| Vector3::new(x, 0.5, 0.5);
+        assert_eq!(hits(&bvh, along(0.5), along(4.5)), [0, 1, 2]);
+        assert_eq!(hits(&bvh, along(38.5), along(38.6)), [19]);
+        // Only through the gap between two boxes.
+        assert!(hits(&bvh, along(1.2), along(1.8)).is_empty());
+        // Past the row altogether.
+        assert!(
+            hits(
+                &bvh,
+                Vector3::new(0.5, 2.0, 0.5),
+                Vector3::new(38.5, 2.0, 0.5)
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn segment_along_an_axis_only_hits_boxes_it_lines_up_with() {
+        let bvh = Bvh::build(vec![unit_box(0.0, 0.0, 0.0), unit_box(0.0, 3.0, 0.0)]);
+        let up = |x: f32, y: f32| Vector3::new(x, y, 0.5);
+        assert_eq!(hits(&bvh, up(0.5, -1.0), up(0.5, 5.0)), [0, 1]);
+        assert!(hits(&bvh, up(1.5, -1.0), up(1.5, 5.0)).is_empty());
+    }
+
+    #[test]
+    fn empty_tree_finds_nothing() {
+        let bvh = Bvh::build(Vec::new());
+        assert!(
+            hits(
+                &bvh,
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(1.0, 1.0, 1.0)
+            )
+            .is_empty()
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! Progress and warning events, for embedding the export in something that
+//! wants to show them its own way rather than have them printed. The
+//! `rbx_obj` binary draws its progress bars and prints its warnings from
+//! these too.
+
+use std::{
+    cell::Cell,
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use rbx_dom_weak::WeakDom;
+use rbx_types::Ref;
+
+use crate::{scene::instance_path, warning::Warning};
+
+type ProgressCallback = dyn FnMut(&ProgressInfo) + Send;
+type WarningCallback = dyn FnMut(&WarningEvent) + Send;
+
+/// Closures the export calls as it goes, in
+/// [`ExportOptions::callbacks`](crate::ExportOptions::callbacks). They're
+/// called from the thread running the export, between steps rather than in
+/// the middle of writing anything, one at a time. A callback starting
+/// another export with the same callbacks would wait on itself forever.
+#[derive(Clone, Default)]
+pub struct ExportCallbacks {
+    progress: Option<Arc<Mutex<ProgressCallback>>>,
+    warning: Option<Arc<Mutex<WarningCallback>>>,
+}
+
+impl ExportCallbacks {
+    /// Called when parsing or writing starts and ends, and for every part
+    /// written in between.
+    pub fn on_progress(mut self, callback: impl FnMut(&ProgressInfo) + Send + 'static) -> Self {
+        self.progress = Some(Arc::new(Mutex::new(callback)));
+        self
+    }
+
+    /// Called for every warning, the same ones `-v` prints.
+    pub fn on_warning(mut self, callback: impl FnMut(&WarningEvent) + Send + 'static) -> Self {
+        self.warning = Some(Arc::new(Mutex::new(callback)));
+        self
+    }
+
+    /// Whether anyone's listening for warnings, since making one costs a
+    /// path lookup.
+    pub(crate) fn wants_warnings(&self) -> bool {
+        self.warning.is_some()
+    }
+
+    pub(crate) fn warning(&self, event: &WarningEvent) {
+        if let Some(callback) = &self.warning {
+            // A callback that panicked once can still be called again.
+            (callback.lock().unwrap_or_else(PoisonError::into_inner))(event);
+        }
+    }
+
+    fn progress(&self, info: &ProgressInfo) {
+        if let Some(callback) = &self.progress {
+            (callback.lock().unwrap_or_else(PoisonError::into_inner))(info);
+        }
+    }
+}
+
+/// What stage of a conversion a [`ProgressInfo`] is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressPhase {
+    /// Reading and decoding the input, which reports no parts.
+    Parsing,
+    /// Writing the parts out. A streamed export counts against an upper
+    /// bound, so it can finish short of `total`.
+    Writing,
+}
+
+/// Where a conversion has got to.
+pub struct ProgressInfo<'a> {
+    /// The file being converted, or a stand-in for an in-memory DOM.
+    pub input: &'a Path,
+    pub phase: ProgressPhase,
+    /// Parts written so far in this phase.
+    pub done: usize,
+    /// Parts this phase will write, when that's known.
+    pub total: Option<usize>,
+    /// The phase is over, successfully or not. Nothing more comes for it.
+    pub finished: bool,
+    part: Option<(&'a WeakDom, Ref)>,
+}
+
+impl ProgressInfo<'_> {
+    /// Where the part just written sits in the tree, like
+    /// `Workspace.Car.Wheel`. Not free, so on big places it's best asked for
+    /// every so often rather than for every part.
+    pub fn part_path(&self) -> Option<String> {
+        self.part
+            .map(|(dom, referent)| instance_path(dom, referent))
+    }
+}
+
+/// A warning as it comes up.
+pub struct WarningEvent<'a> {
+    pub warning: &'a Warning,
+    /// Its code is in `ExportOptions::fail_on`, so it fails the run.
+    pub fails: bool,
+}
+
+/// Reports one phase's progress to the callbacks, ending it when dropped so
+/// it ends even when the phase bails out with an error.
+pub(crate) struct Progress<'a> {
+    callbacks: &'a ExportCallbacks,
+    input: &'a Path,
+    phase: ProgressPhase,
+    total: Option<usize>,
+    done: Cell<usize>,
+}
+
+impl<'a> Progress<'a> {
+    pub fn start(
+        callbacks: &'a ExportCallbacks,
+        input: &'a Path,
+        phase: ProgressPhase,
+        total: Option<usize>,
+    ) -> Self {
+        let progress = Progress {
+            callbacks,
+            input,
+            phase,
+            total,
+            done: Cell::new(0),
+        };
+        progress.report(None, false);
+        progress
+    }
+
+    /// Counts one more part written.
+    pub fn part(&self, dom: &WeakDom, referent: Ref) {
+        self.done.set(self.done.get() + 1);
+        self.report(Some((dom, referent)), false);
+    }
+
+    fn report(&self, part: Option<(&WeakDom, Ref)>, finished: bool) {
+        self.callbacks.progress(&ProgressInfo {
+            input: self.input,
+            phase: self.phase,
+            done: self.done.get(),
+            total: self.total,
+            finished,
+            part,
+        });
+    }
+}
+
+impl Drop for Progress<'_> {
+    fn drop(&mut self) {
+        self.report(None, true);
+    }
+}
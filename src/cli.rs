@@ -0,0 +1,788 @@
+//! Command line parsing, and turning the flags into `ExportOptions`.
+
+use std::{
+    num::{NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+};
+
+use clap::{
+    Args, Parser, Subcommand, ValueEnum,
+    builder::{PossibleValue, PossibleValuesParser},
+};
+use clap_complete::Shell;
+use rbx_types::Vector3;
+
+use rbx_obj::{
+    Aabb, DoubleSided, ExportError, ExportOptions, ExportOptionsBuilder, MtlTransparency,
+    MtllibStyle, Normals, OutputFormat, Pose, TimingsFormat, TrussStyle, Unsupported, WarningCode,
+    Wireframe, assets::AssetCache,
+};
+
+/// Convert Roblox places/models to obj
+#[derive(Parser)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // Running without a subcommand converts, so `rbx_obj place.rbxl` keeps working.
+    #[command(flatten)]
+    pub convert: ConvertArgs,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Convert places or models to OBJ (the default when no subcommand is given)
+    Convert(Box<ConvertArgs>),
+    /// Print a summary of what a file contains and what would be exported
+    Info(InfoArgs),
+    /// Check for content the exporter can't handle; exits with 1 if any is found
+    Validate(ValidateArgs),
+    /// Turn --format bobj files back into OBJ; each gets an .obj next to it
+    DecodeBobj(DecodeBobjArgs),
+    /// Draw the parts that changed between two saves: added green, moved
+    /// yellow, removed red and recolored blue
+    Diff(DiffArgs),
+    /// Print a shell completion script
+    Completions(CompletionsArgs),
+}
+
+/// Which part of the DOM gets looked at; shared by every subcommand.
+#[derive(Args)]
+pub struct FilterArgs {
+    /// Top-level services to export, by class name
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_name = "SERVICES",
+        default_value = "Workspace",
+        conflicts_with = "all_services"
+    )]
+    services: Vec<String>,
+
+    /// Export every top-level service
+    #[arg(long)]
+    all_services: bool,
+
+    /// Skip instances with Archivable set to false, and everything under them
+    #[arg(long)]
+    respect_archivable: bool,
+
+    /// Skip anything carrying this tag, along with everything under it
+    #[arg(long = "exclude-tag", value_name = "TAG")]
+    exclude_tags: Vec<String>,
+
+    /// Only export parts with this tag on themselves or an ancestor
+    #[arg(long = "include-tag", value_name = "TAG")]
+    include_tags: Vec<String>,
+
+    /// Only export parts whose position is inside this box, in studs
+    #[arg(
+        long,
+        value_name = "X_MIN,Y_MIN,Z_MIN,X_MAX,Y_MAX,Z_MAX",
+        value_parser = parse_region,
+        allow_hyphen_values = true
+    )]
+    region: Option<Aabb>,
+
+    /// Export parts parented under Camera instances
+    #[arg(long)]
+    include_cameras: bool,
+
+    /// Export parts with Visible set to false
+    #[arg(long)]
+    include_hidden: bool,
+
+    /// Don't descend more than N levels below the top-level services
+    #[arg(long, value_name = "N", default_value_t = 64)]
+    max_depth: usize,
+}
+
+impl FilterArgs {
+    /// A builder with the filters set and everything else at its default.
+    pub fn builder(self) -> ExportOptionsBuilder {
+        ExportOptions::builder()
+            .services((!self.all_services).then(|| {
+                self.services
+                    .iter()
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            }))
+            .respect_archivable(self.respect_archivable)
+            .exclude_tags(self.exclude_tags)
+            .include_tags(self.include_tags)
+            .region(self.region)
+            .include_cameras(self.include_cameras)
+            .include_hidden(self.include_hidden)
+            .max_depth(self.max_depth)
+    }
+
+    /// Resolves the filters, leaving everything else at its default.
+    pub fn options(self) -> Result<ExportOptions, ExportError> {
+        self.builder().build()
+    }
+}
+
+#[derive(Args)]
+pub struct InfoArgs {
+    /// Place or model files to look at
+    #[arg(required = true, value_name = "FILE")]
+    pub inputs: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    /// How many levels of the instance tree to print (0 leaves it out)
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub tree_depth: usize,
+
+    /// Print the summary as a line of JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct ValidateArgs {
+    /// Place or model files to look at
+    #[arg(required = true, value_name = "FILE")]
+    pub inputs: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    /// Kinds of content not to complain about
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "KINDS")]
+    pub ignore: Vec<Unsupported>,
+}
+
+#[derive(Args)]
+pub struct DecodeBobjArgs {
+    /// .bobj files to decode
+    #[arg(required = true, value_name = "FILE")]
+    pub inputs: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct DiffArgs {
+    /// The earlier save
+    pub old: PathBuf,
+
+    /// The later save, whose parts get drawn
+    pub new: PathBuf,
+
+    /// Where to write the OBJ; the MTL goes next to it
+    #[arg(short, long, value_name = "FILE")]
+    pub output: PathBuf,
+
+    #[command(flatten)]
+    pub filter: FilterArgs,
+}
+
+#[derive(Args)]
+pub struct CompletionsArgs {
+    /// Shell to complete for
+    #[arg(value_enum)]
+    pub shell: Shell,
+}
+
+#[derive(Args)]
+pub struct ConvertArgs {
+    /// Place or model files to convert; each gets an .obj and .mtl next to it
+    #[arg(required = true, value_name = "FILE")]
+    pub inputs: Vec<PathBuf>,
+
+    /// Read settings from this TOML file, keyed by flag name; flags given here win (defaults to rbxl-to-obj.toml next to the first input, if there is one)
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Print the settings after merging in the config file, then exit
+    #[arg(long)]
+    pub print_config: bool,
+
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    /// Export rod, rope, spring, ball socket and hinge constraints as line segments
+    #[arg(long)]
+    export_constraints: bool,
+
+    /// Export SelectionBoxes and SelectionSpheres as line outlines around their Adornee
+    #[arg(long)]
+    export_selection_effects: bool,
+
+    /// Add studs to Studs surfaces and a stub to weld, hinge and motor surfaces of blocks
+    #[arg(long)]
+    export_surface_details: bool,
+
+    /// Color parts without a Color from their Color attribute or a Model's PrimaryColor
+    #[arg(long)]
+    inherit_color: bool,
+
+    /// Also write a CSV with a row per exported part
+    #[arg(long, value_name = "PATH")]
+    part_info_csv: Option<PathBuf>,
+
+    /// Mirror the scene across the X axis
+    #[arg(long)]
+    mirror_x: bool,
+
+    /// Mirror the scene across the Y axis
+    #[arg(long)]
+    mirror_y: bool,
+
+    /// Mirror the scene across the Z axis
+    #[arg(long)]
+    mirror_z: bool,
+
+    /// Write the scene followed by a mirrored copy (across X unless a --mirror flag says otherwise)
+    #[arg(long)]
+    mirror_and_append: bool,
+
+    /// Negate Z and flip the winding, for pipelines that treat Roblox coordinates as left-handed
+    #[arg(long)]
+    right_handed: bool,
+
+    /// Read part sizes from the lengths of the CFrame orientation columns instead of Size, for files from tools that scale parts that way
+    #[arg(long)]
+    cframe_scale: bool,
+
+    /// Place parts held together by Motor6Ds, Welds and WeldConstraints from the joints' offsets, starting at the HumanoidRootPart or an anchored part, for rigs saved with their limbs piled at the origin
+    #[arg(long)]
+    solve_joints: bool,
+
+    /// With --solve-joints, which pose rigs come out in
+    #[arg(long, value_enum, default_value_t, requires = "solve_joints")]
+    pose: Pose,
+
+    /// Leave out the Lighting settings written as OBJ/MTL comments
+    #[arg(long)]
+    no_lighting: bool,
+
+    /// Leave out vertex normals, except for wedges, like older versions
+    #[arg(long)]
+    no_normals: bool,
+
+    /// Which faces share vertex normals
+    #[arg(long, value_enum, default_value_t, conflicts_with = "no_normals")]
+    normals: Normals,
+
+    /// With --normals auto, the angle faces have to meet at for a hard edge between them
+    #[arg(
+        long,
+        value_name = "DEGREES",
+        default_value_t = 30.0,
+        value_parser = parse_angle,
+        requires = "normals"
+    )]
+    crease_angle: f32,
+
+    /// Write texture coordinates, laid out in studs the way Roblox tiles materials
+    #[arg(long, conflicts_with_all = ["weld", "bake_material_id_texture"])]
+    uvs: bool,
+
+    /// With --uvs, how many studs one texture tile covers
+    #[arg(
+        long,
+        value_name = "STUDS",
+        default_value_t = 4.0,
+        value_parser = parse_size,
+        requires = "uvs"
+    )]
+    uv_studs_per_tile: f32,
+
+    /// Export unrecognized classes that have a Size and CFrame as blocks
+    #[arg(long)]
+    unknown_as_box: bool,
+
+    /// List unrecognized part-like classes and how many of each were found
+    #[arg(long)]
+    print_unknown_classes: bool,
+
+    /// Merge vertices at the same position, optionally snapping to an EPSILON grid first
+    #[arg(
+        long,
+        value_name = "EPSILON",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "0",
+        value_parser = parse_epsilon
+    )]
+    weld: Option<f32>,
+
+    /// Only weld vertices between parts sharing a material
+    #[arg(long)]
+    weld_per_material: bool,
+
+    /// Group anchored and unanchored parts separately
+    #[arg(long)]
+    group_anchored: bool,
+
+    /// Write a comment with extra info before each part's geometry
+    #[arg(long)]
+    annotate_faces: bool,
+
+    /// Write an `o` line naming each part by its path, like Workspace/Map/Wall
+    #[arg(long, conflicts_with_all = ["weld", "voxelize"])]
+    object_names: bool,
+
+    /// What goes between the names in --object-names' paths
+    #[arg(
+        long,
+        value_name = "CHAR",
+        default_value_t = '/',
+        requires = "object_names"
+    )]
+    name_separator: char,
+
+    /// Drop faces pressed flat against a face of another part
+    #[arg(
+        long,
+        value_name = "EPSILON",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "0.001",
+        value_parser = parse_epsilon
+    )]
+    remove_hidden_faces: Option<f32>,
+
+    /// After welding, split triangles where a vertex lies within EPSILON of their edge, to close rendering cracks (slow on big places)
+    #[arg(
+        long,
+        value_name = "EPSILON",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "0.001",
+        value_parser = parse_epsilon,
+        requires = "weld"
+    )]
+    fix_tjunctions: Option<f32>,
+
+    /// Drop faces that another part sits right against
+    #[arg(long)]
+    omit_interior: bool,
+
+    /// Combine unrotated same-colored blocks that share whole faces
+    #[arg(long)]
+    merge_boxes: bool,
+
+    /// Turn the whole scene into cubes of SIZE studs, filling each one a part's bounding box touches
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        conflicts_with_all = ["lods", "remove_hidden_faces", "omit_interior"]
+    )]
+    voxelize: Option<f32>,
+
+    /// Split the faces of blocks into quads no more than STUDS across, for lightmap baking and per-vertex lighting
+    #[arg(long, value_name = "STUDS", value_parser = parse_size)]
+    resample_size: Option<f32>,
+
+    /// Write every material's color as a pixel of <OUTPUT>_palette.png and point each face's texture coordinates at it, leaving one material
+    #[arg(long, conflicts_with = "weld")]
+    bake_material_id_texture: bool,
+
+    /// Also write <OUTPUT>_materials.py, a Blender script setting up the OBJ's materials as Principled BSDFs that match their Roblox Material
+    #[arg(long, conflicts_with = "bake_material_id_texture")]
+    blender_materials: bool,
+
+    /// How the MTL writes a partly transparent color
+    #[arg(long, value_enum, default_value_t)]
+    mtl_transparency: MtlTransparency,
+
+    /// Write each MTL into this directory instead of next to its OBJ
+    #[arg(long, value_name = "DIR")]
+    mtl_dir: Option<PathBuf>,
+
+    /// How the OBJ's mtllib line refers to the MTL
+    #[arg(long, value_enum, default_value_t)]
+    mtllib_style: MtllibStyle,
+
+    /// Order parts along a Z-order curve so nearby parts end up close together in the file
+    #[arg(long)]
+    spatial_sort: bool,
+
+    /// How TrussParts are meshed
+    #[arg(long, value_enum, default_value_t)]
+    truss_style: TrussStyle,
+
+    /// Color parts carrying TAG (or under something that does) with a fixed color
+    #[arg(long = "tag-material", value_name = "TAG=#RRGGBB", value_parser = parse_tag_material)]
+    tag_materials: Vec<(String, (u8, u8, u8))>,
+
+    /// Write triangle edges as lines instead of faces
+    #[arg(long, conflicts_with = "wireframe_and_faces")]
+    wireframe: bool,
+
+    /// Write the faces plus their edges in a separate material
+    #[arg(long)]
+    wireframe_and_faces: bool,
+
+    /// Give faces a flipped copy so viewers that cull back faces show them from both sides: on every part, or only on thin ones
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        num_args = 0..=1,
+        default_missing_value = "all"
+    )]
+    double_sided: DoubleSided,
+
+    /// With --double-sided thin, how thin a part has to be along its thinnest side
+    #[arg(
+        long,
+        value_name = "STUDS",
+        default_value_t = 0.2,
+        value_parser = parse_size,
+        requires = "double_sided"
+    )]
+    double_sided_threshold: f32,
+
+    /// Write parts as they're found so meshes don't pile up in memory (the parsed place itself still has to fit, usually a few times the file size); rules out flags needing the whole scene
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "weld",
+            "merge_boxes",
+            "remove_hidden_faces",
+            "omit_interior",
+            "mirror_and_append",
+            "lods",
+            "spatial_sort",
+            "voxelize",
+            "bake_material_id_texture",
+        ]
+    )]
+    stream: bool,
+
+    /// Start a new OBJ file whenever the next part would go past N vertices
+    #[arg(long, value_name = "N", conflicts_with = "weld")]
+    max_vertices_per_file: Option<NonZeroUsize>,
+
+    /// Print the ids of all referenced rbxassetid:// assets instead of exporting
+    #[arg(long)]
+    list_textures: bool,
+
+    /// With --list-textures, download each asset into DIR, or reuse the copy already there, and print its path after its id
+    #[arg(long, value_name = "DIR", requires = "list_textures")]
+    asset_cache: Option<PathBuf>,
+
+    /// Don't download anything: --asset-cache only hands out what it already has and fails for the rest
+    #[arg(long, requires = "asset_cache")]
+    offline: bool,
+
+    /// Download at most N assets at once (default 4)
+    #[arg(long, value_name = "N", requires = "asset_cache")]
+    asset_concurrency: Option<NonZeroUsize>,
+
+    /// Print every material the export would create, with how many parts and faces use it, instead of exporting
+    #[arg(long, conflicts_with_all = ["list_textures", "dry_run"])]
+    list_materials: bool,
+
+    /// Print --list-materials as a line of JSON
+    #[arg(long, requires = "list_materials")]
+    json: bool,
+
+    /// Decide what to export and print the summary with estimated counts, but don't mesh or write anything
+    #[arg(long, conflicts_with = "list_textures")]
+    dry_run: bool,
+
+    /// Print a top-down ASCII sketch of the parts' bounding boxes to stderr before writing, at most WxH characters
+    #[arg(
+        long,
+        value_name = "WxH",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "80x40",
+        value_parser = parse_preview_size,
+        conflicts_with = "stream"
+    )]
+    preview: Option<(usize, usize)>,
+
+    /// Also write the --preview sketch into the OBJ as comments
+    #[arg(long, requires = "preview")]
+    preview_in_obj: bool,
+
+    /// Write N progressively simplified detail levels
+    #[arg(long, value_name = "N")]
+    lods: Option<NonZeroUsize>,
+
+    /// What to write: the OBJ, the OBJ plus extras, or JSON instead
+    #[arg(long, value_enum, default_value_t)]
+    format: OutputFormat,
+
+    /// Write the --format json document as CBOR instead, in a .cbor file
+    #[arg(long, conflicts_with = "format")]
+    json_binary: bool,
+
+    /// Lower ball and cylinder detail, then leave out the smallest parts, until the export fits in N triangles
+    #[arg(long, value_name = "N", conflicts_with_all = ["stream", "lods", "voxelize"])]
+    max_triangles: Option<NonZeroUsize>,
+
+    /// Number of threads generating meshes (defaults to one per logical core)
+    #[arg(long, value_name = "N")]
+    pub threads: Option<NonZeroUsize>,
+
+    /// Give up after N seconds, leaving whatever was written so far
+    #[arg(long, value_name = "N")]
+    pub timeout_seconds: Option<NonZeroU64>,
+
+    /// Write the OBJ and MTL from a Tokio runtime while the export runs on its blocking threads
+    #[cfg(feature = "async")]
+    #[arg(long = "async", conflicts_with_all = ["lods", "max_vertices_per_file"])]
+    pub run_async: bool,
+
+    /// Print how long each step took to stderr
+    #[arg(long, conflicts_with = "timings_json")]
+    timings: bool,
+
+    /// Print the timings as JSON
+    #[arg(long)]
+    timings_json: bool,
+
+    /// Only print errors, leaving out the summary and warnings
+    #[arg(long, short)]
+    pub quiet: bool,
+
+    /// Print each skip and fallback decision (-vv also lists every exported part)
+    #[arg(long, short, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Also write the summary as JSON, a line per input file
+    #[arg(long, value_name = "PATH")]
+    summary_json: Option<PathBuf>,
+
+    /// Write every skip and fallback as a line of JSON with a stable code, to PATH or to stderr with -
+    #[arg(long, value_name = "PATH")]
+    warnings_jsonl: Option<PathBuf>,
+
+    /// Exit with 1 if any of these warnings come up (comma-separated codes, or all); the export still finishes so they all get listed
+    #[arg(long, value_delimiter = ',', value_name = "CODES", value_parser = fail_on_values())]
+    fail_on: Vec<String>,
+
+    /// With --fail-on, delete the files written when the export fails
+    #[arg(long, requires = "fail_on")]
+    no_partial_output: bool,
+
+    /// Skip instances that run into an error, list them at the end and exit with 1, instead of stopping at the first
+    #[arg(long)]
+    best_effort: bool,
+
+    /// Stop at the first instance that runs into an error (the default)
+    #[arg(long, conflicts_with = "best_effort")]
+    strict: bool,
+}
+
+impl ConvertArgs {
+    /// The cache `--asset-cache` names, if it does.
+    pub fn asset_cache(&self) -> Option<AssetCache> {
+        let mut cache = AssetCache::new(self.asset_cache.clone()?);
+        if self.offline {
+            cache = cache.offline();
+        }
+        if let Some(concurrency) = self.asset_concurrency {
+            cache = cache.concurrency(concurrency);
+        }
+        Some(cache)
+    }
+
+    /// Resolves the flags into the settings the export works from.
+    pub fn options(self) -> Result<ExportOptions, ExportError> {
+        let mirror = [self.mirror_x, self.mirror_y, self.mirror_z];
+        self.filter
+            .builder()
+            .export_constraints(self.export_constraints)
+            .export_selection_effects(self.export_selection_effects)
+            .export_surface_details(self.export_surface_details)
+            .inherit_color(self.inherit_color)
+            .part_info_csv(self.part_info_csv)
+            // Appending a mirrored copy without picking an axis mirrors across X.
+            .mirror(if self.mirror_and_append && mirror == [false; 3] {
+                [true, false, false]
+            } else {
+                mirror
+            })
+            .mirror_and_append(self.mirror_and_append)
+            .right_handed(self.right_handed)
+            .cframe_scale(self.cframe_scale)
+            .solve_joints(self.solve_joints)
+            .pose(self.pose)
+            .no_lighting(self.no_lighting)
+            .no_normals(self.no_normals)
+            .normals(self.normals)
+            .crease_angle(self.crease_angle)
+            .uv_studs_per_tile(self.uvs.then_some(self.uv_studs_per_tile))
+            .unknown_as_box(self.unknown_as_box)
+            .print_unknown_classes(self.print_unknown_classes)
+            .weld(self.weld)
+            .weld_per_material(self.weld_per_material)
+            .group_anchored(self.group_anchored)
+            .annotate_faces(self.annotate_faces)
+            .object_names(self.object_names)
+            .name_separator(self.name_separator)
+            .remove_hidden_faces(self.remove_hidden_faces)
+            .fix_tjunctions(self.fix_tjunctions)
+            .omit_interior(self.omit_interior)
+            .merge_boxes(self.merge_boxes)
+            .voxelize(self.voxelize)
+            .resample_size(self.resample_size)
+            .bake_material_id_texture(self.bake_material_id_texture)
+            .blender_materials(self.blender_materials)
+            .mtl_transparency(self.mtl_transparency)
+            .mtl_dir(self.mtl_dir)
+            .mtllib_style(self.mtllib_style)
+            .spatial_sort(self.spatial_sort)
+            .truss_style(self.truss_style)
+            .tag_materials(self.tag_materials)
+            .wireframe(match (self.wireframe, self.wireframe_and_faces) {
+                (_, true) => Wireframe::EdgesAndFaces,
+                (true, false) => Wireframe::Edges,
+                (false, false) => Wireframe::Off,
+            })
+            .double_sided(self.double_sided)
+            .double_sided_threshold(self.double_sided_threshold)
+            .stream(self.stream)
+            .max_vertices_per_file(self.max_vertices_per_file.map(NonZeroUsize::get))
+            .list_textures(self.list_textures)
+            .dry_run(self.dry_run)
+            .list_materials(self.list_materials)
+            .json(self.json)
+            .preview(self.preview)
+            .preview_in_obj(self.preview_in_obj)
+            .lods(self.lods.map(NonZeroUsize::get))
+            .max_triangles(self.max_triangles.map(NonZeroUsize::get))
+            .format(if self.json_binary {
+                OutputFormat::Json
+            } else {
+                self.format
+            })
+            .json_binary(self.json_binary)
+            .timings(match (self.timings, self.timings_json) {
+                (_, true) => Some(TimingsFormat::Json),
+                (true, false) => Some(TimingsFormat::Text),
+                (false, false) => None,
+            })
+            .quiet(self.quiet)
+            .summary_json(self.summary_json)
+            .warnings_jsonl(self.warnings_jsonl)
+            // `all` leaves out what the filters drop on purpose, or every
+            // place would fail on its Terrain.
+            .fail_on(if self.fail_on.iter().any(|code| code == "all") {
+                WarningCode::value_variants()
+                    .iter()
+                    .copied()
+                    .filter(|&code| !matches!(code, WarningCode::Pruned | WarningCode::Filtered))
+                    .collect()
+            } else {
+                self.fail_on
+                    .iter()
+                    .map(|code| WarningCode::from_str(code, false).unwrap())
+                    .collect()
+            })
+            .no_partial_output(self.no_partial_output)
+            .best_effort(self.best_effort && !self.strict)
+            .build()
+    }
+}
+
+/// Every warning code, plus `all`.
+fn fail_on_values() -> PossibleValuesParser {
+    let codes = WarningCode::value_variants()
+        .iter()
+        .filter_map(ValueEnum::to_possible_value);
+    PossibleValuesParser::new(codes.chain([PossibleValue::new("all")]))
+}
+
+/// A distance that can be zero but not negative, NaN or infinite.
+fn parse_epsilon(value: &str) -> Result<f32, String> {
+    let epsilon: f32 = value
+        .parse()
+        .map_err(|_| format!("invalid number {}", value))?;
+    if !epsilon.is_finite() || epsilon < 0.0 {
+        return Err(format!("{} isn't a finite distance of 0 or more", value));
+    }
+    Ok(epsilon)
+}
+
+fn parse_size(value: &str) -> Result<f32, String> {
+    let size: f32 = value
+        .parse()
+        .map_err(|_| format!("invalid number {}", value))?;
+    if !size.is_finite() || size <= 0.0 {
+        return Err(format!("{} isn't a finite size above 0", value));
+    }
+    Ok(size)
+}
+
+fn parse_angle(value: &str) -> Result<f32, String> {
+    let angle: f32 = value
+        .parse()
+        .map_err(|_| format!("invalid number {}", value))?;
+    if !(0.0..=180.0).contains(&angle) {
+        return Err(format!("{} isn't an angle between 0 and 180", value));
+    }
+    Ok(angle)
+}
+
+/// Parses six comma-separated coordinates, the box's minimum corner and
+/// then its maximum.
+fn parse_region(value: &str) -> Result<Aabb, String> {
+    let invalid = || {
+        format!(
+            "expected X_MIN,Y_MIN,Z_MIN,X_MAX,Y_MAX,Z_MAX, e.g. -100,0,-100,100,50,100, got {}",
+            value
+        )
+    };
+    let coordinates: Vec<f32> = value
+        .split(',')
+        .map(|c| c.trim().parse().ok().filter(|c: &f32| c.is_finite()))
+        .collect::<Option<_>>()
+        .ok_or_else(invalid)?;
+    let [x_min, y_min, z_min, x_max, y_max, z_max] = coordinates[..] else {
+        return Err(invalid());
+    };
+    if x_min > x_max || y_min > y_max || z_min > z_max {
+        return Err(format!(
+            "{}: each minimum has to be at most its maximum",
+            value
+        ));
+    }
+    Ok(Aabb {
+        min: Vector3::new(x_min, y_min, z_min),
+        max: Vector3::new(x_max, y_max, z_max),
+    })
+}
+
+/// Parses `WIDTHxHEIGHT`, e.g. `80x40`.
+fn parse_preview_size(value: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("expected WIDTHxHEIGHT, e.g. 80x40, got {}", value);
+    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+    let width: usize = width.parse().map_err(|_| invalid())?;
+    let height: usize = height.parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok((width, height))
+}
+
+fn parse_tag_material(spec: &str) -> Result<(String, (u8, u8, u8)), String> {
+    let (tag, hex) = spec.rsplit_once('=').ok_or("expected <tag>=<color_hex>")?;
+    let color = parse_hex_color(hex).ok_or(format!("invalid color {}", hex))?;
+    Ok((tag.to_string(), color))
+}
+
+/// Parses `#RRGGBB` (the `#` is optional).
+fn parse_hex_color(hex: &str) -> Option<(u8, u8, u8)> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
@@ -0,0 +1,20 @@
+//! `std::time::Instant`, except on `wasm32-unknown-unknown`, which has no
+//! clock and panics when asked for the time. Timings read zero there.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+#[derive(Clone, Copy)]
+pub struct Instant;
+
+#[cfg(target_arch = "wasm32")]
+impl Instant {
+    pub fn now() -> Self {
+        Instant
+    }
+
+    pub fn elapsed(&self) -> std::time::Duration {
+        std::time::Duration::ZERO
+    }
+}
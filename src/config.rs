@@ -0,0 +1,238 @@
+//! Config files for `convert`: TOML whose keys are the long flag names. The
+//! file's settings get spliced into the command line ahead of the flags the
+//! user typed, leaving out anything they typed themselves, so those win.
+
+use std::{
+    error::Error,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, parser::ValueSource};
+
+use crate::cli::{Cli, Command};
+
+/// Picked up from next to the first input when `--config` isn't given.
+const DEFAULT_CONFIG_NAME: &str = "rbxl-to-obj.toml";
+
+/// Flags that only make sense on the command line.
+const NOT_CONFIGURABLE: &[&str] = &["config", "print-config", "help", "version"];
+
+/// The command line after merging in the config file, for `--print-config`.
+pub struct Effective {
+    matches: ArgMatches,
+    config: Option<PathBuf>,
+    /// Flags whose values came from the config file.
+    from_config: Vec<String>,
+}
+
+/// Parses the command line, merging in the config file if there is one.
+pub fn load() -> Result<(Cli, Effective), Box<dyn Error>> {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    let matches = Cli::command().get_matches_from(&args);
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    let (convert, explicit) = match &cli.command {
+        Some(Command::Convert(convert)) => (&**convert, true),
+        Some(_) => return Ok((cli, Effective::without_config(matches))),
+        None => (&cli.convert, false),
+    };
+    let config = convert.config.clone().or_else(|| {
+        let dir = convert.inputs.first()?.parent()?;
+        Some(dir.join(DEFAULT_CONFIG_NAME)).filter(|path| path.is_file())
+    });
+    let Some(config) = config else {
+        return Ok((cli, Effective::without_config(matches)));
+    };
+
+    let typed = if explicit {
+        matches.subcommand_matches("convert").unwrap()
+    } else {
+        &matches
+    };
+    let (flags, from_config) = config_flags(&config, typed)?;
+    // After the program name, and after `convert` if it was spelled out.
+    let at = if explicit { 2 } else { 1 };
+    args.splice(at..at, flags);
+
+    let matches = Cli::command().get_matches_from(&args);
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    Ok((
+        cli,
+        Effective {
+            matches,
+            config: Some(config),
+            from_config,
+        },
+    ))
+}
+
+/// Turns the config file into flags, skipping the ones already in `typed`.
+fn config_flags(
+    path: &Path,
+    typed: &ArgMatches,
+) -> Result<(Vec<OsString>, Vec<String>), Box<dyn Error>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("couldn't read config {}: {}", path.display(), e))?;
+    let table: toml::Table = text
+        .parse()
+        .map_err(|e| format!("invalid config {}: {}", path.display(), e))?;
+
+    let command = Cli::command();
+    let convert = command.find_subcommand("convert").unwrap();
+    let configurable: Vec<&str> = convert
+        .get_arguments()
+        .filter_map(|arg| arg.get_long())
+        .filter(|long| !NOT_CONFIGURABLE.contains(long))
+        .collect();
+
+    let mut flags = Vec::new();
+    let mut keys = Vec::new();
+    for (key, value) in &table {
+        let long = key.replace('_', "-");
+        let Some(arg) = convert
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+            .filter(|_| configurable.contains(&long.as_str()))
+        else {
+            let mut message = format!("unknown key `{}` in {}", key, path.display());
+            if let Some(nearest) = nearest_key(&long, &configurable) {
+                message += &format!(", did you mean `{}`?", nearest);
+            }
+            return Err(message.into());
+        };
+        if typed.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+
+        let flag = format!("--{}", long);
+        let invalid = || format!("invalid value for `{}` in {}", key, path.display());
+        match value {
+            toml::Value::Boolean(true) => flags.push(flag.into()),
+            toml::Value::Boolean(false) => {}
+            toml::Value::Integer(count) if matches!(arg.get_action(), ArgAction::Count) => {
+                let count = usize::try_from(*count).map_err(|_| invalid())?;
+                flags.extend((0..count).map(|_| OsString::from(&flag)));
+            }
+            toml::Value::Array(items) => {
+                for item in items {
+                    let item = scalar(item).ok_or_else(invalid)?;
+                    flags.push(format!("{}={}", flag, item).into());
+                }
+            }
+            value => {
+                let value = scalar(value).ok_or_else(invalid)?;
+                flags.push(format!("{}={}", flag, value).into());
+            }
+        }
+        keys.push(long);
+    }
+    Ok((flags, keys))
+}
+
+/// A string, number or bool as it'd be typed on the command line.
+fn scalar(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(s) => Some(s.clone()),
+        toml::Value::Integer(i) => Some(i.to_string()),
+        toml::Value::Float(f) => Some(f.to_string()),
+        toml::Value::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// The valid key closest to a mistyped one, if any is close enough to be a
+/// plausible typo.
+fn nearest_key<'a>(key: &str, valid: &[&'a str]) -> Option<&'a str> {
+    valid
+        .iter()
+        .map(|&candidate| (edit_distance(key, candidate), candidate))
+        .filter(|&(distance, _)| distance <= key.len().max(3) / 3)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substituted = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+impl Effective {
+    fn without_config(matches: ArgMatches) -> Self {
+        Effective {
+            matches,
+            config: None,
+            from_config: Vec::new(),
+        }
+    }
+
+    /// Prints every convert setting with a value as TOML, noting where
+    /// each one came from.
+    pub fn print_config(&self) {
+        let matches = self
+            .matches
+            .subcommand_matches("convert")
+            .unwrap_or(&self.matches);
+        match &self.config {
+            Some(path) => println!("# config: {}", path.display()),
+            None => println!("# no config file"),
+        }
+
+        let command = Cli::command();
+        let convert = command.find_subcommand("convert").unwrap();
+        for arg in convert.get_arguments() {
+            let Some(long) = arg.get_long() else {
+                continue;
+            };
+            if NOT_CONFIGURABLE.contains(&long) {
+                continue;
+            }
+            let id = arg.get_id().as_str();
+            let value = if matches!(arg.get_action(), ArgAction::Count) {
+                matches.get_count(id).to_string()
+            } else {
+                let Some(raw) = matches.get_raw(id) else {
+                    continue;
+                };
+                let values: Vec<String> = raw
+                    .map(|value| toml_value(&value.to_string_lossy()))
+                    .collect();
+                match (arg.get_action(), values.as_slice()) {
+                    (ArgAction::Append, _) => format!("[{}]", values.join(", ")),
+                    (_, [value]) => value.clone(),
+                    _ => format!("[{}]", values.join(", ")),
+                }
+            };
+            let source = if self.from_config.iter().any(|key| key == long) {
+                "config"
+            } else {
+                match matches.value_source(id) {
+                    Some(ValueSource::DefaultValue) => "default",
+                    _ => "command line",
+                }
+            };
+            println!("{} = {} # {}", long, value, source);
+        }
+    }
+}
+
+/// Writes a raw flag value as a TOML bool, number or string.
+fn toml_value(raw: &str) -> String {
+    if raw == "true" || raw == "false" || raw.parse::<f64>().is_ok_and(f64::is_finite) {
+        raw.to_string()
+    } else {
+        toml::Value::String(raw.to_string()).to_string()
+    }
+}
@@ -0,0 +1,293 @@
+//! `rbx_obj diff`: what changed between two saves of a place, drawn as the
+//! parts involved in a color per kind of change.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use rbx_dom_weak::WeakDom;
+use rbx_types::{CFrame, Ref, Vector3};
+
+use crate::{
+    ExportError,
+    options::ExportOptions,
+    scene::{MATERIAL_PLASTIC, PartRecord, Scene, collect_scene, select_top_level},
+};
+
+/// How far a part has to move or grow, in studs, to count as changed. Saves
+/// round-trip floats exactly, so this only has to absorb the odd re-save.
+const EPSILON: f32 = 1e-4;
+
+const ADDED_COLOR: (u8, u8, u8, u8) = (0, 200, 0, 255);
+const MOVED_COLOR: (u8, u8, u8, u8) = (255, 200, 0, 255);
+const REMOVED_COLOR: (u8, u8, u8, u8) = (220, 0, 0, 255);
+const RECOLORED_COLOR: (u8, u8, u8, u8) = (0, 120, 255, 255);
+
+/// One part that differs between two saves. Referents are into the new DOM,
+/// except `Removed`'s, which is into the old one.
+#[derive(Clone, Copy, Debug)]
+pub enum DiffEntry {
+    /// Only in the new save.
+    Added(Ref),
+    /// Only in the old save, which was at this `CFrame` and `Size`.
+    Removed(Ref, CFrame, Vector3),
+    /// Moved, turned or resized, from the first `CFrame` to the second.
+    Moved(Ref, CFrame, CFrame),
+    /// Only the color changed.
+    Recolored(Ref),
+}
+
+/// Compares the parts `opts` would export from each DOM. Referents differ
+/// between saves, so parts are matched by their name and their parent's
+/// name, in the order they come up when several share both.
+pub fn diff_doms(
+    old: &WeakDom,
+    new: &WeakDom,
+    opts: &ExportOptions,
+) -> Result<Vec<DiffEntry>, ExportError> {
+    diff_records(old, new, opts).map(|(entries, _)| entries)
+}
+
+/// The parts to draw for `diff_doms`: the new save's parts that were added,
+/// moved or recolored, and a block where each removed one was, each in its
+/// change's color: green for added, yellow for moved, red for removed and
+/// blue for recolored.
+pub(crate) fn diff_records(
+    old: &WeakDom,
+    new: &WeakDom,
+    opts: &ExportOptions,
+) -> Result<(Vec<DiffEntry>, Vec<PartRecord>), ExportError> {
+    let old_parts = collect_parts(old, opts)?;
+    let new_parts = collect_parts(new, opts)?;
+    let entries = diff_parts(old, &old_parts, new, &new_parts);
+
+    let mut by_ref: HashMap<Ref, PartRecord> = new_parts
+        .into_iter()
+        .map(|part| (part.referent, part))
+        .collect();
+    let records = entries
+        .iter()
+        .filter_map(|entry| {
+            let (part, color) = match *entry {
+                DiffEntry::Added(r) => (by_ref.remove(&r)?, ADDED_COLOR),
+                DiffEntry::Moved(r, _, _) => (by_ref.remove(&r)?, MOVED_COLOR),
+                DiffEntry::Recolored(r) => (by_ref.remove(&r)?, RECOLORED_COLOR),
+                DiffEntry::Removed(r, cframe, size) => {
+                    let block = PartRecord {
+                        referent: r,
+                        name: old
+                            .get_by_ref(r)
+                            .map_or_else(String::new, |i| i.name.clone()),
+                        object_name: None,
+                        class: "Part".to_string(),
+                        shape: 1,
+                        surfaces: [0; 6],
+                        size,
+                        cframe,
+                        color: REMOVED_COLOR,
+                        material: MATERIAL_PLASTIC,
+                        anchored: true,
+                        unique_id: None,
+                    };
+                    (block, REMOVED_COLOR)
+                }
+            };
+            Some(PartRecord {
+                color,
+                material: MATERIAL_PLASTIC,
+                ..part
+            })
+        })
+        .collect();
+    Ok((entries, records))
+}
+
+fn collect_parts(dom: &WeakDom, opts: &ExportOptions) -> Result<Vec<PartRecord>, ExportError> {
+    let (top_level, _) = select_top_level(dom, opts);
+    let mut parts = Vec::new();
+    collect_scene(
+        dom,
+        &top_level,
+        opts,
+        &mut Scene::default(),
+        None,
+        &mut |part| parts.push(part),
+    )?;
+    Ok(parts)
+}
+
+/// The new parts in order, each added or checked against its match, then
+/// the old parts nothing matched, in their order.
+fn diff_parts(
+    old: &WeakDom,
+    old_parts: &[PartRecord],
+    new: &WeakDom,
+    new_parts: &[PartRecord],
+) -> Vec<DiffEntry> {
+    let key = |dom: &WeakDom, part: &PartRecord| {
+        let parent = dom
+            .get_by_ref(part.referent)
+            .and_then(|inst| dom.get_by_ref(inst.parent()))
+            .map_or_else(String::new, |parent| parent.name.clone());
+        (parent, part.name.clone())
+    };
+    let mut unmatched: HashMap<(String, String), VecDeque<&PartRecord>> = HashMap::new();
+    for part in old_parts {
+        unmatched.entry(key(old, part)).or_default().push_back(part);
+    }
+
+    let mut entries = Vec::new();
+    let mut matched = HashSet::new();
+    for part in new_parts {
+        let Some(before) = unmatched
+            .get_mut(&key(new, part))
+            .and_then(VecDeque::pop_front)
+        else {
+            entries.push(DiffEntry::Added(part.referent));
+            continue;
+        };
+        matched.insert(before.referent);
+        if moved(before, part) {
+            entries.push(DiffEntry::Moved(part.referent, before.cframe, part.cframe));
+        } else if before.color != part.color {
+            entries.push(DiffEntry::Recolored(part.referent));
+        }
+    }
+    entries.extend(
+        old_parts
+            .iter()
+            .filter(|part| !matched.contains(&part.referent))
+            .map(|part| DiffEntry::Removed(part.referent, part.cframe, part.size)),
+    );
+    entries
+}
+
+fn moved(before: &PartRecord, after: &PartRecord) -> bool {
+    let (a, b) = (&before.cframe.orientation, &after.cframe.orientation);
+    [
+        (before.cframe.position, after.cframe.position),
+        (before.size, after.size),
+        (a.x, b.x),
+        (a.y, b.y),
+        (a.z, b.z),
+    ]
+    .iter()
+    .any(|(p, q)| {
+        (p.x - q.x).abs() > EPSILON || (p.y - q.y).abs() > EPSILON || (p.z - q.z).abs() > EPSILON
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rbx_dom_weak::InstanceBuilder;
+    use rbx_types::{Color3uint8, Matrix3};
+
+    use super::*;
+
+    fn part(name: &str, size: f32, y: f32, color: u8) -> InstanceBuilder {
+        InstanceBuilder::new("Part")
+            .with_name(name)
+            .with_property("Size", Vector3::new(size, 1.0, size))
+            .with_property(
+                "CFrame",
+                CFrame::new(Vector3::new(0.0, y, 0.0), Matrix3::identity()),
+            )
+            .with_property("Color", Color3uint8::new(color, color, color))
+    }
+
+    /// A place with a Model for each of `models`, holding its parts.
+    fn place(models: Vec<(&str, Vec<InstanceBuilder>)>) -> WeakDom {
+        let mut dom = WeakDom::new(InstanceBuilder::new("DataModel"));
+        let workspace = dom.insert(dom.root_ref(), InstanceBuilder::new("Workspace"));
+        for (name, parts) in models {
+            let model = dom.insert(workspace, InstanceBuilder::new("Model").with_name(name));
+            for part in parts {
+                dom.insert(model, part);
+            }
+        }
+        dom
+    }
+
+    /// Each entry as its kind and the part's name, with the height a
+    /// removed or moved part was at.
+    fn describe(old: &WeakDom, new: &WeakDom) -> Vec<String> {
+        let name = |dom: &WeakDom, r: Ref| dom.get_by_ref(r).unwrap().name.clone();
+        diff_doms(old, new, &ExportOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|entry| match entry {
+                DiffEntry::Added(r) => format!("added {}", name(new, r)),
+                DiffEntry::Removed(r, cframe, _) => {
+                    format!("removed {} at {}", name(old, r), cframe.position.y)
+                }
+                DiffEntry::Moved(r, from, to) => format!(
+                    "moved {} from {} to {}",
+                    name(new, r),
+                    from.position.y,
+                    to.position.y
+                ),
+                DiffEntry::Recolored(r) => format!("recolored {}", name(new, r)),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reports_each_kind_of_change() {
+        let old = place(vec![(
+            "House",
+            vec![
+                part("Wall", 4.0, 0.0, 100),
+                part("Shed", 2.0, 0.0, 100),
+                part("Door", 2.0, 0.0, 100),
+                part("Roof", 4.0, 5.0, 100),
+                part("Floor", 4.0, 0.0, 100),
+            ],
+        )]);
+        let new = place(vec![(
+            "House",
+            vec![
+                part("Wall", 4.0, 0.0, 100),
+                part("Door", 2.0, 3.0, 100),
+                part("Roof", 6.0, 5.0, 100),
+                part("Floor", 4.0, 0.0, 200),
+                part("Chimney", 1.0, 8.0, 100),
+            ],
+        )]);
+        assert_eq!(
+            describe(&old, &new),
+            [
+                "moved Door from 0 to 3",
+                "moved Roof from 5 to 5",
+                "recolored Floor",
+                "added Chimney",
+                "removed Shed at 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_changed_is_no_entries() {
+        let dom = || place(vec![("House", vec![part("Wall", 4.0, 0.0, 100)])]);
+        assert!(describe(&dom(), &dom()).is_empty());
+    }
+
+    #[test]
+    fn parts_sharing_a_name_match_in_order() {
+        let steps = |heights: &[f32]| -> Vec<InstanceBuilder> {
+            heights.iter().map(|&y| part("Step", 1.0, y, 100)).collect()
+        };
+        let old = place(vec![("Stairs", steps(&[0.0, 1.0, 2.0]))]);
+        let new = place(vec![
+            ("Stairs", steps(&[0.0, 1.5])),
+            ("Ladder", steps(&[0.0])),
+        ]);
+        // The second step moved and the third, with nothing left to match,
+        // was removed. The same name under another parent is a new part.
+        assert_eq!(
+            describe(&old, &new),
+            [
+                "moved Step from 1 to 1.5",
+                "added Step",
+                "removed Step at 2",
+            ]
+        );
+    }
+}
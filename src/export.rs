@@ -0,0 +1,4320 @@
+//! The export itself: reading a file, collecting and meshing its parts, and
+//! writing the OBJ and everything that goes with it.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, hash_map::Entry},
+    error::Error,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{self, Component, Path, PathBuf},
+    time::Duration,
+};
+
+use rbx_dom_weak::{Instance, Ustr, WeakDom};
+use rbx_types::{CFrame, Matrix3, Ref, Variant, Vector3};
+
+use crate::{
+    ExportError, ExportReport, InstanceError, MaterialUsage, PhaseTiming,
+    blender_preset::{PresetMaterial, write_blender_materials},
+    bobj::write_bobj,
+    bvh,
+    callbacks::{Progress, ProgressPhase},
+    cancel::CancellationToken,
+    clock::Instant,
+    diff::{DiffEntry, diff_records},
+    geometry_json::{GeometryPart, write_cbor, write_json},
+    json_escape,
+    mesh::{
+        Mesh, Tessellation, apply_cframe, apply_matrix3, crease_normals, cross, dot, local_mesh,
+        mesh_counts, normalize, sphere_mesh, sub, transform_vertices, wedge_face_normals,
+    },
+    options::{
+        DoubleSided, ExportOptions, MtlTransparency, MtllibStyle, Normals, OutputFormat, Wireframe,
+    },
+    palette::Palette,
+    parallel::*,
+    preview,
+    scene::{
+        MATERIAL_PLASTIC, PartRecord, Scene, collect_scene, color3_to_rgb, count_parts,
+        descendants, find_cframe, find_service, get_cframe, instance_path, object_name_component,
+        read_dom, roblox_material_name, select_top_level,
+    },
+    summary::RunSummary,
+    tjunction::{find_tjunctions, fix_tjunction, fix_tjunction_with_normals},
+    transform::cframe_inverse,
+    voxel::{greedy_mesh_voxels, voxelize_scene},
+    warning::{self, Warning, WarningCode},
+};
+
+#[derive(Default)]
+struct ExportState {
+    vertex_offset: usize,
+    normal_offset: usize,
+    texcoord_offset: usize,
+    face_offset: usize,
+    /// Each material's number, in the order they were written, and name.
+    material_map: HashMap<MaterialKey, (usize, String)>,
+    constraint_materials: Vec<&'static str>,
+    selection_materials: HashSet<String>,
+    part_info: Option<Box<dyn Write>>,
+    /// Mirroring applied during the current pass over the scene.
+    mirror: [bool; 3],
+    name_suffix: &'static str,
+    welder: Option<Welder>,
+    /// With `bake_material_id_texture`, where each material's pixel is,
+    /// numbered as in `material_map`.
+    palette: Option<Palette>,
+    current_group: Option<&'static str>,
+    /// The `o` names written so far, with `object_names`.
+    object_names: HashSet<String>,
+    /// Reused between parts so vertex and face lines go out in one write.
+    line_buffer: String,
+    scratch_vertices: Vec<Vector3>,
+    scratch_faces: Vec<Face>,
+    /// Each face's corners' `vn` numbers, also reused between parts.
+    scratch_normals: Vec<[usize; 3]>,
+    /// Each face's corners' `vt` numbers, the same way.
+    scratch_texcoords: Vec<[usize; 3]>,
+    parts_written: usize,
+    parts_by_class: BTreeMap<String, usize>,
+    vertices_written: usize,
+    faces_written: usize,
+}
+
+/// Exports one place or model file to `output` and the files next to it,
+/// giving up part way if `cancel` fires.
+pub(crate) fn convert(
+    opts: &ExportOptions,
+    path: &Path,
+    output: &Path,
+    cancel: Option<&CancellationToken>,
+) -> Result<ExportReport, Box<dyn Error>> {
+    opts.validate()?;
+    let mut timings = Timings::default();
+    let run_started = Instant::now();
+
+    let started = Instant::now();
+    let path = path.to_path_buf();
+    let parsing = Progress::start(&opts.callbacks, &path, ProgressPhase::Parsing, None);
+    let dom = read_dom(&path)?;
+    drop(parsing);
+    timings.add("parse", started.elapsed(), None);
+
+    if opts.list_textures {
+        return Ok(ExportReport {
+            asset_ids: asset_ids(&dom),
+            ..ExportReport::default()
+        });
+    }
+
+    if opts.dry_run {
+        return dry_run(opts, &path, output, &dom, run_started);
+    }
+    if opts.list_materials {
+        return Ok(ExportReport {
+            material_usage: list_materials(opts, &dom)?,
+            ..ExportReport::default()
+        });
+    }
+    if matches!(opts.format, OutputFormat::Json | OutputFormat::Bobj) {
+        return export_geometry(opts, &path, output, &dom, cancel, run_started, timings);
+    }
+
+    let source = ObjSource {
+        dom: &dom,
+        input: &path,
+        parts: None,
+    };
+    export_obj_file(opts, source, output, cancel, run_started, timings)
+}
+
+/// `rbx_obj diff`: reads both files and writes the new one's changed parts
+/// to `output`, with a block for each removed part, colored by
+/// [`diff_records`]. Only plain OBJ exports are supported.
+pub(crate) fn convert_diff(
+    opts: &ExportOptions,
+    old_path: &Path,
+    new_path: &Path,
+    output: &Path,
+) -> Result<ExportReport, Box<dyn Error>> {
+    opts.validate()?;
+    if opts.stream || opts.dry_run || opts.format != OutputFormat::Obj {
+        return Err(ExportError::Unsupported(
+            "diff only writes a plain OBJ, without --stream or --dry-run".to_string(),
+        )
+        .into());
+    }
+    let run_started = Instant::now();
+    let mut timings = Timings::default();
+    let started = Instant::now();
+    let old = read_dom(old_path)?;
+    let new = read_dom(new_path)?;
+    timings.add("parse", started.elapsed(), None);
+
+    let (entries, parts) = diff_records(&old, &new, opts)?;
+    let count = |kind: fn(&DiffEntry) -> bool| entries.iter().filter(|e| kind(e)).count();
+    log::info!(
+        "{} added, {} removed, {} moved, {} recolored",
+        count(|e| matches!(e, DiffEntry::Added(..))),
+        count(|e| matches!(e, DiffEntry::Removed(..))),
+        count(|e| matches!(e, DiffEntry::Moved(..))),
+        count(|e| matches!(e, DiffEntry::Recolored(..))),
+    );
+
+    let source = ObjSource {
+        dom: &new,
+        input: new_path,
+        parts: Some(parts),
+    };
+    export_obj_file(opts, source, output, None, run_started, timings)
+}
+
+/// How far [`read_for_async`] got: the whole answer, when there's no OBJ to
+/// write, or the input read and waiting for [`convert_into`].
+#[cfg(feature = "async")]
+pub(crate) enum AsyncRead {
+    Done(ExportReport),
+    Read(ReadInput),
+}
+
+/// An input parsed for [`convert_into`], with the run's timings so far.
+#[cfg(feature = "async")]
+pub(crate) struct ReadInput {
+    dom: WeakDom,
+    run_started: Instant,
+    timings: Timings,
+}
+
+/// The first half of `convert_file_async`, before any file is created:
+/// checks the options and reads `path`, and answers the listings and dry
+/// runs. The options that write the OBJ as several files, or not at all,
+/// are `Unsupported`.
+#[cfg(feature = "async")]
+pub(crate) fn read_for_async(
+    opts: &ExportOptions,
+    path: &Path,
+    output: &Path,
+) -> Result<AsyncRead, Box<dyn Error>> {
+    opts.validate()?;
+    let unsupported = [
+        (opts.lods.is_some(), "--lods"),
+        (
+            opts.max_vertices_per_file.is_some(),
+            "--max-vertices-per-file",
+        ),
+        (opts.format == OutputFormat::Json, "--format json"),
+        (opts.format == OutputFormat::Bobj, "--format bobj"),
+    ];
+    if let Some((_, flag)) = unsupported.iter().find(|(set, _)| *set) {
+        return Err(
+            ExportError::Unsupported(format!("{} can't be written asynchronously", flag)).into(),
+        );
+    }
+    let mut timings = Timings::default();
+    let run_started = Instant::now();
+
+    let started = Instant::now();
+    let parsing = Progress::start(&opts.callbacks, path, ProgressPhase::Parsing, None);
+    let dom = read_dom(path)?;
+    drop(parsing);
+    timings.add("parse", started.elapsed(), None);
+
+    if opts.list_textures {
+        return Ok(AsyncRead::Done(ExportReport {
+            asset_ids: asset_ids(&dom),
+            ..ExportReport::default()
+        }));
+    }
+    if opts.dry_run {
+        return dry_run(opts, path, output, &dom, run_started).map(AsyncRead::Done);
+    }
+    if opts.list_materials {
+        return Ok(AsyncRead::Done(ExportReport {
+            material_usage: list_materials(opts, &dom)?,
+            ..ExportReport::default()
+        }));
+    }
+    Ok(AsyncRead::Read(ReadInput {
+        dom,
+        run_started,
+        timings,
+    }))
+}
+
+/// The second half of `convert_file_async`: exports what [`read_for_async`]
+/// read, like [`convert`] does, except that the OBJ and MTL go into `obj`
+/// and `mtl` to be written to `output` and the MTL beside it. Other files
+/// are still written here.
+#[cfg(feature = "async")]
+pub(crate) fn convert_into(
+    opts: &ExportOptions,
+    read: ReadInput,
+    path: &Path,
+    output: &Path,
+    obj: &mut dyn Write,
+    mtl: &mut dyn Write,
+    cancel: Option<&CancellationToken>,
+) -> Result<ExportReport, Box<dyn Error>> {
+    let mtl_path = mtl_path(output, opts);
+    let mtl_name = mtllib_reference(output, &mtl_path, opts.mtllib_style)
+        .map_err(|e| ExportError::io(&mtl_path, e))?;
+    let target = ObjTarget {
+        output: Some(output),
+        obj: ObjFiles::buffer(obj, Some(output.to_path_buf()), mtl_name.clone()),
+        mtl,
+        mtl_name,
+        outputs: vec![mtl_path],
+    };
+    let source = ObjSource {
+        dom: &read.dom,
+        input: path,
+        parts: None,
+    };
+    export_obj(opts, source, target, cancel, read.run_started, read.timings)
+}
+
+/// Runs [`export_obj`] into `output` (or its first detail level) and the
+/// MTL beside it.
+fn export_obj_file(
+    opts: &ExportOptions,
+    source: ObjSource,
+    output: &Path,
+    cancel: Option<&CancellationToken>,
+    run_started: Instant,
+    timings: Timings,
+) -> Result<ExportReport, Box<dyn Error>> {
+    let obj_path = if opts.lods.is_some() {
+        lod_path(output, 0)
+    } else {
+        output.to_path_buf()
+    };
+    let mtl_path = mtl_path(output, opts);
+    let mtl_name = mtllib_reference(&obj_path, &mtl_path, opts.mtllib_style)
+        .map_err(|e| ExportError::io(&mtl_path, e))?;
+    let mut mtl = create_file(&mtl_path)?;
+    let target = ObjTarget {
+        output: Some(output),
+        obj: ObjFiles::create(obj_path, mtl_name.clone(), opts.max_vertices_per_file)?,
+        mtl: &mut mtl,
+        mtl_name,
+        outputs: vec![mtl_path],
+    };
+    export_obj(opts, source, target, cancel, run_started, timings)
+}
+
+/// Creates `path` for writing, naming it in the error if that fails.
+fn create_file(path: &Path) -> Result<BufWriter<File>, ExportError> {
+    File::create(path)
+        .map(BufWriter::new)
+        .map_err(|e| ExportError::io(path, e))
+}
+
+/// Passes `result` on, first deleting every file the export has started if
+/// it was cancelled, so an interrupted run doesn't leave half an export
+/// behind that looks like a whole one. Only the OBJ export needs this: the
+/// others don't create anything until they can no longer be cancelled.
+fn remove_if_cancelled<T>(
+    result: Result<T, ExportError>,
+    outputs: &[PathBuf],
+    obj: &ObjFiles,
+) -> Result<T, ExportError> {
+    if let Err(ExportError::Cancelled) = result {
+        for path in outputs.iter().chain(&obj.paths) {
+            match fs::remove_file(path) {
+                Ok(()) => log::info!("removed partial output {}", path.display()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => log::warn!("couldn't remove partial output {}: {}", path.display(), e),
+            }
+        }
+    }
+    result
+}
+
+/// Stands in for the file name in the summary of an in-memory export.
+const IN_MEMORY_INPUT: &str = "<in-memory DOM>";
+
+/// What the OBJ of an in-memory export calls its MTL.
+const IN_MEMORY_MTL_NAME: &str = "scene.mtl";
+
+/// Exports a DOM that's already loaded into `obj` and `mtl`, dropping the
+/// materials without an `mtl`. Options that write files of their own, like
+/// `part_info_csv`, still do.
+pub(crate) fn export_dom(
+    opts: &ExportOptions,
+    dom: &WeakDom,
+    obj: &mut dyn Write,
+    mtl: Option<&mut dyn Write>,
+    cancel: Option<&CancellationToken>,
+) -> Result<ExportReport, Box<dyn Error>> {
+    opts.validate()?;
+    let run_started = Instant::now();
+    let input = Path::new(IN_MEMORY_INPUT);
+    if opts.list_textures {
+        return Ok(ExportReport {
+            asset_ids: asset_ids(dom),
+            ..ExportReport::default()
+        });
+    }
+    if opts.list_materials {
+        return Ok(ExportReport {
+            material_usage: list_materials(opts, dom)?,
+            ..ExportReport::default()
+        });
+    }
+    let unsupported = [
+        (opts.dry_run, "--dry-run"),
+        (opts.format == OutputFormat::Json, "--format json"),
+        (opts.format == OutputFormat::Bobj, "--format bobj"),
+    ];
+    if let Some((_, flag)) = unsupported.iter().find(|(set, _)| *set) {
+        return Err(ExportError::Unsupported(format!("{} needs an output path", flag)).into());
+    }
+
+    let mut discard = io::sink();
+    let target = ObjTarget {
+        output: None,
+        obj: ObjFiles::buffer(obj, None, IN_MEMORY_MTL_NAME.to_string()),
+        mtl: mtl.unwrap_or(&mut discard),
+        mtl_name: IN_MEMORY_MTL_NAME.to_string(),
+        outputs: Vec::new(),
+    };
+    let source = ObjSource {
+        dom,
+        input,
+        parts: None,
+    };
+    export_obj(
+        opts,
+        source,
+        target,
+        cancel,
+        run_started,
+        Timings::default(),
+    )
+}
+
+/// `--list-textures`: every referenced asset id, in order.
+fn asset_ids(dom: &WeakDom) -> Vec<u64> {
+    let mut ids: Vec<u64> = collect_asset_ids(dom).into_iter().collect();
+    ids.sort_unstable();
+    ids
+}
+
+/// Where an OBJ export writes.
+struct ObjTarget<'a> {
+    /// The OBJ's path, which the extra files are named after, or `None` when
+    /// writing into the caller's buffers.
+    output: Option<&'a Path>,
+    obj: ObjFiles<'a>,
+    mtl: &'a mut dyn Write,
+    /// What the OBJ's `mtllib` line refers to.
+    mtl_name: String,
+    /// Files already written, to go in the summary.
+    outputs: Vec<PathBuf>,
+}
+
+/// What an OBJ export reads.
+struct ObjSource<'a> {
+    dom: &'a WeakDom,
+    /// The file `dom` came from, for the summary and progress.
+    input: &'a Path,
+    /// Parts to write instead of the ones collected from `dom`, which is
+    /// then only read for its Lighting.
+    parts: Option<Vec<PartRecord>>,
+}
+
+/// Collects and meshes the scene and writes it to `target`, with
+/// everything a plain export does from there: LODs, extras, the summary.
+fn export_obj(
+    opts: &ExportOptions,
+    source: ObjSource,
+    target: ObjTarget,
+    cancel: Option<&CancellationToken>,
+    run_started: Instant,
+    mut timings: Timings,
+) -> Result<ExportReport, Box<dyn Error>> {
+    let ObjSource {
+        dom,
+        input,
+        parts: given_parts,
+    } = source;
+    let ObjTarget {
+        output,
+        mut obj,
+        mtl,
+        mtl_name,
+        mut outputs,
+    } = target;
+
+    // These write more files, named after the OBJ's.
+    let named_after = |flag: &str| {
+        output.ok_or_else(|| {
+            ExportError::Unsupported(format!(
+                "{} writes extra files, so it needs an output path",
+                flag
+            ))
+        })
+    };
+    let lod_output = opts.lods.map(|_| named_after("--lods")).transpose()?;
+    let xml_path = (opts.format == OutputFormat::Mitsuba)
+        .then(|| named_after("--format mitsuba"))
+        .transpose()?
+        .map(|output| output.with_extension("xml"));
+    if opts.max_vertices_per_file.is_some() {
+        named_after("--max-vertices-per-file")?;
+    }
+    let palette_path = opts
+        .bake_material_id_texture
+        .then(|| named_after("--bake-material-id-texture"))
+        .transpose()?
+        .map(palette_path);
+    let blender_path = opts
+        .blender_materials
+        .then(|| named_after("--blender-materials"))
+        .transpose()?
+        .map(|output| (blender_materials_path(output), output.file_name().unwrap()));
+    write_obj_header(&mut obj, dom, opts, &mtl_name)?;
+    if !opts.no_lighting {
+        export_fog_comment(dom, mtl)?;
+    }
+
+    if opts.wireframe == Wireframe::EdgesAndFaces {
+        writeln!(mtl, "newmtl {}", WIREFRAME_MATERIAL)?;
+        writeln!(mtl, "Kd 0 0 0")?;
+        writeln!(mtl)?;
+    }
+
+    let mut state = ExportState::default();
+    if let Some(csv_path) = &opts.part_info_csv {
+        outputs.push(csv_path.clone());
+        let mut csv = create_file(csv_path)?;
+        writeln!(
+            csv,
+            "name,class,x,y,z,size_x,size_y,size_z,\
+             rot_r00,rot_r01,rot_r02,rot_r10,rot_r11,rot_r12,rot_r20,rot_r21,rot_r22,\
+             r,g,b,a,material,vertex_start,vertex_count,face_start,face_count,unique_id"
+        )?;
+        state.part_info = Some(Box::new(csv));
+    }
+
+    let (top_level, skipped_services) = select_top_level(dom, opts);
+
+    let passes = mirror_passes(opts);
+
+    let mut scene = new_scene(opts, &skipped_services);
+    let mut parts = Vec::new();
+    let mut sketch = Vec::new();
+    let diffing = given_parts.is_some();
+    let started = Instant::now();
+    if opts.stream {
+        // Meshes go straight to the writer, so memory use doesn't grow with
+        // the number of parts.
+        state.mirror = passes[0];
+        let progress = Progress::start(
+            &opts.callbacks,
+            input,
+            ProgressPhase::Writing,
+            Some(count_parts(dom, &top_level)),
+        );
+        let mut result = Ok(());
+        let mut emit = |part: PartRecord| {
+            progress.part(dom, part.referent);
+            if result.is_ok() {
+                let mut mesh = post_process(
+                    opts,
+                    &part,
+                    local_mesh(
+                        &part,
+                        opts.truss_style,
+                        Tessellation::default().resampled(opts.resample_size),
+                    ),
+                );
+                transform_vertices(&mut mesh.0, &part.cframe);
+                result = obj
+                    .reserve(mesh.0.len(), &mut state)
+                    .map_err(Into::into)
+                    .and_then(|_| write_part(&part, &mesh, opts, &mut obj, mtl, &mut state));
+            }
+        };
+        remove_if_cancelled(
+            collect_scene(dom, &top_level, opts, &mut scene, cancel, &mut emit),
+            &outputs,
+            &obj,
+        )?;
+        result?;
+        timings.add(
+            "traversal, meshing and writing (streamed)",
+            started.elapsed(),
+            None,
+        );
+    } else {
+        match given_parts {
+            Some(given) => parts = given,
+            None => remove_if_cancelled(
+                collect_scene(dom, &top_level, opts, &mut scene, cancel, &mut |part| {
+                    parts.push(part)
+                }),
+                &outputs,
+                &obj,
+            )?,
+        }
+        timings.add("traversal", started.elapsed(), None);
+
+        if let Some(size) = opts.preview {
+            sketch = preview::render(&parts, size);
+            if opts.preview_in_obj {
+                for line in &sketch {
+                    writeln!(obj, "# Preview: {}", line)?;
+                }
+            }
+        }
+    }
+
+    if scene.depth_limited > 0 {
+        writeln!(obj, "# max depth reached")?;
+    }
+
+    if opts.merge_boxes {
+        parts = merge_box_parts(parts);
+    }
+    let constraint_lines = if opts.export_constraints && !diffing {
+        constraint_lines(dom, opts, &mut scene)?
+    } else {
+        Vec::new()
+    };
+    let selection_outlines = if opts.export_selection_effects && !diffing {
+        selection_outlines(dom, opts, &mut scene)?
+    } else {
+        Vec::new()
+    };
+    let voxels = opts
+        .voxelize
+        .map(|size| voxelize(&mut parts, size, &mut timings))
+        .transpose()?;
+    if let Some(palette_path) = palette_path {
+        // The texture's size depends on how many materials there are, so
+        // they're all numbered before any part is written.
+        let mut colors = Vec::new();
+        for part in &parts {
+            if let Entry::Vacant(entry) = state.material_map.entry(MaterialKey::of(part)) {
+                let name = entry.key().name();
+                entry.insert((colors.len(), name));
+                colors.push(part.color);
+            }
+        }
+        let palette = Palette::new(colors);
+        palette.write_png(&palette_path)?;
+        writeln!(mtl, "newmtl {}", PALETTE_MATERIAL)?;
+        writeln!(mtl, "Kd 1 1 1")?;
+        writeln!(
+            mtl,
+            "map_Kd {}",
+            palette_path.file_name().unwrap().to_string_lossy()
+        )?;
+        writeln!(mtl)?;
+        outputs.push(palette_path);
+        state.palette = Some(palette);
+    }
+
+    let detail = opts.max_triangles.map_or(Ok(0), |budget| {
+        fit_triangle_budget(opts, &mut parts, budget)
+    })?;
+
+    let mut lod_levels = Vec::new();
+    let mut scene_radius = 0.0;
+    let mut full_detail_paths = Vec::new();
+    for level in 0..opts.lods.unwrap_or(1) {
+        let tessellation = Tessellation::for_lod(level + detail).resampled(opts.resample_size);
+        if level > 0 {
+            // Thresholds only grow, so each level can keep trimming the last one's parts.
+            let min_size = lod_min_part_size(level);
+            parts.retain(|part| part.size.x.max(part.size.y).max(part.size.z) >= min_size);
+
+            // Only the full-detail level gets rows in the part CSV.
+            state.part_info = None;
+            state.vertex_offset = 0;
+            state.normal_offset = 0;
+            state.texcoord_offset = 0;
+            state.face_offset = 0;
+            state.current_group = None;
+            state.object_names.clear();
+            obj = ObjFiles::create(
+                lod_path(lod_output.unwrap(), level),
+                mtl_name.clone(),
+                opts.max_vertices_per_file,
+            )?;
+            write_obj_header(&mut obj, dom, opts, &mtl_name)?;
+        }
+
+        let meshes = match &voxels {
+            Some(mesh) => vec![mesh.clone()],
+            None => world_meshes(opts, &mut parts, tessellation, &mut timings),
+        };
+        if level == 0 && opts.lods.is_some() {
+            scene_radius = bounding_radius(&meshes);
+        }
+
+        let started = Instant::now();
+        state.welder = opts
+            .weld
+            .map(|epsilon| Welder::new(epsilon, opts.weld_per_material));
+
+        let progress = Progress::start(
+            &opts.callbacks,
+            input,
+            ProgressPhase::Writing,
+            Some(parts.len() * passes.len()),
+        );
+        for (pass, &mirror) in passes.iter().enumerate() {
+            state.mirror = mirror;
+            state.name_suffix = if pass > 0 { "_mirror" } else { "" };
+
+            for (part, mesh) in parts.iter().zip(&meshes) {
+                if let Some(cancel) = cancel {
+                    remove_if_cancelled(cancel.check().map_err(Into::into), &outputs, &obj)?;
+                }
+                progress.part(dom, part.referent);
+                obj.reserve(mesh.0.len(), &mut state)?;
+                write_part(part, mesh, opts, &mut obj, mtl, &mut state)?;
+            }
+        }
+
+        drop(progress);
+
+        if let Some(mut welder) = state.welder.take() {
+            if let Some(epsilon) = opts.fix_tjunctions {
+                let started = Instant::now();
+                let added = welder.fix_tjunctions(epsilon);
+                state.faces_written += added;
+                log::info!("split T-junctions into {} more triangles", added);
+                timings.add("T-junctions", started.elapsed(), None);
+            }
+            welder.finish(&mut obj, &mut state.vertex_offset, opts.wireframe)?;
+        }
+
+        if opts.export_constraints {
+            for &mirror in &passes {
+                state.mirror = mirror;
+                let vertices = constraint_lines
+                    .iter()
+                    .map(|line| line.shape.vertex_count())
+                    .sum();
+                obj.reserve(vertices, &mut state)?;
+                export_constraints(&constraint_lines, &mut obj, mtl, &mut state)?;
+            }
+        }
+        if opts.export_selection_effects {
+            for &mirror in &passes {
+                state.mirror = mirror;
+                let vertices = selection_outlines
+                    .iter()
+                    .map(|outline| outline.vertices.len())
+                    .sum();
+                obj.reserve(vertices, &mut state)?;
+                export_selection_outlines(&selection_outlines, &mut obj, mtl, &mut state)?;
+            }
+        }
+
+        let obj_paths = obj.finish()?;
+        timings.add("writing", started.elapsed(), None);
+        if level == 0 {
+            full_detail_paths = obj_paths.clone();
+        }
+        outputs.extend(obj_paths.iter().cloned());
+
+        if opts.lods.is_some() {
+            let triangles =
+                meshes.iter().map(|(_, faces)| faces.len()).sum::<usize>() * passes.len();
+            let file = obj_paths[0]
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string();
+            log::info!("{}: {} triangles", file, triangles);
+            lod_levels.push((file, triangles));
+        }
+    }
+
+    if let Some(output) = lod_output {
+        outputs.push(write_lod_manifest(output, &lod_levels, scene_radius)?);
+    }
+
+    if let Some(xml_path) = xml_path {
+        write_mitsuba_scene(
+            &xml_path,
+            &full_detail_paths,
+            dom,
+            &state.material_map,
+            &parts,
+            passes[0],
+        )?;
+        outputs.push(xml_path);
+    }
+
+    if let Some((blender_path, obj_name)) = blender_path {
+        let mut named: Vec<_> = state.material_map.iter().collect();
+        named.sort_by_key(|(_, (number, _))| *number);
+        let materials: Vec<_> = named
+            .into_iter()
+            .map(|(key, (_, name))| PresetMaterial {
+                name,
+                color: (key.r, key.g, key.b, key.a),
+                material: key.material_enum,
+            })
+            .collect();
+        write_blender_materials(
+            &mut create_file(&blender_path)?,
+            &obj_name.to_string_lossy(),
+            &materials,
+        )?;
+        outputs.push(blender_path);
+    }
+
+    log_scene_notes(opts, dom, &scene, &skipped_services);
+
+    mtl.flush()?;
+    if let Some(csv) = state.part_info.as_mut() {
+        csv.flush()?;
+    }
+    let mut report = finish_run(
+        opts,
+        &RunSummary {
+            input,
+            scene: &scene,
+            parts_by_class: &state.parts_by_class,
+            vertices: state.vertices_written,
+            faces: state.faces_written,
+            materials: state.material_map.len(),
+            outputs: &outputs,
+            elapsed: run_started.elapsed(),
+            dry_run: false,
+        },
+    )?;
+    report.errors = std::mem::take(&mut scene.errors);
+    report.phases = timings.phases;
+    report.preview = sketch;
+    Ok(report)
+}
+
+/// Records the summary and warnings of a finished run. If a
+/// `--fail-on` warning came up, the outputs get deleted when asked to.
+fn finish_run(opts: &ExportOptions, summary: &RunSummary) -> Result<ExportReport, Box<dyn Error>> {
+    let mut report = ExportReport {
+        parts_by_class: summary.parts_by_class.clone(),
+        skipped: summary.scene.skipped.clone(),
+        not_exported: summary
+            .scene
+            .unsupported
+            .iter()
+            .map(|(&kind, &(count, _))| (kind, count))
+            .collect(),
+        instances_visited: summary.scene.visited,
+        vertices: summary.vertices,
+        faces: summary.faces,
+        materials: summary.materials,
+        outputs: summary.outputs.to_vec(),
+        failures: summary.scene.failures,
+        elapsed: summary.elapsed,
+        dry_run: summary.dry_run,
+        ..ExportReport::default()
+    };
+    if let Some(json_path) = &opts.summary_json {
+        summary.append_json(json_path)?;
+    }
+    if let Some(jsonl_path) = &opts.warnings_jsonl {
+        warning::append_jsonl(jsonl_path, summary.input, &summary.scene.warnings)?;
+    }
+
+    if summary.scene.failures > 0 {
+        log::error!(
+            "{}: {} warnings treated as errors by --fail-on",
+            summary.input.display(),
+            summary.scene.failures
+        );
+        if opts.no_partial_output && !summary.dry_run {
+            for output in report.outputs.drain(..) {
+                fs::remove_file(&output).map_err(|e| ExportError::io(&output, e))?;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// The mirroring of each pass over the scene: one, or two with
+/// `--mirror-and-append`.
+fn mirror_passes(opts: &ExportOptions) -> Vec<[bool; 3]> {
+    let passes = if opts.mirror_and_append {
+        vec![[false; 3], opts.mirror]
+    } else {
+        vec![opts.mirror]
+    };
+    // --right-handed is one more Z flip on top of each pass's mirroring.
+    passes
+        .into_iter()
+        .map(|[x, y, z]| [x, y, z != opts.right_handed])
+        .collect()
+}
+
+/// `voxelize`: swaps `parts` for a single stand-in part holding the whole
+/// grid and returns its mesh, already in world space.
+fn voxelize(
+    parts: &mut Vec<PartRecord>,
+    size: f32,
+    timings: &mut Timings,
+) -> Result<Mesh, ExportError> {
+    let started = Instant::now();
+    let grid = voxelize_scene(parts, size)?;
+    let mesh = greedy_mesh_voxels(&grid);
+    log::info!(
+        "voxelized {} parts into a {}x{}x{} grid ({} triangles)",
+        parts.len(),
+        grid.dims[0],
+        grid.dims[1],
+        grid.dims[2],
+        mesh.1.len()
+    );
+    *parts = vec![PartRecord {
+        referent: Ref::none(),
+        name: "Voxels".to_string(),
+        object_name: None,
+        class: "Voxels".to_string(),
+        shape: 1,
+        surfaces: [0; 6],
+        size: Vector3::new(
+            grid.dims[0] as f32 * size,
+            grid.dims[1] as f32 * size,
+            grid.dims[2] as f32 * size,
+        ),
+        cframe: CFrame::new(Vector3::new(0.0, 0.0, 0.0), Matrix3::identity()),
+        color: VOXEL_COLOR,
+        material: MATERIAL_PLASTIC,
+        anchored: true,
+        unique_id: None,
+    }];
+    timings.add("voxelization", started.elapsed(), None);
+    Ok(mesh)
+}
+
+/// Medium stone grey, what Studio gives new parts.
+const VOXEL_COLOR: (u8, u8, u8, u8) = (163, 162, 165, 255);
+
+/// `merge_boxes`, logging what it did.
+fn merge_box_parts(parts: Vec<PartRecord>) -> Vec<PartRecord> {
+    let before = parts.len();
+    let (parts, merged) = merge_boxes(parts);
+    let boxes = parts.len() + merged - before;
+    log::info!(
+        "merged {} parts into {} boxes ({} fewer triangles)",
+        merged,
+        boxes,
+        (merged - boxes) * 12
+    );
+    parts
+}
+
+/// Runs `opts.postprocessors` over a part's mesh, in order.
+fn post_process(opts: &ExportOptions, part: &PartRecord, mesh: Mesh) -> Mesh {
+    opts.postprocessors.iter().fold(mesh, |mesh, processor| {
+        processor.process(&part.name, &part.class, mesh)
+    })
+}
+
+/// Meshes every part in world space, then drops and reorders faces and
+/// parts as the culling and sorting flags ask. `parts` ends up in the same
+/// order as the meshes.
+fn world_meshes(
+    opts: &ExportOptions,
+    parts: &mut Vec<PartRecord>,
+    tessellation: Tessellation,
+    timings: &mut Timings,
+) -> Vec<Mesh> {
+    // Meshing and transforming dominates on big places, so it runs in
+    // parallel; writing stays sequential to keep the output deterministic.
+    let started = Instant::now();
+    let (mut meshes, cpu): (Vec<_>, Vec<_>) = parts
+        .par_iter()
+        .map(|part| {
+            let started = Instant::now();
+            let mesh = post_process(opts, part, local_mesh(part, opts.truss_style, tessellation));
+            (mesh, started.elapsed())
+        })
+        .unzip();
+    timings.add("mesh generation", started.elapsed(), Some(cpu.iter().sum()));
+
+    let started = Instant::now();
+    let cpu: Duration = meshes
+        .par_iter_mut()
+        .zip(parts.par_iter())
+        .map(|((vertices, _), part)| {
+            let started = Instant::now();
+            transform_vertices(vertices, &part.cframe);
+            started.elapsed()
+        })
+        .sum();
+    timings.add("transformation", started.elapsed(), Some(cpu));
+
+    let started = Instant::now();
+    if let Some(epsilon) = opts.remove_hidden_faces {
+        let removed = remove_hidden_faces(parts, &mut meshes, epsilon);
+        log::info!("removed {} hidden faces", removed);
+    }
+
+    if opts.omit_interior {
+        let omitted = omit_interior_faces(parts, &mut meshes);
+        log::info!("omitted {} interior faces", omitted);
+    }
+    if opts.remove_hidden_faces.is_some() || opts.omit_interior {
+        timings.add("face culling", started.elapsed(), None);
+    }
+
+    if opts.spatial_sort {
+        sort_spatially(parts, &mut meshes);
+    }
+    meshes
+}
+
+/// Where `--format json` writes, in place of the OBJ.
+fn geometry_path(output: &Path, opts: &ExportOptions) -> PathBuf {
+    output.with_extension(match opts.format {
+        OutputFormat::Bobj => "bobj",
+        _ if opts.json_binary => "cbor",
+        _ => "json",
+    })
+}
+
+/// `--format json` and `--format bobj`: collects, meshes and culls the scene
+/// the same way the OBJ export does, then writes it as one JSON (or CBOR)
+/// document, or as a `.bobj` and the MTL its material names refer to.
+fn export_geometry(
+    opts: &ExportOptions,
+    path: &Path,
+    output: &Path,
+    dom: &WeakDom,
+    cancel: Option<&CancellationToken>,
+    run_started: Instant,
+    mut timings: Timings,
+) -> Result<ExportReport, Box<dyn Error>> {
+    let (top_level, skipped_services) = select_top_level(dom, opts);
+    let mut scene = new_scene(opts, &skipped_services);
+    let mut parts = Vec::new();
+    let started = Instant::now();
+    collect_scene(dom, &top_level, opts, &mut scene, cancel, &mut |part| {
+        parts.push(part)
+    })?;
+    timings.add("traversal", started.elapsed(), None);
+
+    let sketch = match opts.preview {
+        Some(size) => preview::render(&parts, size),
+        None => Vec::new(),
+    };
+    if opts.merge_boxes {
+        parts = merge_box_parts(parts);
+    }
+    let detail = opts.max_triangles.map_or(Ok(0), |budget| {
+        fit_triangle_budget(opts, &mut parts, budget)
+    })?;
+    let tessellation = Tessellation::for_lod(detail).resampled(opts.resample_size);
+    let meshes = world_meshes(opts, &mut parts, tessellation, &mut timings);
+
+    let started = Instant::now();
+    let mut geometry = Vec::new();
+    for (pass, mirror) in mirror_passes(opts).into_iter().enumerate() {
+        // An odd number of mirrored axes turns the mesh inside out.
+        let flip_winding = mirror.iter().filter(|&&m| m).count() % 2 == 1;
+        for (part, (vertices, faces)) in parts.iter().zip(&meshes) {
+            if let Some(cancel) = cancel {
+                cancel.check()?;
+            }
+            geometry.push(GeometryPart {
+                name: if pass > 0 {
+                    format!("{}_mirror", part.name)
+                } else {
+                    part.name.clone()
+                },
+                class: &part.class,
+                vertices: vertices.iter().map(|&v| mirror_point(v, mirror)).collect(),
+                faces: {
+                    let mut faces: Vec<Face> = faces
+                        .iter()
+                        .map(|&(a, b, c)| if flip_winding { (a, c, b) } else { (a, b, c) })
+                        .collect();
+                    if is_double_sided(opts, part) {
+                        add_back_faces(&mut faces);
+                    }
+                    faces
+                },
+                color: part.color,
+                material: MaterialKey::of(part).name(),
+            });
+        }
+    }
+
+    let mut outputs = Vec::new();
+    if opts.format == OutputFormat::Bobj {
+        let mtl_path = output.with_extension("mtl");
+        let mut mtl = create_file(&mtl_path)?;
+        let mut written = HashSet::new();
+        for part in &parts {
+            let key = MaterialKey::of(part);
+            if written.insert(key) {
+                write_material(&mut mtl, &key, &key.name(), opts.mtl_transparency)?;
+            }
+        }
+        mtl.flush()?;
+        outputs.push(mtl_path);
+    }
+    let output = geometry_path(output, opts);
+    let mut out = create_file(&output)?;
+    match opts.format {
+        OutputFormat::Bobj => write_bobj(&mut out, &geometry)?,
+        _ if opts.json_binary => write_cbor(&mut out, &geometry)?,
+        _ => write_json(&mut out, &geometry)?,
+    }
+    out.flush()?;
+    outputs.push(output);
+    timings.add("writing", started.elapsed(), None);
+
+    let mut parts_by_class = BTreeMap::new();
+    for part in &geometry {
+        *parts_by_class.entry(part.class.to_string()).or_default() += 1;
+    }
+    let vertices = geometry.iter().map(|part| part.vertices.len()).sum();
+    let faces = geometry.iter().map(|part| part.faces.len()).sum();
+    let materials = parts
+        .iter()
+        .map(MaterialKey::of)
+        .collect::<HashSet<_>>()
+        .len();
+    log_scene_notes(opts, dom, &scene, &skipped_services);
+
+    let mut report = finish_run(
+        opts,
+        &RunSummary {
+            input: path,
+            scene: &scene,
+            parts_by_class: &parts_by_class,
+            vertices,
+            faces,
+            materials,
+            outputs: &outputs,
+            elapsed: run_started.elapsed(),
+            dry_run: false,
+        },
+    )?;
+    report.errors = std::mem::take(&mut scene.errors);
+    report.phases = timings.phases;
+    report.preview = sketch;
+    Ok(report)
+}
+
+/// A scene to collect into, with `skipped_services` already warned about.
+fn new_scene(opts: &ExportOptions, skipped_services: &[String]) -> Scene {
+    let mut scene = Scene {
+        keep_warnings: opts.warnings_jsonl.is_some(),
+        fail_on: opts.fail_on.clone(),
+        callbacks: opts.callbacks.clone(),
+        ..Scene::default()
+    };
+    for service in skipped_services {
+        scene.warn(|| Warning {
+            code: WarningCode::SkippedService,
+            class: service.clone(),
+            path: service.clone(),
+            detail: "has parts but isn't in --services".to_string(),
+        });
+    }
+    scene
+}
+
+/// Logs what the traversal left out, once it's done.
+fn log_scene_notes(
+    opts: &ExportOptions,
+    dom: &WeakDom,
+    scene: &Scene,
+    skipped_services: &[String],
+) {
+    if scene.depth_limited > 0 {
+        log::warn!(
+            "max depth of {} reached, skipped the children of {} instances (use --max-depth to go deeper)",
+            opts.max_depth,
+            scene.depth_limited
+        );
+    }
+
+    if let Some(&parent) = scene.broken_children.first() {
+        let path = instance_path(dom, parent);
+        log::warn!(
+            "skipped {} children missing from the file or already visited (e.g. under {}), the file may be corrupt",
+            scene.broken_children.len(),
+            if path.is_empty() {
+                "the top level"
+            } else {
+                &path
+            }
+        );
+    }
+
+    if opts.print_unknown_classes {
+        for (class, count) in &scene.unknown_classes {
+            log::info!("unknown class {}: {}", class, count);
+        }
+    }
+
+    for (kind, (count, examples)) in &scene.unsupported {
+        log::warn!(
+            "{} {} (e.g. {})",
+            count,
+            kind.describe(),
+            instance_path(dom, examples[0])
+        );
+    }
+
+    if !scene.pruned.is_empty() {
+        let pruned: Vec<String> = scene
+            .pruned
+            .iter()
+            .map(|(class, count)| format!("{} {}", count, class))
+            .collect();
+        log::info!("pruned (with descendants): {}", pruned.join(", "));
+    }
+
+    if !skipped_services.is_empty() {
+        log::warn!(
+            "skipped services containing geometry: {} (use --services or --all-services to include them)",
+            skipped_services.join(", ")
+        );
+    }
+}
+
+/// Makes the same decisions as an export and prints the summary it would
+/// end with. Geometry is counted from each part's shape rather than meshed,
+/// so it's what a plain export writes, before welding, culling or merging.
+fn dry_run(
+    opts: &ExportOptions,
+    path: &Path,
+    output: &Path,
+    dom: &WeakDom,
+    run_started: Instant,
+) -> Result<ExportReport, Box<dyn Error>> {
+    let (top_level, skipped_services) = select_top_level(dom, opts);
+    let mut scene = new_scene(opts, &skipped_services);
+    let mut parts = Vec::new();
+    collect_scene(dom, &top_level, opts, &mut scene, None, &mut |part| {
+        parts.push(part)
+    })?;
+    log_scene_notes(opts, dom, &scene, &skipped_services);
+    let detail = opts.max_triangles.map_or(Ok(0), |budget| {
+        fit_triangle_budget(opts, &mut parts, budget)
+    })?;
+
+    let passes = if opts.mirror_and_append { 2 } else { 1 };
+    let mut parts_by_class = BTreeMap::new();
+    let (mut vertices, mut faces) = (0, 0);
+    let mut obj_paths = Vec::new();
+    for level in 0..opts.lods.unwrap_or(1) {
+        let tessellation = Tessellation::for_lod(level + detail).resampled(opts.resample_size);
+        let min_size = lod_min_part_size(level);
+        let (mut level_vertices, mut level_faces) = (0, 0);
+        for part in &parts {
+            if part.size.x.max(part.size.y).max(part.size.z) < min_size {
+                continue;
+            }
+            let (v, f) = mesh_counts(part, opts.truss_style, tessellation);
+            let sides = if is_double_sided(opts, part) { 2 } else { 1 };
+            level_vertices += v * passes;
+            level_faces += f * passes * sides;
+            *parts_by_class.entry(part.class.clone()).or_default() += passes;
+        }
+        vertices += level_vertices;
+        faces += level_faces;
+
+        let obj_path = if opts.lods.is_some() {
+            lod_path(output, level)
+        } else {
+            output.to_path_buf()
+        };
+        // The split only happens between parts, so this can be one short.
+        let files = opts
+            .max_vertices_per_file
+            .map_or(1, |max| level_vertices.div_ceil(max).max(1));
+        let stem = obj_path.file_stem().unwrap().to_string_lossy().to_string();
+        obj_paths.push(obj_path.clone());
+        obj_paths.extend(
+            (2..=files).map(|n| obj_path.with_file_name(format!("{}_part{}.obj", stem, n))),
+        );
+    }
+
+    let mut outputs = vec![mtl_path(output, opts)];
+    outputs.extend(opts.part_info_csv.iter().cloned());
+    if opts.bake_material_id_texture {
+        outputs.push(palette_path(output));
+    }
+    outputs.extend(obj_paths);
+    if opts.blender_materials {
+        outputs.push(blender_materials_path(output));
+    }
+    if opts.lods.is_some() {
+        outputs.push(lod_manifest_path(output));
+    }
+    if opts.format == OutputFormat::Mitsuba {
+        outputs.push(output.with_extension("xml"));
+    }
+
+    match opts.format {
+        OutputFormat::Json => outputs = vec![geometry_path(output, opts)],
+        OutputFormat::Bobj => {
+            outputs = vec![output.with_extension("mtl"), geometry_path(output, opts)]
+        }
+        _ => {}
+    }
+
+    let materials: HashSet<_> = parts.iter().map(MaterialKey::of).collect();
+    let mut report = finish_run(
+        opts,
+        &RunSummary {
+            input: path,
+            scene: &scene,
+            parts_by_class: &parts_by_class,
+            vertices,
+            faces,
+            materials: materials.len(),
+            outputs: &outputs,
+            elapsed: run_started.elapsed(),
+            dry_run: true,
+        },
+    )?;
+    report.errors = std::mem::take(&mut scene.errors);
+    Ok(report)
+}
+
+/// The materials an export would create, most used first.
+fn list_materials(
+    opts: &ExportOptions,
+    dom: &WeakDom,
+) -> Result<Vec<MaterialUsage>, Box<dyn Error>> {
+    let (top_level, skipped_services) = select_top_level(dom, opts);
+    let mut scene = new_scene(opts, &skipped_services);
+    let mut parts = Vec::new();
+    collect_scene(dom, &top_level, opts, &mut scene, None, &mut |part| {
+        parts.push(part)
+    })?;
+
+    let passes = if opts.mirror_and_append { 2 } else { 1 };
+    let tessellation = Tessellation::default().resampled(opts.resample_size);
+    let mut material_map: HashMap<MaterialKey, MaterialUsage> = HashMap::new();
+    for part in &parts {
+        let key = MaterialKey::of(part);
+        let usage = material_map.entry(key).or_insert_with(|| MaterialUsage {
+            name: key.name(),
+            rgba: (key.r, key.g, key.b, key.a),
+            parts: 0,
+            faces: 0,
+        });
+        usage.parts += passes;
+        let sides = if is_double_sided(opts, part) { 2 } else { 1 };
+        usage.faces += mesh_counts(part, opts.truss_style, tessellation).1 * passes * sides;
+    }
+    let mut materials: Vec<_> = material_map.into_values().collect();
+    materials.sort_by(|a, b| {
+        (b.parts, b.faces)
+            .cmp(&(a.parts, a.faces))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(materials)
+}
+
+/// Time spent in each phase of the export, in the order they first ran.
+/// Phases that run once per LOD level add up.
+#[derive(Default)]
+struct Timings {
+    phases: Vec<PhaseTiming>,
+}
+
+impl Timings {
+    fn add(&mut self, phase: &'static str, wall: Duration, cpu: Option<Duration>) {
+        match self.phases.iter_mut().find(|timing| timing.name == phase) {
+            Some(timing) => {
+                timing.wall += wall;
+                if let (Some(total_cpu), Some(cpu)) = (&mut timing.cpu, cpu) {
+                    *total_cpu += cpu;
+                }
+            }
+            None => self.phases.push(PhaseTiming {
+                name: phase,
+                wall,
+                cpu,
+            }),
+        }
+    }
+}
+
+/// Writes the lines every OBJ starts with: a comment, the Lighting settings
+/// and the `mtllib` reference.
+fn write_obj_header(
+    obj: &mut dyn Write,
+    dom: &WeakDom,
+    opts: &ExportOptions,
+    mtl_name: &str,
+) -> Result<(), Box<dyn Error>> {
+    writeln!(obj, "# Exported from Roblox place")?;
+    if !opts.no_lighting {
+        export_lighting_comment(dom, obj)?;
+    }
+    writeln!(obj, "mtllib {}", mtl_name)?;
+    Ok(())
+}
+
+/// `max_triangles`: lowers the sphere and cylinder detail a level at a time,
+/// then drops the smallest parts, until `mesh_counts` says what's left fits
+/// in `budget` triangles, logging each step. Returns the detail level to
+/// mesh at, as for `Tessellation::for_lod`, or `Unsupported` if not even the
+/// largest part fits.
+fn fit_triangle_budget(
+    opts: &ExportOptions,
+    parts: &mut Vec<PartRecord>,
+    budget: usize,
+) -> Result<usize, ExportError> {
+    let passes = if opts.mirror_and_append { 2 } else { 1 };
+    let triangles = |part: &PartRecord, detail: usize| {
+        let tessellation = Tessellation::for_lod(detail).resampled(opts.resample_size);
+        let sides = if is_double_sided(opts, part) { 2 } else { 1 };
+        mesh_counts(part, opts.truss_style, tessellation).1 * passes * sides
+    };
+    let estimate = |parts: &[PartRecord], detail: usize| -> usize {
+        parts.iter().map(|part| triangles(part, detail)).sum()
+    };
+
+    let mut detail = 0;
+    let mut total = estimate(parts, detail);
+    if total <= budget {
+        log::info!("{} triangles, within the budget of {}", total, budget);
+        return Ok(detail);
+    }
+    while total > budget && detail < Tessellation::COARSEST_LOD {
+        detail += 1;
+        total = estimate(parts, detail);
+        log::info!(
+            "lowered sphere and cylinder detail to LOD level {}: {} triangles",
+            detail,
+            total
+        );
+    }
+    if total > budget {
+        // Smallest first, the sort keeping ties in file order, so the same
+        // input and budget always drop the same parts.
+        let longest_side = |part: &PartRecord| part.size.x.max(part.size.y).max(part.size.z);
+        let mut order: Vec<usize> = (0..parts.len()).collect();
+        order.sort_by(|&a, &b| longest_side(&parts[a]).total_cmp(&longest_side(&parts[b])));
+        let mut dropped = vec![false; parts.len()];
+        let (mut count, mut largest) = (0, 0.0f32);
+        for &i in &order {
+            if total <= budget {
+                break;
+            }
+            total -= triangles(&parts[i], detail);
+            dropped[i] = true;
+            count += 1;
+            largest = longest_side(&parts[i]);
+        }
+        if count == parts.len() {
+            let biggest = &parts[*order.last().unwrap()];
+            return Err(ExportError::Unsupported(format!(
+                "a budget of {} triangles doesn't fit any part; the largest takes {}",
+                budget,
+                triangles(biggest, detail)
+            )));
+        }
+        let mut index = 0;
+        parts.retain(|_| {
+            index += 1;
+            !dropped[index - 1]
+        });
+        log::info!(
+            "dropped the {} smallest parts, up to {} studs long: {} triangles",
+            count,
+            largest,
+            total
+        );
+    }
+    Ok(detail)
+}
+
+/// Parts whose largest dimension is below this (in studs) are left out of a
+/// LOD level: nothing at level 0, then 1, 2, 4, ...
+fn lod_min_part_size(level: usize) -> f32 {
+    match level {
+        0 => 0.0,
+        _ => 2f32.powi(level as i32 - 1),
+    }
+}
+
+/// Suggested camera distance (in studs) at which to switch to a LOD level.
+fn lod_switch_distance(level: usize) -> f32 {
+    match level {
+        0 => 0.0,
+        _ => 200.0 * 2f32.powi(level as i32 - 1),
+    }
+}
+
+/// How big the scene looks, in degrees across, from a LOD level's switch
+/// distance: the level is meant for when it looks smaller than that.
+/// Level 0 is for any size.
+fn lod_screen_size(level: usize, scene_radius: f32) -> f32 {
+    match level {
+        0 => 180.0,
+        _ => {
+            2.0 * (scene_radius / lod_switch_distance(level))
+                .atan()
+                .to_degrees()
+        }
+    }
+}
+
+/// Half the diagonal of the box around every mesh, or 0 without any.
+fn bounding_radius(meshes: &[Mesh]) -> f32 {
+    let corners: Vec<Vector3> = meshes
+        .iter()
+        .filter(|(vertices, _)| !vertices.is_empty())
+        .flat_map(|(vertices, _)| {
+            let bounds = bvh::Aabb::from_points(vertices);
+            [bounds.min, bounds.max]
+        })
+        .collect();
+    if corners.is_empty() {
+        return 0.0;
+    }
+    let bounds = bvh::Aabb::from_points(&corners);
+    let diagonal = sub(bounds.max, bounds.min);
+    dot(diagonal, diagonal).sqrt() / 2.0
+}
+
+fn lod_path(output: &Path, level: usize) -> PathBuf {
+    let stem = output.file_stem().unwrap().to_string_lossy();
+    output.with_file_name(format!("{}_lod{}.obj", stem, level))
+}
+
+/// Where the MTL for `output` goes: beside it, or in `mtl_dir`.
+pub(crate) fn mtl_path(output: &Path, opts: &ExportOptions) -> PathBuf {
+    let beside = output.with_extension("mtl");
+    match &opts.mtl_dir {
+        Some(dir) => dir.join(beside.file_name().unwrap()),
+        None => beside,
+    }
+}
+
+/// What the `mtllib` line of the OBJ at `obj_path` calls the MTL at
+/// `mtl_path`, with forward slashes whatever the platform uses. A relative
+/// reference between Windows drives can't be had, so that's absolute too.
+fn mtllib_reference(obj_path: &Path, mtl_path: &Path, style: MtllibStyle) -> io::Result<String> {
+    let reference = match style {
+        MtllibStyle::Filename => PathBuf::from(mtl_path.file_name().unwrap()),
+        MtllibStyle::RelativeToObj => {
+            let mtl_path = lexically_normal(&path::absolute(mtl_path)?);
+            let obj_dir = lexically_normal(&path::absolute(obj_path)?)
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            relative_path(&obj_dir, &mtl_path).unwrap_or(mtl_path)
+        }
+        MtllibStyle::Absolute => lexically_normal(&path::absolute(mtl_path)?),
+    };
+    Ok(reference
+        .to_string_lossy()
+        .replace(path::MAIN_SEPARATOR, "/"))
+}
+
+/// `path` with its `.` components dropped and each `..` taking the name
+/// before it with it, without looking at the file system.
+fn lexically_normal(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normal.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normal.pop();
+                }
+                // `..` at the root is the root.
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normal.push(component),
+            },
+            _ => normal.push(component),
+        }
+    }
+    normal
+}
+
+/// The path that leads from the directory `from` to `to`, both absolute,
+/// or `None` if they're on different drives.
+fn relative_path(from: &Path, to: &Path) -> Option<PathBuf> {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    if from.first() != to.first() {
+        return None;
+    }
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    relative.extend(&to[common..]);
+    Some(relative)
+}
+
+fn palette_path(output: &Path) -> PathBuf {
+    let stem = output.file_stem().unwrap().to_string_lossy();
+    output.with_file_name(format!("{}_palette.png", stem))
+}
+
+fn blender_materials_path(output: &Path) -> PathBuf {
+    let stem = output.file_stem().unwrap().to_string_lossy();
+    output.with_file_name(format!("{}_materials.py", stem))
+}
+
+/// Writes `<stem>_lods.json` describing each written level.
+fn lod_manifest_path(output: &Path) -> PathBuf {
+    let stem = output.file_stem().unwrap().to_string_lossy();
+    output.with_file_name(format!("{}_lods.json", stem))
+}
+
+fn write_lod_manifest(
+    output: &Path,
+    levels: &[(String, usize)],
+    scene_radius: f32,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let json_path = lod_manifest_path(output);
+    let mut json = create_file(&json_path)?;
+
+    writeln!(json, "{{")?;
+    writeln!(json, "  \"levels\": [")?;
+    for (level, (file, triangles)) in levels.iter().enumerate() {
+        writeln!(json, "    {{")?;
+        writeln!(json, "      \"level\": {},", level)?;
+        writeln!(json, "      \"file\": \"{}\",", json_escape(file))?;
+        writeln!(json, "      \"triangles\": {},", triangles)?;
+        writeln!(
+            json,
+            "      \"min_part_size\": {},",
+            lod_min_part_size(level)
+        )?;
+        writeln!(
+            json,
+            "      \"switch_distance\": {},",
+            lod_switch_distance(level)
+        )?;
+        writeln!(
+            json,
+            "      \"screen_size\": {}",
+            lod_screen_size(level, scene_radius)
+        )?;
+        let comma = if level + 1 < levels.len() { "," } else { "" };
+        writeln!(json, "    }}{}", comma)?;
+    }
+    writeln!(json, "  ]")?;
+    writeln!(json, "}}")?;
+    json.flush()?;
+    Ok(json_path)
+}
+
+/// `object_names`: the part's path, with `_2`, `_3` and so on added when an
+/// earlier part took it already, as parts in the same model often share a
+/// name. Merged boxes and the like go by their own name.
+fn unique_object_name(
+    taken: &mut HashSet<String>,
+    part: &PartRecord,
+    opts: &ExportOptions,
+    suffix: &str,
+) -> String {
+    let mut base = match &part.object_name {
+        Some(name) => name.clone(),
+        None => object_name_component(&part.name, opts.name_separator),
+    };
+    base.push_str(suffix);
+    let mut name = base.clone();
+    let mut n = 1;
+    while taken.contains(&name) {
+        n += 1;
+        name = format!("{}_{}", base, n);
+    }
+    taken.insert(name.clone());
+    name
+}
+
+fn write_part(
+    part: &PartRecord,
+    (vertices, faces): &Mesh,
+    opts: &ExportOptions,
+    obj: &mut dyn Write,
+    mtl: &mut dyn Write,
+    state: &mut ExportState,
+) -> Result<(), Box<dyn Error>> {
+    let (r, g, b, a) = part.color;
+    let material_count = state.material_map.len();
+    let (material_number, mat_name) = match state.material_map.entry(MaterialKey::of(part)) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            let name = entry.key().name();
+            write_material(mtl, entry.key(), &name, opts.mtl_transparency)?;
+            entry.insert((material_count, name))
+        }
+    };
+
+    // Reuse the previous part's buffers instead of allocating new ones.
+    let mut mirrored_vertices = std::mem::take(&mut state.scratch_vertices);
+    mirrored_vertices.clear();
+    mirrored_vertices.extend(vertices.iter().map(|&v| mirror_point(v, state.mirror)));
+    let vertices = mirrored_vertices;
+
+    // An odd number of mirrored axes turns the mesh inside out.
+    let flip_winding = state.mirror.iter().filter(|&&m| m).count() % 2 == 1;
+    let mut oriented_faces = std::mem::take(&mut state.scratch_faces);
+    oriented_faces.clear();
+    oriented_faces.extend(
+        faces
+            .iter()
+            .map(|&(a, b, c)| if flip_winding { (a, c, b) } else { (a, b, c) }),
+    );
+    let front = oriented_faces.len();
+    if opts.wireframe != Wireframe::Edges && is_double_sided(opts, part) {
+        add_back_faces(&mut oriented_faces);
+    }
+    let faces = oriented_faces;
+
+    let group = match (opts.group_anchored, part.anchored) {
+        (false, _) => None,
+        (true, true) => Some("anchored_geometry"),
+        (true, false) => Some("dynamic_props"),
+    };
+
+    let mut notes = String::new();
+    if let Some(id) = part.unique_id {
+        notes.push_str(&format!("# UniqueId: {}\n", id));
+    }
+    if opts.annotate_faces {
+        notes.push_str(&format!("# anchored: {}\n", part.anchored));
+    }
+
+    let welding = state.welder.is_some();
+    if !welding {
+        if let Some(name) = group
+            && group != state.current_group
+        {
+            writeln!(obj, "g {}", name)?;
+            state.current_group = group;
+        }
+        if opts.object_names {
+            let name = unique_object_name(&mut state.object_names, part, opts, state.name_suffix);
+            writeln!(obj, "o {}", name)?;
+        }
+        obj.write_all(notes.as_bytes())?;
+    }
+    let buf = &mut state.line_buffer;
+    buf.clear();
+    // Welded vertices only get written once they're all merged, at the end.
+    if !welding {
+        for &v in vertices.iter() {
+            push_vertex(buf, v);
+        }
+    }
+    let frame = PartFrame::new(part, state.mirror);
+    let mut normals = std::mem::take(&mut state.scratch_normals);
+    normals.clear();
+    let written = if opts.wireframe == Wireframe::Edges {
+        None
+    } else if !opts.no_normals {
+        Some(push_normals(
+            buf,
+            &frame,
+            &vertices,
+            &faces,
+            front,
+            opts,
+            &mut normals,
+        ))
+    } else if part.class == "WedgePart" {
+        push_wedge_normals(buf, part, &vertices, &faces, state.mirror);
+        normals.extend((0..faces.len()).map(|i| [i; 3]));
+        Some(faces.len())
+    } else {
+        None
+    };
+    for corners in &mut normals {
+        *corners = corners.map(|n| n + state.normal_offset);
+    }
+    state.normal_offset += written.unwrap_or(0);
+
+    // Welded vertices are shared between parts, so there's no vertex range to report.
+    let (vertex_range, face_count) = match state.welder.as_mut() {
+        Some(welder) => {
+            // Normals aren't merged, so they go out right away.
+            obj.write_all(buf.as_bytes())?;
+            let count = welder.add(
+                MaterialKey::of(part),
+                &vertices,
+                WeldGroup {
+                    group,
+                    material: mat_name.clone(),
+                    notes,
+                    faces: faces.clone(),
+                    normals: written.map(|_| normals.clone()),
+                },
+            );
+            state.scratch_normals = normals;
+            (None, count)
+        }
+        None => {
+            let mut texcoords = std::mem::take(&mut state.scratch_texcoords);
+            texcoords.clear();
+            let material = match &state.palette {
+                // A part has the one material, so all its corners share a `vt`.
+                Some(palette) => {
+                    let (u, v) = palette.texcoord(*material_number);
+                    buf.push_str("vt ");
+                    push_float(buf, u);
+                    buf.push(' ');
+                    push_float(buf, v);
+                    buf.push('\n');
+                    texcoords.resize(faces.len(), [state.texcoord_offset; 3]);
+                    state.texcoord_offset += 1;
+                    PALETTE_MATERIAL
+                }
+                None => {
+                    if let Some(studs) = opts.uv_studs_per_tile
+                        && opts.wireframe != Wireframe::Edges
+                    {
+                        // Back faces show the same texture as their front.
+                        let count = push_texcoords(
+                            buf,
+                            &frame,
+                            studs,
+                            &vertices,
+                            &faces[..front],
+                            &mut texcoords,
+                        );
+                        for i in 0..faces.len() - front {
+                            let [a, b, c] = texcoords[i];
+                            texcoords.push([a, c, b]);
+                        }
+                        for corners in &mut texcoords {
+                            *corners = corners.map(|t| t + state.texcoord_offset);
+                        }
+                        state.texcoord_offset += count;
+                    }
+                    mat_name.as_str()
+                }
+            };
+            push_geometry(
+                buf,
+                material,
+                &faces,
+                state.vertex_offset,
+                written.map(|_| normals.as_slice()),
+                (!texcoords.is_empty()).then_some(texcoords.as_slice()),
+                opts.wireframe,
+            );
+            obj.write_all(buf.as_bytes())?;
+            state.scratch_normals = normals;
+            state.scratch_texcoords = texcoords;
+            let range = (state.vertex_offset + 1, vertices.len());
+            state.vertex_offset += vertices.len();
+            (Some(range), faces.len())
+        }
+    };
+    let face_count = if opts.wireframe == Wireframe::Edges {
+        0
+    } else {
+        face_count
+    };
+
+    if let Some(csv) = state.part_info.as_mut() {
+        let cframe = &part.cframe;
+        let m = &cframe.orientation;
+        writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            csv_field(&format!("{}{}", part.name, state.name_suffix)),
+            part.class,
+            cframe.position.x,
+            cframe.position.y,
+            cframe.position.z,
+            part.size.x,
+            part.size.y,
+            part.size.z,
+            m.x.x,
+            m.x.y,
+            m.x.z,
+            m.y.x,
+            m.y.y,
+            m.y.z,
+            m.z.x,
+            m.z.y,
+            m.z.z,
+            r,
+            g,
+            b,
+            a,
+            mat_name,
+            match vertex_range {
+                Some((start, count)) => format!("{},{}", start, count),
+                None => ",".to_string(),
+            },
+            state.face_offset + 1,
+            face_count,
+            part.unique_id.map_or_else(String::new, |id| id.to_string())
+        )?;
+    }
+
+    state.face_offset += face_count;
+    state.parts_written += 1;
+    match state.parts_by_class.get_mut(&part.class) {
+        Some(count) => *count += 1,
+        None => {
+            state.parts_by_class.insert(part.class.clone(), 1);
+        }
+    }
+    state.vertices_written += vertices.len();
+    state.faces_written += face_count;
+    state.scratch_vertices = vertices;
+    state.scratch_faces = faces;
+
+    Ok(())
+}
+
+/// Writes the MTL entry for `key`, called `name`.
+/// Opaque materials leave out `d` and `Tr`, which default to opaque,
+/// unless `transparency` asks for both.
+fn write_material(
+    mtl: &mut dyn Write,
+    key: &MaterialKey,
+    name: &str,
+    transparency: MtlTransparency,
+) -> io::Result<()> {
+    let [r, g, b, a] = [key.r, key.g, key.b, key.a].map(|channel| channel as f32 / 255.0);
+    writeln!(mtl, "newmtl {}", name)?;
+    writeln!(mtl, "Kd {} {} {}", r, g, b)?;
+    if key.a < 255 || transparency == MtlTransparency::Both {
+        if transparency != MtlTransparency::Tr {
+            writeln!(mtl, "d {}", a)?;
+        }
+        if transparency != MtlTransparency::D {
+            writeln!(mtl, "Tr {}", 1.0 - a)?;
+        }
+    }
+    writeln!(mtl)
+}
+
+/// Parts share an MTL material when their color and `Material` match.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct MaterialKey {
+    r: u8,
+    g: u8,
+    b: u8,
+    a: u8,
+    /// `Enum.Material` value.
+    material_enum: u32,
+}
+
+impl MaterialKey {
+    fn of(part: &PartRecord) -> Self {
+        let (r, g, b, a) = part.color;
+        MaterialKey {
+            r,
+            g,
+            b,
+            a,
+            material_enum: part.material,
+        }
+    }
+
+    /// The MTL name, the `Material` then the color and alpha in hex, like
+    /// `Metal_C44000_FF`.
+    fn name(&self) -> String {
+        format!(
+            "{}_{:02X}{:02X}{:02X}_{:02X}",
+            roblox_material_name(self.material_enum),
+            self.r,
+            self.g,
+            self.b,
+            self.a
+        )
+    }
+}
+
+type WeldKey = (Option<MaterialKey>, [i64; 3]);
+pub(crate) type Face = (usize, usize, usize);
+
+struct WeldGroup {
+    group: Option<&'static str>,
+    material: String,
+    /// The part's `# UniqueId` and `--annotate-faces` comments.
+    notes: String,
+    faces: Vec<Face>,
+    /// A normal index per corner of `faces`, unless the part has none.
+    normals: Option<Vec<[usize; 3]>>,
+}
+
+/// The OBJ output, which moves on to `<stem>_partK.obj` whenever the next
+/// part would push the current file past the vertex limit.
+struct ObjFiles<'a> {
+    /// Where the first file went, or `None` when writing into the caller's
+    /// buffer, which never gets split.
+    base_path: Option<PathBuf>,
+    mtl_name: String,
+    max_vertices: Option<usize>,
+    writer: Box<dyn Write + 'a>,
+    paths: Vec<PathBuf>,
+}
+
+impl<'a> ObjFiles<'a> {
+    fn create(
+        path: PathBuf,
+        mtl_name: String,
+        max_vertices: Option<usize>,
+    ) -> Result<Self, ExportError> {
+        Ok(ObjFiles {
+            writer: Box::new(create_file(&path)?),
+            paths: vec![path.clone()],
+            base_path: Some(path),
+            mtl_name,
+            max_vertices,
+        })
+    }
+
+    /// Writes everything into `writer` instead of files, which ends up at
+    /// `path` if it's given.
+    fn buffer(writer: &'a mut dyn Write, path: Option<PathBuf>, mtl_name: String) -> Self {
+        ObjFiles {
+            writer: Box::new(writer),
+            paths: path.into_iter().collect(),
+            base_path: None,
+            mtl_name,
+            max_vertices: None,
+        }
+    }
+
+    /// Makes room for `count` more vertices, starting a new file (and
+    /// restarting the indices) if they wouldn't fit in the current one.
+    /// Something bigger than the limit on its own still goes in one piece.
+    fn reserve(&mut self, count: usize, state: &mut ExportState) -> Result<(), ExportError> {
+        let (Some(max), Some(base_path)) = (self.max_vertices, &self.base_path) else {
+            return Ok(());
+        };
+        if state.vertex_offset == 0 || state.vertex_offset + count <= max {
+            return Ok(());
+        }
+
+        self.writer.flush()?;
+        let stem = base_path.file_stem().unwrap().to_string_lossy();
+        let path = base_path.with_file_name(format!("{}_part{}.obj", stem, self.paths.len() + 1));
+        self.writer = Box::new(create_file(&path)?);
+        self.paths.push(path);
+
+        writeln!(self.writer, "# Exported from Roblox place")?;
+        writeln!(self.writer, "mtllib {}", self.mtl_name)?;
+        state.vertex_offset = 0;
+        state.normal_offset = 0;
+        state.texcoord_offset = 0;
+        state.current_group = None;
+        Ok(())
+    }
+
+    /// Flushes the last file and returns every file written, in order.
+    fn finish(&mut self) -> io::Result<Vec<PathBuf>> {
+        self.writer.flush()?;
+        Ok(std::mem::take(&mut self.paths))
+    }
+}
+
+impl Write for ObjFiles<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.writer.write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Merges vertices sharing a (quantized) position so abutting parts stop
+/// duplicating them. Faces can only be written once the vertex table is final,
+/// so the whole scene's faces are held in memory until `finish`.
+struct Welder {
+    epsilon: f32,
+    per_material: bool,
+    lookup: HashMap<WeldKey, usize>,
+    vertices: Vec<Vector3>,
+    groups: Vec<WeldGroup>,
+}
+
+impl Welder {
+    fn new(epsilon: f32, per_material: bool) -> Self {
+        Welder {
+            epsilon,
+            per_material,
+            lookup: HashMap::new(),
+            vertices: Vec::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    fn quantize(&self, v: Vector3) -> [i64; 3] {
+        quantize(v, self.epsilon)
+    }
+
+    /// Adds a part's geometry, with `part.faces` indexing into `vertices`,
+    /// and returns how many of its faces survived; faces whose corners got
+    /// welded together are dropped.
+    fn add(
+        &mut self,
+        material_key: MaterialKey,
+        vertices: &[Vector3],
+        mut part: WeldGroup,
+    ) -> usize {
+        let scope = if self.per_material {
+            Some(material_key)
+        } else {
+            None
+        };
+        let remap: Vec<usize> = vertices
+            .iter()
+            .map(|&v| {
+                let key = (scope, self.quantize(v));
+                let welded = &mut self.vertices;
+                *self.lookup.entry(key).or_insert_with(|| {
+                    welded.push(v);
+                    welded.len() - 1
+                })
+            })
+            .collect();
+
+        let mut kept = 0;
+        for i in 0..part.faces.len() {
+            let (a, b, c) = part.faces[i];
+            let (a, b, c) = (remap[a], remap[b], remap[c]);
+            if a != b && b != c && a != c {
+                part.faces[kept] = (a, b, c);
+                if let Some(normals) = &mut part.normals {
+                    normals[kept] = normals[i];
+                }
+                kept += 1;
+            }
+        }
+        part.faces.truncate(kept);
+        if let Some(normals) = &mut part.normals {
+            normals.truncate(kept);
+        }
+        self.groups.push(part);
+        kept
+    }
+
+    /// Splits triangles at every T-junction and returns how many were added.
+    /// Splitting one edge can leave more junctions on its halves, so this
+    /// goes a few rounds.
+    fn fix_tjunctions(&mut self, epsilon: f32) -> usize {
+        let count = |groups: &[WeldGroup]| groups.iter().map(|g| g.faces.len()).sum::<usize>();
+        let before = count(&self.groups);
+        for _ in 0..TJUNCTION_ROUNDS {
+            let faces: Vec<Face> = self
+                .groups
+                .iter()
+                .flat_map(|g| g.faces.iter().copied())
+                .collect();
+            let junctions = find_tjunctions(&self.vertices, &faces, epsilon);
+            if junctions.is_empty() {
+                break;
+            }
+            for tj in &junctions {
+                for group in &mut self.groups {
+                    match &mut group.normals {
+                        Some(normals) => fix_tjunction_with_normals(&mut group.faces, normals, tj),
+                        None => fix_tjunction(&mut group.faces, tj),
+                    }
+                }
+            }
+        }
+        count(&self.groups) - before
+    }
+
+    fn finish(
+        self,
+        obj: &mut dyn Write,
+        vertex_offset: &mut usize,
+        wireframe: Wireframe,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut buf = String::new();
+        for &v in self.vertices.iter() {
+            push_vertex(&mut buf, v);
+            if buf.len() > FLUSH_THRESHOLD {
+                obj.write_all(buf.as_bytes())?;
+                buf.clear();
+            }
+        }
+        obj.write_all(buf.as_bytes())?;
+        buf.clear();
+
+        let mut current_group = None;
+        for group in self.groups.iter() {
+            if let Some(name) = group.group
+                && group.group != current_group
+            {
+                writeln!(obj, "g {}", name)?;
+                current_group = group.group;
+            }
+            buf.push_str(&group.notes);
+            push_geometry(
+                &mut buf,
+                &group.material,
+                &group.faces,
+                *vertex_offset,
+                group.normals.as_deref(),
+                None,
+                wireframe,
+            );
+            obj.write_all(buf.as_bytes())?;
+            buf.clear();
+        }
+        *vertex_offset += self.vertices.len();
+        Ok(())
+    }
+}
+
+/// Rounds of T-junction splitting before giving up on the rest. Each round
+/// can split every edge once, so this covers edges with thousands of
+/// vertices along them.
+const TJUNCTION_ROUNDS: usize = 12;
+
+/// How much formatted output to collect before handing it to the writer.
+pub(crate) const FLUSH_THRESHOLD: usize = 64 * 1024;
+
+/// Material the edges go into when they're written alongside the faces.
+const WIREFRAME_MATERIAL: &str = "wireframe_edges";
+
+/// The one material every part gets with `bake_material_id_texture`.
+const PALETTE_MATERIAL: &str = "palette";
+
+/// Appends the `usemtl` line and then the faces, edges or both for one run of
+/// faces sharing a material.
+fn push_geometry(
+    buf: &mut String,
+    material: &str,
+    faces: &[Face],
+    offset: usize,
+    normals: Option<&[[usize; 3]]>,
+    texcoords: Option<&[[usize; 3]]>,
+    wireframe: Wireframe,
+) {
+    buf.push_str("usemtl ");
+    buf.push_str(material);
+    buf.push('\n');
+
+    if wireframe != Wireframe::Edges {
+        for (i, &f) in faces.iter().enumerate() {
+            push_face(
+                buf,
+                f,
+                offset,
+                texcoords.map(|t| t[i]),
+                normals.map(|n| n[i]),
+            );
+        }
+    }
+    if wireframe != Wireframe::Off {
+        if wireframe == Wireframe::EdgesAndFaces {
+            buf.push_str("usemtl ");
+            buf.push_str(WIREFRAME_MATERIAL);
+            buf.push('\n');
+        }
+        let mut ints = itoa::Buffer::new();
+        for (a, b) in extract_edges(faces) {
+            buf.push_str("l ");
+            buf.push_str(ints.format(a + offset + 1));
+            buf.push(' ');
+            buf.push_str(ints.format(b + offset + 1));
+            buf.push('\n');
+        }
+    }
+}
+
+/// Lists every edge of the faces once, as `(min, max)` vertex index pairs in
+/// the order they first appear.
+fn extract_edges(faces: &[(usize, usize, usize)]) -> Vec<(usize, usize)> {
+    let mut seen = HashSet::new();
+    let mut edges = Vec::new();
+    for &(a, b, c) in faces {
+        for (from, to) in [(a, b), (b, c), (c, a)] {
+            let edge = (from.min(to), from.max(to));
+            if seen.insert(edge) {
+                edges.push(edge);
+            }
+        }
+    }
+    edges
+}
+
+/// Appends a `v x y z` line.
+pub(crate) fn push_vertex(buf: &mut String, v: Vector3) {
+    buf.push_str("v ");
+    push_float(buf, v.x);
+    buf.push(' ');
+    push_float(buf, v.y);
+    buf.push(' ');
+    push_float(buf, v.z);
+    buf.push('\n');
+}
+
+/// How far off a ball's or cylinder's curved surface, as a fraction of its
+/// radius squared, a vertex can be and still count as on it. Loose enough
+/// for the rounding of parts far from the origin.
+const SURFACE_TOLERANCE: f32 = 0.01;
+
+/// A part's own axes, for working out normals and texture coordinates for
+/// its vertices once they've been placed and mirrored.
+struct PartFrame<'a> {
+    part: &'a PartRecord,
+    to_local: CFrame,
+    half: Vector3,
+    /// `Some(true)` for a cylinder, `Some(false)` for a ball.
+    curved: Option<bool>,
+    mirror: [bool; 3],
+}
+
+impl<'a> PartFrame<'a> {
+    fn new(part: &'a PartRecord, mirror: [bool; 3]) -> Self {
+        PartFrame {
+            part,
+            to_local: cframe_inverse(&part.cframe),
+            half: Vector3::new(part.size.x / 2.0, part.size.y / 2.0, part.size.z / 2.0),
+            curved: match (part.class.as_str(), part.shape) {
+                ("Part", 0) => Some(false),
+                ("Part", 2) => Some(true),
+                _ => None,
+            },
+            mirror,
+        }
+    }
+
+    /// Where a written vertex is in the part's own space.
+    fn local(&self, v: Vector3) -> Vector3 {
+        apply_cframe(mirror_point(v, self.mirror), &self.to_local)
+    }
+
+    /// A local point scaled so a ball's surface, or a cylinder's side, is at
+    /// length 1. A cylinder's X is left out.
+    fn unit(&self, local: Vector3) -> Vector3 {
+        let x = match self.curved {
+            Some(true) => 0.0,
+            _ => local.x / self.half.x,
+        };
+        Vector3::new(x, local.y / self.half.y, local.z / self.half.z)
+    }
+
+    /// Whether a face with these local corners is part of a ball's surface
+    /// or a cylinder's side, rather than a cap, a surface detail or a flat
+    /// part. A cylinder's caps meet its side at the rim, so they're told
+    /// apart by which way they face.
+    fn is_curved(&self, corners: [Vector3; 3]) -> bool {
+        let Some(cylinder) = self.curved else {
+            return false;
+        };
+        if cylinder {
+            let facing = cross(sub(corners[1], corners[0]), sub(corners[2], corners[0]));
+            if facing.x.abs() >= 0.5 * dot(facing, facing).sqrt() {
+                return false;
+            }
+        }
+        corners.iter().all(|&local| {
+            let unit = self.unit(local);
+            (dot(unit, unit) - 1.0).abs() <= SURFACE_TOLERANCE
+        })
+    }
+
+    /// The way out of the curved surface at a local point on it, in the
+    /// written vertices' space. A ball is an ellipsoid and a cylinder
+    /// elliptic around X, so that's the point over each half size squared.
+    fn surface_normal(&self, local: Vector3) -> Vector3 {
+        let unit = self.unit(local);
+        let out = Vector3::new(
+            unit.x / self.half.x,
+            unit.y / self.half.y,
+            unit.z / self.half.z,
+        );
+        let world = apply_matrix3(&self.part.cframe.orientation, out);
+        mirror_point(normalize(world), self.mirror)
+    }
+}
+
+/// Appends `vn` lines for a part's faces, which have already been mirrored
+/// and flipped, and puts each face's corners' normals into `corners`,
+/// numbered from 0 in the order they're written. Returns how many there
+/// are. Faces from `front` on are the back sides `--double-sided` added,
+/// flipped copies of the ones before in the same order, and get their
+/// normals turned around.
+///
+/// With `Normals::Smooth`, faces on a ball's surface, or around a
+/// cylinder's side, get one normal per vertex pointing straight out of the
+/// surface so they shade smooth. Everything else, including surface details
+/// stuck on a ball, gets one normal per face, worked out from the triangle,
+/// as does every face with `Normals::Flat`. `Normals::Auto` leaves it to
+/// `crease_normals`.
+fn push_normals(
+    buf: &mut String,
+    frame: &PartFrame,
+    vertices: &[Vector3],
+    faces: &[Face],
+    front: usize,
+    opts: &ExportOptions,
+    corners: &mut Vec<[usize; 3]>,
+) -> usize {
+    let start = corners.len();
+    let mut normals = if opts.normals == Normals::Auto {
+        let (normals, numbers) = crease_normals(vertices, &faces[..front], opts.crease_angle);
+        corners.extend(numbers);
+        normals
+    } else {
+        let mut normals = Vec::new();
+        let mut push = |normal: Vector3| {
+            normals.push(normal);
+            normals.len() - 1
+        };
+        let mut smooth: Vec<Option<usize>> = vec![None; vertices.len()];
+        // Parts have a handful of distinct flat normals, so a list will do.
+        // Triangles of the same flat face come out a rounding error apart.
+        let mut flat_normals: Vec<(Vector3, usize)> = Vec::new();
+
+        for &(a, b, c) in &faces[..front] {
+            let local = [a, b, c].map(|index| frame.local(vertices[index]));
+            let face_corners = if opts.normals == Normals::Smooth && frame.is_curved(local) {
+                let mut numbers = [0; 3];
+                for (number, (index, local)) in
+                    numbers.iter_mut().zip([a, b, c].into_iter().zip(local))
+                {
+                    *number =
+                        *smooth[index].get_or_insert_with(|| push(frame.surface_normal(local)));
+                }
+                numbers
+            } else {
+                let facing = cross(sub(vertices[b], vertices[a]), sub(vertices[c], vertices[a]));
+                let flat = if dot(facing, facing) > 0.0 {
+                    normalize(facing)
+                } else {
+                    Vector3::new(0.0, 1.0, 0.0)
+                };
+                let number = match flat_normals
+                    .iter()
+                    .find(|(normal, _)| dot(*normal, flat) > 1.0 - 1e-5)
+                {
+                    Some(&(_, number)) => number,
+                    None => {
+                        let number = push(flat);
+                        flat_normals.push((flat, number));
+                        number
+                    }
+                };
+                [number; 3]
+            };
+            corners.push(face_corners);
+        }
+        normals
+    };
+
+    if faces.len() > front {
+        let mut flipped: Vec<Option<usize>> = vec![None; normals.len()];
+        for i in start..start + faces.len() - front {
+            let [a, b, c] = corners[i];
+            let back = [a, c, b].map(|number| {
+                *flipped[number].get_or_insert_with(|| {
+                    let n = normals[number];
+                    normals.push(Vector3::new(-n.x, -n.y, -n.z));
+                    normals.len() - 1
+                })
+            });
+            corners.push(back);
+        }
+    }
+
+    // Normals around different vertices often face the same way, like the
+    // groups `crease_normals` makes at each corner of a block's face.
+    let mut distinct: HashMap<[i32; 3], usize> = HashMap::new();
+    let renumbered: Vec<usize> = normals
+        .into_iter()
+        .map(|normal| {
+            let key = [normal.x, normal.y, normal.z].map(|c| (c * 1e5).round() as i32);
+            let count = distinct.len();
+            *distinct.entry(key).or_insert_with(|| {
+                buf.push_str("vn ");
+                push_float(buf, normal.x);
+                buf.push(' ');
+                push_float(buf, normal.y);
+                buf.push(' ');
+                push_float(buf, normal.z);
+                buf.push('\n');
+                count
+            })
+        })
+        .collect();
+    for face in &mut corners[start..] {
+        *face = face.map(|n| renumbered[n]);
+    }
+    distinct.len()
+}
+
+/// Appends `vt` lines for a part's faces and puts each face's corners'
+/// texture coordinates into `corners`, numbered from 0 in the order they're
+/// written. Returns how many there are. One unit is `studs` studs, the way
+/// Roblox tiles materials, measured in the part's own space so textures
+/// turn with it.
+///
+/// Flat faces are mapped straight onto their plane, with V running up the
+/// face (or towards the back, for faces pointing up or down). A cylinder's
+/// side is unrolled around X, with U around it and V along it. A ball is
+/// mapped by longitude and latitude, as measured around its middle.
+fn push_texcoords(
+    buf: &mut String,
+    frame: &PartFrame,
+    studs: f32,
+    vertices: &[Vector3],
+    faces: &[Face],
+    corners: &mut Vec<[usize; 3]>,
+) -> usize {
+    let half = frame.half;
+    let mut written: HashMap<(usize, u32, u32), usize> = HashMap::new();
+    for &(a, b, c) in faces {
+        let local = [a, b, c].map(|index| frame.local(vertices[index]));
+        let uvs = if frame.is_curved(local) {
+            let cylinder = frame.curved == Some(true);
+            let radius = if cylinder {
+                (half.y + half.z) / 2.0
+            } else {
+                (half.x + half.y + half.z) / 3.0
+            };
+            let mut uvs = local.map(|local| {
+                let unit = frame.unit(local);
+                if cylinder {
+                    (unit.z.atan2(unit.y) * radius / studs, local.x / studs)
+                } else {
+                    let latitude = unit.y.clamp(-1.0, 1.0).asin();
+                    (
+                        unit.z.atan2(unit.x) * radius / studs,
+                        latitude * radius / studs,
+                    )
+                }
+            });
+            // A face across the seam gets its low end moved round a turn.
+            let turn = std::f32::consts::TAU * radius / studs;
+            let highest = uvs.iter().map(|uv| uv.0).fold(f32::MIN, f32::max);
+            for uv in &mut uvs {
+                if highest - uv.0 > turn / 2.0 {
+                    uv.0 += turn;
+                }
+            }
+            uvs
+        } else {
+            let mut facing = cross(sub(local[1], local[0]), sub(local[2], local[0]));
+            // Mirroring flipped the face; turning it back keeps each
+            // vertex's coordinates what they were before mirroring.
+            if frame.mirror.iter().filter(|&&m| m).count() % 2 == 1 {
+                facing = Vector3::new(-facing.x, -facing.y, -facing.z);
+            }
+            if dot(facing, facing) > 0.0 {
+                let normal = normalize(facing);
+                let up = if normal.y.abs() > 0.99 {
+                    Vector3::new(0.0, 0.0, 1.0)
+                } else {
+                    Vector3::new(0.0, 1.0, 0.0)
+                };
+                let across = normalize(cross(up, normal));
+                let upward = cross(normal, across);
+                local.map(|p| (dot(p, across) / studs, dot(p, upward) / studs))
+            } else {
+                [(0.0, 0.0); 3]
+            }
+        };
+        let mut numbers = [0; 3];
+        for (number, (index, (u, v))) in numbers.iter_mut().zip([a, b, c].into_iter().zip(uvs)) {
+            let count = written.len();
+            *number = *written
+                .entry((index, u.to_bits(), v.to_bits()))
+                .or_insert_with(|| {
+                    buf.push_str("vt ");
+                    push_float(buf, u);
+                    buf.push(' ');
+                    push_float(buf, v);
+                    buf.push('\n');
+                    count
+                });
+        }
+        corners.push(numbers);
+    }
+    written.len()
+}
+
+/// Appends a `vn x y z` line for each face of a wedge, in face order.
+///
+/// Faces may have been dropped or flipped by the time they get here, so each
+/// one takes whichever of the wedge's analytic normals points the same way
+/// it does rather than going by position.
+fn push_wedge_normals(
+    buf: &mut String,
+    part: &PartRecord,
+    vertices: &[Vector3],
+    faces: &[Face],
+    mirror: [bool; 3],
+) {
+    // The turned-around ones are for the back faces of `--double-sided`.
+    let normals: Vec<Vector3> = wedge_face_normals(part.size)
+        .into_iter()
+        .flat_map(|n| [n, Vector3::new(-n.x, -n.y, -n.z)])
+        .map(|n| mirror_point(apply_matrix3(&part.cframe.orientation, n), mirror))
+        .collect();
+    for &(a, b, c) in faces {
+        let facing = cross(sub(vertices[b], vertices[a]), sub(vertices[c], vertices[a]));
+        let normal = normals
+            .iter()
+            .copied()
+            .max_by(|&p, &q| dot(p, facing).total_cmp(&dot(q, facing)))
+            .unwrap();
+        buf.push_str("vn ");
+        push_float(buf, normal.x);
+        buf.push(' ');
+        push_float(buf, normal.y);
+        buf.push(' ');
+        push_float(buf, normal.z);
+        buf.push('\n');
+    }
+}
+
+/// Whether `--double-sided` gives `part` back faces.
+fn is_double_sided(opts: &ExportOptions, part: &PartRecord) -> bool {
+    match opts.double_sided {
+        DoubleSided::Off => false,
+        DoubleSided::All => true,
+        DoubleSided::Thin => {
+            part.size.x.min(part.size.y).min(part.size.z) < opts.double_sided_threshold
+        }
+    }
+}
+
+/// Appends a copy of every face wound the other way, facing inwards.
+fn add_back_faces(faces: &mut Vec<Face>) {
+    for i in 0..faces.len() {
+        let (a, b, c) = faces[i];
+        faces.push((a, c, b));
+    }
+}
+
+/// Appends an `f a b c` line, turning the 0-based indices into 1-based ones
+/// past `offset`. With `texcoords` or `normals`, each corner refers to its
+/// own `vt` or `vn`.
+fn push_face(
+    buf: &mut String,
+    (a, b, c): Face,
+    offset: usize,
+    texcoords: Option<[usize; 3]>,
+    normals: Option<[usize; 3]>,
+) {
+    let mut ints = itoa::Buffer::new();
+    buf.push('f');
+    for (corner, index) in [a, b, c].into_iter().enumerate() {
+        buf.push(' ');
+        buf.push_str(ints.format(index + offset + 1));
+        if texcoords.is_some() || normals.is_some() {
+            buf.push('/');
+        }
+        if let Some(texcoords) = texcoords {
+            buf.push_str(ints.format(texcoords[corner] + 1));
+        }
+        if let Some(normals) = normals {
+            buf.push('/');
+            buf.push_str(ints.format(normals[corner] + 1));
+        }
+    }
+    buf.push('\n');
+}
+
+/// Appends a float spelled exactly the way `Display` would, without going
+/// through the formatting machinery. ryu finds the same shortest digits,
+/// it just writes `1.0` and `1e-7` where `Display` writes `1` and `0.0000001`.
+pub(crate) fn push_float(buf: &mut String, value: f32) {
+    if !value.is_finite() {
+        buf.push_str(&value.to_string());
+        return;
+    }
+
+    let mut floats = ryu::Buffer::new();
+    let formatted = floats.format_finite(value);
+    let Some((mantissa, exponent)) = formatted.split_once('e') else {
+        let plain = formatted.strip_suffix(".0").unwrap_or(formatted);
+        // When the value sits exactly halfway between two shortest spellings
+        // ryu picks the even one, while `Display` rounds away from zero.
+        if let Some((int, frac)) = plain.split_once('.')
+            && frac.len() < 10
+        {
+            let scaled = (value as f64).abs() * 10f64.powi(frac.len() as i32);
+            if scaled.fract() == 0.5 {
+                let digits = format!("{:0>1$}", scaled.ceil() as u64, frac.len() + 1);
+                let split = digits.len() - frac.len();
+                if int.starts_with('-') {
+                    buf.push('-');
+                }
+                buf.push_str(&digits[..split]);
+                buf.push('.');
+                buf.push_str(&digits[split..]);
+                return;
+            }
+        }
+        buf.push_str(plain);
+        return;
+    };
+
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", mantissa),
+    };
+    let int_len = mantissa.find('.').unwrap_or(mantissa.len()) as i32;
+    let digits = mantissa.replace('.', "");
+    let digits = digits.trim_end_matches('0');
+    let digits = if digits.is_empty() { "0" } else { digits };
+    let point = int_len + exponent.parse::<i32>().unwrap();
+
+    buf.push_str(sign);
+    if point <= 0 {
+        buf.push_str("0.");
+        buf.extend(std::iter::repeat_n('0', (-point) as usize));
+        buf.push_str(digits);
+    } else if point as usize >= digits.len() {
+        buf.push_str(digits);
+        buf.extend(std::iter::repeat_n('0', point as usize - digits.len()));
+    } else {
+        buf.push_str(&digits[..point as usize]);
+        buf.push('.');
+        buf.push_str(&digits[point as usize..]);
+    }
+}
+
+/// Collapses runs of unrotated, same-colored blocks that share whole faces
+/// into single larger blocks. Returns the new part list and how many of the
+/// original parts were folded into merged boxes.
+fn merge_boxes(parts: Vec<PartRecord>) -> (Vec<PartRecord>, usize) {
+    struct MergeBox {
+        part: PartRecord,
+        min: [f32; 3],
+        max: [f32; 3],
+        count: usize,
+    }
+
+    let quantize = |v: f32| (v / 0.001).round() as i64;
+
+    let (candidates, mut output): (Vec<_>, Vec<_>) = parts.into_iter().partition(|part| {
+        part.class == "Part" && part.shape == 1 && part.cframe.orientation == Matrix3::identity()
+    });
+
+    let mut boxes: Vec<MergeBox> = candidates
+        .into_iter()
+        .map(|part| {
+            let p = part.cframe.position;
+            let half = [part.size.x / 2.0, part.size.y / 2.0, part.size.z / 2.0];
+            MergeBox {
+                min: [p.x - half[0], p.y - half[1], p.z - half[2]],
+                max: [p.x + half[0], p.y + half[1], p.z + half[2]],
+                part,
+                count: 1,
+            }
+        })
+        .collect();
+
+    // Sweep along each axis in turn, joining neighbours whose cross-sections
+    // match exactly, until a full round doesn't merge anything.
+    loop {
+        let before = boxes.len();
+        for axis in 0..3 {
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let key = |b: &MergeBox| {
+                (
+                    b.part.color,
+                    b.part.material,
+                    b.part.anchored,
+                    b.part.surfaces,
+                    [b.min[u], b.max[u], b.min[v], b.max[v]].map(quantize),
+                )
+            };
+            boxes.sort_by(|a, b| {
+                key(a)
+                    .cmp(&key(b))
+                    .then(a.min[axis].total_cmp(&b.min[axis]))
+            });
+
+            let mut merged: Vec<MergeBox> = Vec::with_capacity(boxes.len());
+            for b in boxes {
+                if let Some(last) = merged.last_mut()
+                    && key(last) == key(&b)
+                    && quantize(last.max[axis]) == quantize(b.min[axis])
+                {
+                    last.max[axis] = b.max[axis];
+                    last.count += b.count;
+                    continue;
+                }
+                merged.push(b);
+            }
+            boxes = merged;
+        }
+        if boxes.len() == before {
+            break;
+        }
+    }
+
+    let mut folded = 0;
+    for mut b in boxes {
+        if b.count > 1 {
+            folded += b.count;
+            b.part.size = Vector3::new(
+                b.max[0] - b.min[0],
+                b.max[1] - b.min[1],
+                b.max[2] - b.min[2],
+            );
+            b.part.cframe = CFrame::new(
+                Vector3::new(
+                    (b.min[0] + b.max[0]) / 2.0,
+                    (b.min[1] + b.max[1]) / 2.0,
+                    (b.min[2] + b.max[2]) / 2.0,
+                ),
+                Matrix3::identity(),
+            );
+            // It's several parts now, not the one whose record it kept.
+            b.part.unique_id = None;
+            b.part.object_name = None;
+        }
+        output.push(b.part);
+    }
+
+    (output, folded)
+}
+
+/// `v` snapped to a grid `epsilon` apart, for points within about `epsilon`
+/// of each other to come out the same. With an `epsilon` of 0 only points
+/// that are exactly the same do.
+fn quantize(v: Vector3, epsilon: f32) -> [i64; 3] {
+    if epsilon > 0.0 {
+        [
+            (v.x / epsilon).round() as i64,
+            (v.y / epsilon).round() as i64,
+            (v.z / epsilon).round() as i64,
+        ]
+    } else {
+        // Adding 0 turns -0 into 0, which has other bits.
+        [
+            (v.x + 0.0).to_bits() as i64,
+            (v.y + 0.0).to_bits() as i64,
+            (v.z + 0.0).to_bits() as i64,
+        ]
+    }
+}
+
+/// Removes faces that exactly overlap a face of another part sitting on the
+/// other side of it, like the touching sides of stacked bricks. Returns how
+/// many triangles were dropped.
+fn remove_hidden_faces(parts: &[PartRecord], meshes: &mut [Mesh], epsilon: f32) -> usize {
+    // Quads are matched as a whole, since two touching boxes don't
+    // necessarily split their shared side along the same diagonal.
+    let mut candidates: HashMap<_, Vec<_>> = HashMap::new();
+    for (mesh_index, (vertices, faces)) in meshes.iter().enumerate() {
+        let mut i = 0;
+        while i < faces.len() {
+            let count = if i + 1 < faces.len() && is_quad(vertices, faces[i], faces[i + 1]) {
+                2
+            } else {
+                1
+            };
+            let mut corners: Vec<[i64; 3]> = faces[i..i + count]
+                .iter()
+                .flat_map(|&(a, b, c)| [a, b, c])
+                .map(|index| quantize(vertices[index], epsilon))
+                .collect();
+            corners.sort_unstable();
+            corners.dedup();
+            candidates
+                .entry(corners)
+                .or_default()
+                .push((mesh_index, i, count));
+            i += count;
+        }
+    }
+
+    let mut hidden: Vec<Vec<bool>> = meshes
+        .iter()
+        .map(|(_, faces)| vec![false; faces.len()])
+        .collect();
+    let mut removed = 0;
+    for matches in candidates.values() {
+        // Anything other than a clean pair is ambiguous, so leave it alone.
+        let [(mesh_a, face_a, count_a), (mesh_b, face_b, count_b)] = matches[..] else {
+            continue;
+        };
+        if mesh_a == mesh_b {
+            continue;
+        }
+
+        // Only hide the pair when the parts are on opposite sides of it,
+        // otherwise it's two coplanar surfaces that are both visible.
+        let (vertices, faces) = &meshes[mesh_a];
+        let (a, b, c) = faces[face_a];
+        let normal = cross(sub(vertices[b], vertices[a]), sub(vertices[c], vertices[a]));
+        let side_a = dot(normal, sub(parts[mesh_a].cframe.position, vertices[a]));
+        let side_b = dot(normal, sub(parts[mesh_b].cframe.position, vertices[a]));
+        if side_a * side_b >= 0.0 {
+            continue;
+        }
+
+        hidden[mesh_a][face_a..face_a + count_a].fill(true);
+        hidden[mesh_b][face_b..face_b + count_b].fill(true);
+        removed += count_a + count_b;
+    }
+
+    for ((_, faces), hidden) in meshes.iter_mut().zip(hidden) {
+        let mut hidden = hidden.into_iter();
+        faces.retain(|_| !hidden.next().unwrap());
+    }
+
+    removed
+}
+
+/// Reorders parts (and their meshes) by the Morton code of their bounding box
+/// centers, so parts close together in the world are close together in the file.
+fn sort_spatially(parts: &mut Vec<PartRecord>, meshes: &mut Vec<Mesh>) {
+    let centers: Vec<Vector3> = meshes
+        .iter()
+        .map(|(vertices, _)| {
+            let bounds = bvh::Aabb::from_points(vertices);
+            Vector3::new(
+                (bounds.min.x + bounds.max.x) / 2.0,
+                (bounds.min.y + bounds.max.y) / 2.0,
+                (bounds.min.z + bounds.max.z) / 2.0,
+            )
+        })
+        .collect();
+    let scene = bvh::Aabb::from_points(&centers);
+    let normalized = |value: f32, min: f32, max: f32| {
+        if max > min {
+            (value - min) / (max - min)
+        } else {
+            0.0
+        }
+    };
+
+    let mut order: Vec<(u64, usize)> = centers
+        .iter()
+        .enumerate()
+        .map(|(index, c)| {
+            let code = to_morton_code(
+                normalized(c.x, scene.min.x, scene.max.x),
+                normalized(c.y, scene.min.y, scene.max.y),
+                normalized(c.z, scene.min.z, scene.max.z),
+            );
+            (code, index)
+        })
+        .collect();
+    // Sorting on the index too keeps ties in their original order.
+    order.sort_unstable();
+
+    let mut old_parts: Vec<Option<PartRecord>> = parts.drain(..).map(Some).collect();
+    let mut old_meshes: Vec<Option<_>> = meshes.drain(..).map(Some).collect();
+    for (_, index) in order {
+        parts.push(old_parts[index].take().unwrap());
+        meshes.push(old_meshes[index].take().unwrap());
+    }
+}
+
+/// Interleaves the bits of three coordinates in 0..=1, each quantized to 21
+/// bits, into a 63-bit Z-order curve index.
+fn to_morton_code(x: f32, y: f32, z: f32) -> u64 {
+    let spread = |v: f32| {
+        let mut v = (v.clamp(0.0, 1.0) * ((1 << 21) - 1) as f32) as u64;
+        v = (v | (v << 32)) & 0x001f_0000_0000_ffff;
+        v = (v | (v << 16)) & 0x001f_0000_ff00_00ff;
+        v = (v | (v << 8)) & 0x100f_00f0_0f00_f00f;
+        v = (v | (v << 4)) & 0x10c3_0c30_c30c_30c3;
+        v = (v | (v << 2)) & 0x1249_2492_4924_9249;
+        v
+    };
+    spread(x) | (spread(y) << 1) | (spread(z) << 2)
+}
+
+/// The furthest past a face to look for a part covering it, in studs.
+const INTERIOR_PROBE_REACH: f32 = 0.05;
+
+/// How far each corner's probe is pulled in toward the face's centroid, as
+/// a fraction of the way there, so it doesn't sit on a neighbour's edge.
+const INTERIOR_PROBE_INSET: f32 = 0.1;
+
+/// Drops faces that other parts sit right up against, by looking a short
+/// step out along each face's normal from its centroid and from just inside
+/// each of its corners. A face only goes if every one of those lands inside
+/// a neighbour. The step is half the shortest side of the parts found there,
+/// so a thin neighbour isn't stepped through, and at most
+/// `INTERIOR_PROBE_REACH`, so a slope isn't stepped off. This is still
+/// approximate: a face covered everywhere but between its probes goes too.
+/// Returns how many triangles were dropped.
+fn omit_interior_faces(parts: &[PartRecord], meshes: &mut [Mesh]) -> usize {
+    let bvh = bvh::Bvh::build(
+        meshes
+            .iter()
+            .map(|(vertices, _)| bvh::Aabb::from_points(vertices))
+            .collect(),
+    );
+
+    meshes
+        .par_iter_mut()
+        .enumerate()
+        .map(|(index, (vertices, faces))| {
+            let center = parts[index].cframe.position;
+            let before = faces.len();
+            faces.retain(|&(a, b, c)| {
+                let (a, b, c) = (vertices[a], vertices[b], vertices[c]);
+                let normal = cross(sub(b, a), sub(c, a));
+                if dot(normal, normal) == 0.0 {
+                    return true;
+                }
+                let centroid = Vector3::new(
+                    (a.x + b.x + c.x) / 3.0,
+                    (a.y + b.y + c.y) / 3.0,
+                    (a.z + b.z + c.z) / 3.0,
+                );
+
+                // Don't trust the winding, just point the normal away from the part.
+                let mut normal = normalize(normal);
+                if dot(normal, sub(centroid, center)) < 0.0 {
+                    normal = Vector3::new(-normal.x, -normal.y, -normal.z);
+                }
+                let step = |from: Vector3, distance: f32| {
+                    Vector3::new(
+                        from.x + normal.x * distance,
+                        from.y + normal.y * distance,
+                        from.z + normal.z * distance,
+                    )
+                };
+                let inset = |corner: Vector3| {
+                    Vector3::new(
+                        corner.x + (centroid.x - corner.x) * INTERIOR_PROBE_INSET,
+                        corner.y + (centroid.y - corner.y) * INTERIOR_PROBE_INSET,
+                        corner.z + (centroid.z - corner.z) * INTERIOR_PROBE_INSET,
+                    )
+                };
+
+                let covered = [centroid, inset(a), inset(b), inset(c)]
+                    .into_iter()
+                    .all(|from| {
+                        let mut adjacent = Vec::new();
+                        bvh.query_segment(
+                            step(from, 0.001),
+                            step(from, INTERIOR_PROBE_REACH),
+                            &mut |other| {
+                                if other != index {
+                                    adjacent.push(other);
+                                }
+                            },
+                        );
+                        let shortest = adjacent
+                            .iter()
+                            .map(|&other| {
+                                let size = parts[other].size;
+                                size.x.min(size.y).min(size.z)
+                            })
+                            .fold(f32::INFINITY, f32::min);
+                        let probe = step(from, (shortest / 2.0).min(INTERIOR_PROBE_REACH));
+                        adjacent
+                            .iter()
+                            .any(|&other| part_contains(&parts[other], probe))
+                    });
+                !covered
+            });
+            before - faces.len()
+        })
+        .sum()
+}
+
+/// Whether a point lies inside a part, going by the shape it's meshed as.
+/// Balls and cylinders count as their true curve rather than its facets, and
+/// trusses as their whole box.
+fn part_contains(part: &PartRecord, point: Vector3) -> bool {
+    let m = &part.cframe.orientation;
+    let d = sub(point, part.cframe.position);
+    let local = Vector3::new(
+        m.x.x * d.x + m.y.x * d.y + m.z.x * d.z,
+        m.x.y * d.x + m.y.y * d.y + m.z.y * d.z,
+        m.x.z * d.x + m.y.z * d.y + m.z.z * d.z,
+    );
+    let half = Vector3::new(part.size.x / 2.0, part.size.y / 2.0, part.size.z / 2.0);
+    if local.x.abs() > half.x || local.y.abs() > half.y || local.z.abs() > half.z {
+        return false;
+    }
+    // How far up the part the point is, and how far along from its left and
+    // its front, each from 0 to 1.
+    let up = (local.y + half.y) / part.size.y;
+    let right = (local.x + half.x) / part.size.x;
+    let back = (local.z + half.z) / part.size.z;
+    let squared = |a: f32, b: f32| a * a + b * b;
+    match (part.class.as_str(), part.shape) {
+        ("Part", 0) => {
+            squared(local.x / half.x, local.y / half.y) + (local.z / half.z).powi(2) <= 1.0
+        }
+        ("Part", 2) => squared(local.y / half.y, local.z / half.z) <= 1.0,
+        ("WedgePart", _) => up <= back,
+        ("CornerWedgePart", _) => up <= right && up <= 1.0 - back,
+        _ => true,
+    }
+}
+
+/// Whether two triangles share an edge and lie in the same plane.
+fn is_quad(vertices: &[Vector3], first: Face, second: Face) -> bool {
+    let first_corners = [first.0, first.1, first.2];
+    let shared = [second.0, second.1, second.2]
+        .iter()
+        .filter(|index| first_corners.contains(index))
+        .count();
+    if shared != 2 {
+        return false;
+    }
+
+    let normal_a = normalize(cross(
+        sub(vertices[first.1], vertices[first.0]),
+        sub(vertices[first.2], vertices[first.0]),
+    ));
+    let normal_b = normalize(cross(
+        sub(vertices[second.1], vertices[second.0]),
+        sub(vertices[second.2], vertices[second.0]),
+    ));
+    dot(normal_a, normal_b).abs() > 0.9999
+}
+
+/// Collects the ids of every `rbxassetid://` asset referenced by any property
+/// in the file (textures, meshes, decals, surface appearances, ...).
+fn collect_asset_ids(dom: &WeakDom) -> HashSet<u64> {
+    descendants(dom, dom.root_ref())
+        .flat_map(|inst| inst.properties.values())
+        .filter_map(|value| match value {
+            Variant::String(s) => asset_id(s),
+            Variant::ContentId(id) => asset_id(id.as_str()),
+            Variant::Content(content) => content.as_uri().and_then(asset_id),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Pulls the numeric id out of an `rbxassetid://` URL.
+fn asset_id(url: &str) -> Option<u64> {
+    let id = url.trim().strip_prefix("rbxassetid://")?;
+    let digits = id.find(|c: char| !c.is_ascii_digit()).unwrap_or(id.len());
+    id[..digits].parse().ok()
+}
+
+/// Quotes a CSV field when it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Writes the Lighting service's scene-wide settings as OBJ comments, since
+/// OBJ has nowhere else to keep them.
+fn export_lighting_comment(dom: &WeakDom, obj: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+    let lighting = match find_service(dom, "Lighting") {
+        Some(lighting) => lighting,
+        None => return Ok(()),
+    };
+
+    for property in [
+        "Ambient",
+        "OutdoorAmbient",
+        "Brightness",
+        "ExposureCompensation",
+        "ClockTime",
+        "GeographicLatitude",
+        "FogColor",
+        "FogStart",
+        "FogEnd",
+    ] {
+        match lighting.properties.get(&Ustr::from(property)) {
+            Some(Variant::Color3(c)) => {
+                writeln!(obj, "# Lighting.{} {} {} {}", property, c.r, c.g, c.b)?
+            }
+            Some(Variant::Float32(v)) => writeln!(obj, "# Lighting.{} {}", property, v)?,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a Mitsuba 3 scene that renders the exported OBJ files under a
+/// sun and sky matching the place's Lighting settings.
+fn write_mitsuba_scene(
+    xml_path: &Path,
+    obj_paths: &[PathBuf],
+    dom: &WeakDom,
+    materials: &HashMap<MaterialKey, (usize, String)>,
+    parts: &[PartRecord],
+    mirror: [bool; 3],
+) -> Result<(), Box<dyn Error>> {
+    let lighting_value = |property: &str, default: f32| match find_service(dom, "Lighting")
+        .and_then(|l| l.properties.get(&Ustr::from(property)))
+    {
+        Some(Variant::Float32(v)) => *v,
+        _ => default,
+    };
+    let latitude = lighting_value("GeographicLatitude", 41.7333);
+    let hour = lighting_value("ClockTime", 14.0);
+
+    // Frame the camera on the parts' bounding box, looking down at it diagonally.
+    let (mut min, mut max) = ([f32::MAX; 3], [f32::MIN; 3]);
+    for part in parts {
+        let p = part.cframe.position;
+        let reach = (part.size.x.powi(2) + part.size.y.powi(2) + part.size.z.powi(2)).sqrt() / 2.0;
+        for (axis, value) in [p.x, p.y, p.z].into_iter().enumerate() {
+            min[axis] = min[axis].min(value - reach);
+            max[axis] = max[axis].max(value + reach);
+        }
+    }
+    if parts.is_empty() {
+        (min, max) = ([-50.0; 3], [50.0; 3]);
+    }
+    let center = [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0);
+    let center = mirror_point(Vector3::new(center[0], center[1], center[2]), mirror);
+    let center = [center.x, center.y, center.z];
+    let extent = [0, 1, 2]
+        .map(|axis| max[axis] - min[axis])
+        .into_iter()
+        .fold(1.0, f32::max);
+
+    let mut xml = create_file(xml_path)?;
+    writeln!(xml, "<scene version=\"3.0.0\">")?;
+    writeln!(xml, "    <integrator type=\"path\">")?;
+    writeln!(xml, "        <integer name=\"max_depth\" value=\"8\"/>")?;
+    writeln!(xml, "    </integrator>")?;
+    writeln!(xml)?;
+    writeln!(xml, "    <sensor type=\"perspective\">")?;
+    writeln!(xml, "        <float name=\"fov\" value=\"45\"/>")?;
+    writeln!(xml, "        <transform name=\"to_world\">")?;
+    writeln!(
+        xml,
+        "            <lookat origin=\"{}, {}, {}\" target=\"{}, {}, {}\" up=\"0, 1, 0\"/>",
+        center[0] + extent,
+        center[1] + extent * 0.75,
+        center[2] + extent,
+        center[0],
+        center[1],
+        center[2]
+    )?;
+    writeln!(xml, "        </transform>")?;
+    writeln!(xml, "        <sampler type=\"independent\">")?;
+    writeln!(
+        xml,
+        "            <integer name=\"sample_count\" value=\"64\"/>"
+    )?;
+    writeln!(xml, "        </sampler>")?;
+    writeln!(xml, "        <film type=\"hdrfilm\">")?;
+    writeln!(xml, "            <integer name=\"width\" value=\"1280\"/>")?;
+    writeln!(xml, "            <integer name=\"height\" value=\"720\"/>")?;
+    writeln!(xml, "        </film>")?;
+    writeln!(xml, "    </sensor>")?;
+    writeln!(xml)?;
+    writeln!(xml, "    <emitter type=\"sunsky\">")?;
+    writeln!(
+        xml,
+        "        <float name=\"latitude\" value=\"{}\"/>",
+        latitude
+    )?;
+    writeln!(xml, "        <float name=\"hour\" value=\"{}\"/>", hour)?;
+    writeln!(xml, "    </emitter>")?;
+    writeln!(xml)?;
+
+    // Mitsuba's OBJ loader ignores usemtl, so the per-material BSDFs are
+    // declared for reference (and for splitting the OBJ by hand), while the
+    // shapes themselves get a neutral one.
+    let mut named: Vec<_> = materials.iter().collect();
+    named.sort_by_key(|(_, (number, _))| *number);
+    for (key, (_, name)) in named {
+        writeln!(xml, "    <bsdf type=\"diffuse\" id=\"{}\">", name)?;
+        writeln!(
+            xml,
+            "        <rgb name=\"reflectance\" value=\"{}, {}, {}\"/>",
+            key.r as f32 / 255.0,
+            key.g as f32 / 255.0,
+            key.b as f32 / 255.0
+        )?;
+        writeln!(xml, "    </bsdf>")?;
+    }
+    writeln!(xml, "    <bsdf type=\"diffuse\" id=\"default\"/>")?;
+    writeln!(xml)?;
+
+    for obj_path in obj_paths {
+        writeln!(xml, "    <shape type=\"obj\">")?;
+        writeln!(
+            xml,
+            "        <string name=\"filename\" value=\"{}\"/>",
+            obj_path.file_name().unwrap().to_string_lossy()
+        )?;
+        writeln!(xml, "        <ref id=\"default\"/>")?;
+        writeln!(xml, "    </shape>")?;
+    }
+    writeln!(xml, "</scene>")?;
+    xml.flush()?;
+    Ok(())
+}
+
+fn export_fog_comment(dom: &WeakDom, mtl: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+    let lighting = match find_service(dom, "Lighting") {
+        Some(lighting) => lighting,
+        None => return Ok(()),
+    };
+
+    let mut wrote_fog = false;
+    if let Some(Variant::Color3(c)) = lighting.properties.get(&Ustr::from("FogColor")) {
+        writeln!(mtl, "# fog_color {} {} {}", c.r, c.g, c.b)?;
+        wrote_fog = true;
+    }
+    for (property, name) in [("FogStart", "fog_start"), ("FogEnd", "fog_end")] {
+        if let Some(Variant::Float32(v)) = lighting.properties.get(&Ustr::from(property)) {
+            writeln!(mtl, "# {} {}", name, v)?;
+            wrote_fog = true;
+        }
+    }
+    if wrote_fog {
+        writeln!(mtl)?;
+    }
+
+    Ok(())
+}
+
+fn constraint_material(class: &str) -> (&'static str, (u8, u8, u8)) {
+    match class {
+        "RodConstraint" => ("constraint_rod", (0, 0, 255)),
+        "RopeConstraint" => ("constraint_rope", (255, 128, 0)),
+        "SpringConstraint" => ("constraint_spring", (0, 200, 0)),
+        "BallSocketConstraint" => ("constraint_ballsocket", (200, 0, 200)),
+        "CylindricalConstraint" => ("constraint_cylindrical", (0, 200, 200)),
+        "PrismaticConstraint" => ("constraint_prismatic", (128, 0, 255)),
+        _ => ("constraint_hinge", (255, 220, 0)),
+    }
+}
+
+/// Where a constraint's attachment is in the world, or `None` if it isn't
+/// attached to anything. Parts in `joint_cframes` are taken to be there.
+fn attachment_position(
+    dom: &WeakDom,
+    joint_cframes: &HashMap<Ref, CFrame>,
+    constraint: &Instance,
+    property: &str,
+) -> Result<Option<Vector3>, ExportError> {
+    let attachment_ref = match constraint.properties.get(&Ustr::from(property)) {
+        Some(Variant::Ref(r)) if r.is_some() => *r,
+        None | Some(Variant::Ref(_)) => return Ok(None),
+        Some(_) => {
+            return Err(ExportError::InvalidProperty {
+                path: instance_path(dom, constraint.referent()),
+                property: property.to_string(),
+                expected: "Ref",
+            });
+        }
+    };
+    let attachment = dom
+        .get_by_ref(attachment_ref)
+        .ok_or(ExportError::MissingInstance(attachment_ref))?;
+    let local = get_cframe(attachment).position;
+
+    // Attachment CFrames are relative to the part they're parented to.
+    Ok(match dom.get_by_ref(attachment.parent()) {
+        Some(parent) => {
+            let cframe = match joint_cframes.get(&parent.referent()) {
+                Some(cframe) => *cframe,
+                None => get_cframe(parent),
+            };
+            Some(apply_cframe(local, &cframe))
+        }
+        None => Some(local),
+    })
+}
+
+/// A constraint, drawn between its two attachments.
+struct ConstraintLine {
+    material: (&'static str, (u8, u8, u8)),
+    from: Vector3,
+    to: Vector3,
+    shape: ConstraintShape,
+}
+
+/// How a constraint is drawn from `from` to `to`.
+#[derive(Clone, Copy)]
+enum ConstraintShape {
+    Line,
+    /// A tube with this many sides, `radius` out from the line and turned
+    /// `angle` radians about it.
+    Tube {
+        sides: usize,
+        radius: f32,
+        angle: f32,
+    },
+}
+
+impl ConstraintShape {
+    fn vertex_count(self) -> usize {
+        match self {
+            ConstraintShape::Line => 2,
+            ConstraintShape::Tube { sides, .. } => sides * 2,
+        }
+    }
+}
+
+/// How thick sliding constraints are drawn for each stud they can slide, or
+/// of their length when they aren't limited, within the bounds below.
+const CONSTRAINT_THICKNESS: f32 = 0.05;
+const CONSTRAINT_MIN_RADIUS: f32 = 0.05;
+const CONSTRAINT_MAX_RADIUS: f32 = 0.5;
+
+/// The shape `inst` is drawn as between its attachments, and where it ends.
+/// `CylindricalConstraint`s are drawn as cylinders turned to their
+/// `CurrentAngle`, and `PrismaticConstraint`s as square rods reaching out to
+/// their `CurrentPosition`, both thicker the further they can slide. Current
+/// values outside the constraint's limits are clamped into them, and
+/// everything else is a line.
+fn constraint_shape(inst: &Instance, from: Vector3, to: Vector3) -> (ConstraintShape, Vector3) {
+    let float = |name: &str| match inst.properties.get(&Ustr::from(name)) {
+        Some(Variant::Float32(value)) => Some(*value),
+        _ => None,
+    };
+    let enabled = |name: &str| {
+        matches!(
+            inst.properties.get(&Ustr::from(name)),
+            Some(Variant::Bool(true))
+        )
+    };
+    let limits = |flag: &str, lower: &str, upper: &str| {
+        enabled(flag)
+            .then(|| Some((float(lower)?, float(upper)?)))
+            .flatten()
+            .filter(|(lower, upper)| lower <= upper)
+    };
+
+    let offset = sub(to, from);
+    let length = dot(offset, offset).sqrt();
+    if length == 0.0 {
+        return (ConstraintShape::Line, to);
+    }
+    let sliding = limits("LimitsEnabled", "LowerLimit", "UpperLimit");
+    let radius = (sliding.map_or(length, |(lower, upper)| upper - lower) * CONSTRAINT_THICKNESS)
+        .clamp(CONSTRAINT_MIN_RADIUS, CONSTRAINT_MAX_RADIUS);
+
+    match inst.class.as_str() {
+        "CylindricalConstraint" => {
+            let mut angle = float("CurrentAngle").unwrap_or(0.0);
+            if let Some((lower, upper)) = limits("AngularLimitsEnabled", "LowerAngle", "UpperAngle")
+            {
+                angle = angle.clamp(lower, upper);
+            }
+            let shape = ConstraintShape::Tube {
+                sides: 8,
+                radius,
+                angle: angle.to_radians(),
+            };
+            (shape, to)
+        }
+        "PrismaticConstraint" => {
+            let end = match float("CurrentPosition") {
+                Some(mut position) => {
+                    if let Some((lower, upper)) = sliding {
+                        position = position.clamp(lower, upper);
+                    }
+                    let scale = position / length;
+                    Vector3::new(
+                        from.x + offset.x * scale,
+                        from.y + offset.y * scale,
+                        from.z + offset.z * scale,
+                    )
+                }
+                None => to,
+            };
+            // Corners on the diagonals, so the rod is `radius * 2` across.
+            let shape = ConstraintShape::Tube {
+                sides: 4,
+                radius: radius * std::f32::consts::SQRT_2,
+                angle: std::f32::consts::FRAC_PI_4,
+            };
+            (shape, end)
+        }
+        _ => (ConstraintShape::Line, to),
+    }
+}
+
+/// The vertices of a tube from `from` to `to`, a ring around each end, and
+/// its faces, numbered from 0 and facing outwards, ends included.
+fn tube_mesh(from: Vector3, to: Vector3, sides: usize, radius: f32, angle: f32) -> Mesh {
+    let axis = normalize(sub(to, from));
+    let helper = if axis.y.abs() < 0.9 {
+        Vector3::new(0.0, 1.0, 0.0)
+    } else {
+        Vector3::new(1.0, 0.0, 0.0)
+    };
+    let u = normalize(cross(axis, helper));
+    let v = cross(axis, u);
+
+    let mut vertices = Vec::with_capacity(sides * 2);
+    for center in [from, to] {
+        for side in 0..sides {
+            let theta = angle + side as f32 * std::f32::consts::TAU / sides as f32;
+            let (sin, cos) = theta.sin_cos();
+            vertices.push(Vector3::new(
+                center.x + (u.x * cos + v.x * sin) * radius,
+                center.y + (u.y * cos + v.y * sin) * radius,
+                center.z + (u.z * cos + v.z * sin) * radius,
+            ));
+        }
+    }
+
+    let mut faces = Vec::with_capacity(sides * 4 - 4);
+    for side in 0..sides {
+        let next = (side + 1) % sides;
+        faces.push((side, next, sides + next));
+        faces.push((side, sides + next, sides + side));
+    }
+    for side in 1..sides - 1 {
+        faces.push((0, side + 1, side));
+        faces.push((sides, sides + side, sides + side + 1));
+    }
+    (vertices, faces)
+}
+
+/// The lines for the scene's constraints, leaving out those not attached at
+/// both ends. With `best_effort`, a constraint that can't be drawn is
+/// skipped and recorded in the scene's errors instead of failing the export.
+fn constraint_lines(
+    dom: &WeakDom,
+    opts: &ExportOptions,
+    scene: &mut Scene,
+) -> Result<Vec<ConstraintLine>, ExportError> {
+    let mut lines = Vec::new();
+    for &constraint_ref in &scene.constraints {
+        let line = dom
+            .get_by_ref(constraint_ref)
+            .ok_or(ExportError::MissingInstance(constraint_ref))
+            .and_then(|inst| {
+                Ok(
+                    match (
+                        attachment_position(dom, &scene.joint_cframes, inst, "Attachment0")?,
+                        attachment_position(dom, &scene.joint_cframes, inst, "Attachment1")?,
+                    ) {
+                        (Some(from), Some(to)) => {
+                            let (shape, to) = constraint_shape(inst, from, to);
+                            Some(ConstraintLine {
+                                material: constraint_material(inst.class.as_str()),
+                                from,
+                                to,
+                                shape,
+                            })
+                        }
+                        _ => None,
+                    },
+                )
+            });
+        match line {
+            Ok(line) => lines.extend(line),
+            Err(error) if opts.best_effort => {
+                let path = instance_path(dom, constraint_ref);
+                log::debug!("{}: {}; skipped", path, error);
+                scene.errors.push(InstanceError { path, error });
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(lines)
+}
+
+fn export_constraints(
+    lines: &[ConstraintLine],
+    obj: &mut dyn Write,
+    mtl: &mut dyn Write,
+    state: &mut ExportState,
+) -> Result<(), Box<dyn Error>> {
+    for line in lines {
+        let (mat_name, (r, g, b)) = line.material;
+        if !state.constraint_materials.contains(&mat_name) {
+            writeln!(mtl, "newmtl {}", mat_name)?;
+            writeln!(
+                mtl,
+                "Kd {} {} {}",
+                r as f32 / 255.0,
+                g as f32 / 255.0,
+                b as f32 / 255.0
+            )?;
+            writeln!(mtl)?;
+            state.constraint_materials.push(mat_name);
+        }
+
+        writeln!(obj, "usemtl {}", mat_name)?;
+        let ConstraintShape::Tube {
+            sides,
+            radius,
+            angle,
+        } = line.shape
+        else {
+            let (p0, p1) = (
+                mirror_point(line.from, state.mirror),
+                mirror_point(line.to, state.mirror),
+            );
+            writeln!(obj, "v {} {} {}", p0.x, p0.y, p0.z)?;
+            writeln!(obj, "v {} {} {}", p1.x, p1.y, p1.z)?;
+            writeln!(
+                obj,
+                "l {} {}",
+                state.vertex_offset + 1,
+                state.vertex_offset + 2
+            )?;
+            state.vertex_offset += 2;
+            continue;
+        };
+
+        // Built from the original ends, then mirrored like parts are.
+        let (vertices, faces) = tube_mesh(line.from, line.to, sides, radius, angle);
+        let flip_winding = state.mirror.iter().filter(|&&m| m).count() % 2 == 1;
+        for v in vertices {
+            let v = mirror_point(v, state.mirror);
+            writeln!(obj, "v {} {} {}", v.x, v.y, v.z)?;
+        }
+        let o = state.vertex_offset + 1;
+        for (a, b, c) in faces {
+            let (b, c) = if flip_winding { (c, b) } else { (b, c) };
+            writeln!(obj, "f {} {} {}", a + o, b + o, c + o)?;
+        }
+        state.vertex_offset += sides * 2;
+    }
+
+    Ok(())
+}
+
+/// A `SelectionBox` or `SelectionSphere`, drawn as lines around its adornee.
+struct SelectionOutline {
+    /// A `SelectionBox`'s `SurfaceColor3` or a `SelectionSphere`'s `Color3`.
+    color: (u8, u8, u8),
+    /// A `SelectionBox`'s `LineThickness`, which OBJ has no way to give a
+    /// line, so it only goes in the material's name.
+    thickness: Option<f32>,
+    vertices: Vec<Vector3>,
+    edges: Vec<(usize, usize)>,
+}
+
+/// The outlines for the scene's selection effects, leaving out those that
+/// are hidden or have no part as their `Adornee`. They sit on the adornee,
+/// unless they have a `CFrame` of their own: a box's edges are the part's
+/// and a sphere is the smallest one around the part. With `best_effort`,
+/// one that can't be drawn is skipped and recorded in the scene's errors.
+fn selection_outlines(
+    dom: &WeakDom,
+    opts: &ExportOptions,
+    scene: &mut Scene,
+) -> Result<Vec<SelectionOutline>, ExportError> {
+    let mut outlines = Vec::new();
+    for &effect_ref in &scene.selection_effects {
+        let outline = dom
+            .get_by_ref(effect_ref)
+            .ok_or(ExportError::MissingInstance(effect_ref))
+            .and_then(|inst| selection_outline(dom, &scene.joint_cframes, inst));
+        match outline {
+            Ok(outline) => outlines.extend(outline),
+            Err(error) if opts.best_effort => {
+                let path = instance_path(dom, effect_ref);
+                log::debug!("{}: {}; skipped", path, error);
+                scene.errors.push(InstanceError { path, error });
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(outlines)
+}
+
+fn selection_outline(
+    dom: &WeakDom,
+    joint_cframes: &HashMap<Ref, CFrame>,
+    inst: &Instance,
+) -> Result<Option<SelectionOutline>, ExportError> {
+    let property = |name: &str| inst.properties.get(&Ustr::from(name));
+    if let Some(Variant::Bool(false)) = property("Visible") {
+        return Ok(None);
+    }
+    let adornee_ref = match property("Adornee") {
+        Some(Variant::Ref(r)) if r.is_some() => *r,
+        None | Some(Variant::Ref(_)) => return Ok(None),
+        Some(_) => {
+            return Err(ExportError::InvalidProperty {
+                path: instance_path(dom, inst.referent()),
+                property: "Adornee".to_string(),
+                expected: "Ref",
+            });
+        }
+    };
+    let adornee = dom
+        .get_by_ref(adornee_ref)
+        .ok_or(ExportError::MissingInstance(adornee_ref))?;
+    let Some(Variant::Vector3(size)) = adornee.properties.get(&Ustr::from("Size")) else {
+        log::debug!(
+            "{}: adornee {} isn't a part; skipped",
+            instance_path(dom, inst.referent()),
+            instance_path(dom, adornee_ref)
+        );
+        return Ok(None);
+    };
+    let cframe = find_cframe(inst)
+        .or_else(|| joint_cframes.get(&adornee_ref).copied())
+        .unwrap_or_else(|| get_cframe(adornee));
+    let is_box = inst.class == "SelectionBox";
+    let color_property = if is_box { "SurfaceColor3" } else { "Color3" };
+    let color = match property(color_property) {
+        Some(Variant::Color3(c)) => color3_to_rgb(c),
+        // All of them default to the same blue.
+        _ => (13, 105, 172),
+    };
+
+    let (local, edges, thickness) = if is_box {
+        let thickness = match property("LineThickness") {
+            Some(Variant::Float32(thickness)) => *thickness,
+            _ => 0.15,
+        };
+        let half = Vector3::new(size.x / 2.0, size.y / 2.0, size.z / 2.0);
+        let corners = (0..8)
+            .map(|i| {
+                let sign = |bit: usize| if i & bit == 0 { -1.0 } else { 1.0 };
+                Vector3::new(half.x * sign(1), half.y * sign(2), half.z * sign(4))
+            })
+            .collect();
+        // Corners one bit apart share an edge.
+        let edges = (0..8)
+            .flat_map(|i| [1, 2, 4].map(|bit| (i, i | bit)))
+            .filter(|&(i, j)| i != j)
+            .collect();
+        (corners, edges, Some(thickness))
+    } else {
+        let diameter = (size.x * size.x + size.y * size.y + size.z * size.z).sqrt();
+        let (vertices, faces) = sphere_mesh(Vector3::new(diameter, diameter, diameter), 1);
+        (vertices, extract_edges(&faces), None)
+    };
+    Ok(Some(SelectionOutline {
+        color,
+        thickness,
+        vertices: local
+            .into_iter()
+            .map(|v| apply_cframe(v, &cframe))
+            .collect(),
+        edges,
+    }))
+}
+
+fn export_selection_outlines(
+    outlines: &[SelectionOutline],
+    obj: &mut dyn Write,
+    mtl: &mut dyn Write,
+    state: &mut ExportState,
+) -> Result<(), Box<dyn Error>> {
+    for outline in outlines {
+        let (r, g, b) = outline.color;
+        let mut mat_name = format!("selection_{:02X}{:02X}{:02X}", r, g, b);
+        if let Some(thickness) = outline.thickness {
+            mat_name.push_str(&format!("_{}", thickness));
+        }
+        if state.selection_materials.insert(mat_name.clone()) {
+            writeln!(mtl, "newmtl {}", mat_name)?;
+            writeln!(
+                mtl,
+                "Kd {} {} {}",
+                r as f32 / 255.0,
+                g as f32 / 255.0,
+                b as f32 / 255.0
+            )?;
+            writeln!(mtl)?;
+        }
+
+        writeln!(obj, "usemtl {}", mat_name)?;
+        for &v in &outline.vertices {
+            let v = mirror_point(v, state.mirror);
+            writeln!(obj, "v {} {} {}", v.x, v.y, v.z)?;
+        }
+        let o = state.vertex_offset + 1;
+        for &(a, b) in &outline.edges {
+            writeln!(obj, "l {} {}", a + o, b + o)?;
+        }
+        state.vertex_offset += outline.vertices.len();
+    }
+
+    Ok(())
+}
+
+fn mirror_point(v: Vector3, mirror: [bool; 3]) -> Vector3 {
+    let flip = |c: f32, m: bool| if m { -c } else { c };
+    Vector3::new(
+        flip(v.x, mirror[0]),
+        flip(v.y, mirror[1]),
+        flip(v.z, mirror[2]),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use rbx_dom_weak::InstanceBuilder;
+    use rbx_types::{Color3, UniqueId};
+
+    use super::*;
+    use crate::options::TrussStyle;
+
+    fn at(x: f32, y: f32, z: f32) -> Vector3 {
+        Vector3::new(x, y, z)
+    }
+
+    /// A place with `parts` under its Workspace.
+    fn place(parts: impl IntoIterator<Item = InstanceBuilder>) -> WeakDom {
+        let mut dom = WeakDom::new(InstanceBuilder::new("DataModel"));
+        let workspace = dom.insert(dom.root_ref(), InstanceBuilder::new("Workspace"));
+        for part in parts {
+            dom.insert(workspace, part);
+        }
+        dom
+    }
+
+    fn block(size: Vector3, position: Vector3) -> InstanceBuilder {
+        InstanceBuilder::new("Part")
+            .with_property("Size", size)
+            .with_property("CFrame", CFrame::new(position, Matrix3::identity()))
+    }
+
+    fn export_text(dom: &WeakDom, opts: &ExportOptions) -> String {
+        let mut obj = Vec::new();
+        export_dom(opts, dom, &mut obj, None, None).unwrap();
+        String::from_utf8(obj).unwrap()
+    }
+
+    fn count_lines(text: &str, prefix: &str) -> usize {
+        text.lines().filter(|line| line.starts_with(prefix)).count()
+    }
+
+    fn triangles(parts: &[PartRecord], detail: usize) -> usize {
+        parts
+            .iter()
+            .map(|part| mesh_counts(part, TrussStyle::Box, Tessellation::for_lod(detail)).1)
+            .sum()
+    }
+
+    #[test]
+    fn budget_lowers_detail_before_dropping_parts() {
+        let ball = |x: f32| PartRecord {
+            shape: 0,
+            ..PartRecord::block(at(2.0, 2.0, 2.0), at(x, 0.0, 0.0))
+        };
+        let mut parts: Vec<PartRecord> = (0..10).map(|i| ball(i as f32 * 4.0)).collect();
+        let opts = ExportOptions::default();
+        let detail = fit_triangle_budget(&opts, &mut parts, 500).unwrap();
+        assert_eq!(parts.len(), 10);
+        assert!(detail > 0);
+        assert!(triangles(&parts, detail) <= 500);
+    }
+
+    #[test]
+    fn budget_drops_the_smallest_parts() {
+        let mut parts: Vec<PartRecord> = (1..=10)
+            .map(|size| {
+                PartRecord::block(at(size as f32, 1.0, 1.0), at(size as f32 * 20.0, 0.0, 0.0))
+            })
+            .collect();
+        let opts = ExportOptions::default();
+        let detail = fit_triangle_budget(&opts, &mut parts, 50).unwrap();
+        // Four blocks at 12 triangles each, kept in file order.
+        assert!(triangles(&parts, detail) <= 50);
+        let sizes: Vec<f32> = parts.iter().map(|part| part.size.x).collect();
+        assert_eq!(sizes, [7.0, 8.0, 9.0, 10.0]);
+    }
+
+    #[test]
+    fn budget_too_small_for_any_part_is_an_error() {
+        let mut parts = vec![PartRecord::block(at(4.0, 1.0, 2.0), at(0.0, 0.0, 0.0))];
+        let opts = ExportOptions::default();
+        let result = fit_triangle_budget(&opts, &mut parts, 5);
+        assert!(matches!(result, Err(ExportError::Unsupported(_))));
+    }
+
+    #[test]
+    fn welding_keeps_normals_and_comments() {
+        let dom = place((0..2).map(|i| {
+            block(at(2.0, 2.0, 2.0), at(i as f32 * 2.0, 0.0, 0.0))
+                .with_property("UniqueId", UniqueId::new(i, 0, 1))
+        }));
+        let opts = ExportOptions::builder()
+            .weld(Some(0.0))
+            .annotate_faces(true)
+            .build()
+            .unwrap();
+        let obj = export_text(&dom, &opts);
+
+        // The cubes share the four corners where they touch.
+        assert_eq!(count_lines(&obj, "v "), 12);
+        assert_eq!(count_lines(&obj, "vn "), 12);
+        assert_eq!(count_lines(&obj, "# UniqueId: "), 2);
+        assert_eq!(count_lines(&obj, "# anchored: "), 2);
+        let faces: Vec<&str> = obj.lines().filter(|line| line.starts_with("f ")).collect();
+        assert_eq!(faces.len(), 24);
+        assert!(
+            faces
+                .iter()
+                .all(|face| face.split(' ').skip(1).all(|c| c.contains("//")))
+        );
+    }
+
+    /// The `v` lines' positions.
+    fn vertices(obj: &str) -> Vec<Vector3> {
+        obj.lines()
+            .filter_map(|line| line.strip_prefix("v "))
+            .map(|rest| {
+                let xyz: Vec<f32> = rest.split(' ').map(|n| n.parse().unwrap()).collect();
+                at(xyz[0], xyz[1], xyz[2])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn special_mesh_offset_moves_the_part_in_its_own_space() {
+        // A 2x1x2 block at the origin, turned by `orientation`, with a
+        // SpecialMesh lifting it 2 studs along its own Y.
+        let offset_block = |orientation: Matrix3| {
+            let mut dom = place([InstanceBuilder::new("Part")
+                .with_property("Size", at(2.0, 1.0, 2.0))
+                .with_property("CFrame", CFrame::new(at(0.0, 0.0, 0.0), orientation))]);
+            let workspace = dom.root().children()[0];
+            let part = dom.get_by_ref(workspace).unwrap().children()[0];
+            dom.insert(
+                part,
+                InstanceBuilder::new("SpecialMesh").with_property("Offset", at(0.0, 2.0, 0.0)),
+            );
+            vertices(&export_text(&dom, &ExportOptions::default()))
+        };
+
+        let plain = vertices(&export_text(
+            &place([block(at(2.0, 1.0, 2.0), at(0.0, 0.0, 0.0))]),
+            &ExportOptions::default(),
+        ));
+        let lifted = offset_block(Matrix3::identity());
+        assert_eq!(plain.len(), lifted.len());
+        for (a, b) in plain.iter().zip(&lifted) {
+            assert_eq!(*b, at(a.x, a.y + 2.0, a.z));
+        }
+
+        // Turned a quarter around Z, the part's own Y points along world -X.
+        let turned = offset_block(Matrix3::new(
+            at(0.0, -1.0, 0.0),
+            at(1.0, 0.0, 0.0),
+            at(0.0, 0.0, 1.0),
+        ));
+        let n = turned.len() as f32;
+        let centre = turned.iter().fold(at(0.0, 0.0, 0.0), |s, v| {
+            at(s.x + v.x / n, s.y + v.y / n, s.z + v.z / n)
+        });
+        assert!((centre.x + 2.0).abs() < 1e-5, "centre at {:?}", centre);
+        assert!(centre.y.abs() < 1e-5 && centre.z.abs() < 1e-5);
+    }
+
+    /// Cubes of side 2 at each position, turned by its orientation, meshed
+    /// in world space.
+    fn cubes(placements: &[(Vector3, Matrix3)]) -> (Vec<PartRecord>, Vec<Mesh>) {
+        placements
+            .iter()
+            .map(|&(position, orientation)| {
+                let mut part = PartRecord::block(at(2.0, 2.0, 2.0), position);
+                part.cframe.orientation = orientation;
+                let (mut vertices, faces) = crate::mesh::cube_mesh(part.size);
+                transform_vertices(&mut vertices, &part.cframe);
+                (part, (vertices, faces))
+            })
+            .unzip()
+    }
+
+    fn hidden_faces(placements: &[(Vector3, Matrix3)]) -> (usize, Vec<usize>) {
+        let (parts, mut meshes) = cubes(placements);
+        let removed = remove_hidden_faces(&parts, &mut meshes, 0.001);
+        (
+            removed,
+            meshes.iter().map(|(_, faces)| faces.len()).collect(),
+        )
+    }
+
+    #[test]
+    fn stacked_cubes_lose_the_sides_they_share() {
+        let identity = Matrix3::identity();
+        let (removed, left) =
+            hidden_faces(&[(at(0.0, 0.0, 0.0), identity), (at(0.0, 2.0, 0.0), identity)]);
+        assert_eq!(removed, 4);
+        assert_eq!(left, [10, 10]);
+
+        // A third on top only hides the middle one's other side.
+        let (removed, left) = hidden_faces(&[
+            (at(0.0, 0.0, 0.0), identity),
+            (at(0.0, 2.0, 0.0), identity),
+            (at(0.0, 4.0, 0.0), identity),
+        ]);
+        assert_eq!(removed, 8);
+        assert_eq!(left, [10, 8, 10]);
+    }
+
+    #[test]
+    fn zero_epsilon_only_matches_exact_corners() {
+        let identity = Matrix3::identity();
+        let (parts, mut meshes) =
+            cubes(&[(at(0.0, 0.0, 0.0), identity), (at(0.0, 2.0, 0.0), identity)]);
+        assert_eq!(remove_hidden_faces(&parts, &mut meshes, 0.0), 4);
+
+        // A hair apart still counts as touching with an epsilon, but not without.
+        let apart = [
+            (at(0.0, 0.0, 0.0), identity),
+            (at(0.0, 2.0001, 0.0), identity),
+        ];
+        let (parts, mut meshes) = cubes(&apart);
+        assert_eq!(remove_hidden_faces(&parts, &mut meshes, 0.0), 0);
+        assert_eq!(hidden_faces(&apart).0, 4);
+    }
+
+    /// How many triangles each of `parts` keeps after `omit_interior_faces`.
+    fn interior_faces(parts: &[PartRecord]) -> Vec<usize> {
+        let mut meshes: Vec<Mesh> = parts
+            .iter()
+            .map(|part| {
+                let (mut vertices, faces) =
+                    local_mesh(part, TrussStyle::Box, Tessellation::default());
+                transform_vertices(&mut vertices, &part.cframe);
+                (vertices, faces)
+            })
+            .collect();
+        omit_interior_faces(parts, &mut meshes);
+        meshes.iter().map(|(_, faces)| faces.len()).collect()
+    }
+
+    #[test]
+    fn face_covered_by_a_block_is_omitted() {
+        // The plate covers all of the cube's top, the cube only part of the
+        // plate's bottom.
+        let cube = || PartRecord::block(at(2.0, 2.0, 2.0), at(0.0, 0.0, 0.0));
+        let plate = PartRecord::block(at(4.0, 1.0, 4.0), at(0.0, 1.5, 0.0));
+        assert_eq!(interior_faces(&[cube(), plate]), [10, 12]);
+
+        // Thinner than the furthest probe, and not stepped through.
+        let sheet = PartRecord::block(at(4.0, 0.02, 4.0), at(0.0, 1.01, 0.0));
+        assert_eq!(interior_faces(&[cube(), sheet]), [10, 12]);
+    }
+
+    #[test]
+    fn face_next_to_a_gap_is_kept() {
+        let cube = PartRecord::block(at(2.0, 2.0, 2.0), at(0.0, 0.0, 0.0));
+        let above = PartRecord::block(at(2.0, 2.0, 2.0), at(0.0, 2.1, 0.0));
+        assert_eq!(interior_faces(&[cube, above]), [12, 12]);
+    }
+
+    #[test]
+    fn face_against_a_wedge_is_omitted() {
+        let wedge = PartRecord {
+            class: "WedgePart".to_string(),
+            ..PartRecord::block(at(2.0, 2.0, 2.0), at(0.0, 0.0, 0.0))
+        };
+        // Against the wedge's upright back, and against the bottom edge its
+        // slope comes down to.
+        let behind = PartRecord::block(at(2.0, 2.0, 2.0), at(0.0, 0.0, 2.0));
+        let in_front = PartRecord::block(at(2.0, 2.0, 2.0), at(0.0, 0.0, -2.0));
+        assert_eq!(interior_faces(&[wedge, behind, in_front]), [6, 10, 12]);
+    }
+
+    #[test]
+    fn offset_or_apart_cubes_keep_every_face() {
+        let identity = Matrix3::identity();
+        // Touching, but only over half the shared side.
+        let (removed, _) =
+            hidden_faces(&[(at(0.0, 0.0, 0.0), identity), (at(1.0, 2.0, 0.0), identity)]);
+        assert_eq!(removed, 0);
+        // Lined up, with a gap in between.
+        let (removed, _) =
+            hidden_faces(&[(at(0.0, 0.0, 0.0), identity), (at(0.0, 2.5, 0.0), identity)]);
+        assert_eq!(removed, 0);
+        // The same cube twice: both sides face the same way.
+        let (removed, _) =
+            hidden_faces(&[(at(0.0, 0.0, 0.0), identity), (at(0.0, 0.0, 0.0), identity)]);
+        assert_eq!(removed, 0);
+    }
+
+    #[test]
+    fn rotated_cubes_match_when_their_sides_line_up() {
+        let identity = Matrix3::identity();
+        let quarter = Matrix3::new(at(0.0, 0.0, 1.0), at(0.0, 1.0, 0.0), at(-1.0, 0.0, 0.0));
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        let eighth = Matrix3::new(at(h, 0.0, h), at(0.0, 1.0, 0.0), at(-h, 0.0, h));
+
+        // A quarter turn lands on the same square, split along the other diagonal.
+        let (removed, _) =
+            hidden_faces(&[(at(0.0, 0.0, 0.0), identity), (at(0.0, 2.0, 0.0), quarter)]);
+        assert_eq!(removed, 4);
+        // Both turned the same way still share a side exactly.
+        let (removed, _) =
+            hidden_faces(&[(at(0.0, 0.0, 0.0), eighth), (at(0.0, 2.0, 0.0), eighth)]);
+        assert_eq!(removed, 4);
+        // Side by side along their turned X axis too.
+        let (removed, _) = hidden_faces(&[
+            (at(0.0, 0.0, 0.0), eighth),
+            (at(2.0 * h, 0.0, -2.0 * h), eighth),
+        ]);
+        assert_eq!(removed, 4);
+        // An eighth turn on top of a straight one only covers part of it.
+        let (removed, _) =
+            hidden_faces(&[(at(0.0, 0.0, 0.0), identity), (at(0.0, 2.0, 0.0), eighth)]);
+        assert_eq!(removed, 0);
+    }
+
+    /// The volume the parts' meshes enclose, summing the signed tetrahedra
+    /// each triangle makes with the origin.
+    fn enclosed_volume(parts: &[PartRecord]) -> f32 {
+        parts
+            .iter()
+            .map(|part| {
+                let (mut vertices, faces) =
+                    local_mesh(part, TrussStyle::Box, Tessellation::default());
+                transform_vertices(&mut vertices, &part.cframe);
+                faces
+                    .iter()
+                    .map(|&(a, b, c)| dot(vertices[a], cross(vertices[b], vertices[c])) / 6.0)
+                    .sum::<f32>()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn meshes_enclose_their_shapes_volume() {
+        let brick = PartRecord::block(at(4.0, 1.0, 2.0), at(10.0, -3.0, 7.0));
+        assert!((enclosed_volume(&[brick]) - 8.0).abs() < 1e-4);
+
+        let mut ball = PartRecord::block(at(2.0, 2.0, 2.0), at(-5.0, 1.0, 0.0));
+        ball.shape = 0;
+        // Tessellated, the ball sits just inside the true sphere.
+        let sphere = 4.0 / 3.0 * std::f32::consts::PI;
+        let volume = enclosed_volume(&[ball]);
+        assert!(volume < sphere && volume > sphere * 0.95, "{}", volume);
+    }
+
+    #[test]
+    fn merging_boxes_keeps_the_volume() {
+        // Two layers of 5x3 bricks, meeting face to face.
+        let mut parts: Vec<PartRecord> = (0..30)
+            .map(|i| {
+                let (x, z, y) = (i % 5, i / 5 % 3, i / 15);
+                PartRecord::block(
+                    at(4.0, 1.0, 2.0),
+                    at(x as f32 * 4.0, y as f32, z as f32 * 2.0),
+                )
+            })
+            .collect();
+        // A red brick on the end, a turned one on top and a ball beside them.
+        let mut red = PartRecord::block(at(4.0, 1.0, 2.0), at(20.0, 0.0, 0.0));
+        red.color = (255, 0, 0, 255);
+        let mut turned = PartRecord::block(at(4.0, 1.0, 2.0), at(8.0, 2.0, 2.0));
+        turned.cframe.orientation =
+            Matrix3::new(at(0.0, 0.0, 1.0), at(0.0, 1.0, 0.0), at(-1.0, 0.0, 0.0));
+        let mut ball = PartRecord::block(at(2.0, 2.0, 2.0), at(-4.0, 0.0, 0.0));
+        ball.shape = 0;
+        parts.extend([red, turned, ball]);
+
+        let before = enclosed_volume(&parts);
+        let (merged, folded) = merge_boxes(parts);
+        assert_eq!(folded, 30);
+        assert_eq!(merged.len(), 4);
+        assert!((enclosed_volume(&merged) - before).abs() < 1e-3);
+        let big = merged.iter().find(|part| part.size.x > 4.0).unwrap();
+        assert_eq!(big.size, at(20.0, 2.0, 6.0));
+    }
+
+    fn mtllib(obj: &str, mtl: &str, style: MtllibStyle) -> String {
+        mtllib_reference(Path::new(obj), Path::new(mtl), style).unwrap()
+    }
+
+    #[test]
+    fn mtllib_names_an_mtl_beside_the_obj() {
+        for style in [MtllibStyle::Filename, MtllibStyle::RelativeToObj] {
+            assert_eq!(mtllib("out/scene.obj", "out/scene.mtl", style), "scene.mtl");
+        }
+    }
+
+    #[test]
+    fn mtllib_finds_an_mtl_in_another_directory() {
+        let (obj, sibling, parent) = ("out/obj/scene.obj", "out/mtl/scene.mtl", "out/scene.mtl");
+        assert_eq!(mtllib(obj, sibling, MtllibStyle::Filename), "scene.mtl");
+        assert_eq!(
+            mtllib(obj, sibling, MtllibStyle::RelativeToObj),
+            "../mtl/scene.mtl"
+        );
+        assert_eq!(
+            mtllib(obj, parent, MtllibStyle::RelativeToObj),
+            "../scene.mtl"
+        );
+        // `.` and `..` in either path are worked out before comparing.
+        assert_eq!(
+            mtllib(
+                "out/./obj/../obj/scene.obj",
+                "out/obj/../mtl/scene.mtl",
+                MtllibStyle::RelativeToObj
+            ),
+            "../mtl/scene.mtl"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mtllib_climbs_to_the_root_when_thats_all_they_share() {
+        assert_eq!(
+            mtllib(
+                "/home/me/scene.obj",
+                "/tmp/mtl/scene.mtl",
+                MtllibStyle::RelativeToObj
+            ),
+            "../../tmp/mtl/scene.mtl"
+        );
+        assert_eq!(
+            relative_path(Path::new("/"), Path::new("/scene.mtl")),
+            Some(PathBuf::from("scene.mtl"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mtllib_gives_an_absolute_path() {
+        assert_eq!(
+            mtllib(
+                "out/scene.obj",
+                "/srv/out/../mtl/./scene.mtl",
+                MtllibStyle::Absolute
+            ),
+            "/srv/mtl/scene.mtl"
+        );
+        let relative = mtllib("out/scene.obj", "mtl/scene.mtl", MtllibStyle::Absolute);
+        assert!(relative.starts_with('/') && relative.ends_with("/mtl/scene.mtl"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn mtllib_on_another_drive_is_absolute() {
+        assert_eq!(
+            relative_path(Path::new(r"C:\out"), Path::new(r"D:\mtl\scene.mtl")),
+            None
+        );
+        assert_eq!(
+            mtllib(
+                r"C:\out\scene.obj",
+                r"D:\mtl\scene.mtl",
+                MtllibStyle::RelativeToObj
+            ),
+            "D:/mtl/scene.mtl"
+        );
+        assert_eq!(
+            mtllib(
+                r"C:\out\scene.obj",
+                r"C:\mtl\scene.mtl",
+                MtllibStyle::RelativeToObj
+            ),
+            "../mtl/scene.mtl"
+        );
+    }
+
+    #[test]
+    fn cylindrical_constraint_turns_to_its_clamped_angle() {
+        let angle = |constraint: InstanceBuilder| {
+            let dom = WeakDom::new(constraint);
+            let (shape, to) = constraint_shape(dom.root(), at(0.0, 0.0, 0.0), at(0.0, 4.0, 0.0));
+            assert_eq!(to, at(0.0, 4.0, 0.0));
+            let ConstraintShape::Tube { sides, angle, .. } = shape else {
+                panic!("not a tube");
+            };
+            assert_eq!(sides, 8);
+            angle.to_degrees()
+        };
+        let cylinder = || InstanceBuilder::new("CylindricalConstraint");
+        assert_eq!(angle(cylinder()), 0.0);
+        let turned = cylinder().with_property("CurrentAngle", 30.0f32);
+        assert!((angle(turned) - 30.0).abs() < 1e-4);
+
+        let limited = |current: f32, enabled: bool| {
+            cylinder()
+                .with_property("CurrentAngle", current)
+                .with_property("AngularLimitsEnabled", enabled)
+                .with_property("LowerAngle", -20.0f32)
+                .with_property("UpperAngle", 45.0f32)
+        };
+        assert!((angle(limited(90.0, true)) - 45.0).abs() < 1e-4);
+        assert!((angle(limited(-90.0, true)) + 20.0).abs() < 1e-4);
+        assert!((angle(limited(90.0, false)) - 90.0).abs() < 1e-4);
+
+        // The tube's corners are turned by it about the axis.
+        let corner = |degrees: f32| {
+            tube_mesh(
+                at(0.0, 0.0, 0.0),
+                at(0.0, 4.0, 0.0),
+                8,
+                1.0,
+                degrees.to_radians(),
+            )
+            .0[0]
+        };
+        let (still, turned) = (corner(0.0), corner(30.0));
+        assert!((dot(still, turned) - 30f32.to_radians().cos()).abs() < 1e-5);
+        assert!(turned.y.abs() < 1e-6);
+    }
+
+    #[test]
+    fn lod_screen_sizes_shrink_with_distance() {
+        let cube = |x: f32| (vec![at(x, 0.0, 0.0), at(x + 2.0, 2.0, 2.0)], Vec::new());
+        // A 12-stud-long box around both, so a radius of half its diagonal.
+        let radius = bounding_radius(&[cube(0.0), (Vec::new(), Vec::new()), cube(10.0)]);
+        assert!((radius - (12f32 * 12.0 + 4.0 + 4.0).sqrt() / 2.0).abs() < 1e-5);
+        assert_eq!(bounding_radius(&[]), 0.0);
+
+        assert_eq!(lod_screen_size(0, 100.0), 180.0);
+        // 200 studs away, a 100-stud radius is 2 * atan(1/2) across.
+        assert!((lod_screen_size(1, 100.0) - 53.130_1).abs() < 1e-3);
+        assert!(lod_screen_size(2, 100.0) < lod_screen_size(1, 100.0));
+        assert_eq!(lod_screen_size(1, 0.0), 0.0);
+    }
+
+    #[test]
+    fn selection_box_material_has_its_surface_color_and_thickness() {
+        let mut dom = place([block(at(2.0, 2.0, 2.0), at(0.0, 0.0, 0.0))]);
+        let part = dom.get_by_ref(dom.root().children()[0]).unwrap().children()[0];
+        let boxes = [
+            (Color3::new(1.0, 0.0, 0.0), 0.3),
+            (Color3::new(1.0, 0.0, 0.0), 0.3),
+            (Color3::new(0.0, 0.0, 1.0), 0.05),
+        ];
+        for (surface, thickness) in boxes {
+            dom.insert(
+                part,
+                InstanceBuilder::new("SelectionBox")
+                    .with_property("Adornee", part)
+                    .with_property("Color3", Color3::new(0.0, 1.0, 0.0))
+                    .with_property("SurfaceColor3", surface)
+                    .with_property("LineThickness", thickness as f32),
+            );
+        }
+        let opts = ExportOptions {
+            export_selection_effects: true,
+            ..Default::default()
+        };
+        let (mut obj, mut mtl) = (Vec::new(), Vec::new());
+        export_dom(&opts, &dom, &mut obj, Some(&mut mtl), None).unwrap();
+        let (obj, mtl) = (
+            String::from_utf8(obj).unwrap(),
+            String::from_utf8(mtl).unwrap(),
+        );
+
+        assert_eq!(count_lines(&obj, "l "), 36);
+        assert_eq!(count_lines(&obj, "usemtl selection_FF0000_0.3"), 2);
+        assert_eq!(count_lines(&obj, "usemtl selection_0000FF_0.05"), 1);
+        assert_eq!(count_lines(&mtl, "newmtl selection_"), 2);
+        assert!(mtl.contains("newmtl selection_FF0000_0.3\nKd 1 0 0\n"));
+    }
+}
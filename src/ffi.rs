@@ -0,0 +1,533 @@
+//! A C interface for calling the converter in-process, behind the `ffi`
+//! feature. `include/rbx_obj.h` declares it; regenerate that with
+//! `cbindgen --config cbindgen.toml --output include/rbx_obj.h` after
+//! changing anything here.
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char, c_int},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use crate::{ExportOptions, OptionValue, export_dom};
+
+/// The conversion worked and the output holds the OBJ and MTL.
+pub const RBXL_OK: c_int = 0;
+/// A pointer was null, or the options weren't valid JSON, named an option
+/// this doesn't know or didn't go together.
+pub const RBXL_INVALID_ARGUMENT: c_int = 1;
+/// The bytes didn't decode or the export failed.
+pub const RBXL_EXPORT_FAILED: c_int = 2;
+/// The converter panicked. That's a bug; the message says where.
+pub const RBXL_PANIC: c_int = 3;
+
+/// What `rbxl_convert_buffer` wrote. Neither buffer is NUL-terminated. Pass
+/// it to `rbxl_free_output` once done with it.
+#[repr(C)]
+pub struct RbxlOutput {
+    pub obj: *mut u8,
+    pub obj_len: usize,
+    pub mtl: *mut u8,
+    pub mtl_len: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Converts the `len` bytes of a binary place or model at `data`, filling
+/// in `out` on success. `options_json` is a JSON object of option names (as
+/// in `ExportOptions`) to values, like `{"weld": 0.001, "merge_boxes":
+/// true, "services": ["Workspace", "Lighting"]}`, taking what
+/// `ExportOptions::set` does, or null for the defaults. Returns one of the `RBXL_*` codes; on
+/// anything but `RBXL_OK`, `out` is left alone and `rbxl_last_error` says
+/// what went wrong.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, `options_json` must be null
+/// or a NUL-terminated string, and `out` must point to an `RbxlOutput` that
+/// can be written.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rbxl_convert_buffer(
+    data: *const u8,
+    len: usize,
+    options_json: *const c_char,
+    out: *mut RbxlOutput,
+) -> c_int {
+    let convert = || {
+        if data.is_null() || out.is_null() {
+            return Err((
+                RBXL_INVALID_ARGUMENT,
+                "data and out can't be null".to_string(),
+            ));
+        }
+        let opts = if options_json.is_null() {
+            Ok(ExportOptions::default())
+        } else {
+            // SAFETY: the caller promises a NUL-terminated string.
+            let json = unsafe { CStr::from_ptr(options_json) };
+            json.to_str()
+                .map_err(|e| e.to_string())
+                .and_then(parse_options)
+        }
+        .map_err(|e| (RBXL_INVALID_ARGUMENT, format!("bad options: {}", e)))?;
+
+        // SAFETY: the caller promises `len` readable bytes.
+        let bytes = unsafe { slice::from_raw_parts(data, len) };
+        let dom = rbx_binary::from_reader(bytes).map_err(|e| {
+            (
+                RBXL_EXPORT_FAILED,
+                format!("couldn't decode the file: {}", e),
+            )
+        })?;
+        let (mut obj, mut mtl) = (Vec::new(), Vec::new());
+        export_dom(&dom, &mut obj, Some(&mut mtl), &opts)
+            .map_err(|e| (RBXL_EXPORT_FAILED, e.to_string()))?;
+        Ok((obj, mtl))
+    };
+    // SAFETY: `convert` checks `out` isn't null before it returns anything,
+    // and the caller promises it's writable.
+    unsafe { deliver(out, convert) }
+}
+
+/// Runs `convert`, writing the OBJ and MTL it returns into `out`, or setting
+/// the last error to why it failed or where it panicked, and returns the
+/// `RBXL_*` code for how it went.
+///
+/// # Safety
+///
+/// `out` must be writable if `convert` returns the OBJ and MTL.
+unsafe fn deliver(
+    out: *mut RbxlOutput,
+    convert: impl FnOnce() -> Result<(Vec<u8>, Vec<u8>), (c_int, String)>,
+) -> c_int {
+    let (code, message) = match panic::catch_unwind(AssertUnwindSafe(convert)) {
+        Ok(Ok((obj, mtl))) => {
+            let (obj, obj_len) = into_raw(obj);
+            let (mtl, mtl_len) = into_raw(mtl);
+            // SAFETY: the caller promises it's writable.
+            unsafe {
+                out.write(RbxlOutput {
+                    obj,
+                    obj_len,
+                    mtl,
+                    mtl_len,
+                })
+            };
+            return RBXL_OK;
+        }
+        Ok(Err((code, message))) => (code, message),
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            (RBXL_PANIC, format!("panicked: {}", message))
+        }
+    };
+    set_last_error(message);
+    code
+}
+
+/// Frees the buffers of an output filled in by `rbxl_convert_buffer` and
+/// zeroes it, so freeing it twice does nothing. Null is ignored.
+///
+/// # Safety
+///
+/// `output` must be null or point to an `RbxlOutput` that's zeroed or was
+/// filled in by `rbxl_convert_buffer`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rbxl_free_output(output: *mut RbxlOutput) {
+    // SAFETY: the caller promises null or a valid output.
+    let Some(output) = (unsafe { output.as_mut() }) else {
+        return;
+    };
+    for (data, len) in [
+        (&mut output.obj, &mut output.obj_len),
+        (&mut output.mtl, &mut output.mtl_len),
+    ] {
+        if !data.is_null() {
+            // SAFETY: made by `into_raw` from a boxed slice of this length.
+            drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(*data, *len)) });
+        }
+        *data = ptr::null_mut();
+        *len = 0;
+    }
+}
+
+/// The message for the last call on this thread that failed, or null if
+/// none has. It stays valid until the next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn rbxl_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+fn set_last_error(message: String) {
+    // Messages with a NUL in them lose everything from it on.
+    let message = CString::new(message).unwrap_or_else(|e| {
+        let end = e.nul_position();
+        CString::new(&e.into_vec()[..end]).unwrap()
+    });
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn into_raw(bytes: Vec<u8>) -> (*mut u8, usize) {
+    let len = bytes.len();
+    (Box::into_raw(bytes.into_boxed_slice()).cast(), len)
+}
+
+/// Reads a JSON object of option names to values, setting each with
+/// `ExportOptions::set`.
+fn parse_options(json: &str) -> Result<ExportOptions, String> {
+    let mut opts = ExportOptions::default();
+    for (key, value) in parse_object(json)? {
+        opts.set(&key, value).map_err(|e| e.to_string())?;
+    }
+    opts.validate().map_err(|e| e.to_string())?;
+    Ok(opts)
+}
+
+/// Parses a JSON object whose values are `true`, `false`, `null`, numbers,
+/// strings or arrays of those, keeping its keys in order.
+fn parse_object(json: &str) -> Result<Vec<(String, OptionValue)>, String> {
+    let mut parser = JsonParser {
+        rest: json.trim_start(),
+    };
+    parser.expect('{')?;
+    let mut fields = Vec::new();
+    if !parser.eat('}') {
+        loop {
+            let key = parser.string()?;
+            parser.expect(':')?;
+            fields.push((key, parser.value()?));
+            if parser.eat('}') {
+                break;
+            }
+            parser.expect(',')?;
+        }
+    }
+    if !parser.rest.is_empty() {
+        return Err(format!("unexpected {} after the object", parser.rest));
+    }
+    Ok(fields)
+}
+
+/// What's left of the JSON being parsed, always without leading whitespace.
+struct JsonParser<'a> {
+    rest: &'a str,
+}
+
+impl JsonParser<'_> {
+    /// Skips `c` if it's next.
+    fn eat(&mut self, c: char) -> bool {
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest.trim_start();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(format!("expected {}, got {}", c, self.next_token()))
+        }
+    }
+
+    /// The start of what's left, for error messages.
+    fn next_token(&self) -> String {
+        match self.rest.chars().next() {
+            Some(_) => self.rest.chars().take(16).collect(),
+            None => "the end".to_string(),
+        }
+    }
+
+    fn value(&mut self) -> Result<OptionValue, String> {
+        if self.eat('[') {
+            let mut items = Vec::new();
+            if !self.eat(']') {
+                loop {
+                    items.push(self.value()?);
+                    if self.eat(']') {
+                        break;
+                    }
+                    self.expect(',')?;
+                }
+            }
+            return Ok(OptionValue::List(items));
+        }
+        if self.rest.starts_with('"') {
+            return self.string().map(OptionValue::String);
+        }
+        for (word, value) in [
+            ("true", OptionValue::Bool(true)),
+            ("false", OptionValue::Bool(false)),
+            ("null", OptionValue::Null),
+        ] {
+            if let Some(rest) = self.rest.strip_prefix(word) {
+                self.rest = rest.trim_start();
+                return Ok(value);
+            }
+        }
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+            .unwrap_or(self.rest.len());
+        let (number, rest) = self.rest.split_at(end);
+        let number = number
+            .parse()
+            .ok()
+            .filter(|n: &f64| n.is_finite())
+            .ok_or_else(|| format!("expected a value, got {}", self.next_token()))?;
+        self.rest = rest.trim_start();
+        Ok(OptionValue::Number(number))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let mut chars = self
+            .rest
+            .strip_prefix('"')
+            .ok_or_else(|| format!("expected a string, got {}", self.next_token()))?
+            .char_indices();
+        let mut string = String::new();
+        let unterminated = || "unterminated string".to_string();
+        loop {
+            let (at, c) = chars.next().ok_or_else(unterminated)?;
+            match c {
+                '"' => {
+                    self.rest = self.rest[at + 2..].trim_start();
+                    return Ok(string);
+                }
+                '\\' => {
+                    let (_, escaped) = chars.next().ok_or_else(unterminated)?;
+                    string.push(match escaped {
+                        '"' | '\\' | '/' => escaped,
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'u' => {
+                            let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .filter(|_| hex.len() == 4)
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| format!("bad escape \\u{}", hex))?
+                        }
+                        other => return Err(format!("bad escape \\{}", other)),
+                    });
+                }
+                c if c < ' ' => return Err("control character in a string".to_string()),
+                c => string.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An 8x1x8 floor with a 2x2x2 crate on it, as the Python tests use.
+    const TWO_BLOCKS: &[u8] = include_bytes!("../python/tests/two_blocks.rbxm");
+
+    fn zeroed() -> RbxlOutput {
+        RbxlOutput {
+            obj: ptr::null_mut(),
+            obj_len: 0,
+            mtl: ptr::null_mut(),
+            mtl_len: 0,
+        }
+    }
+
+    fn last_error() -> String {
+        // SAFETY: only called after a failing call, so it's a live message.
+        unsafe { CStr::from_ptr(rbxl_last_error()) }
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    /// Runs `rbxl_convert_buffer` on `data`, returning its code and, when it
+    /// worked, the OBJ and MTL.
+    fn convert(data: &[u8], options: Option<&str>) -> (c_int, Option<(String, String)>) {
+        let options = options.map(|json| CString::new(json).unwrap());
+        let mut out = zeroed();
+        // SAFETY: everything points at live values of the right lengths.
+        let code = unsafe {
+            rbxl_convert_buffer(
+                data.as_ptr(),
+                data.len(),
+                options.as_ref().map_or(ptr::null(), |json| json.as_ptr()),
+                &mut out,
+            )
+        };
+        if code != RBXL_OK {
+            assert!(out.obj.is_null() && out.mtl.is_null());
+            return (code, None);
+        }
+        // SAFETY: filled in by the successful call above.
+        let text = |data, len| {
+            String::from_utf8(unsafe { slice::from_raw_parts(data, len) }.to_vec()).unwrap()
+        };
+        let written = (text(out.obj, out.obj_len), text(out.mtl, out.mtl_len));
+        // SAFETY: as above, and freeing twice is allowed.
+        unsafe {
+            rbxl_free_output(&mut out);
+            assert!(out.obj.is_null() && out.obj_len == 0 && out.mtl.is_null());
+            rbxl_free_output(&mut out);
+        }
+        (code, Some(written))
+    }
+
+    fn count_lines(text: &str, prefix: &str) -> usize {
+        text.lines().filter(|line| line.starts_with(prefix)).count()
+    }
+
+    #[test]
+    fn converts_a_buffer() {
+        let (code, written) = convert(TWO_BLOCKS, None);
+        assert_eq!(code, RBXL_OK);
+        let (obj, mtl) = written.unwrap();
+        assert_eq!(count_lines(&obj, "v "), 16);
+        assert_eq!(count_lines(&obj, "f "), 24);
+        assert_eq!(count_lines(&mtl, "newmtl "), 2);
+
+        // Options of every kind.
+        let options = r#"{"weld": 0.001, "services": ["Workspace"], "normals": "flat"}"#;
+        let (code, written) = convert(TWO_BLOCKS, Some(options));
+        assert_eq!(code, RBXL_OK, "{}", last_error());
+        assert!(written.unwrap().0.starts_with("# "));
+    }
+
+    #[test]
+    fn null_pointers_are_invalid_arguments() {
+        let mut out = zeroed();
+        // SAFETY: null is what's being tested, and `out` is live.
+        let code = unsafe { rbxl_convert_buffer(ptr::null(), 0, ptr::null(), &mut out) };
+        assert_eq!(code, RBXL_INVALID_ARGUMENT);
+        assert_eq!(last_error(), "data and out can't be null");
+
+        // SAFETY: as above.
+        let code = unsafe {
+            rbxl_convert_buffer(
+                TWO_BLOCKS.as_ptr(),
+                TWO_BLOCKS.len(),
+                ptr::null(),
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(code, RBXL_INVALID_ARGUMENT);
+        // SAFETY: null is ignored.
+        unsafe { rbxl_free_output(ptr::null_mut()) };
+    }
+
+    #[test]
+    fn bad_options_and_bytes_are_reported() {
+        let (code, _) = convert(TWO_BLOCKS, Some(r#"{"no_such_option": true}"#));
+        assert_eq!(code, RBXL_INVALID_ARGUMENT);
+        assert_eq!(last_error(), "bad options: unknown option no_such_option");
+
+        let (code, _) = convert(TWO_BLOCKS, Some(r#"{"stream": true, "weld": 0}"#));
+        assert_eq!(code, RBXL_INVALID_ARGUMENT);
+        assert_eq!(
+            last_error(),
+            "bad options: stream can't be combined with weld"
+        );
+
+        let (code, _) = convert(b"not a place", None);
+        assert_eq!(code, RBXL_EXPORT_FAILED);
+        assert!(last_error().starts_with("couldn't decode the file"));
+    }
+
+    #[test]
+    fn panics_come_back_as_rbxl_panic() {
+        let mut out = zeroed();
+        // SAFETY: `out` is live, and isn't written since nothing's returned.
+        let code = unsafe { deliver(&mut out, || panic!("boom")) };
+        assert_eq!(code, RBXL_PANIC);
+        assert_eq!(last_error(), "panicked: boom");
+        assert!(out.obj.is_null());
+
+        // SAFETY: as above.
+        let code = unsafe { deliver(&mut out, || panic!("{} {}", "formatted", 2)) };
+        assert_eq!(code, RBXL_PANIC);
+        assert_eq!(last_error(), "panicked: formatted 2");
+    }
+
+    #[test]
+    fn parses_json_objects() {
+        assert!(parse_object(" { } ").unwrap().is_empty());
+        let fields = parse_object(
+            r#"{"name_separator": ",", "services": ["Work:space", "A\"b\\cé\n"],
+                "weld": -1.5e-3, "mirror": [true, false, null], "empty": []}"#,
+        )
+        .unwrap();
+        let text = |s: &str| OptionValue::String(s.to_string());
+        assert_eq!(
+            fields,
+            [
+                ("name_separator".to_string(), text(",")),
+                (
+                    "services".to_string(),
+                    OptionValue::List(vec![text("Work:space"), text("A\"b\\c\u{e9}\n")])
+                ),
+                ("weld".to_string(), OptionValue::Number(-1.5e-3)),
+                (
+                    "mirror".to_string(),
+                    OptionValue::List(vec![
+                        OptionValue::Bool(true),
+                        OptionValue::Bool(false),
+                        OptionValue::Null
+                    ])
+                ),
+                ("empty".to_string(), OptionValue::List(Vec::new())),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        for json in [
+            "",
+            "[]",
+            "{",
+            r#"{"weld" 1}"#,
+            r#"{"weld": 1,}"#,
+            r#"{"weld": 1 "stream": true}"#,
+            r#"{weld: 1}"#,
+            r#"{"weld": 1e999}"#,
+            r#"{"weld": tru}"#,
+            r#"{"services": ["Workspace"}"#,
+            r#"{"name_separator": "\q"}"#,
+            r#"{"name_separator": "\u12"}"#,
+            r#"{"name_separator": "unterminated}"#,
+            "{\"name_separator\": \"\t\"}",
+            r#"{"weld": 1} trailing"#,
+        ] {
+            assert!(parse_object(json).is_err(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn options_go_through_export_options_set() {
+        let opts = parse_options(r#"{"services": null, "max_depth": 3, "pose": "bind"}"#).unwrap();
+        assert!(opts.services.is_none());
+        assert_eq!(opts.max_depth, 3);
+        assert_eq!(
+            parse_options(r#"{"max_depth": true}"#).err().unwrap(),
+            "max_depth should be a number"
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! `--format json` and `--json-binary`: the whole scene's geometry as one
+//! document, for pipelines that would rather not parse OBJ. The binary
+//! variant is the same document encoded as CBOR (RFC 8949).
+
+use std::io::{self, Write};
+
+use rbx_types::Vector3;
+
+use crate::{
+    export::{Face, push_float},
+    json_escape,
+};
+
+/// A part's mesh in world space, ready to be written out.
+pub struct GeometryPart<'a> {
+    pub name: String,
+    pub class: &'a str,
+    pub vertices: Vec<Vector3>,
+    /// 0-based indices into `vertices`.
+    pub faces: Vec<Face>,
+    pub color: (u8, u8, u8, u8),
+    /// The MTL material an OBJ export would give the part, which only
+    /// `--format bobj` writes.
+    pub material: String,
+}
+
+impl GeometryPart<'_> {
+    /// The color's channels scaled to 0–1, alpha last.
+    fn channels(&self) -> [f32; 4] {
+        let (r, g, b, a) = self.color;
+        [r, g, b, a].map(|channel| channel as f32 / 255.0)
+    }
+}
+
+/// Writes `{"parts":[...]}` with an object per part holding its `name`,
+/// `class`, `vertices`, `faces`, `color` and `alpha`.
+pub fn write_json(out: &mut dyn Write, parts: &[GeometryPart]) -> io::Result<()> {
+    let mut ints = itoa::Buffer::new();
+    let mut buf = String::new();
+    out.write_all(b"{\"parts\":[")?;
+    for (i, part) in parts.iter().enumerate() {
+        buf.clear();
+        if i > 0 {
+            buf.push(',');
+        }
+        buf.push_str("{\"name\":\"");
+        buf.push_str(&json_escape(&part.name));
+        buf.push_str("\",\"class\":\"");
+        buf.push_str(&json_escape(part.class));
+        buf.push_str("\",\"vertices\":[");
+        for (j, v) in part.vertices.iter().enumerate() {
+            if j > 0 {
+                buf.push(',');
+            }
+            push_json_array(&mut buf, [v.x, v.y, v.z]);
+        }
+        buf.push_str("],\"faces\":[");
+        for (j, &(a, b, c)) in part.faces.iter().enumerate() {
+            if j > 0 {
+                buf.push(',');
+            }
+            buf.push('[');
+            buf.push_str(ints.format(a));
+            buf.push(',');
+            buf.push_str(ints.format(b));
+            buf.push(',');
+            buf.push_str(ints.format(c));
+            buf.push(']');
+        }
+        let [r, g, b, alpha] = part.channels();
+        buf.push_str("],\"color\":");
+        push_json_array(&mut buf, [r, g, b]);
+        buf.push_str(",\"alpha\":");
+        push_json_number(&mut buf, alpha);
+        buf.push('}');
+        out.write_all(buf.as_bytes())?;
+    }
+    out.write_all(b"]}\n")
+}
+
+fn push_json_array(buf: &mut String, values: [f32; 3]) {
+    buf.push('[');
+    for (i, value) in values.into_iter().enumerate() {
+        if i > 0 {
+            buf.push(',');
+        }
+        push_json_number(buf, value);
+    }
+    buf.push(']');
+}
+
+/// JSON has no NaN or infinity, so those become `null`.
+fn push_json_number(buf: &mut String, value: f32) {
+    if value.is_finite() {
+        push_float(buf, value);
+    } else {
+        buf.push_str("null");
+    }
+}
+
+const CBOR_UNSIGNED: u8 = 0;
+const CBOR_TEXT: u8 = 3;
+const CBOR_ARRAY: u8 = 4;
+const CBOR_MAP: u8 = 5;
+const CBOR_FLOAT32: u8 = 0xfa;
+
+/// Writes the same document as `write_json`, encoded as CBOR. Floats are
+/// written as single precision, which is all the geometry has anyway.
+pub fn write_cbor(out: &mut dyn Write, parts: &[GeometryPart]) -> io::Result<()> {
+    let mut buf = Vec::new();
+    cbor_head(&mut buf, CBOR_MAP, 1);
+    cbor_text(&mut buf, "parts");
+    cbor_head(&mut buf, CBOR_ARRAY, parts.len());
+    for part in parts {
+        cbor_head(&mut buf, CBOR_MAP, 6);
+        cbor_text(&mut buf, "name");
+        cbor_text(&mut buf, &part.name);
+        cbor_text(&mut buf, "class");
+        cbor_text(&mut buf, part.class);
+        cbor_text(&mut buf, "vertices");
+        cbor_head(&mut buf, CBOR_ARRAY, part.vertices.len());
+        for v in &part.vertices {
+            cbor_floats(&mut buf, [v.x, v.y, v.z]);
+        }
+        cbor_text(&mut buf, "faces");
+        cbor_head(&mut buf, CBOR_ARRAY, part.faces.len());
+        for &(a, b, c) in &part.faces {
+            cbor_head(&mut buf, CBOR_ARRAY, 3);
+            for index in [a, b, c] {
+                cbor_head(&mut buf, CBOR_UNSIGNED, index);
+            }
+        }
+        let [r, g, b, alpha] = part.channels();
+        cbor_text(&mut buf, "color");
+        cbor_floats(&mut buf, [r, g, b]);
+        cbor_text(&mut buf, "alpha");
+        buf.push(CBOR_FLOAT32);
+        buf.extend(alpha.to_be_bytes());
+        out.write_all(&buf)?;
+        buf.clear();
+    }
+    out.write_all(&buf)
+}
+
+/// A data item's initial byte for `major` type, followed by `value` in the
+/// fewest bytes that hold it.
+fn cbor_head(buf: &mut Vec<u8>, major: u8, value: usize) {
+    let major = major << 5;
+    let value = value as u64;
+    match value {
+        0..=23 => buf.push(major | value as u8),
+        24..=0xff => buf.extend([major | 24, value as u8]),
+        0x100..=0xffff => {
+            buf.push(major | 25);
+            buf.extend((value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            buf.push(major | 26);
+            buf.extend((value as u32).to_be_bytes());
+        }
+        _ => {
+            buf.push(major | 27);
+            buf.extend(value.to_be_bytes());
+        }
+    }
+}
+
+fn cbor_text(buf: &mut Vec<u8>, text: &str) {
+    cbor_head(buf, CBOR_TEXT, text.len());
+    buf.extend_from_slice(text.as_bytes());
+}
+
+fn cbor_floats(buf: &mut Vec<u8>, values: [f32; 3]) {
+    cbor_head(buf, CBOR_ARRAY, 3);
+    for value in values {
+        buf.push(CBOR_FLOAT32);
+        buf.extend(value.to_be_bytes());
+    }
+}
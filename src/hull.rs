@@ -0,0 +1,113 @@
+//! Convex hulls of part meshes, for `ConvexHullProcessor`.
+
+use std::collections::HashSet;
+
+use rbx_types::Vector3;
+
+use crate::mesh::{Mesh, cross, dot, normalize, sub};
+
+/// How far off a face a point has to be, relative to the size of the point
+/// cloud, to count as outside it rather than on it.
+const RELATIVE_EPSILON: f32 = 1e-5;
+
+/// The convex hull of `points`, built incrementally: start from a
+/// tetrahedron of four far-apart points, then for each point outside the
+/// hull so far, replace the faces it can see with a fan from it to their
+/// outline. Triangles are wound counter-clockwise seen from outside, and
+/// the vertex list holds only the hull's corners. Points that all lie on a
+/// plane or a line have no hull with volume, so they give `None`.
+pub fn convex_hull(points: &[Vector3]) -> Option<Mesh> {
+    let first = *points.first()?;
+    let (min, max) = points.iter().fold((first, first), |(min, max), p| {
+        (
+            Vector3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+            Vector3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+        )
+    });
+    let extent = sub(max, min);
+    let epsilon = dot(extent, extent).sqrt() * RELATIVE_EPSILON;
+
+    let farthest = |distance: &dyn Fn(Vector3) -> f32| {
+        (0..points.len())
+            .map(|i| (distance(points[i]), i))
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .filter(|&(d, _)| d > epsilon)
+            .map(|(_, i)| i)
+    };
+    let a = (0..points.len()).min_by(|&i, &j| points[i].x.total_cmp(&points[j].x))?;
+    let b = farthest(&|p| {
+        let d = sub(p, points[a]);
+        dot(d, d).sqrt()
+    })?;
+    let ab = normalize(sub(points[b], points[a]));
+    let c = farthest(&|p| {
+        let d = cross(ab, sub(p, points[a]));
+        dot(d, d).sqrt()
+    })?;
+    let normal = normalize(cross(sub(points[b], points[a]), sub(points[c], points[a])));
+    let d = farthest(&|p| dot(normal, sub(p, points[a])).abs())?;
+
+    // Turn each face of the starting tetrahedron away from the corner it
+    // doesn't touch.
+    let mut faces: Vec<[usize; 3]> = [[a, b, c], [a, c, d], [a, d, b], [b, d, c]]
+        .into_iter()
+        .zip([d, b, c, a])
+        .map(|(face, opposite)| {
+            if distance(points, face, points[opposite]) > 0.0 {
+                [face[0], face[2], face[1]]
+            } else {
+                face
+            }
+        })
+        .collect();
+
+    for (i, &point) in points.iter().enumerate() {
+        if [a, b, c, d].contains(&i) {
+            continue;
+        }
+        let (visible, kept): (Vec<[usize; 3]>, Vec<[usize; 3]>) = faces
+            .iter()
+            .partition(|&&face| distance(points, face, point) > epsilon);
+        if visible.is_empty() {
+            continue;
+        }
+
+        // The outline of the visible faces is the edges they don't share
+        // with each other.
+        let edges: Vec<(usize, usize)> = visible
+            .iter()
+            .flat_map(|&[p, q, r]| [(p, q), (q, r), (r, p)])
+            .collect();
+        let shared: HashSet<(usize, usize)> = edges.iter().copied().collect();
+        faces = kept;
+        faces.extend(
+            edges
+                .iter()
+                .filter(|&&(p, q)| !shared.contains(&(q, p)))
+                .map(|&(p, q)| [p, q, i]),
+        );
+    }
+
+    let mut remap = vec![usize::MAX; points.len()];
+    let mut vertices = Vec::new();
+    let faces = faces
+        .iter()
+        .map(|face| {
+            let [p, q, r] = face.map(|index| {
+                if remap[index] == usize::MAX {
+                    remap[index] = vertices.len();
+                    vertices.push(points[index]);
+                }
+                remap[index]
+            });
+            (p, q, r)
+        })
+        .collect();
+    Some((vertices, faces))
+}
+
+/// How far `point` is in front of `face`, negative behind it.
+fn distance(points: &[Vector3], [a, b, c]: [usize; 3], point: Vector3) -> f32 {
+    let normal = normalize(cross(sub(points[b], points[a]), sub(points[c], points[a])));
+    dot(normal, sub(point, points[a]))
+}
@@ -0,0 +1,373 @@
+//! The `info` and `validate` subcommands, which read a file without
+//! writing anything.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    error::Error,
+    path::Path,
+};
+
+use rbx_dom_weak::{Instance, Ustr, WeakDom};
+use rbx_types::{Ref, Variant, Vector3};
+
+use crate::{
+    ExportError,
+    bvh::Aabb,
+    json_escape,
+    mesh::{Tessellation, apply_cframe, local_mesh},
+    options::ExportOptions,
+    scene::{
+        PartRecord, Scene, Unsupported, collect_scene, descendants, get_cframe, instance_path,
+        read_dom, select_top_level,
+    },
+};
+
+/// Every kind of BasePart, for the class census and the world bounds.
+const GEOMETRY_CLASSES: &[&str] = &[
+    "Part",
+    "WedgePart",
+    "CornerWedgePart",
+    "TrussPart",
+    "MeshPart",
+    "UnionOperation",
+    "NegateOperation",
+    "IntersectOperation",
+];
+
+/// Walks the same subtrees `convert` would, keeping the parts it finds.
+fn survey(
+    dom: &WeakDom,
+    opts: &ExportOptions,
+) -> Result<(Scene, Vec<PartRecord>, Vec<String>), ExportError> {
+    let (top_level, skipped_services) = select_top_level(dom, opts);
+    let mut scene = Scene::default();
+    let mut parts = Vec::new();
+    collect_scene(dom, &top_level, opts, &mut scene, None, &mut |part| {
+        parts.push(part)
+    })?;
+    Ok((scene, parts, skipped_services))
+}
+
+/// Counts instances by class under the selected top-level instances.
+fn class_counts(dom: &WeakDom, opts: &ExportOptions) -> BTreeMap<String, usize> {
+    let (top_level, _) = select_top_level(dom, opts);
+    let mut counts = BTreeMap::new();
+    for root in top_level {
+        for inst in descendants(dom, root) {
+            *counts.entry(inst.class.to_string()).or_default() += 1;
+        }
+    }
+    counts
+}
+
+fn format_counts<K: std::fmt::Display>(counts: &BTreeMap<K, usize>) -> String {
+    counts
+        .iter()
+        .map(|(class, count)| format!("{} {}", class, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Everything `info` reports about one file.
+struct Summary {
+    tree: Vec<TreeNode>,
+    instances: usize,
+    visited: usize,
+    classes: BTreeMap<&'static str, usize>,
+    terrain: bool,
+    parts: usize,
+    constraints: usize,
+    bounds: Option<Aabb>,
+    triangles: usize,
+    colors: usize,
+    materials: usize,
+    pruned: BTreeMap<&'static str, usize>,
+    skipped_services: Vec<String>,
+    depth_limited: usize,
+}
+
+struct TreeNode {
+    name: String,
+    class: String,
+    /// Number of children, whether or not they're listed below.
+    child_count: usize,
+    children: Vec<TreeNode>,
+}
+
+/// Children missing from the DOM are left out, the same as `convert` skips
+/// them. A loop in the tree just repeats until `depth` runs out.
+fn tree_node(dom: &WeakDom, inst: &Instance, depth: usize) -> TreeNode {
+    TreeNode {
+        name: inst.name.clone(),
+        class: inst.class.to_string(),
+        child_count: inst.children().len(),
+        children: if depth > 1 {
+            inst.children()
+                .iter()
+                .filter_map(|&child| dom.get_by_ref(child))
+                .map(|child| tree_node(dom, child, depth - 1))
+                .collect()
+        } else {
+            Vec::new()
+        },
+    }
+}
+
+/// Box around every BasePart under the selected top-level instances, using
+/// their `Size` and `CFrame` whether or not they'd be exported.
+fn world_bounds(dom: &WeakDom, top_level: &[Ref]) -> Option<Aabb> {
+    let mut corners = Vec::new();
+    for &root in top_level {
+        for inst in descendants(dom, root) {
+            if !GEOMETRY_CLASSES.contains(&inst.class.as_str()) {
+                continue;
+            }
+            let half = match inst.properties.get(&Ustr::from("Size")) {
+                Some(Variant::Vector3(size)) => {
+                    Vector3::new(size.x / 2.0, size.y / 2.0, size.z / 2.0)
+                }
+                _ => Vector3::new(0.5, 0.5, 0.5),
+            };
+            let cframe = get_cframe(inst);
+            for corner in 0..8 {
+                let sign = |bit: usize| if corner & bit == 0 { -1.0 } else { 1.0 };
+                let local = Vector3::new(sign(1) * half.x, sign(2) * half.y, sign(4) * half.z);
+                corners.push(apply_cframe(local, &cframe));
+            }
+        }
+    }
+    (!corners.is_empty()).then(|| Aabb::from_points(&corners))
+}
+
+fn summarize(
+    dom: &WeakDom,
+    opts: &ExportOptions,
+    tree_depth: usize,
+) -> Result<Summary, ExportError> {
+    let (top_level, _) = select_top_level(dom, opts);
+    let (scene, parts, skipped_services) = survey(dom, opts)?;
+
+    let mut classes = BTreeMap::new();
+    for (class, count) in class_counts(dom, opts) {
+        if let Some(&known) = GEOMETRY_CLASSES.iter().find(|&&c| c == class) {
+            classes.insert(known, count);
+        }
+    }
+
+    // Triangles as a default `convert` would write them, before any of the
+    // face-dropping flags.
+    let triangles = parts
+        .iter()
+        .map(|part| {
+            local_mesh(part, opts.truss_style, Tessellation::default())
+                .1
+                .len()
+        })
+        .sum();
+    let colors: HashSet<_> = parts
+        .iter()
+        .map(|part| (part.color.0, part.color.1, part.color.2))
+        .collect();
+    let materials: HashSet<_> = parts
+        .iter()
+        .map(|part| (part.color, part.material))
+        .collect();
+
+    Ok(Summary {
+        tree: dom
+            .root()
+            .children()
+            .iter()
+            .filter(|_| tree_depth > 0)
+            .filter_map(|&r| dom.get_by_ref(r))
+            .map(|inst| tree_node(dom, inst, tree_depth))
+            .collect(),
+        instances: descendants(dom, dom.root_ref()).count() - 1,
+        visited: scene.visited,
+        classes,
+        terrain: descendants(dom, dom.root_ref()).any(|inst| inst.class == "Terrain"),
+        parts: parts.len(),
+        constraints: scene.constraints.len(),
+        bounds: world_bounds(dom, &top_level),
+        triangles,
+        colors: colors.len(),
+        materials: materials.len(),
+        pruned: scene.pruned,
+        skipped_services,
+        depth_limited: scene.depth_limited,
+    })
+}
+
+fn tree_lines(lines: &mut Vec<String>, nodes: &[TreeNode], indent: usize) {
+    for node in nodes {
+        if node.children.is_empty() && node.child_count > 0 {
+            lines.push(format!(
+                "{:indent$}{} ({}, {} children)",
+                "",
+                node.name,
+                node.class,
+                node.child_count,
+                indent = indent
+            ));
+        } else {
+            lines.push(format!(
+                "{:indent$}{} ({})",
+                "",
+                node.name,
+                node.class,
+                indent = indent
+            ));
+        }
+        tree_lines(lines, &node.children, indent + 2);
+    }
+}
+
+fn tree_json(nodes: &[TreeNode]) -> String {
+    let nodes: Vec<String> = nodes
+        .iter()
+        .map(|node| {
+            format!(
+                "{{\"name\":\"{}\",\"class\":\"{}\",\"child_count\":{},\"children\":{}}}",
+                json_escape(&node.name),
+                json_escape(&node.class),
+                node.child_count,
+                tree_json(&node.children)
+            )
+        })
+        .collect();
+    format!("[{}]", nodes.join(","))
+}
+
+fn counts_json<K: std::fmt::Display>(counts: &BTreeMap<K, usize>) -> String {
+    let fields: Vec<String> = counts
+        .iter()
+        .map(|(key, count)| format!("\"{}\":{}", json_escape(&key.to_string()), count))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn json_text(path: &Path, summary: &Summary) -> String {
+    let bounds = match &summary.bounds {
+        Some(b) => format!(
+            "{{\"min\":[{},{},{}],\"max\":[{},{},{}]}}",
+            b.min.x, b.min.y, b.min.z, b.max.x, b.max.y, b.max.z
+        ),
+        None => "null".to_string(),
+    };
+    let skipped: Vec<String> = summary
+        .skipped_services
+        .iter()
+        .map(|s| format!("\"{}\"", json_escape(s)))
+        .collect();
+    format!(
+        "{{\"file\":\"{}\",\"tree\":{},\"instances\":{},\"visited\":{},\"classes\":{},\"terrain\":{},\"parts\":{},\"constraints\":{},\"bounds\":{},\"triangles\":{},\"colors\":{},\"materials\":{},\"pruned\":{},\"skipped_services\":[{}],\"depth_limited\":{}}}",
+        json_escape(&path.display().to_string()),
+        tree_json(&summary.tree),
+        summary.instances,
+        summary.visited,
+        counts_json(&summary.classes),
+        summary.terrain,
+        summary.parts,
+        summary.constraints,
+        bounds,
+        summary.triangles,
+        summary.colors,
+        summary.materials,
+        counts_json(&summary.pruned),
+        skipped.join(","),
+        summary.depth_limited
+    )
+}
+
+fn text(path: &Path, summary: &Summary) -> String {
+    let mut lines = vec![path.display().to_string()];
+    if !summary.tree.is_empty() {
+        lines.push("  tree:".to_string());
+        tree_lines(&mut lines, &summary.tree, 4);
+    }
+    lines.push(format!("  instances: {}", summary.instances));
+    lines.push(format!("  visited: {}", summary.visited));
+    lines.push(format!("  classes: {}", format_counts(&summary.classes)));
+    lines.push(format!(
+        "  terrain: {}",
+        if summary.terrain { "yes" } else { "no" }
+    ));
+    lines.push(format!("  parts: {}", summary.parts));
+    lines.push(format!("  constraints: {}", summary.constraints));
+    if let Some(b) = &summary.bounds {
+        lines.push(format!(
+            "  bounds: ({}, {}, {}) to ({}, {}, {})",
+            b.min.x, b.min.y, b.min.z, b.max.x, b.max.y, b.max.z
+        ));
+    }
+    lines.push(format!("  triangles: ~{}", summary.triangles));
+    lines.push(format!("  colors: {}", summary.colors));
+    lines.push(format!("  materials: {}", summary.materials));
+    if !summary.pruned.is_empty() {
+        lines.push(format!("  pruned: {}", format_counts(&summary.pruned)));
+    }
+    if !summary.skipped_services.is_empty() {
+        lines.push(format!(
+            "  skipped services with parts: {}",
+            summary.skipped_services.join(", ")
+        ));
+    }
+    if summary.depth_limited > 0 {
+        lines.push(format!(
+            "  cut off by --max-depth: {}",
+            summary.depth_limited
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Describes what a file contains and how much of it would be exported,
+/// as a line of JSON or as indented text, for the caller to print.
+pub fn info(
+    opts: &ExportOptions,
+    path: &Path,
+    tree_depth: usize,
+    json: bool,
+) -> Result<String, Box<dyn Error>> {
+    let dom = read_dom(path)?;
+    let summary = summarize(&dom, opts, tree_depth)?;
+    Ok(if json {
+        json_text(path, &summary)
+    } else {
+        text(path, &summary)
+    })
+}
+
+/// One kind of content [`validate`] found that won't come out right.
+#[derive(Debug)]
+pub struct Finding {
+    pub kind: Unsupported,
+    pub count: usize,
+    /// Paths of the first few instances of it, like `Workspace.Car.Body`.
+    pub examples: Vec<String>,
+}
+
+/// Looks for anything the exporter can't reproduce faithfully, apart from
+/// the `ignore`d kinds. The file is clean if nothing comes back.
+pub fn validate(
+    opts: &ExportOptions,
+    path: &Path,
+    ignore: &[Unsupported],
+) -> Result<Vec<Finding>, Box<dyn Error>> {
+    let dom = read_dom(path)?;
+    let (scene, _, _) = survey(&dom, opts)?;
+
+    Ok(scene
+        .unsupported
+        .into_iter()
+        .filter(|(kind, _)| !ignore.contains(kind))
+        .map(|(kind, (count, examples))| Finding {
+            kind,
+            count,
+            examples: examples
+                .into_iter()
+                .map(|example| instance_path(&dom, example))
+                .collect(),
+        })
+        .collect())
+}
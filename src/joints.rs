@@ -0,0 +1,259 @@
+//! `--solve-joints`: places parts through the joints holding them together,
+//! for rigs and models saved with their parts' `CFrame`s left at the origin.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use rbx_dom_weak::{Instance, Ustr, WeakDom};
+use rbx_types::{CFrame, Matrix3, Ref, Variant, Vector3};
+
+use crate::{
+    options::Pose,
+    scene::{descendants, get_cframe},
+    transform::{cframe_inverse, cframe_mul},
+};
+
+/// Joints with a `C0` and `C1`, `JointInstance`s.
+const OFFSET_JOINTS: &[&str] = &["Motor6D", "Weld", "ManualWeld", "Snap", "Glue", "Motor"];
+
+/// One joint: `part1` sits at `part0 * offset`.
+struct Joint {
+    part0: Ref,
+    part1: Ref,
+    offset: CFrame,
+}
+
+/// World `CFrame`s for every part a joint under `roots` reaches, worked
+/// out as `Part0.CFrame * C0 * C1:Inverse()` from a root part outwards.
+/// With `Pose::Current`, a Motor6D's `Transform` goes between `C0` and
+/// `C1:Inverse()`, the way Roblox applies animations.
+/// Each group of joined parts starts from its `HumanoidRootPart`, or else
+/// its first anchored part, or else its first part, which keeps its own
+/// `CFrame`. A `WeldConstraint` keeps whatever offset its parts were saved
+/// at. Where joints disagree, the first one reached wins. Parts no joint
+/// touches aren't in the map.
+pub fn solve_joints(dom: &WeakDom, roots: &[Ref], pose: Pose) -> HashMap<Ref, CFrame> {
+    let joints: Vec<Joint> = roots
+        .iter()
+        .flat_map(|&root| descendants(dom, root))
+        .filter_map(|inst| joint(dom, inst, pose))
+        .collect();
+
+    let mut edges: HashMap<Ref, Vec<(Ref, CFrame)>> = HashMap::new();
+    let mut parts = Vec::new();
+    for joint in &joints {
+        for part in [joint.part0, joint.part1] {
+            if !edges.contains_key(&part) {
+                parts.push(part);
+            }
+            edges.entry(part).or_default();
+        }
+        edges
+            .get_mut(&joint.part0)
+            .unwrap()
+            .push((joint.part1, joint.offset));
+        edges
+            .get_mut(&joint.part1)
+            .unwrap()
+            .push((joint.part0, cframe_inverse(&joint.offset)));
+    }
+
+    let named_root = |part: &Ref| {
+        dom.get_by_ref(*part)
+            .is_some_and(|inst| inst.name == "HumanoidRootPart")
+    };
+    let anchored = |part: &Ref| {
+        dom.get_by_ref(*part).is_some_and(|inst| {
+            matches!(
+                inst.properties.get(&Ustr::from("Anchored")),
+                Some(Variant::Bool(true))
+            )
+        })
+    };
+    let starts = parts
+        .iter()
+        .filter(|part| named_root(part))
+        .chain(parts.iter().filter(|part| anchored(part)))
+        .chain(&parts);
+
+    let mut solved = HashMap::new();
+    let mut queue = VecDeque::new();
+    let mut reached = HashSet::new();
+    for &start in starts {
+        if !reached.insert(start) {
+            continue;
+        }
+        let Some(inst) = dom.get_by_ref(start) else {
+            continue;
+        };
+        solved.insert(start, get_cframe(inst));
+        queue.push_back(start);
+        while let Some(part) = queue.pop_front() {
+            let cframe = solved[&part];
+            for &(next, offset) in &edges[&part] {
+                if reached.insert(next) {
+                    solved.insert(next, cframe_mul(&cframe, &offset));
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    solved
+}
+
+/// The joint `inst` makes, if it's one and both its parts are in the DOM.
+fn joint(dom: &WeakDom, inst: &Instance, pose: Pose) -> Option<Joint> {
+    let weld_constraint = inst.class == "WeldConstraint";
+    if !weld_constraint && !OFFSET_JOINTS.contains(&inst.class.as_str()) {
+        return None;
+    }
+    let property = |name: &str| inst.properties.get(&Ustr::from(name));
+    // Files can carry WeldConstraint's parts under their serialized names.
+    let part = |names: [&str; 2]| {
+        names.into_iter().find_map(|name| match property(name) {
+            Some(Variant::Ref(part)) if dom.get_by_ref(*part).is_some() => Some(*part),
+            _ => None,
+        })
+    };
+    let part0 = part(["Part0", "Part0Internal"])?;
+    let part1 = part(["Part1", "Part1Internal"])?;
+    if part0 == part1 {
+        return None;
+    }
+
+    let offset = if weld_constraint {
+        let stored = |part| get_cframe(dom.get_by_ref(part).unwrap());
+        cframe_mul(&cframe_inverse(&stored(part0)), &stored(part1))
+    } else {
+        // All three default to no offset.
+        let offset = |name| match property(name) {
+            Some(Variant::CFrame(cf)) => *cf,
+            _ => CFrame::new(Vector3::new(0.0, 0.0, 0.0), Matrix3::identity()),
+        };
+        let mut c0 = offset("C0");
+        if pose == Pose::Current && inst.class == "Motor6D" {
+            c0 = cframe_mul(&c0, &offset("Transform"));
+        }
+        cframe_mul(&c0, &cframe_inverse(&offset("C1")))
+    };
+    Some(Joint {
+        part0,
+        part1,
+        offset,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rbx_dom_weak::InstanceBuilder;
+
+    use super::*;
+    use crate::transform::matrix3_from_orientation;
+
+    fn at(x: f32, y: f32, z: f32) -> Vector3 {
+        Vector3::new(x, y, z)
+    }
+
+    fn moved(x: f32, y: f32, z: f32) -> CFrame {
+        CFrame::new(at(x, y, z), Matrix3::identity())
+    }
+
+    fn turned(degrees: Vector3) -> CFrame {
+        CFrame::new(at(0.0, 0.0, 0.0), matrix3_from_orientation(degrees))
+    }
+
+    /// Part of an R6 character caught mid-animation: the torso turned a
+    /// quarter to the left and the right arm swung out sideways. Only the
+    /// root part was saved with a real `CFrame`. Returns the DOM, the model
+    /// and the torso, right arm and left leg.
+    fn character() -> (WeakDom, Ref, [Ref; 3]) {
+        let mut dom = WeakDom::new(InstanceBuilder::new("DataModel"));
+        let model = dom.insert(dom.root_ref(), InstanceBuilder::new("Model"));
+        let part = |name: &str| InstanceBuilder::new("Part").with_name(name);
+        let root = dom.insert(
+            model,
+            part("HumanoidRootPart").with_property("CFrame", moved(0.0, 3.0, 0.0)),
+        );
+        let torso = dom.insert(model, part("Torso"));
+        let arm = dom.insert(model, part("Right Arm"));
+        let leg = dom.insert(model, part("Left Leg"));
+
+        let motors = [
+            (
+                "RootJoint",
+                root,
+                torso,
+                moved(0.0, 0.0, 0.0),
+                moved(0.0, 0.0, 0.0),
+                Some(turned(at(0.0, 90.0, 0.0))),
+            ),
+            (
+                "Right Shoulder",
+                torso,
+                arm,
+                moved(1.5, 0.5, 0.0),
+                moved(0.0, 0.5, 0.0),
+                Some(turned(at(0.0, 0.0, 90.0))),
+            ),
+            (
+                "Left Hip",
+                torso,
+                leg,
+                moved(-0.5, -1.0, 0.0),
+                moved(0.0, 1.0, 0.0),
+                None,
+            ),
+        ];
+        for (name, part0, part1, c0, c1, transform) in motors {
+            let mut motor = InstanceBuilder::new("Motor6D")
+                .with_name(name)
+                .with_property("Part0", part0)
+                .with_property("Part1", part1)
+                .with_property("C0", c0)
+                .with_property("C1", c1);
+            if let Some(transform) = transform {
+                motor = motor.with_property("Transform", transform);
+            }
+            dom.insert(torso, motor);
+        }
+        (dom, model, [torso, arm, leg])
+    }
+
+    fn assert_near(actual: Vector3, expected: Vector3) {
+        let off = (actual.x - expected.x)
+            .abs()
+            .max((actual.y - expected.y).abs())
+            .max((actual.z - expected.z).abs());
+        assert!(off < 1e-4, "{:?} isn't {:?}", actual, expected);
+    }
+
+    #[test]
+    fn bind_pose_ignores_transforms() {
+        let (dom, model, [torso, arm, leg]) = character();
+        let solved = solve_joints(&dom, &[model], Pose::Bind);
+        assert_near(solved[&torso].position, at(0.0, 3.0, 0.0));
+        assert_eq!(solved[&torso].orientation, Matrix3::identity());
+        assert_near(solved[&arm].position, at(1.5, 3.0, 0.0));
+        assert_near(solved[&leg].position, at(-0.5, 1.0, 0.0));
+    }
+
+    #[test]
+    fn current_pose_applies_transforms_down_the_chain() {
+        let (dom, model, [torso, arm, leg]) = character();
+        let solved = solve_joints(&dom, &[model], Pose::Current);
+
+        // Turned left, the torso's right side faces -Z.
+        let m = solved[&torso].orientation;
+        assert_near(at(m.x.x, m.y.x, m.z.x), at(0.0, 0.0, -1.0));
+        assert_near(solved[&torso].position, at(0.0, 3.0, 0.0));
+
+        // The shoulder turns with the torso, and the arm swings out from it
+        // so that its length runs along the torso's right.
+        assert_near(solved[&arm].position, at(0.0, 3.5, -2.0));
+        let m = solved[&arm].orientation;
+        let down = at(-m.x.y, -m.y.y, -m.z.y);
+        assert_near(down, at(0.0, 0.0, -1.0));
+
+        // The hip has no Transform, so the leg just follows the torso round.
+        assert_near(solved[&leg].position, at(0.0, 1.0, 0.5));
+    }
+}
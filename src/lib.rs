@@ -0,0 +1,356 @@
+//! Converts Roblox places and models (`.rbxl`/`.rbxm`) to OBJ, or to JSON
+//! for engines that would rather not parse OBJ.
+//!
+//! [`convert_file`] is the whole export the `rbx_obj` binary runs for each
+//! input, driven by the same [`ExportOptions`] its flags resolve into, and
+//! [`export_dom`] does the same for a DOM that's already loaded, writing
+//! into buffers. The [`mesh`] module has the part meshes on their own, and
+//! [`MeshPostProcessor`]s in `ExportOptions::postprocessors` get to change
+//! them before they're written. [`bobj_to_obj`] turns the output of
+//! `OutputFormat::Bobj` back into an OBJ. With the `async` feature,
+//! `export_dom_async` writes into Tokio's `AsyncWrite`s instead, and with
+//! `assets`, [`assets`] downloads the assets a place refers to into a cache.
+
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use rbx_dom_weak::WeakDom;
+use rbx_types::Ref;
+
+#[cfg(feature = "assets")]
+pub mod assets;
+#[cfg(feature = "async")]
+mod async_export;
+mod blender_preset;
+mod bobj;
+mod bvh;
+mod callbacks;
+mod cancel;
+mod clock;
+mod diff;
+mod export;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod geometry_json;
+#[cfg(feature = "convex-hull")]
+mod hull;
+mod inspect;
+mod joints;
+pub mod mesh;
+mod options;
+mod palette;
+mod parallel;
+pub mod postprocess;
+mod preview;
+mod scene;
+#[cfg(feature = "simplify")]
+mod simplify;
+mod summary;
+mod tjunction;
+mod transform;
+mod voxel;
+mod warning;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
+
+#[cfg(feature = "async")]
+pub use crate::async_export::{convert_file_async, export_dom_async};
+pub use crate::{
+    bobj::bobj_to_obj,
+    bvh::Aabb,
+    callbacks::{ExportCallbacks, ProgressInfo, ProgressPhase, WarningEvent},
+    cancel::{CancellationToken, Cancelled},
+    diff::{DiffEntry, diff_doms},
+    inspect::{Finding, info, validate},
+    options::{
+        DoubleSided, ExportOptions, ExportOptionsBuilder, MtlTransparency, MtllibStyle, Normals,
+        OptionValue, OutputFormat, Pose, TimingsFormat, TrussStyle, Wireframe,
+    },
+    postprocess::MeshPostProcessor,
+    scene::Unsupported,
+    warning::{Warning, WarningCode},
+};
+
+/// What a conversion wrote, and what it found along the way. Nothing is
+/// printed; the `rbx_obj` binary prints this.
+#[derive(Debug, Default)]
+pub struct ExportReport {
+    /// Parts written per class, counting each mirrored copy.
+    pub parts_by_class: BTreeMap<String, usize>,
+    /// Parts left out, counted by why.
+    pub skipped: BTreeMap<&'static str, usize>,
+    /// Content that didn't come out right, counted by kind.
+    pub not_exported: BTreeMap<Unsupported, usize>,
+    pub instances_visited: usize,
+    pub vertices: usize,
+    pub faces: usize,
+    pub materials: usize,
+    /// Every file written, in the order they were created.
+    pub outputs: Vec<PathBuf>,
+    /// How many warnings came up whose code is in `ExportOptions::fail_on`.
+    /// The outputs are still there unless `no_partial_output` was set.
+    pub failures: usize,
+    pub elapsed: Duration,
+    /// Nothing was written; the counts are estimates and `outputs` are the
+    /// files a real run would write.
+    pub dry_run: bool,
+    /// Instances left out because of an error, with `best_effort` on.
+    pub errors: Vec<InstanceError>,
+    /// How long each phase took, in the order they first ran.
+    pub phases: Vec<PhaseTiming>,
+    /// With `preview`, the top-down sketch of the parts, a row per string.
+    pub preview: Vec<String>,
+    /// With `list_textures`, every asset id the file refers to, in order.
+    /// Nothing else is written.
+    pub asset_ids: Vec<u64>,
+    /// With `list_materials`, the materials an export would create, most
+    /// used first. Nothing else is written.
+    pub material_usage: Vec<MaterialUsage>,
+}
+
+impl ExportReport {
+    pub fn parts(&self) -> usize {
+        self.parts_by_class.values().sum()
+    }
+}
+
+/// One phase of a conversion in [`ExportReport::phases`]. Phases that run
+/// once per LOD level add up.
+#[derive(Debug, Clone)]
+pub struct PhaseTiming {
+    pub name: &'static str,
+    pub wall: Duration,
+    /// For phases that run in parallel, the time summed over all threads.
+    pub cpu: Option<Duration>,
+}
+
+/// One material in [`ExportReport::material_usage`].
+#[derive(Debug, Clone)]
+pub struct MaterialUsage {
+    /// The name it gets in the MTL.
+    pub name: String,
+    pub rgba: (u8, u8, u8, u8),
+    /// Parts using it, counting each mirrored copy.
+    pub parts: usize,
+    pub faces: usize,
+}
+
+/// Why a conversion stopped.
+#[derive(Debug)]
+pub enum ExportError {
+    /// Reading the input or writing an output failed. `path` is the file
+    /// when it's known, which it isn't for the writers passed to
+    /// [`export_dom`] or for a write failing part way through a file.
+    Io {
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    /// The input isn't a place or model `rbx_binary` can decode.
+    Parse {
+        path: PathBuf,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    /// A referent that isn't in the DOM. Files never have these, but DOMs
+    /// put together by hand can.
+    MissingInstance(Ref),
+    /// The instance at `path` has `property`, but not holding the `expected`
+    /// kind of value.
+    InvalidProperty {
+        path: String,
+        property: String,
+        expected: &'static str,
+    },
+    /// The options ask for something this export can't do, like `lods`
+    /// without an output path to name the extra files after.
+    Unsupported(String),
+    /// The token passed to [`convert_file_cancellable`] or
+    /// [`export_dom_cancellable`] was cancelled.
+    Cancelled,
+    /// Anything else.
+    Other(Box<dyn Error + Send + Sync>),
+}
+
+impl ExportError {
+    fn io(path: &Path, source: io::Error) -> Self {
+        ExportError::Io {
+            path: Some(path.to_path_buf()),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "{}: {}", path.display(), source),
+            ExportError::Io { path: None, source } => source.fmt(f),
+            ExportError::Parse { path, source } => {
+                write!(f, "couldn't decode {}: {}", path.display(), source)
+            }
+            ExportError::MissingInstance(referent) => {
+                write!(f, "referent {} isn't in the DOM", referent)
+            }
+            ExportError::InvalidProperty {
+                path,
+                property,
+                expected,
+            } => write!(f, "{}.{} should be a {}", path, property, expected),
+            ExportError::Unsupported(message) => f.write_str(message),
+            ExportError::Cancelled => Cancelled.fmt(f),
+            ExportError::Other(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for ExportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ExportError::Io { source, .. } => Some(source),
+            ExportError::Parse { source, .. } => Some(source.as_ref()),
+            ExportError::Other(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<Cancelled> for ExportError {
+    fn from(_: Cancelled) -> Self {
+        ExportError::Cancelled
+    }
+}
+
+impl From<io::Error> for ExportError {
+    fn from(source: io::Error) -> Self {
+        ExportError::Io { path: None, source }
+    }
+}
+
+impl From<Box<dyn Error>> for ExportError {
+    fn from(error: Box<dyn Error>) -> Self {
+        if error.is::<Cancelled>() {
+            return ExportError::Cancelled;
+        }
+        let error = match error.downcast::<ExportError>() {
+            Ok(export) => return *export,
+            Err(error) => error,
+        };
+        match error.downcast::<io::Error>() {
+            Ok(io) => ExportError::from(*io),
+            // What it says is all that's kept, for `ExportError` to be
+            // `Send` like the rest of the report.
+            Err(error) => ExportError::Other(error.to_string().into()),
+        }
+    }
+}
+
+/// An error that only concerns one instance, which `best_effort` skips
+/// instead of failing the whole export over.
+#[derive(Debug)]
+pub struct InstanceError {
+    /// The instance's path in the tree, like `Workspace.Car.Rope`.
+    pub path: String,
+    pub error: ExportError,
+}
+
+impl fmt::Display for InstanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.error)
+    }
+}
+
+/// Converts `input`, writing the OBJ to `output` and everything else that
+/// goes with it (the MTL, extra detail levels, `--format` extras) next to it
+/// under the same name. With `OutputFormat::Json` only the `.json` (or
+/// `.cbor`) beside `output` gets written, and with `OutputFormat::Bobj` the
+/// `.bobj` and the MTL.
+pub fn convert_file(
+    input: &Path,
+    output: &Path,
+    opts: &ExportOptions,
+) -> Result<ExportReport, ExportError> {
+    export::convert(opts, input, output, None).map_err(Into::into)
+}
+
+/// [`convert_file`], giving up at the next part once `cancel` fires. Files
+/// already started are deleted again, so a cancelled run leaves nothing
+/// half written behind.
+pub fn convert_file_cancellable(
+    input: &Path,
+    output: &Path,
+    opts: &ExportOptions,
+    cancel: &CancellationToken,
+) -> Result<ExportReport, ExportError> {
+    export::convert(opts, input, output, Some(cancel)).map_err(Into::into)
+}
+
+/// Compares two saves of a place and writes the parts that changed to
+/// `output` as an OBJ, colored by change: green for added, yellow for moved
+/// or resized, blue for recolored, and a red block where each removed part
+/// was. `opts` picks the parts on both sides, as for [`convert_file`];
+/// streaming, dry runs and formats other than OBJ are `Unsupported`.
+pub fn convert_diff(
+    old: &Path,
+    new: &Path,
+    output: &Path,
+    opts: &ExportOptions,
+) -> Result<ExportReport, ExportError> {
+    export::convert_diff(opts, old, new, output).map_err(Into::into)
+}
+
+/// Exports a DOM that's already in memory, say one built with
+/// `rbx_dom_weak`, writing the OBJ into `obj` and the materials into `mtl`.
+/// The OBJ's `mtllib` line names `scene.mtl`. Options that write files named
+/// after the OBJ (`lods`, `max_vertices_per_file`, `OutputFormat::Mitsuba`,
+/// `OutputFormat::Json`, `OutputFormat::Bobj`, `bake_material_id_texture` and
+/// `blender_materials`)
+/// and `dry_run` are an error here, while the ones that name their own file (`part_info_csv`,
+/// `summary_json`, `warnings_jsonl`) still write it.
+pub fn export_dom(
+    dom: &WeakDom,
+    obj: &mut dyn Write,
+    mtl: Option<&mut dyn Write>,
+    opts: &ExportOptions,
+) -> Result<ExportReport, ExportError> {
+    export::export_dom(opts, dom, obj, mtl, None).map_err(Into::into)
+}
+
+/// [`export_dom`], giving up at the next part once `cancel` fires. Whatever
+/// has gone into the writers by then stays there, but files of its own
+/// (`part_info_csv`) are deleted.
+pub fn export_dom_cancellable(
+    dom: &WeakDom,
+    obj: &mut dyn Write,
+    mtl: Option<&mut dyn Write>,
+    opts: &ExportOptions,
+    cancel: &CancellationToken,
+) -> Result<ExportReport, ExportError> {
+    export::export_dom(opts, dom, obj, mtl, Some(cancel)).map_err(Into::into)
+}
+
+/// `value` escaped to go between the quotes of a JSON string: quotes,
+/// backslashes and control characters, so a name with a newline in it
+/// doesn't split a JSON line in two.
+pub fn json_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c < ' ' => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
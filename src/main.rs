@@ -1,392 +1,291 @@
 use std::{
-    collections::HashMap,
-    env,
     error::Error,
     fs::File,
-    io::{BufReader, BufWriter, Write},
-    path::PathBuf,
+    io::{self, BufReader, BufWriter, IsTerminal, Write},
+    path::Path,
+    process::ExitCode,
+    sync::Arc,
+    time::Duration,
 };
 
-use rbx_binary;
-use rbx_dom_weak::{Ustr, WeakDom};
-use rbx_types::{CFrame, Matrix3, Ref, Variant, Vector3};
+mod cli;
+mod config;
+mod progress_bars;
+mod report;
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <place.rbxl>", args[0]);
-        return Ok(());
-    }
-
-    let path = PathBuf::from(&args[1]);
-    let file = BufReader::new(File::open(&path)?);
-    let dom: WeakDom = rbx_binary::from_reader(file)?;
-
-    let mut obj_path = path.clone();
-    obj_path.set_extension("obj");
-    let mut mtl_path = path.clone();
-    mtl_path.set_extension("mtl");
-
-    let mut obj = BufWriter::new(File::create(&obj_path)?);
-    let mut mtl = BufWriter::new(File::create(&mtl_path)?);
-
-    writeln!(obj, "# Exported from Roblox place")?;
-    writeln!(
-        obj,
-        "mtllib {}",
-        mtl_path.file_name().unwrap().to_string_lossy()
-    )?;
+use clap::CommandFactory;
+use rbx_obj::{CancellationToken, ExportCallbacks, ExportError, WarningEvent, assets::AssetKey};
 
-    let mut vertex_offset = 0;
-    let mut material_map: HashMap<(u8, u8, u8, u8), String> = HashMap::new();
-    let mut next_mat_id = 0;
-
-    for &child_ref in dom.root().children() {
-        export_instance(
-            &dom,
-            child_ref,
-            &mut obj,
-            &mut mtl,
-            &mut vertex_offset,
-            &mut material_map,
-            &mut next_mat_id,
-        )?;
-    }
-
-    Ok(())
-}
+use crate::cli::{Cli, Command};
 
-fn export_instance(
-    dom: &WeakDom,
-    inst_ref: Ref,
-    obj: &mut dyn Write,
-    mtl: &mut dyn Write,
-    vertex_offset: &mut usize,
-    material_map: &mut HashMap<(u8, u8, u8, u8), String>,
-    next_mat_id: &mut usize,
-) -> Result<(), Box<dyn Error>> {
-    let inst = dom.get_by_ref(inst_ref).unwrap();
+fn main() -> Result<ExitCode, Box<dyn Error>> {
+    let (cli, effective) = config::load()?;
 
-    match inst.class.as_str() {
-        "Part" | "WedgePart" | "CornerWedgePart" => {
-            let size = match inst.properties.get(&Ustr::from("Size")) {
-                Some(Variant::Vector3(v)) => *v,
-                _ => Vector3::new(1.0, 1.0, 1.0),
-            };
+    match cli
+        .command
+        .unwrap_or(Command::Convert(Box::new(cli.convert)))
+    {
+        Command::Convert(mut args) => {
+            if args.print_config {
+                effective.print_config();
+                return Ok(ExitCode::SUCCESS);
+            }
+            init_logging(args.quiet, args.verbose);
+            if let Some(threads) = args.threads {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads.get())
+                    .build_global()?;
+            }
 
-            let cframe = match inst.properties.get(&Ustr::from("CFrame")) {
-                Some(Variant::CFrame(cf)) => *cf,
-                _ => CFrame {
-                    position: Vector3::new(0.0, 0.0, 0.0),
-                    orientation: Matrix3::identity(),
-                },
-            };
+            let cancel = CancellationToken::default();
+            let on_interrupt = cancel.clone();
+            ctrlc::set_handler(move || {
+                // Parsing and meshing don't check the token, so a second
+                // Ctrl-C gets out right away.
+                if on_interrupt.check().is_err() {
+                    std::process::exit(130);
+                }
+                log::warn!("export cancelled; removing partial output");
+                on_interrupt.cancel();
+            })?;
+            if let Some(seconds) = args.timeout_seconds {
+                let on_timeout = cancel.clone();
+                std::thread::spawn(move || {
+                    std::thread::sleep(Duration::from_secs(seconds.get()));
+                    log::warn!(
+                        "export timed out after {} seconds; removing partial output",
+                        seconds
+                    );
+                    on_timeout.cancel();
+                });
+            }
 
-            let (r, g, b) = match inst.properties.get(&Ustr::from("Color")) {
-                Some(Variant::Color3uint8(c)) => (c.r, c.g, c.b),
-                _ => (255, 255, 255),
+            // `--async` writes from a runtime on this thread, while the
+            // export runs on its blocking threads.
+            #[cfg(feature = "async")]
+            let runtime = match args.run_async {
+                true => Some(tokio::runtime::Builder::new_current_thread().build()?),
+                false => None,
             };
-
-            let transparency = match inst.properties.get(&Ustr::from("Transparency")) {
-                Some(Variant::Float32(t)) => *t,
-                _ => 0.0,
+            let inputs = std::mem::take(&mut args.inputs);
+            let asset_cache = args.asset_cache();
+            let mut opts = match args.options() {
+                Ok(opts) => opts,
+                Err(error) => {
+                    log::error!("{}", error);
+                    return Ok(ExitCode::from(exit_code(&error)));
+                }
             };
-            let a = ((1.0 - transparency) * 255.0) as u8;
-
-            let mat_key = (r, g, b, a);
-            let mat_name = material_map.entry(mat_key).or_insert_with(|| {
-                let name = format!("mat_{}", *next_mat_id);
-                *next_mat_id += 1;
-                let (rf, gf, bf, af) = (
-                    r as f32 / 255.0,
-                    g as f32 / 255.0,
-                    b as f32 / 255.0,
-                    a as f32 / 255.0,
+            opts.callbacks = callbacks(&opts);
+            let opts = Arc::new(opts);
+            // Every input appends a line, so start from an empty file.
+            if let Some(json_path) = &opts.summary_json {
+                File::create(json_path)?;
+            }
+            if let Some(jsonl_path) = &opts.warnings_jsonl
+                && jsonl_path != Path::new("-")
+            {
+                File::create(jsonl_path)?;
+            }
+            let mut clean = true;
+            for input in &inputs {
+                let output = input.with_extension("obj");
+                #[cfg(feature = "async")]
+                let converted = match &runtime {
+                    Some(runtime) => runtime.block_on(rbx_obj::convert_file_async(
+                        input,
+                        &output,
+                        opts.clone(),
+                        &cancel,
+                    )),
+                    None => rbx_obj::convert_file_cancellable(input, &output, &opts, &cancel),
+                };
+                #[cfg(not(feature = "async"))]
+                let converted = rbx_obj::convert_file_cancellable(input, &output, &opts, &cancel);
+                match converted {
+                    Ok(report) => {
+                        match &asset_cache {
+                            Some(cache) => {
+                                let keys: Vec<AssetKey> = report
+                                    .asset_ids
+                                    .iter()
+                                    .map(|&id| AssetKey::latest(id))
+                                    .collect();
+                                let assets = cache.get_all(&keys);
+                                report::print_assets(&assets);
+                                clean &= assets.iter().all(Result::is_ok);
+                            }
+                            None => report::print(input, &opts, &report),
+                        }
+                        if !report.errors.is_empty() {
+                            log::error!(
+                                "{}: skipped {} instances after errors",
+                                input.display(),
+                                report.errors.len()
+                            );
+                        }
+                        clean &= report.failures == 0 && report.errors.is_empty();
+                    }
+                    Err(error) => {
+                        report_error(input, &error);
+                        return Ok(ExitCode::from(exit_code(&error)));
+                    }
+                }
+            }
+            if !clean {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::Info(args) => {
+            init_logging(false, 0);
+            let opts = args.filter.options()?;
+            for input in &args.inputs {
+                println!(
+                    "{}",
+                    rbx_obj::info(&opts, input, args.tree_depth, args.json)?
                 );
-                writeln!(mtl, "newmtl {}", name).unwrap();
-                writeln!(mtl, "Kd {} {} {}", rf, gf, bf).unwrap();
-                writeln!(mtl, "d {}", af).unwrap();
-                writeln!(mtl).unwrap();
-                name
-            });
-
-            writeln!(obj, "usemtl {}", mat_name)?;
-
-            let (local_vertices, local_faces) = match inst.class.as_str() {
-                "Part" => {
-                    let shape = match inst.properties.get(&Ustr::from("Shape")) {
-                        Some(Variant::Enum(e)) => e.to_u32(),
-                        _ => 1,
-                    };
-                    match shape {
-                        0 => sphere_mesh(size, 2, 0),
-                        1 => cube_mesh(size),
-                        2 => cylinder_mesh(size, 24),
-                        _ => cube_mesh(size),
+            }
+        }
+        Command::Completions(args) => {
+            let mut command = Cli::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(args.shell, &mut command, name, &mut io::stdout());
+        }
+        Command::Validate(args) => {
+            init_logging(false, 0);
+            let opts = args.filter.options()?;
+            let mut clean = true;
+            for input in &args.inputs {
+                match rbx_obj::validate(&opts, input, &args.ignore) {
+                    Ok(findings) => {
+                        for finding in &findings {
+                            println!(
+                                "{}: {} {}",
+                                input.display(),
+                                finding.count,
+                                finding.kind.describe()
+                            );
+                            for example in &finding.examples {
+                                println!("    {}", example);
+                            }
+                        }
+                        if findings.is_empty() {
+                            println!("{}: ok", input.display());
+                        }
+                        clean &= findings.is_empty();
+                    }
+                    Err(error) => {
+                        let error = ExportError::from(error);
+                        report_error(input, &error);
+                        return Ok(ExitCode::from(exit_code(&error)));
                     }
                 }
-                "WedgePart" => wedge_mesh(size),
-                "CornerWedgePart" => corner_wedge_mesh(size),
-                _ => cube_mesh(size),
-            };
-
-            for v in local_vertices.iter() {
-                let pos = apply_cframe(*v, &cframe);
-                writeln!(obj, "v {} {} {}", pos.x, pos.y, pos.z)?;
             }
-
-            for f in local_faces.iter() {
-                writeln!(
-                    obj,
-                    "f {} {} {}",
-                    f.0 + *vertex_offset + 1,
-                    f.1 + *vertex_offset + 1,
-                    f.2 + *vertex_offset + 1
-                )?;
+            if !clean {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Command::DecodeBobj(args) => {
+            for input in &args.inputs {
+                decode_bobj(input)?;
+            }
+        }
+        Command::Diff(args) => {
+            init_logging(false, 0);
+            let opts = args.filter.options()?;
+            match rbx_obj::convert_diff(&args.old, &args.new, &args.output, &opts) {
+                Ok(report) => report::print(&args.new, &opts, &report),
+                Err(error) => {
+                    report_error(&args.new, &error);
+                    return Ok(ExitCode::from(exit_code(&error)));
+                }
             }
-
-            *vertex_offset += local_vertices.len();
         }
-        _ => {}
-    }
-
-    for &child_ref in inst.children() {
-        export_instance(
-            dom,
-            child_ref,
-            obj,
-            mtl,
-            vertex_offset,
-            material_map,
-            next_mat_id,
-        )?;
     }
 
-    Ok(())
-}
-
-fn apply_matrix3(m: &Matrix3, v: Vector3) -> Vector3 {
-    Vector3::new(
-        m.x.x * v.x + m.x.y * v.y + m.x.z * v.z,
-        m.y.x * v.x + m.y.y * v.y + m.y.z * v.z,
-        m.z.x * v.x + m.z.y * v.y + m.z.z * v.z,
-    )
-}
-
-fn apply_cframe(v: Vector3, cf: &CFrame) -> Vector3 {
-    let r = apply_matrix3(&cf.orientation, v);
-    Vector3::new(
-        r.x + cf.position.x,
-        r.y + cf.position.y,
-        r.z + cf.position.z,
-    )
-}
-
-fn cube_mesh(size: Vector3) -> (Vec<Vector3>, Vec<(usize, usize, usize)>) {
-    let sx = size.x / 2.0;
-    let sy = size.y / 2.0;
-    let sz = size.z / 2.0;
-
-    let vertices = vec![
-        Vector3::new(-sx, -sy, -sz),
-        Vector3::new(sx, -sy, -sz),
-        Vector3::new(sx, sy, -sz),
-        Vector3::new(-sx, sy, -sz),
-        Vector3::new(-sx, -sy, sz),
-        Vector3::new(sx, -sy, sz),
-        Vector3::new(sx, sy, sz),
-        Vector3::new(-sx, sy, sz),
-    ];
-
-    let faces = vec![
-        (0, 1, 2),
-        (0, 2, 3),
-        (4, 5, 6),
-        (4, 6, 7),
-        (0, 1, 5),
-        (0, 5, 4),
-        (1, 2, 6),
-        (1, 6, 5),
-        (2, 3, 7),
-        (2, 7, 6),
-        (3, 0, 4),
-        (3, 4, 7),
-    ];
-
-    (vertices, faces)
+    Ok(ExitCode::SUCCESS)
 }
 
-fn sphere_mesh(
-    size: Vector3,
-    subdivisions: usize,
-    _unused: usize,
-) -> (Vec<Vector3>, Vec<(usize, usize, usize)>) {
-    let radius_x = size.x / 2.0;
-    let radius_y = size.y / 2.0;
-    let radius_z = size.z / 2.0;
-
-    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
-
-    let mut vertices = vec![
-        Vector3::new(-1.0, t, 0.0),
-        Vector3::new(1.0, t, 0.0),
-        Vector3::new(-1.0, -t, 0.0),
-        Vector3::new(1.0, -t, 0.0),
-        Vector3::new(0.0, -1.0, t),
-        Vector3::new(0.0, 1.0, t),
-        Vector3::new(0.0, -1.0, -t),
-        Vector3::new(0.0, 1.0, -t),
-        Vector3::new(t, 0.0, -1.0),
-        Vector3::new(t, 0.0, 1.0),
-        Vector3::new(-t, 0.0, -1.0),
-        Vector3::new(-t, 0.0, 1.0),
-    ];
-
-    let mut faces = vec![
-        (0, 11, 5),
-        (0, 5, 1),
-        (0, 1, 7),
-        (0, 7, 10),
-        (0, 10, 11),
-        (1, 5, 9),
-        (5, 11, 4),
-        (11, 10, 2),
-        (10, 7, 6),
-        (7, 1, 8),
-        (3, 9, 4),
-        (3, 4, 2),
-        (3, 2, 6),
-        (3, 6, 8),
-        (3, 8, 9),
-        (4, 9, 5),
-        (2, 4, 11),
-        (6, 2, 10),
-        (8, 6, 7),
-        (9, 8, 1),
-    ];
-
-    for v in vertices.iter_mut() {
-        let len = (v.x * v.x + v.y * v.y + v.z * v.z).sqrt();
-        v.x /= len;
-        v.y /= len;
-        v.z /= len;
+/// Draws progress bars on a terminal and prints warnings: those `--fail-on`
+/// fails as errors, the rest for `-v`.
+fn callbacks(opts: &rbx_obj::ExportOptions) -> ExportCallbacks {
+    let mut callbacks = ExportCallbacks::default();
+    // Debug output would keep tearing the bar.
+    if !opts.quiet && !log::log_enabled!(log::Level::Debug) && io::stderr().is_terminal() {
+        callbacks = callbacks.on_progress(progress_bars::progress_bars());
     }
-
-    for _ in 0..subdivisions {
-        let mut new_faces = Vec::new();
-        let mut mid_cache = HashMap::<(usize, usize), usize>::new();
-
-        let get_midpoint = |a: usize,
-                            b: usize,
-                            vertices: &mut Vec<Vector3>,
-                            cache: &mut HashMap<(usize, usize), usize>|
-         -> usize {
-            let key = if a < b { (a, b) } else { (b, a) };
-            if let Some(&idx) = cache.get(&key) {
-                return idx;
+    if !opts.fail_on.is_empty() || log::log_enabled!(log::Level::Debug) {
+        callbacks = callbacks.on_warning(|event: &WarningEvent| {
+            if event.fails {
+                log::error!("{}", event.warning);
+            } else {
+                log::debug!("{}", event.warning);
             }
-            let va = vertices[a];
-            let vb = vertices[b];
-            let mut vm = Vector3::new(
-                (va.x + vb.x) / 2.0,
-                (va.y + vb.y) / 2.0,
-                (va.z + vb.z) / 2.0,
-            );
-            let len = (vm.x * vm.x + vm.y * vm.y + vm.z * vm.z).sqrt();
-            vm.x /= len;
-            vm.y /= len;
-            vm.z /= len;
-            let idx = vertices.len();
-            vertices.push(vm);
-            cache.insert(key, idx);
-            idx
-        };
-
-        for &(a, b, c) in faces.iter() {
-            let ab = get_midpoint(a, b, &mut vertices, &mut mid_cache);
-            let bc = get_midpoint(b, c, &mut vertices, &mut mid_cache);
-            let ca = get_midpoint(c, a, &mut vertices, &mut mid_cache);
-            new_faces.push((a, ab, ca));
-            new_faces.push((b, bc, ab));
-            new_faces.push((c, ca, bc));
-            new_faces.push((ab, bc, ca));
-        }
-
-        faces = new_faces;
-    }
-
-    for v in vertices.iter_mut() {
-        v.x *= radius_x;
-        v.y *= radius_y;
-        v.z *= radius_z;
+        });
     }
-
-    (vertices, faces)
+    callbacks
 }
 
-fn cylinder_mesh(size: Vector3, steps: usize) -> (Vec<Vector3>, Vec<(usize, usize, usize)>) {
-    let mut vertices = Vec::new();
-    let mut faces = Vec::new();
-
-    let x_half = size.x / 2.0;
-    let y_half = size.y / 2.0;
-    let z_half = size.z / 2.0;
-
-    for i in 0..steps {
-        let theta = 2.0 * std::f32::consts::PI * i as f32 / steps as f32;
-        let cos_theta = theta.cos();
-        let sin_theta = theta.sin();
-
-        vertices.push(Vector3::new(
-            -x_half,
-            y_half * cos_theta,
-            z_half * sin_theta,
-        ));
-        vertices.push(Vector3::new(x_half, y_half * cos_theta, z_half * sin_theta));
-    }
-
-    vertices.push(Vector3::new(-x_half, 0.0, 0.0));
-    vertices.push(Vector3::new(x_half, 0.0, 0.0));
+/// Writes `input`'s OBJ next to it, referring to the MTL the export wrote
+/// beside it if that's still there.
+fn decode_bobj(input: &Path) -> Result<(), Box<dyn Error>> {
+    let with_path = |path: &Path, e: io::Error| format!("{}: {}", path.display(), e);
+    let mut bobj = BufReader::new(File::open(input).map_err(|e| with_path(input, e))?);
+    let output = input.with_extension("obj");
+    let mut obj = BufWriter::new(File::create(&output).map_err(|e| with_path(&output, e))?);
+    let mtl_path = input.with_extension("mtl");
+    let mtl_name = mtl_path
+        .exists()
+        .then(|| mtl_path.file_name().unwrap().to_string_lossy());
+    rbx_obj::bobj_to_obj(&mut bobj, &mut obj, mtl_name.as_deref())
+        .map_err(|e| with_path(input, e))?;
+    Ok(())
+}
 
-    for i in 0..steps {
-        let next = (i + 1) % steps;
-        faces.push((i * 2, next * 2, next * 2 + 1));
-        faces.push((i * 2, next * 2 + 1, i * 2 + 1));
-        faces.push((i * 2, next * 2, vertices.len() - 2));
-        faces.push((i * 2 + 1, next * 2 + 1, vertices.len() - 1));
+/// Prints why converting `input` failed, naming the file unless the error
+/// already does.
+fn report_error(input: &Path, error: &ExportError) {
+    match error {
+        ExportError::Io { path: Some(_), .. } | ExportError::Parse { .. } => {
+            log::error!("{}", error)
+        }
+        _ => log::error!("{}: {}", input.display(), error),
     }
-
-    (vertices, faces)
 }
 
-fn wedge_mesh(size: Vector3) -> (Vec<Vector3>, Vec<(usize, usize, usize)>) {
-    let sx = size.x / 2.0;
-    let sy = size.y / 2.0;
-    let sz = size.z / 2.0;
-
-    let vertices = vec![
-        Vector3::new(-sx, -sy, -sz),
-        Vector3::new(sx, -sy, -sz),
-        Vector3::new(sx, -sy, sz),
-        Vector3::new(-sx, -sy, sz),
-        Vector3::new(-sx, sy, sz),
-        Vector3::new(sx, sy, sz),
-    ];
-
-    let faces = vec![
-        (0, 1, 2),
-        (0, 2, 3),
-        (0, 1, 4),
-        (1, 5, 4),
-        (3, 2, 5),
-        (3, 5, 4),
-        (0, 3, 4),
-        (1, 2, 5),
-    ];
-
-    (vertices, faces)
+/// A distinct exit code for each kind of failure, so scripts can tell a
+/// broken input from a full disk. 1 is left for `--fail-on` and anything
+/// unexpected, and 2 is clap's for bad arguments.
+fn exit_code(error: &ExportError) -> u8 {
+    match error {
+        ExportError::Io { .. } => 3,
+        ExportError::Parse { .. } => 4,
+        ExportError::MissingInstance(_) => 5,
+        ExportError::InvalidProperty { .. } => 6,
+        ExportError::Unsupported(_) => 7,
+        // Same as a second Ctrl-C, which exits without waiting.
+        ExportError::Cancelled => 130,
+        ExportError::Other(_) => 1,
+    }
 }
 
-fn corner_wedge_mesh(size: Vector3) -> (Vec<Vector3>, Vec<(usize, usize, usize)>) {
-    wedge_mesh(size)
+/// Routes `log` records to stderr: errors only with `--quiet`, notes and
+/// warnings by default, then per-part decisions for each `-v`.
+/// `RBX_OBJ_LOG` takes env_logger filters (e.g. `rbx_obj::scene=trace`) on top.
+fn init_logging(quiet: bool, verbose: u8) {
+    let level = match (quiet, verbose) {
+        (true, _) => log::LevelFilter::Error,
+        (false, 0) => log::LevelFilter::Info,
+        (false, 1) => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_env("RBX_OBJ_LOG")
+        .format(|f, record| match record.level() {
+            log::Level::Error => writeln!(f, "error: {}", record.args()),
+            log::Level::Warn => writeln!(f, "warning: {}", record.args()),
+            _ => writeln!(f, "{}", record.args()),
+        })
+        .init();
 }
@@ -0,0 +1,1168 @@
+//! Triangle meshes for each kind of part, built in the part's local space.
+//!
+//! Every mesh is a list of vertices plus triangles as indices into it, in
+//! Roblox's axes with Y up. Sizes are the part's full `Size`, so a mesh
+//! spans -size/2 to size/2 around the origin.
+
+use std::collections::HashMap;
+
+use rbx_types::{CFrame, Matrix3, Vector3};
+
+use crate::{options::TrussStyle, scene::PartRecord};
+
+/// Vertices, and triangles as indices into them.
+pub type Mesh = (Vec<Vector3>, Vec<(usize, usize, usize)>);
+
+/// How finely curved shapes are tessellated, and flat ones resampled.
+#[derive(Clone, Copy)]
+pub(crate) struct Tessellation {
+    sphere_subdivisions: usize,
+    cylinder_steps: usize,
+    /// Balls whose longest side is under this many studs are meshed as
+    /// blocks instead.
+    boxed_ball_size: f32,
+    /// The longest a block's faces are left along either side, see
+    /// `resampled_cube_mesh`.
+    resample_size: Option<f32>,
+}
+
+impl Default for Tessellation {
+    fn default() -> Self {
+        Tessellation {
+            sphere_subdivisions: 2,
+            cylinder_steps: 24,
+            boxed_ball_size: 0.0,
+            resample_size: None,
+        }
+    }
+}
+
+impl Tessellation {
+    /// The `for_lod` level past which nothing gets any coarser.
+    pub(crate) const COARSEST_LOD: usize = 2;
+
+    /// Each level drops a sphere subdivision and halves the cylinder
+    /// segments, down to an icosahedron and a hexagonal prism. From level 2
+    /// on, balls under 4 studs are blocks, which take 12 triangles to the
+    /// icosahedron's 20 and look no different that far away.
+    pub(crate) fn for_lod(level: usize) -> Self {
+        let full = Tessellation::default();
+        Tessellation {
+            sphere_subdivisions: full.sphere_subdivisions.saturating_sub(level),
+            cylinder_steps: (full.cylinder_steps >> level.min(8)).max(6),
+            boxed_ball_size: if level >= 2 { 4.0 } else { 0.0 },
+            resample_size: None,
+        }
+    }
+
+    /// The same, with blocks resampled to `resample_size`.
+    pub(crate) fn resampled(self, resample_size: Option<f32>) -> Self {
+        Tessellation {
+            resample_size,
+            ..self
+        }
+    }
+
+    fn ball_as_box(self, size: Vector3) -> bool {
+        size.x.max(size.y).max(size.z) < self.boxed_ball_size
+    }
+
+    fn box_mesh(self, size: Vector3) -> Mesh {
+        match self.resample_size {
+            Some(max_size) => resampled_cube_mesh(size, max_size),
+            None => cube_mesh(size),
+        }
+    }
+
+    fn box_counts(self, size: Vector3) -> (usize, usize) {
+        let Some(max_size) = self.resample_size else {
+            return (8, 12);
+        };
+        let [x, y, z] = [size.x, size.y, size.z].map(|length| {
+            let mut segments = 1;
+            while length.is_finite() && length / segments as f32 > max_size {
+                segments *= 2;
+            }
+            segments
+        });
+        // The grid points on the outside of an x by y by z block of cells.
+        let vertices = (x + 1) * (y + 1) * (z + 1) - (x - 1) * (y - 1) * (z - 1);
+        (vertices, 4 * (x * y + y * z + z * x))
+    }
+}
+
+/// Builds a part's mesh around the origin, before its CFrame is applied,
+/// including any surface details.
+pub(crate) fn local_mesh(
+    part: &PartRecord,
+    truss_style: TrussStyle,
+    tessellation: Tessellation,
+) -> Mesh {
+    let mut mesh = shape_mesh(part, truss_style, tessellation);
+    if part.surfaces != [0; 6] {
+        append_mesh(&mut mesh, surface_details_mesh(part));
+    }
+    mesh
+}
+
+fn shape_mesh(part: &PartRecord, truss_style: TrussStyle, tessellation: Tessellation) -> Mesh {
+    let size = part.size;
+    match part.class.as_str() {
+        "Part" => match part.shape {
+            0 if tessellation.ball_as_box(size) => tessellation.box_mesh(size),
+            0 => sphere_mesh(size, tessellation.sphere_subdivisions),
+            1 => tessellation.box_mesh(size),
+            2 => cylinder_mesh(size, tessellation.cylinder_steps),
+            _ => tessellation.box_mesh(size),
+        },
+        "WedgePart" => wedge_mesh(size),
+        "CornerWedgePart" => corner_wedge_mesh(size),
+        "TrussPart" => match truss_style {
+            TrussStyle::Box => tessellation.box_mesh(size),
+            TrussStyle::Triangle => {
+                let brace_count = ((size.y / size.x) as usize).clamp(1, 20);
+                triangular_truss_mesh(size, brace_count)
+            }
+            TrussStyle::LShape => lshape_truss_mesh(size),
+        },
+        _ => tessellation.box_mesh(size),
+    }
+}
+
+/// How many vertices and triangles `local_mesh` would build for a part,
+/// without building them.
+pub(crate) fn mesh_counts(
+    part: &PartRecord,
+    truss_style: TrussStyle,
+    tessellation: Tessellation,
+) -> (usize, usize) {
+    let (vertices, faces) = shape_counts(part, truss_style, tessellation);
+    let (extra_vertices, extra_faces) = surface_details_counts(part);
+    (vertices + extra_vertices, faces + extra_faces)
+}
+
+fn shape_counts(
+    part: &PartRecord,
+    truss_style: TrussStyle,
+    tessellation: Tessellation,
+) -> (usize, usize) {
+    let box_counts = tessellation.box_counts(part.size);
+    match part.class.as_str() {
+        "Part" => match part.shape {
+            0 if tessellation.ball_as_box(part.size) => box_counts,
+            0 => {
+                // Each subdivision quadruples the faces of the icosahedron.
+                let quads = 4usize.pow(tessellation.sphere_subdivisions as u32);
+                (10 * quads + 2, 20 * quads)
+            }
+            2 => {
+                let steps = tessellation.cylinder_steps;
+                (2 * steps + 2, 4 * steps)
+            }
+            _ => box_counts,
+        },
+        "WedgePart" => (6, 8),
+        "CornerWedgePart" => (5, 6),
+        "TrussPart" => match truss_style {
+            TrussStyle::Box => box_counts,
+            TrussStyle::Triangle => {
+                let brace_count = ((part.size.y / part.size.x) as usize).clamp(1, 20);
+                let quads = 3 * (2 + brace_count);
+                (4 * quads + 6, 2 * quads + 2)
+            }
+            TrussStyle::LShape => (12, 20),
+        },
+        _ => box_counts,
+    }
+}
+
+/// One of a block's six faces, named like its `*Surface` property.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FaceId {
+    Top,
+    Bottom,
+    Front,
+    Back,
+    Left,
+    Right,
+}
+
+impl FaceId {
+    pub const ALL: [FaceId; 6] = [
+        FaceId::Top,
+        FaceId::Bottom,
+        FaceId::Front,
+        FaceId::Back,
+        FaceId::Left,
+        FaceId::Right,
+    ];
+
+    /// The part property holding this face's surface type.
+    pub fn surface_property(self) -> &'static str {
+        match self {
+            FaceId::Top => "TopSurface",
+            FaceId::Bottom => "BottomSurface",
+            FaceId::Front => "FrontSurface",
+            FaceId::Back => "BackSurface",
+            FaceId::Left => "LeftSurface",
+            FaceId::Right => "RightSurface",
+        }
+    }
+
+    /// The axis the face looks along (0 = X, 1 = Y, 2 = Z) and which way.
+    /// Parts face -Z, so that's the front.
+    fn axis(self) -> (usize, f32) {
+        match self {
+            FaceId::Top => (1, 1.0),
+            FaceId::Bottom => (1, -1.0),
+            FaceId::Front => (2, -1.0),
+            FaceId::Back => (2, 1.0),
+            FaceId::Left => (0, -1.0),
+            FaceId::Right => (0, 1.0),
+        }
+    }
+}
+
+/// `Enum.SurfaceType` values that get a stub with `surface_details_mesh`.
+const SURFACE_WELD: u32 = 2;
+const SURFACE_STUDS: u32 = 3;
+const SURFACE_HINGE: u32 = 6;
+const SURFACE_MOTOR: u32 = 7;
+const SURFACE_STEPPING_MOTOR: u32 = 8;
+
+const STUD_RADIUS: f32 = 0.3;
+const STUD_HEIGHT: f32 = 0.3;
+/// Studs are tiny and there can be a lot of them, so they're coarse.
+const STUD_STEPS: usize = 8;
+const HINGE_STEPS: usize = 12;
+
+/// Extra geometry showing a block's surface types: studs on `Studs` faces,
+/// and a short cylinder in the middle of weld, hinge and motor faces. Only
+/// plain blocks get any, since those are the only parts whose faces are flat
+/// rectangles to put them on.
+pub(crate) fn surface_details_mesh(part: &PartRecord) -> Mesh {
+    let mut mesh = (Vec::new(), Vec::new());
+    if part.class != "Part" || part.shape != 1 {
+        return mesh;
+    }
+    for (face, &surface) in FaceId::ALL.iter().zip(&part.surfaces) {
+        let details = match surface {
+            SURFACE_STUDS => stud_array_mesh(*face, part.size),
+            SURFACE_WELD | SURFACE_HINGE | SURFACE_MOTOR | SURFACE_STEPPING_MOTOR => {
+                hinge_stub_mesh(*face, part.size)
+            }
+            _ => continue,
+        };
+        append_mesh(&mut mesh, details);
+    }
+    mesh
+}
+
+/// How many vertices and triangles `surface_details_mesh` would add.
+fn surface_details_counts(part: &PartRecord) -> (usize, usize) {
+    if part.class != "Part" || part.shape != 1 {
+        return (0, 0);
+    }
+    let cylinder = |steps: usize| (2 * steps + 2, 4 * steps);
+    let extents = [part.size.x, part.size.y, part.size.z];
+    let mut counts = (0, 0);
+    for (face, &surface) in FaceId::ALL.iter().zip(&part.surfaces) {
+        let (count, (vertices, faces)) = match surface {
+            SURFACE_STUDS => {
+                let (axis, _) = face.axis();
+                let studs = extents[(axis + 1) % 3].floor() as usize
+                    * extents[(axis + 2) % 3].floor() as usize;
+                (studs, cylinder(STUD_STEPS))
+            }
+            SURFACE_WELD | SURFACE_HINGE | SURFACE_MOTOR | SURFACE_STEPPING_MOTOR => {
+                (1, cylinder(HINGE_STEPS))
+            }
+            _ => continue,
+        };
+        counts.0 += count * vertices;
+        counts.1 += count * faces;
+    }
+    counts
+}
+
+/// A grid of studs, one per stud of the face's area, standing on the given
+/// face of a block of `size`. Faces less than a stud across get none.
+pub fn stud_array_mesh(face: FaceId, size: Vector3) -> Mesh {
+    let (axis, sign) = face.axis();
+    let extents = [size.x, size.y, size.z];
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+    let (columns, rows) = (extents[u].floor() as usize, extents[v].floor() as usize);
+
+    let mut mesh = (Vec::new(), Vec::new());
+    for i in 0..columns {
+        for j in 0..rows {
+            let mut center = [0.0; 3];
+            center[axis] = sign * (extents[axis] + STUD_HEIGHT) / 2.0;
+            // Centered on the face, so any leftover is split between the edges.
+            center[u] = i as f32 + 0.5 - columns as f32 / 2.0;
+            center[v] = j as f32 + 0.5 - rows as f32 / 2.0;
+            append_mesh(
+                &mut mesh,
+                stub_cylinder(axis, center, STUD_RADIUS, STUD_HEIGHT, STUD_STEPS),
+            );
+        }
+    }
+    mesh
+}
+
+/// A short cylinder sticking out of the middle of the given face of a block
+/// of `size`, half of it sunk into the block, marking a weld, hinge or motor
+/// surface. It's a tenth of the face's narrower side in radius, and twice
+/// that long.
+pub fn hinge_stub_mesh(face: FaceId, size: Vector3) -> Mesh {
+    let (axis, sign) = face.axis();
+    let extents = [size.x, size.y, size.z];
+    let radius = extents[(axis + 1) % 3].min(extents[(axis + 2) % 3]) * 0.1;
+    let mut center = [0.0; 3];
+    center[axis] = sign * extents[axis] / 2.0;
+    stub_cylinder(axis, center, radius, 2.0 * radius, HINGE_STEPS)
+}
+
+/// A cylinder along `axis` centered on `center`.
+fn stub_cylinder(axis: usize, center: [f32; 3], radius: f32, length: f32, steps: usize) -> Mesh {
+    let (mut vertices, faces) =
+        cylinder_mesh(Vector3::new(length, 2.0 * radius, 2.0 * radius), steps);
+    // Rotating the axes round keeps the winding, unlike swapping two.
+    for v in &mut vertices {
+        let mut turned = [0.0; 3];
+        turned[axis] = v.x;
+        turned[(axis + 1) % 3] = v.y;
+        turned[(axis + 2) % 3] = v.z;
+        *v = Vector3::new(
+            turned[0] + center[0],
+            turned[1] + center[1],
+            turned[2] + center[2],
+        );
+    }
+    (vertices, faces)
+}
+
+/// Adds `other`'s triangles to `mesh`, offsetting their indices.
+pub(crate) fn append_mesh(mesh: &mut Mesh, (vertices, faces): Mesh) {
+    let offset = mesh.0.len();
+    mesh.0.extend(vertices);
+    mesh.1.extend(
+        faces
+            .into_iter()
+            .map(|(a, b, c)| (a + offset, b + offset, c + offset)),
+    );
+}
+
+/// Moves mesh vertices from the part's local space into the world.
+pub fn transform_vertices(vertices: &mut [Vector3], cframe: &CFrame) {
+    for v in vertices.iter_mut() {
+        *v = apply_cframe(*v, cframe);
+    }
+}
+
+/// Rotates `v` by `m`, whose fields are its rows.
+pub fn apply_matrix3(m: &Matrix3, v: Vector3) -> Vector3 {
+    Vector3::new(
+        m.x.x * v.x + m.x.y * v.y + m.x.z * v.z,
+        m.y.x * v.x + m.y.y * v.y + m.y.z * v.z,
+        m.z.x * v.x + m.z.y * v.y + m.z.z * v.z,
+    )
+}
+
+/// Takes a point from a CFrame's local space into the space it's relative to.
+pub fn apply_cframe(v: Vector3, cf: &CFrame) -> Vector3 {
+    let r = apply_matrix3(&cf.orientation, v);
+    Vector3::new(
+        r.x + cf.position.x,
+        r.y + cf.position.y,
+        r.z + cf.position.z,
+    )
+}
+
+/// A block: 8 corners, 12 triangles.
+pub fn cube_mesh(size: Vector3) -> Mesh {
+    let sx = size.x / 2.0;
+    let sy = size.y / 2.0;
+    let sz = size.z / 2.0;
+
+    let vertices = vec![
+        Vector3::new(-sx, -sy, -sz),
+        Vector3::new(sx, -sy, -sz),
+        Vector3::new(sx, sy, -sz),
+        Vector3::new(-sx, sy, -sz),
+        Vector3::new(-sx, -sy, sz),
+        Vector3::new(sx, -sy, sz),
+        Vector3::new(sx, sy, sz),
+        Vector3::new(-sx, sy, sz),
+    ];
+
+    let faces = vec![
+        (0, 2, 1),
+        (0, 3, 2),
+        (4, 5, 6),
+        (4, 6, 7),
+        (0, 1, 5),
+        (0, 5, 4),
+        (1, 2, 6),
+        (1, 6, 5),
+        (2, 3, 7),
+        (2, 7, 6),
+        (3, 0, 4),
+        (3, 4, 7),
+    ];
+
+    (vertices, faces)
+}
+
+/// A block whose faces are split into a grid of quads no more than
+/// `max_size` along either side, so lightmap bakers and per-vertex lighting
+/// get vertices across big floors and walls instead of only at the corners.
+/// Neighbouring faces split their shared edges at the same points, and
+/// share those vertices.
+pub fn resampled_cube_mesh(size: Vector3, max_size: f32) -> Mesh {
+    // `cube_mesh`'s faces as quads, wound the same way.
+    const QUADS: [[usize; 4]; 6] = [
+        [0, 3, 2, 1],
+        [4, 5, 6, 7],
+        [0, 1, 5, 4],
+        [1, 2, 6, 5],
+        [2, 3, 7, 6],
+        [3, 0, 4, 7],
+    ];
+    let (corners, _) = cube_mesh(size);
+    let mut vertices = Vec::new();
+    let mut faces = Vec::new();
+    let mut numbers: HashMap<[u32; 3], usize> = HashMap::new();
+    for [a, b, c, d] in QUADS {
+        let quads = subdivide_face(corners[a], corners[b], corners[c], corners[d], max_size);
+        for (v0, v1, v2, v3) in quads {
+            let [i0, i1, i2, i3] = [v0, v1, v2, v3].map(|v| {
+                *numbers
+                    .entry([v.x, v.y, v.z].map(f32::to_bits))
+                    .or_insert_with(|| {
+                        vertices.push(v);
+                        vertices.len() - 1
+                    })
+            });
+            faces.push((i0, i1, i2));
+            faces.push((i0, i2, i3));
+        }
+    }
+    (vertices, faces)
+}
+
+/// Splits the parallelogram `v0 v1 v2 v3` in half, across whichever pair
+/// of sides is longer than `max_size`, and its halves in turn, until every
+/// side is `max_size` or shorter. The quads keep the corners' winding.
+fn subdivide_face(
+    v0: Vector3,
+    v1: Vector3,
+    v2: Vector3,
+    v3: Vector3,
+    max_size: f32,
+) -> Vec<(Vector3, Vector3, Vector3, Vector3)> {
+    // Infinite sides would never get short enough.
+    let length = |a: Vector3, b: Vector3| {
+        let d = sub(b, a);
+        let length = dot(d, d).sqrt();
+        if length.is_finite() { length } else { 0.0 }
+    };
+    let mid = |a: Vector3, b: Vector3| {
+        Vector3::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0, (a.z + b.z) / 2.0)
+    };
+    if length(v0, v1).max(length(v3, v2)) > max_size {
+        let (m01, m32) = (mid(v0, v1), mid(v3, v2));
+        let mut quads = subdivide_face(v0, m01, m32, v3, max_size);
+        quads.extend(subdivide_face(m01, v1, v2, m32, max_size));
+        quads
+    } else if length(v1, v2).max(length(v0, v3)) > max_size {
+        let (m12, m03) = (mid(v1, v2), mid(v0, v3));
+        let mut quads = subdivide_face(v0, v1, m12, m03, max_size);
+        quads.extend(subdivide_face(m03, m12, v2, v3, max_size));
+        quads
+    } else {
+        vec![(v0, v1, v2, v3)]
+    }
+}
+
+/// Icosahedron-based unit sphere that can be subdivided in place.
+struct IcosphereBuilder {
+    vertices: Vec<Vector3>,
+    faces: Vec<(usize, usize, usize)>,
+    mid_cache: HashMap<(usize, usize), usize>,
+}
+
+impl IcosphereBuilder {
+    fn new() -> Self {
+        let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+
+        let vertices = vec![
+            Vector3::new(-1.0, t, 0.0),
+            Vector3::new(1.0, t, 0.0),
+            Vector3::new(-1.0, -t, 0.0),
+            Vector3::new(1.0, -t, 0.0),
+            Vector3::new(0.0, -1.0, t),
+            Vector3::new(0.0, 1.0, t),
+            Vector3::new(0.0, -1.0, -t),
+            Vector3::new(0.0, 1.0, -t),
+            Vector3::new(t, 0.0, -1.0),
+            Vector3::new(t, 0.0, 1.0),
+            Vector3::new(-t, 0.0, -1.0),
+            Vector3::new(-t, 0.0, 1.0),
+        ];
+
+        let faces = vec![
+            (0, 11, 5),
+            (0, 5, 1),
+            (0, 1, 7),
+            (0, 7, 10),
+            (0, 10, 11),
+            (1, 5, 9),
+            (5, 11, 4),
+            (11, 10, 2),
+            (10, 7, 6),
+            (7, 1, 8),
+            (3, 9, 4),
+            (3, 4, 2),
+            (3, 2, 6),
+            (3, 6, 8),
+            (3, 8, 9),
+            (4, 9, 5),
+            (2, 4, 11),
+            (6, 2, 10),
+            (8, 6, 7),
+            (9, 8, 1),
+        ];
+
+        IcosphereBuilder {
+            vertices: vertices.into_iter().map(normalize).collect(),
+            faces,
+            mid_cache: HashMap::new(),
+        }
+    }
+
+    /// Returns the vertex halfway along edge `a`-`b`, pushed out onto the unit
+    /// sphere. Each edge's midpoint is only created once.
+    fn midpoint(&mut self, a: usize, b: usize) -> usize {
+        let key = if a < b { (a, b) } else { (b, a) };
+        if let Some(&idx) = self.mid_cache.get(&key) {
+            return idx;
+        }
+        let va = self.vertices[a];
+        let vb = self.vertices[b];
+        let vm = normalize(Vector3::new(
+            (va.x + vb.x) / 2.0,
+            (va.y + vb.y) / 2.0,
+            (va.z + vb.z) / 2.0,
+        ));
+        let idx = self.vertices.len();
+        self.vertices.push(vm);
+        self.mid_cache.insert(key, idx);
+        idx
+    }
+
+    /// Splits every triangle into four.
+    fn subdivide(&mut self) {
+        let faces = std::mem::take(&mut self.faces);
+        for (a, b, c) in faces {
+            let ab = self.midpoint(a, b);
+            let bc = self.midpoint(b, c);
+            let ca = self.midpoint(c, a);
+            self.faces.push((a, ab, ca));
+            self.faces.push((b, bc, ab));
+            self.faces.push((c, ca, bc));
+            self.faces.push((ab, bc, ca));
+        }
+    }
+
+    fn scale(&mut self, rx: f32, ry: f32, rz: f32) {
+        for v in self.vertices.iter_mut() {
+            v.x *= rx;
+            v.y *= ry;
+            v.z *= rz;
+        }
+    }
+}
+
+pub(crate) fn sub(a: Vector3, b: Vector3) -> Vector3 {
+    Vector3::new(a.x - b.x, a.y - b.y, a.z - b.z)
+}
+
+pub(crate) fn cross(a: Vector3, b: Vector3) -> Vector3 {
+    Vector3::new(
+        a.y * b.z - a.z * b.y,
+        a.z * b.x - a.x * b.z,
+        a.x * b.y - a.y * b.x,
+    )
+}
+
+pub(crate) fn dot(a: Vector3, b: Vector3) -> f32 {
+    a.x * b.x + a.y * b.y + a.z * b.z
+}
+
+pub(crate) fn normalize(v: Vector3) -> Vector3 {
+    let len = (v.x * v.x + v.y * v.y + v.z * v.z).sqrt();
+    Vector3::new(v.x / len, v.y / len, v.z / len)
+}
+
+/// An icosphere squashed to fit `size`, subdivided `subdivisions` times
+/// (each one quadruples the triangles; Roblox's balls look right at 2).
+pub fn sphere_mesh(size: Vector3, subdivisions: usize) -> Mesh {
+    let mut sphere = IcosphereBuilder::new();
+    for _ in 0..subdivisions {
+        sphere.subdivide();
+    }
+    sphere.scale(size.x / 2.0, size.y / 2.0, size.z / 2.0);
+
+    (sphere.vertices, sphere.faces)
+}
+
+/// A capped cylinder along X, like Roblox's, with `steps` segments around.
+pub fn cylinder_mesh(size: Vector3, steps: usize) -> Mesh {
+    let mut vertices = Vec::new();
+    let mut faces = Vec::new();
+
+    let x_half = size.x / 2.0;
+    let y_half = size.y / 2.0;
+    let z_half = size.z / 2.0;
+
+    for i in 0..steps {
+        let theta = 2.0 * std::f32::consts::PI * i as f32 / steps as f32;
+        let cos_theta = theta.cos();
+        let sin_theta = theta.sin();
+
+        vertices.push(Vector3::new(
+            -x_half,
+            y_half * cos_theta,
+            z_half * sin_theta,
+        ));
+        vertices.push(Vector3::new(x_half, y_half * cos_theta, z_half * sin_theta));
+    }
+
+    vertices.push(Vector3::new(-x_half, 0.0, 0.0));
+    vertices.push(Vector3::new(x_half, 0.0, 0.0));
+
+    for i in 0..steps {
+        let next = (i + 1) % steps;
+        faces.push((i * 2, next * 2, next * 2 + 1));
+        faces.push((i * 2, next * 2 + 1, i * 2 + 1));
+        faces.push((i * 2, vertices.len() - 2, next * 2));
+        faces.push((i * 2 + 1, next * 2 + 1, vertices.len() - 1));
+    }
+
+    (vertices, faces)
+}
+
+/// Triangular truss running along Y: three rails joined by alternating
+/// diagonal braces on each side, with the ends capped.
+pub fn triangular_truss_mesh(size: Vector3, brace_count: usize) -> Mesh {
+    let mut vertices = Vec::new();
+    let mut faces = Vec::new();
+
+    let radius = size.x / 2.0;
+    let y_half = size.y / 2.0;
+    let width = size.x * 0.1;
+
+    let corners: Vec<(f32, f32)> = (0..3)
+        .map(|i| {
+            let theta = std::f32::consts::FRAC_PI_2 + 2.0 * std::f32::consts::PI * i as f32 / 3.0;
+            (radius * theta.cos(), radius * theta.sin())
+        })
+        .collect();
+
+    let mut quad = |a: Vector3, b: Vector3, c: Vector3, d: Vector3| {
+        let base = vertices.len();
+        vertices.extend([a, b, c, d]);
+        faces.push((base, base + 1, base + 2));
+        faces.push((base, base + 2, base + 3));
+    };
+
+    for i in 0..3 {
+        let (px, pz) = corners[i];
+        let (qx, qz) = corners[(i + 1) % 3];
+        let len = ((qx - px).powi(2) + (qz - pz).powi(2)).sqrt();
+        let (dx, dz) = ((qx - px) / len * width, (qz - pz) / len * width);
+
+        let at = |x: f32, z: f32, y: f32| Vector3::new(x, y, z);
+
+        // Rails along both edges of this side.
+        quad(
+            at(px, pz, -y_half),
+            at(px + dx, pz + dz, -y_half),
+            at(px + dx, pz + dz, y_half),
+            at(px, pz, y_half),
+        );
+        quad(
+            at(qx - dx, qz - dz, -y_half),
+            at(qx, qz, -y_half),
+            at(qx, qz, y_half),
+            at(qx - dx, qz - dz, y_half),
+        );
+
+        // Braces zigzag from one rail to the other.
+        for k in 0..brace_count {
+            let y0 = -y_half + size.y * k as f32 / brace_count as f32;
+            let y1 = -y_half + size.y * (k + 1) as f32 / brace_count as f32;
+            let (bottom, top) = if k % 2 == 0 { (y0, y1) } else { (y1, y0) };
+            quad(
+                at(px, pz, bottom),
+                at(px + dx, pz + dz, bottom),
+                at(qx, qz, top),
+                at(qx - dx, qz - dz, top),
+            );
+        }
+    }
+
+    for y in [-y_half, y_half] {
+        let base = vertices.len();
+        vertices.extend(corners.iter().map(|&(x, z)| Vector3::new(x, y, z)));
+        if y < 0.0 {
+            faces.push((base, base + 2, base + 1));
+        } else {
+            faces.push((base, base + 1, base + 2));
+        }
+    }
+
+    (vertices, faces)
+}
+
+/// L-shaped angle profile extruded along Y.
+pub fn lshape_truss_mesh(size: Vector3) -> Mesh {
+    let sx = size.x / 2.0;
+    let sy = size.y / 2.0;
+    let sz = size.z / 2.0;
+    let t = size.x.min(size.z) * 0.25;
+
+    let profile = [
+        (-sx, -sz),
+        (sx, -sz),
+        (sx, -sz + t),
+        (-sx + t, -sz + t),
+        (-sx + t, sz),
+        (-sx, sz),
+    ];
+
+    let mut vertices: Vec<Vector3> = profile
+        .iter()
+        .map(|&(x, z)| Vector3::new(x, -sy, z))
+        .collect();
+    vertices.extend(profile.iter().map(|&(x, z)| Vector3::new(x, sy, z)));
+
+    let n = profile.len();
+    let mut faces = Vec::new();
+    for i in 0..n {
+        let j = (i + 1) % n;
+        faces.push((i, j, j + n));
+        faces.push((i, j + n, i + n));
+    }
+
+    // Every corner of the L is visible from the outer corner, so a fan works.
+    for i in 1..n - 1 {
+        faces.push((0, i + 1, i));
+        faces.push((n, n + i, n + i + 1));
+    }
+
+    (vertices, faces)
+}
+
+/// A WedgePart: a block cut diagonally from the top back edge down to the
+/// bottom front edge.
+pub fn wedge_mesh(size: Vector3) -> Mesh {
+    let sx = size.x / 2.0;
+    let sy = size.y / 2.0;
+    let sz = size.z / 2.0;
+
+    let vertices = vec![
+        Vector3::new(-sx, -sy, -sz),
+        Vector3::new(sx, -sy, -sz),
+        Vector3::new(sx, -sy, sz),
+        Vector3::new(-sx, -sy, sz),
+        Vector3::new(-sx, sy, sz),
+        Vector3::new(sx, sy, sz),
+    ];
+
+    let faces = vec![
+        (0, 1, 2),
+        (0, 2, 3),
+        (0, 4, 1),
+        (1, 4, 5),
+        (3, 2, 5),
+        (3, 5, 4),
+        (0, 3, 4),
+        (1, 5, 2),
+    ];
+
+    (vertices, faces)
+}
+
+/// Outward normals for the triangles of `wedge_mesh`, in the same order.
+/// The slope's normal depends on how tall the wedge is compared to its depth.
+pub fn wedge_face_normals(size: Vector3) -> Vec<Vector3> {
+    let bottom = Vector3::new(0.0, -1.0, 0.0);
+    let slope = normalize(Vector3::new(0.0, size.z, -size.y));
+    let back = Vector3::new(0.0, 0.0, 1.0);
+    let left = Vector3::new(-1.0, 0.0, 0.0);
+    let right = Vector3::new(1.0, 0.0, 0.0);
+    vec![bottom, bottom, slope, slope, back, back, left, right]
+}
+
+/// Vertex normals for any triangle mesh, going by its shape alone: faces
+/// sharing an edge are smoothed together when they meet at less than
+/// `crease_angle` degrees, and keep a hard edge otherwise. Each vertex gets
+/// one normal per group of faces around it that are smoothed together,
+/// averaged over those faces weighted by their area.
+///
+/// Returns the normals and, for each face, which normal each of its
+/// corners uses. Faces only count as sharing an edge when they share its
+/// vertex indices, so a mesh that repeats its vertices along a seam keeps
+/// that seam hard. A face with no area goes along with its neighbours.
+pub fn crease_normals(
+    vertices: &[Vector3],
+    faces: &[(usize, usize, usize)],
+    crease_angle: f32,
+) -> (Vec<Vector3>, Vec<[usize; 3]>) {
+    let facing: Vec<Vector3> = faces
+        .iter()
+        .map(|&(a, b, c)| cross(sub(vertices[b], vertices[a]), sub(vertices[c], vertices[a])))
+        .collect();
+    let unit = |face: usize| {
+        let facing = facing[face];
+        (dot(facing, facing) > 0.0).then(|| normalize(facing))
+    };
+
+    let mut edges: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (face, &(a, b, c)) in faces.iter().enumerate() {
+        for (from, to) in [(a, b), (b, c), (c, a)] {
+            if from != to {
+                edges
+                    .entry((from.min(to), from.max(to)))
+                    .or_default()
+                    .push(face);
+            }
+        }
+    }
+
+    // Corners (face * 3 + which) that end up sharing a normal, as a
+    // disjoint-set forest.
+    let mut parent: Vec<usize> = (0..faces.len() * 3).collect();
+    fn root(parent: &mut [usize], mut corner: usize) -> usize {
+        while parent[corner] != corner {
+            parent[corner] = parent[parent[corner]];
+            corner = parent[corner];
+        }
+        corner
+    }
+    let corner = |face: usize, vertex: usize| {
+        let (a, b, _) = faces[face];
+        face * 3
+            + if vertex == a {
+                0
+            } else if vertex == b {
+                1
+            } else {
+                2
+            }
+    };
+    let threshold = crease_angle.to_radians().cos();
+    for (&(from, to), around) in &edges {
+        for (i, &face) in around.iter().enumerate() {
+            for &other in &around[i + 1..] {
+                let smooth = match (unit(face), unit(other)) {
+                    (Some(n), Some(m)) => dot(n, m) > threshold,
+                    _ => true,
+                };
+                if smooth {
+                    for vertex in [from, to] {
+                        let a = root(&mut parent, corner(face, vertex));
+                        let b = root(&mut parent, corner(other, vertex));
+                        parent[a] = b;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut sums = vec![Vector3::new(0.0, 0.0, 0.0); parent.len()];
+    for corner in 0..parent.len() {
+        let root = root(&mut parent, corner);
+        let facing = facing[corner / 3];
+        let sum = &mut sums[root];
+        *sum = Vector3::new(sum.x + facing.x, sum.y + facing.y, sum.z + facing.z);
+    }
+    let mut numbers = vec![None; parent.len()];
+    let mut normals = Vec::new();
+    let corners = (0..faces.len())
+        .map(|face| {
+            [0, 1, 2].map(|which| {
+                let root = root(&mut parent, face * 3 + which);
+                *numbers[root].get_or_insert_with(|| {
+                    let sum = sums[root];
+                    normals.push(if dot(sum, sum) > 0.0 {
+                        normalize(sum)
+                    } else {
+                        Vector3::new(0.0, 1.0, 0.0)
+                    });
+                    normals.len() - 1
+                })
+            })
+        })
+        .collect();
+    (normals, corners)
+}
+
+/// A CornerWedgePart: a block's bottom face rising to a single top corner
+/// at the right front, with upright faces on the right and front and slopes
+/// down to the left and back edges.
+pub fn corner_wedge_mesh(size: Vector3) -> Mesh {
+    let sx = size.x / 2.0;
+    let sy = size.y / 2.0;
+    let sz = size.z / 2.0;
+
+    let vertices = vec![
+        Vector3::new(-sx, -sy, -sz),
+        Vector3::new(sx, -sy, -sz),
+        Vector3::new(sx, -sy, sz),
+        Vector3::new(-sx, -sy, sz),
+        Vector3::new(sx, sy, -sz),
+    ];
+
+    let faces = vec![
+        (0, 1, 2),
+        (0, 2, 3),
+        (0, 4, 1),
+        (1, 4, 2),
+        (0, 3, 4),
+        (3, 2, 4),
+    ];
+
+    (vertices, faces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(class: &str, shape: u32, size: Vector3) -> PartRecord {
+        PartRecord {
+            class: class.to_string(),
+            shape,
+            ..PartRecord::block(size, Vector3::new(0.0, 0.0, 0.0))
+        }
+    }
+
+    fn bounds(vertices: &[Vector3]) -> (Vector3, Vector3) {
+        let fold = |pick: fn(f32, f32) -> f32, start: f32| {
+            vertices
+                .iter()
+                .fold(Vector3::new(start, start, start), |b, v| {
+                    Vector3::new(pick(b.x, v.x), pick(b.y, v.y), pick(b.z, v.z))
+                })
+        };
+        (fold(f32::min, f32::MAX), fold(f32::max, f32::MIN))
+    }
+
+    fn assert_near(actual: Vector3, expected: Vector3, tolerance: f32) {
+        let off = sub(actual, expected);
+        assert!(
+            dot(off, off).sqrt() <= tolerance,
+            "{:?} isn't {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn counts_match_meshes() {
+        let size = Vector3::new(4.0, 3.0, 9.0);
+        let mut parts = vec![
+            part("Part", 0, size),
+            part("Part", 0, Vector3::new(2.0, 2.0, 2.0)),
+            part("Part", 1, size),
+            part("Part", 2, size),
+            part("WedgePart", 0, size),
+            part("CornerWedgePart", 0, size),
+            part("TrussPart", 0, Vector3::new(2.0, 10.0, 2.0)),
+            part("Seat", 0, size),
+        ];
+        let mut studded = part("Part", 1, size);
+        studded.surfaces = [SURFACE_STUDS, 0, SURFACE_HINGE, 0, SURFACE_WELD, 0];
+        parts.push(studded);
+
+        let styles = [TrussStyle::Box, TrussStyle::Triangle, TrussStyle::LShape];
+        for part in &parts {
+            for style in styles {
+                for level in 0..=Tessellation::COARSEST_LOD + 1 {
+                    for resample in [None, Some(2.0)] {
+                        let tessellation = Tessellation::for_lod(level).resampled(resample);
+                        let (vertices, faces) = local_mesh(part, style, tessellation);
+                        assert_eq!(
+                            mesh_counts(part, style, tessellation),
+                            (vertices.len(), faces.len()),
+                            "{} shape {} at level {} resampled to {:?}",
+                            part.class,
+                            part.shape,
+                            level,
+                            resample
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn small_balls_become_blocks_from_level_two() {
+        let small = part("Part", 0, Vector3::new(3.0, 3.0, 3.0));
+        let large = part("Part", 0, Vector3::new(4.0, 4.0, 4.0));
+        let mesh = |part: &PartRecord, level| {
+            local_mesh(part, TrussStyle::Box, Tessellation::for_lod(level))
+        };
+        let block = cube_mesh(small.size);
+
+        assert_eq!(mesh(&small, 1), sphere_mesh(small.size, 1));
+        assert_eq!(mesh(&small, 2), block);
+        assert_eq!(mesh(&small, 3), block);
+        // 4 studs isn't under the cutoff, so it stays an icosahedron.
+        assert_eq!(mesh(&large, 2), sphere_mesh(large.size, 0));
+    }
+
+    #[test]
+    fn cube_spans_its_size() {
+        let size = Vector3::new(2.0, 4.0, 6.0);
+        let (min, max) = bounds(&cube_mesh(size).0);
+        assert_near(min, Vector3::new(-1.0, -2.0, -3.0), 0.0);
+        assert_near(max, Vector3::new(1.0, 2.0, 3.0), 0.0);
+    }
+
+    #[test]
+    fn sphere_stays_inside_its_size() {
+        let size = Vector3::new(2.0, 4.0, 6.0);
+        let (vertices, _) = sphere_mesh(size, 2);
+        for v in &vertices {
+            // On the ellipsoid inscribed in the part's box.
+            let on_unit = Vector3::new(v.x / 1.0, v.y / 2.0, v.z / 3.0);
+            assert!((dot(on_unit, on_unit).sqrt() - 1.0).abs() < 1e-5);
+        }
+        let (min, max) = bounds(&vertices);
+        assert_near(min, Vector3::new(-1.0, -2.0, -3.0), 0.1);
+        assert_near(max, Vector3::new(1.0, 2.0, 3.0), 0.1);
+    }
+
+    #[test]
+    fn cylinder_runs_along_x() {
+        let size = Vector3::new(5.0, 2.0, 2.0);
+        let (min, max) = bounds(&cylinder_mesh(size, 24).0);
+        assert_near(min, Vector3::new(-2.5, -1.0, -1.0), 1e-5);
+        assert_near(max, Vector3::new(2.5, 1.0, 1.0), 1e-5);
+    }
+
+    #[test]
+    fn corner_wedge_rises_to_one_corner() {
+        let (vertices, faces) = corner_wedge_mesh(Vector3::new(2.0, 2.0, 2.0));
+        let top: Vec<&Vector3> = vertices.iter().filter(|v| v.y > 0.0).collect();
+        assert_eq!(top.len(), 1);
+        assert_near(*top[0], Vector3::new(1.0, 1.0, -1.0), 0.0);
+        // Every face points away from a point inside.
+        let inside = Vector3::new(0.5, -0.5, -0.5);
+        for &(a, b, c) in &faces {
+            let facing = cross(sub(vertices[b], vertices[a]), sub(vertices[c], vertices[a]));
+            assert!(dot(facing, sub(vertices[a], inside)) > 0.0);
+        }
+    }
+
+    #[test]
+    fn rotated_cframe_turns_then_moves() {
+        // CFrame.new(10, 0, 0) * CFrame.Angles(0, math.pi / 2, 0)
+        let cframe = CFrame::new(
+            Vector3::new(10.0, 0.0, 0.0),
+            Matrix3::new(
+                Vector3::new(0.0, 0.0, 1.0),
+                Vector3::new(0.0, 1.0, 0.0),
+                Vector3::new(-1.0, 0.0, 0.0),
+            ),
+        );
+        assert_near(
+            apply_cframe(Vector3::new(1.0, 0.0, 0.0), &cframe),
+            Vector3::new(10.0, 0.0, -1.0),
+            1e-6,
+        );
+        assert_near(
+            apply_cframe(Vector3::new(0.0, 2.0, 3.0), &cframe),
+            Vector3::new(13.0, 2.0, 0.0),
+            1e-6,
+        );
+    }
+
+    fn facing(vertices: &[Vector3], (a, b, c): (usize, usize, usize)) -> Vector3 {
+        normalize(cross(
+            sub(vertices[b], vertices[a]),
+            sub(vertices[c], vertices[a]),
+        ))
+    }
+
+    fn same_direction(a: Vector3, b: Vector3) -> bool {
+        dot(a, b) > 0.9999
+    }
+
+    #[test]
+    fn cube_creases_into_six_hard_normals() {
+        let (vertices, faces) = cube_mesh(Vector3::new(2.0, 4.0, 6.0));
+        let (normals, corners) = crease_normals(&vertices, &faces, 30.0);
+        for (&face, corners) in faces.iter().zip(&corners) {
+            for n in corners {
+                assert!(same_direction(normals[*n], facing(&vertices, face)));
+            }
+        }
+        let mut directions: Vec<Vector3> = Vec::new();
+        for &n in &normals {
+            if !directions.iter().any(|&d| same_direction(d, n)) {
+                directions.push(n);
+            }
+        }
+        assert_eq!(directions.len(), 6);
+    }
+
+    #[test]
+    fn sphere_creases_into_smooth_normals() {
+        let (vertices, faces) = sphere_mesh(Vector3::new(2.0, 2.0, 2.0), 2);
+        let (normals, corners) = crease_normals(&vertices, &faces, 30.0);
+        // One normal per vertex, pointing straight out of the sphere.
+        assert_eq!(normals.len(), vertices.len());
+        for (&(a, b, c), corners) in faces.iter().zip(&corners) {
+            for (vertex, n) in [a, b, c].into_iter().zip(corners) {
+                assert!(dot(normals[*n], normalize(vertices[vertex])) > 0.99);
+            }
+        }
+    }
+
+    #[test]
+    fn cylinder_creases_into_hard_caps_and_a_smooth_side() {
+        let steps = 24;
+        let (vertices, faces) = cylinder_mesh(Vector3::new(4.0, 2.0, 2.0), steps);
+        let (normals, corners) = crease_normals(&vertices, &faces, 30.0);
+        let mut side_normals = Vec::new();
+        for (&face, corners) in faces.iter().zip(&corners) {
+            let flat = facing(&vertices, face);
+            let (a, b, c) = face;
+            if flat.x.abs() > 0.9999 {
+                // A cap: every corner takes the cap's own normal.
+                for n in corners {
+                    assert!(same_direction(normals[*n], flat));
+                }
+                continue;
+            }
+            for (vertex, n) in [a, b, c].into_iter().zip(corners) {
+                let v = vertices[vertex];
+                // Averaged from the faces around the vertex, so close to
+                // straight out rather than exactly.
+                let outward = normalize(Vector3::new(0.0, v.y, v.z));
+                assert!(normals[*n].x.abs() < 1e-5 && dot(normals[*n], outward) > 0.99);
+                side_normals.push(*n);
+            }
+        }
+        // Shared around the side, one per rim vertex.
+        side_normals.sort_unstable();
+        side_normals.dedup();
+        assert_eq!(side_normals.len(), steps * 2);
+    }
+}
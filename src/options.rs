@@ -0,0 +1,1217 @@
+//! The settings an export works from.
+
+use std::path::PathBuf;
+
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use rbx_types::Vector3;
+
+use crate::{
+    ExportError, bvh::Aabb, callbacks::ExportCallbacks, postprocess::MeshPostProcessor,
+    warning::WarningCode,
+};
+
+/// Everything that decides what gets exported and how. `Default` matches
+/// running `rbx_obj` with no flags.
+pub struct ExportOptions {
+    pub export_constraints: bool,
+    /// Draw `SelectionBox`es and `SelectionSphere`s as lines, see
+    /// `--export-selection-effects`.
+    pub export_selection_effects: bool,
+    /// Add studs and hinge stubs for blocks' surface types.
+    pub export_surface_details: bool,
+    pub respect_archivable: bool,
+    pub inherit_color: bool,
+    /// Class names of the top-level services to export, or `None` for all of them.
+    pub services: Option<Vec<String>>,
+    pub part_info_csv: Option<PathBuf>,
+    pub exclude_tags: Vec<String>,
+    pub include_tags: Vec<String>,
+    /// Only export parts whose position is inside this box.
+    pub region: Option<Aabb>,
+    /// Axes (x, y, z) whose coordinates get negated.
+    pub mirror: [bool; 3],
+    pub mirror_and_append: bool,
+    /// Negate Z on top of any mirroring, see `--right-handed`.
+    pub right_handed: bool,
+    /// Take part sizes from the orientation's column lengths, see `--cframe-scale`.
+    pub cframe_scale: bool,
+    /// Place parts held by joints relative to each other, see `--solve-joints`.
+    pub solve_joints: bool,
+    /// Which pose `solve_joints` puts rigs in, see `--pose`.
+    pub pose: Pose,
+    pub include_cameras: bool,
+    pub include_hidden: bool,
+    pub no_lighting: bool,
+    /// Only write normals for wedges, see `--no-normals`.
+    pub no_normals: bool,
+    /// Which faces share normals, see `--normals`.
+    pub normals: Normals,
+    /// With `Normals::Auto`, the angle in degrees faces have to meet at
+    /// for a hard edge between them.
+    pub crease_angle: f32,
+    /// Write texture coordinates, tiling every this many studs, see `--uvs`.
+    pub uv_studs_per_tile: Option<f32>,
+    /// Export unrecognized classes with a `Size` and `CFrame` as blocks.
+    pub unknown_as_box: bool,
+    pub print_unknown_classes: bool,
+    /// Welding tolerance; zero only merges bit-identical positions.
+    pub weld: Option<f32>,
+    pub weld_per_material: bool,
+    pub group_anchored: bool,
+    pub annotate_faces: bool,
+    /// Write an `o` line before each part naming it by its path from the
+    /// top-level service, like `Workspace/Map/Wall`.
+    pub object_names: bool,
+    /// What goes between the names in `object_names`' paths.
+    pub name_separator: char,
+    pub remove_hidden_faces: Option<f32>,
+    /// Distance within which a vertex counts as lying on an edge.
+    pub fix_tjunctions: Option<f32>,
+    pub omit_interior: bool,
+    pub merge_boxes: bool,
+    /// Replace the scene with a grid of cubes this big, filling every cube a
+    /// part's bounding box touches. Ignored with `stream`.
+    pub voxelize: Option<f32>,
+    /// Split blocks' faces into quads no bigger than this, in studs.
+    pub resample_size: Option<f32>,
+    /// Give every face texture coordinates into `<stem>_palette.png`, which
+    /// holds each material's color as a pixel, and write one material.
+    pub bake_material_id_texture: bool,
+    /// Also write `<stem>_materials.py`, see `--blender-materials`.
+    pub blender_materials: bool,
+    /// How the MTL writes a color's alpha, see `--mtl-transparency`.
+    pub mtl_transparency: MtlTransparency,
+    /// Write the MTL into this directory instead of beside the OBJ. Only
+    /// for exports to files; `export_dom` writes into the writer it's given.
+    pub mtl_dir: Option<PathBuf>,
+    /// How the OBJ's `mtllib` line refers to the MTL, see `--mtllib-style`.
+    pub mtllib_style: MtllibStyle,
+    pub spatial_sort: bool,
+    pub truss_style: TrussStyle,
+    /// Colors forced onto parts carrying a tag, in the order they were given.
+    pub tag_materials: Vec<(String, (u8, u8, u8))>,
+    pub wireframe: Wireframe,
+    /// Which parts get a flipped copy of their faces, see `--double-sided`.
+    pub double_sided: DoubleSided,
+    /// With `DoubleSided::Thin`, how thin a part's thinnest side has to be,
+    /// in studs.
+    pub double_sided_threshold: f32,
+    /// Write each part as soon as it's found instead of collecting the scene
+    /// first. The DOM is still read in whole before that.
+    pub stream: bool,
+    pub max_vertices_per_file: Option<usize>,
+    pub list_textures: bool,
+    /// Go through the parts and report what would be written, without writing.
+    pub dry_run: bool,
+    /// Print the materials the export would create instead of exporting.
+    pub list_materials: bool,
+    /// Print `list_materials` as JSON.
+    pub json: bool,
+    /// Columns and rows of the top-down sketch printed before writing.
+    pub preview: Option<(usize, usize)>,
+    /// Put the sketch at the top of the OBJ as comments too.
+    pub preview_in_obj: bool,
+    /// Number of detail levels to write, each to its own `<stem>_lodK.obj`.
+    pub lods: Option<usize>,
+    /// Coarsen balls and cylinders, then leave out the smallest parts, until
+    /// the estimated triangle count is at most this, see `--max-triangles`.
+    pub max_triangles: Option<usize>,
+    pub format: OutputFormat,
+    /// Encode `OutputFormat::Json` as CBOR.
+    pub json_binary: bool,
+    pub max_depth: usize,
+    pub timings: Option<TimingsFormat>,
+    /// Read by the `rbx_obj` binary, which then prints only errors. The
+    /// library itself never prints.
+    pub quiet: bool,
+    /// File collecting a JSON summary line per converted input.
+    pub summary_json: Option<PathBuf>,
+    /// File collecting every warning as a JSON line, or `-` for stderr.
+    pub warnings_jsonl: Option<PathBuf>,
+    /// Warnings that make the export fail once it's written.
+    pub fail_on: Vec<WarningCode>,
+    /// Delete what was written if a `fail_on` warning came up.
+    pub no_partial_output: bool,
+    /// Skip instances that run into an error, listing them in
+    /// `ExportReport::errors`, rather than failing the export on the first.
+    pub best_effort: bool,
+    /// Run on every part's mesh in order, before it's written.
+    pub postprocessors: Vec<Box<dyn MeshPostProcessor>>,
+    /// Progress and warnings as they happen, which the export doesn't print
+    /// itself.
+    pub callbacks: ExportCallbacks,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            export_constraints: false,
+            export_selection_effects: false,
+            export_surface_details: false,
+            respect_archivable: false,
+            inherit_color: false,
+            services: Some(vec!["Workspace".to_string()]),
+            part_info_csv: None,
+            exclude_tags: Vec::new(),
+            include_tags: Vec::new(),
+            region: None,
+            mirror: [false; 3],
+            mirror_and_append: false,
+            right_handed: false,
+            cframe_scale: false,
+            solve_joints: false,
+            pose: Pose::Bind,
+            include_cameras: false,
+            include_hidden: false,
+            no_lighting: false,
+            no_normals: false,
+            normals: Normals::Smooth,
+            crease_angle: 30.0,
+            uv_studs_per_tile: None,
+            unknown_as_box: false,
+            print_unknown_classes: false,
+            weld: None,
+            weld_per_material: false,
+            group_anchored: false,
+            annotate_faces: false,
+            object_names: false,
+            name_separator: '/',
+            remove_hidden_faces: None,
+            fix_tjunctions: None,
+            omit_interior: false,
+            merge_boxes: false,
+            voxelize: None,
+            resample_size: None,
+            bake_material_id_texture: false,
+            blender_materials: false,
+            mtl_transparency: MtlTransparency::D,
+            mtl_dir: None,
+            mtllib_style: MtllibStyle::Filename,
+            spatial_sort: false,
+            truss_style: TrussStyle::Box,
+            tag_materials: Vec::new(),
+            wireframe: Wireframe::Off,
+            double_sided: DoubleSided::Off,
+            double_sided_threshold: 0.2,
+            stream: false,
+            max_vertices_per_file: None,
+            list_textures: false,
+            dry_run: false,
+            list_materials: false,
+            json: false,
+            preview: None,
+            preview_in_obj: false,
+            lods: None,
+            max_triangles: None,
+            format: OutputFormat::Obj,
+            json_binary: false,
+            max_depth: 64,
+            timings: None,
+            quiet: false,
+            summary_json: None,
+            warnings_jsonl: None,
+            fail_on: Vec::new(),
+            no_partial_output: false,
+            best_effort: false,
+            postprocessors: Vec::new(),
+            callbacks: ExportCallbacks::default(),
+        }
+    }
+}
+
+impl ExportOptions {
+    /// An `ExportOptionsBuilder` starting from the defaults.
+    pub fn builder() -> ExportOptionsBuilder {
+        ExportOptionsBuilder::default()
+    }
+
+    /// Checks that the options go together, naming the first pair that
+    /// doesn't. The exports check this before starting, so setting the
+    /// fields directly gets the same checks as going through the builder.
+    pub fn validate(&self) -> Result<(), ExportError> {
+        let distances = [
+            ("weld", self.weld),
+            ("remove_hidden_faces", self.remove_hidden_faces),
+            ("fix_tjunctions", self.fix_tjunctions),
+        ];
+        for (name, distance) in distances {
+            if let Some(distance) = distance
+                && !(distance.is_finite() && distance >= 0.0)
+            {
+                return invalid(format!(
+                    "{} should be a finite distance of 0 or more, not {}",
+                    name, distance
+                ));
+            }
+        }
+        let sizes = [
+            ("voxelize", self.voxelize),
+            ("uv_studs_per_tile", self.uv_studs_per_tile),
+            ("double_sided_threshold", Some(self.double_sided_threshold)),
+            ("resample_size", self.resample_size),
+        ];
+        for (name, size) in sizes {
+            if let Some(size) = size
+                && !(size.is_finite() && size > 0.0)
+            {
+                return invalid(format!(
+                    "{} should be a finite size above 0, not {}",
+                    name, size
+                ));
+            }
+        }
+        let counts = [
+            ("lods", self.lods),
+            ("max_vertices_per_file", self.max_vertices_per_file),
+            ("max_triangles", self.max_triangles),
+        ];
+        for (name, count) in counts {
+            if count == Some(0) {
+                return invalid(format!("{} should be at least 1", name));
+            }
+        }
+        if let Some(Aabb { min, max }) = self.region
+            && !(min.x <= max.x && min.y <= max.y && min.z <= max.z)
+        {
+            return invalid(format!(
+                "region's minimum ({}, {}, {}) is past its maximum ({}, {}, {})",
+                min.x, min.y, min.z, max.x, max.y, max.z
+            ));
+        }
+        if let Some((width, height)) = self.preview
+            && (width == 0 || height == 0)
+        {
+            return invalid(format!("preview can't be {}x{}", width, height));
+        }
+
+        if !(0.0..=180.0).contains(&self.crease_angle) {
+            return invalid(format!(
+                "crease_angle should be between 0 and 180 degrees, not {}",
+                self.crease_angle
+            ));
+        }
+
+        if self.name_separator.is_whitespace() || self.name_separator.is_control() {
+            return invalid(format!(
+                "name_separator can't be whitespace or a control character, not {:?}",
+                self.name_separator
+            ));
+        }
+
+        let needs = [
+            (
+                self.fix_tjunctions.is_some(),
+                "fix_tjunctions",
+                self.weld.is_some(),
+                "weld",
+            ),
+            (
+                self.preview_in_obj,
+                "preview_in_obj",
+                self.preview.is_some(),
+                "preview",
+            ),
+            (self.json, "json", self.list_materials, "list_materials"),
+            (
+                self.pose == Pose::Current,
+                "pose Current",
+                self.solve_joints,
+                "solve_joints",
+            ),
+            (
+                self.no_partial_output,
+                "no_partial_output",
+                !self.fail_on.is_empty(),
+                "fail_on",
+            ),
+            (
+                self.name_separator != '/',
+                "name_separator",
+                self.object_names,
+                "object_names",
+            ),
+            (
+                self.json_binary,
+                "json_binary",
+                self.format == OutputFormat::Json,
+                "format Json",
+            ),
+        ];
+        if let Some((_, name, _, needed)) = needs.iter().find(|(set, _, has, _)| *set && !has) {
+            return invalid(format!("{} only works with {}", name, needed));
+        }
+
+        let geometry_format = match self.format {
+            OutputFormat::Json => Some("format Json"),
+            OutputFormat::Bobj => Some("format Bobj"),
+            _ => None,
+        };
+        let conflicts = [
+            (
+                ("stream", self.stream),
+                &[
+                    ("weld", self.weld.is_some()),
+                    ("merge_boxes", self.merge_boxes),
+                    ("remove_hidden_faces", self.remove_hidden_faces.is_some()),
+                    ("omit_interior", self.omit_interior),
+                    ("mirror_and_append", self.mirror_and_append),
+                    ("lods", self.lods.is_some()),
+                    ("spatial_sort", self.spatial_sort),
+                    ("voxelize", self.voxelize.is_some()),
+                    ("bake_material_id_texture", self.bake_material_id_texture),
+                    ("max_triangles", self.max_triangles.is_some()),
+                ][..],
+            ),
+            (
+                ("bake_material_id_texture", self.bake_material_id_texture),
+                &[
+                    ("weld", self.weld.is_some()),
+                    ("uv_studs_per_tile", self.uv_studs_per_tile.is_some()),
+                    ("blender_materials", self.blender_materials),
+                ][..],
+            ),
+            (
+                ("uv_studs_per_tile", self.uv_studs_per_tile.is_some()),
+                &[("weld", self.weld.is_some())][..],
+            ),
+            (
+                ("object_names", self.object_names),
+                &[
+                    ("weld", self.weld.is_some()),
+                    ("voxelize", self.voxelize.is_some()),
+                ][..],
+            ),
+            (
+                ("no_normals", self.no_normals),
+                &[("normals", self.normals != Normals::Smooth)][..],
+            ),
+            (
+                ("voxelize", self.voxelize.is_some()),
+                &[
+                    ("lods", self.lods.is_some()),
+                    ("remove_hidden_faces", self.remove_hidden_faces.is_some()),
+                    ("omit_interior", self.omit_interior),
+                    ("max_triangles", self.max_triangles.is_some()),
+                ][..],
+            ),
+            (
+                ("max_triangles", self.max_triangles.is_some()),
+                &[("lods", self.lods.is_some())][..],
+            ),
+            (
+                (
+                    "max_vertices_per_file",
+                    self.max_vertices_per_file.is_some(),
+                ),
+                &[("weld", self.weld.is_some())][..],
+            ),
+            (
+                ("list_materials", self.list_materials),
+                &[
+                    ("list_textures", self.list_textures),
+                    ("dry_run", self.dry_run),
+                ][..],
+            ),
+            (
+                (
+                    geometry_format.unwrap_or_default(),
+                    geometry_format.is_some(),
+                ),
+                &[
+                    ("stream", self.stream),
+                    ("weld", self.weld.is_some()),
+                    ("lods", self.lods.is_some()),
+                    (
+                        "max_vertices_per_file",
+                        self.max_vertices_per_file.is_some(),
+                    ),
+                    ("wireframe", self.wireframe != Wireframe::Off),
+                    ("export_constraints", self.export_constraints),
+                    ("export_selection_effects", self.export_selection_effects),
+                    ("part_info_csv", self.part_info_csv.is_some()),
+                    ("preview_in_obj", self.preview_in_obj),
+                    ("voxelize", self.voxelize.is_some()),
+                    ("bake_material_id_texture", self.bake_material_id_texture),
+                    ("uv_studs_per_tile", self.uv_studs_per_tile.is_some()),
+                    ("blender_materials", self.blender_materials),
+                    ("object_names", self.object_names),
+                    ("mtl_dir", self.mtl_dir.is_some()),
+                    ("mtllib_style", self.mtllib_style != MtllibStyle::Filename),
+                ][..],
+            ),
+        ];
+        for ((name, set), others) in conflicts {
+            if let Some((other, _)) = others.iter().find(|(_, other_set)| set && *other_set) {
+                return invalid(format!("{} can't be combined with {}", name, other));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid(message: String) -> Result<(), ExportError> {
+    Err(ExportError::Unsupported(message))
+}
+
+/// A value for [`ExportOptions::set`], in the shapes JSON and Python have.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptionValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    List(Vec<OptionValue>),
+}
+
+impl ExportOptions {
+    /// Sets the option whose field is called `name`, for the bindings, which
+    /// get options by name. Every field but `postprocessors` and `callbacks`
+    /// can be set, with
+    ///
+    /// - a `Bool` for flags, a `Number` for numbers and a `String` for paths
+    ///   and `name_separator`, or `Null` for the ones that are `Option`s;
+    /// - a `String` naming the variant for the enums, spelled as their flags
+    ///   take it, like `"relative-to-obj"` or `"edges-and-faces"`, and
+    ///   `"text"` or `"json"` for `timings`;
+    /// - a `List` of `String`s for `services`, the tag lists and `fail_on`,
+    ///   whose codes are as `WarningCode::as_str` gives them;
+    /// - a `List` of three `Bool`s for `mirror`, two `Number`s for `preview`
+    ///   and six for `region`, its minimum corner and then its maximum;
+    /// - and a `List` of `[tag, [r, g, b]]` `List`s for `tag_materials`.
+    ///
+    /// Anything else is `Unsupported`. The options aren't checked against
+    /// each other until `validate`.
+    pub fn set(&mut self, name: &str, value: OptionValue) -> Result<(), ExportError> {
+        let wrong =
+            |expected: &str| ExportError::Unsupported(format!("{} should be {}", name, expected));
+        let flag = || match value {
+            OptionValue::Bool(b) => Ok(b),
+            _ => Err(wrong("true or false")),
+        };
+        let number = |value: &OptionValue| match *value {
+            OptionValue::Number(n) => Ok(n),
+            _ => Err(wrong("a number")),
+        };
+        let optional = |value: &OptionValue| match value {
+            OptionValue::Null => Ok(None),
+            value => number(value).map(Some),
+        };
+        let whole = |n: f64| {
+            (n.fract() == 0.0 && (0.0..=usize::MAX as f64).contains(&n))
+                .then_some(n as usize)
+                .ok_or_else(|| wrong("a whole number"))
+        };
+        let string = |value: &OptionValue| match value {
+            OptionValue::String(s) => Ok(s.clone()),
+            _ => Err(wrong("a string")),
+        };
+        let path = || match &value {
+            OptionValue::Null => Ok(None),
+            value => string(value).map(|s| Some(PathBuf::from(s))),
+        };
+        let list = |value: &OptionValue, len: Option<usize>| match value {
+            OptionValue::List(items) if len.is_none_or(|len| items.len() == len) => {
+                Ok(items.clone())
+            }
+            _ => Err(wrong(&match len {
+                Some(len) => format!("a list of {}", len),
+                None => "a list".to_string(),
+            })),
+        };
+        let strings = |value: &OptionValue| {
+            list(value, None)?
+                .iter()
+                .map(string)
+                .collect::<Result<Vec<_>, _>>()
+        };
+        fn choice<T: Copy>(
+            name: &str,
+            value: &OptionValue,
+            choices: &[(&str, T)],
+        ) -> Result<T, ExportError> {
+            let names: Vec<&str> = choices.iter().map(|(choice, _)| *choice).collect();
+            match value {
+                OptionValue::String(s) => choices.iter().find(|(choice, _)| choice == s),
+                _ => None,
+            }
+            .map(|&(_, variant)| variant)
+            .ok_or_else(|| {
+                ExportError::Unsupported(format!("{} should be one of {}", name, names.join(", ")))
+            })
+        }
+
+        match name {
+            "export_constraints" => self.export_constraints = flag()?,
+            "export_selection_effects" => self.export_selection_effects = flag()?,
+            "export_surface_details" => self.export_surface_details = flag()?,
+            "respect_archivable" => self.respect_archivable = flag()?,
+            "inherit_color" => self.inherit_color = flag()?,
+            "services" => {
+                self.services = match value {
+                    OptionValue::Null => None,
+                    ref value => Some(strings(value)?),
+                }
+            }
+            "part_info_csv" => self.part_info_csv = path()?,
+            "exclude_tags" => self.exclude_tags = strings(&value)?,
+            "include_tags" => self.include_tags = strings(&value)?,
+            "region" => {
+                self.region = match value {
+                    OptionValue::Null => None,
+                    ref value => {
+                        let c = list(value, Some(6))?
+                            .iter()
+                            .map(|c| number(c).map(|c| c as f32))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Some(Aabb {
+                            min: Vector3::new(c[0], c[1], c[2]),
+                            max: Vector3::new(c[3], c[4], c[5]),
+                        })
+                    }
+                }
+            }
+            "mirror" => {
+                let axes = list(&value, Some(3))?;
+                for (axis, mirrored) in self.mirror.iter_mut().zip(&axes) {
+                    match mirrored {
+                        OptionValue::Bool(b) => *axis = *b,
+                        _ => return Err(wrong("a list of 3 true or false")),
+                    }
+                }
+            }
+            "mirror_and_append" => self.mirror_and_append = flag()?,
+            "right_handed" => self.right_handed = flag()?,
+            "cframe_scale" => self.cframe_scale = flag()?,
+            "solve_joints" => self.solve_joints = flag()?,
+            "pose" => {
+                self.pose = choice(
+                    name,
+                    &value,
+                    &[("bind", Pose::Bind), ("current", Pose::Current)],
+                )?
+            }
+            "include_cameras" => self.include_cameras = flag()?,
+            "include_hidden" => self.include_hidden = flag()?,
+            "no_lighting" => self.no_lighting = flag()?,
+            "no_normals" => self.no_normals = flag()?,
+            "normals" => {
+                self.normals = choice(
+                    name,
+                    &value,
+                    &[
+                        ("flat", Normals::Flat),
+                        ("smooth", Normals::Smooth),
+                        ("auto", Normals::Auto),
+                    ],
+                )?
+            }
+            "crease_angle" => self.crease_angle = number(&value)? as f32,
+            "uv_studs_per_tile" => self.uv_studs_per_tile = optional(&value)?.map(|n| n as f32),
+            "unknown_as_box" => self.unknown_as_box = flag()?,
+            "print_unknown_classes" => self.print_unknown_classes = flag()?,
+            "weld" => self.weld = optional(&value)?.map(|n| n as f32),
+            "weld_per_material" => self.weld_per_material = flag()?,
+            "group_anchored" => self.group_anchored = flag()?,
+            "annotate_faces" => self.annotate_faces = flag()?,
+            "object_names" => self.object_names = flag()?,
+            "name_separator" => {
+                let separator = string(&value)?;
+                let mut chars = separator.chars();
+                self.name_separator = match (chars.next(), chars.next()) {
+                    (Some(c), None) => c,
+                    _ => return Err(wrong("a single character")),
+                }
+            }
+            "remove_hidden_faces" => self.remove_hidden_faces = optional(&value)?.map(|n| n as f32),
+            "fix_tjunctions" => self.fix_tjunctions = optional(&value)?.map(|n| n as f32),
+            "omit_interior" => self.omit_interior = flag()?,
+            "merge_boxes" => self.merge_boxes = flag()?,
+            "voxelize" => self.voxelize = optional(&value)?.map(|n| n as f32),
+            "resample_size" => self.resample_size = optional(&value)?.map(|n| n as f32),
+            "bake_material_id_texture" => self.bake_material_id_texture = flag()?,
+            "blender_materials" => self.blender_materials = flag()?,
+            "mtl_transparency" => {
+                self.mtl_transparency = choice(
+                    name,
+                    &value,
+                    &[
+                        ("d", MtlTransparency::D),
+                        ("tr", MtlTransparency::Tr),
+                        ("both", MtlTransparency::Both),
+                    ],
+                )?
+            }
+            "mtl_dir" => self.mtl_dir = path()?,
+            "mtllib_style" => {
+                self.mtllib_style = choice(
+                    name,
+                    &value,
+                    &[
+                        ("filename", MtllibStyle::Filename),
+                        ("relative-to-obj", MtllibStyle::RelativeToObj),
+                        ("absolute", MtllibStyle::Absolute),
+                    ],
+                )?
+            }
+            "spatial_sort" => self.spatial_sort = flag()?,
+            "truss_style" => {
+                self.truss_style = choice(
+                    name,
+                    &value,
+                    &[
+                        ("box", TrussStyle::Box),
+                        ("triangle", TrussStyle::Triangle),
+                        ("lshape", TrussStyle::LShape),
+                    ],
+                )?
+            }
+            "tag_materials" => {
+                let invalid = || wrong("a list of [tag, [r, g, b]] lists");
+                self.tag_materials = list(&value, None)?
+                    .iter()
+                    .map(|pair| {
+                        let OptionValue::List(pair) = pair else {
+                            return Err(invalid());
+                        };
+                        let [OptionValue::String(tag), OptionValue::List(rgb)] = &pair[..] else {
+                            return Err(invalid());
+                        };
+                        let channels: Vec<u8> = rgb
+                            .iter()
+                            .map(|channel| match *channel {
+                                OptionValue::Number(n)
+                                    if n.fract() == 0.0 && (0.0..=255.0).contains(&n) =>
+                                {
+                                    Some(n as u8)
+                                }
+                                _ => None,
+                            })
+                            .collect::<Option<_>>()
+                            .ok_or_else(invalid)?;
+                        let [r, g, b] = channels[..] else {
+                            return Err(invalid());
+                        };
+                        Ok((tag.clone(), (r, g, b)))
+                    })
+                    .collect::<Result<_, _>>()?
+            }
+            "wireframe" => {
+                self.wireframe = choice(
+                    name,
+                    &value,
+                    &[
+                        ("off", Wireframe::Off),
+                        ("edges", Wireframe::Edges),
+                        ("edges-and-faces", Wireframe::EdgesAndFaces),
+                    ],
+                )?
+            }
+            "double_sided" => {
+                self.double_sided = choice(
+                    name,
+                    &value,
+                    &[
+                        ("off", DoubleSided::Off),
+                        ("all", DoubleSided::All),
+                        ("thin", DoubleSided::Thin),
+                    ],
+                )?
+            }
+            "double_sided_threshold" => self.double_sided_threshold = number(&value)? as f32,
+            "stream" => self.stream = flag()?,
+            "max_vertices_per_file" => {
+                self.max_vertices_per_file = optional(&value)?.map(whole).transpose()?
+            }
+            "list_textures" => self.list_textures = flag()?,
+            "dry_run" => self.dry_run = flag()?,
+            "list_materials" => self.list_materials = flag()?,
+            "json" => self.json = flag()?,
+            "preview" => {
+                self.preview = match value {
+                    OptionValue::Null => None,
+                    ref value => {
+                        let size = list(value, Some(2))?;
+                        Some((whole(number(&size[0])?)?, whole(number(&size[1])?)?))
+                    }
+                }
+            }
+            "preview_in_obj" => self.preview_in_obj = flag()?,
+            "lods" => self.lods = optional(&value)?.map(whole).transpose()?,
+            "max_triangles" => self.max_triangles = optional(&value)?.map(whole).transpose()?,
+            "format" => {
+                self.format = choice(
+                    name,
+                    &value,
+                    &[
+                        ("obj", OutputFormat::Obj),
+                        ("mitsuba", OutputFormat::Mitsuba),
+                        ("json", OutputFormat::Json),
+                        ("bobj", OutputFormat::Bobj),
+                    ],
+                )?
+            }
+            "json_binary" => self.json_binary = flag()?,
+            "max_depth" => self.max_depth = whole(number(&value)?)?,
+            "timings" => {
+                self.timings = match value {
+                    OptionValue::Null => None,
+                    ref value => Some(choice(
+                        name,
+                        value,
+                        &[("text", TimingsFormat::Text), ("json", TimingsFormat::Json)],
+                    )?),
+                }
+            }
+            "quiet" => self.quiet = flag()?,
+            "summary_json" => self.summary_json = path()?,
+            "warnings_jsonl" => self.warnings_jsonl = path()?,
+            "fail_on" => {
+                self.fail_on = strings(&value)?
+                    .iter()
+                    .map(|code| {
+                        WarningCode::from_name(code).ok_or_else(|| wrong("a list of warning codes"))
+                    })
+                    .collect::<Result<_, _>>()?
+            }
+            "no_partial_output" => self.no_partial_output = flag()?,
+            "best_effort" => self.best_effort = flag()?,
+            "postprocessors" | "callbacks" => {
+                return Err(ExportError::Unsupported(format!(
+                    "{} can't be set by name",
+                    name
+                )));
+            }
+            _ => return Err(ExportError::Unsupported(format!("unknown option {}", name))),
+        }
+        Ok(())
+    }
+}
+
+/// Builds `ExportOptions` a setting at a time, starting from the defaults,
+/// with `build` checking they go together. Each setter is named after the
+/// field it sets.
+#[derive(Default)]
+pub struct ExportOptionsBuilder {
+    opts: ExportOptions,
+}
+
+impl ExportOptionsBuilder {
+    /// Adds a post-processor after the ones already added.
+    pub fn postprocessor(mut self, postprocessor: impl MeshPostProcessor + 'static) -> Self {
+        self.opts.postprocessors.push(Box::new(postprocessor));
+        self
+    }
+
+    /// The options, if `ExportOptions::validate` is happy with them.
+    pub fn build(self) -> Result<ExportOptions, ExportError> {
+        self.opts.validate()?;
+        Ok(self.opts)
+    }
+}
+
+macro_rules! setters {
+    ($($field:ident: $ty:ty,)*) => {
+        impl ExportOptionsBuilder {
+            $(
+                pub fn $field(mut self, value: $ty) -> Self {
+                    self.opts.$field = value;
+                    self
+                }
+            )*
+        }
+    };
+}
+
+setters! {
+    export_constraints: bool,
+    export_selection_effects: bool,
+    export_surface_details: bool,
+    respect_archivable: bool,
+    inherit_color: bool,
+    services: Option<Vec<String>>,
+    part_info_csv: Option<PathBuf>,
+    exclude_tags: Vec<String>,
+    include_tags: Vec<String>,
+    region: Option<Aabb>,
+    mirror: [bool; 3],
+    mirror_and_append: bool,
+    right_handed: bool,
+    cframe_scale: bool,
+    solve_joints: bool,
+    pose: Pose,
+    include_cameras: bool,
+    include_hidden: bool,
+    no_lighting: bool,
+    no_normals: bool,
+    normals: Normals,
+    crease_angle: f32,
+    uv_studs_per_tile: Option<f32>,
+    unknown_as_box: bool,
+    print_unknown_classes: bool,
+    weld: Option<f32>,
+    weld_per_material: bool,
+    group_anchored: bool,
+    annotate_faces: bool,
+    object_names: bool,
+    name_separator: char,
+    remove_hidden_faces: Option<f32>,
+    fix_tjunctions: Option<f32>,
+    omit_interior: bool,
+    merge_boxes: bool,
+    voxelize: Option<f32>,
+    resample_size: Option<f32>,
+    bake_material_id_texture: bool,
+    blender_materials: bool,
+    mtl_transparency: MtlTransparency,
+    mtl_dir: Option<PathBuf>,
+    mtllib_style: MtllibStyle,
+    spatial_sort: bool,
+    truss_style: TrussStyle,
+    tag_materials: Vec<(String, (u8, u8, u8))>,
+    wireframe: Wireframe,
+    double_sided: DoubleSided,
+    double_sided_threshold: f32,
+    stream: bool,
+    max_vertices_per_file: Option<usize>,
+    list_textures: bool,
+    dry_run: bool,
+    list_materials: bool,
+    json: bool,
+    preview: Option<(usize, usize)>,
+    preview_in_obj: bool,
+    lods: Option<usize>,
+    max_triangles: Option<usize>,
+    format: OutputFormat,
+    json_binary: bool,
+    max_depth: usize,
+    timings: Option<TimingsFormat>,
+    quiet: bool,
+    summary_json: Option<PathBuf>,
+    warnings_jsonl: Option<PathBuf>,
+    fail_on: Vec<WarningCode>,
+    no_partial_output: bool,
+    best_effort: bool,
+    callbacks: ExportCallbacks,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TimingsFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum OutputFormat {
+    #[default]
+    Obj,
+    /// The OBJ plus a Mitsuba 3 scene file referencing it.
+    Mitsuba,
+    /// Every part's mesh, name and color in one JSON file, instead of the OBJ and MTL.
+    Json,
+    /// The whole mesh in one binary .bobj file (see BOBJ_FORMAT.md) and the MTL, instead of the OBJ.
+    Bobj,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Wireframe {
+    #[default]
+    Off,
+    Edges,
+    EdgesAndFaces,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum MtlTransparency {
+    /// `d`, the opacity, what most importers read
+    #[default]
+    D,
+    /// `Tr`, the transparency (1 - d), for older importers
+    Tr,
+    /// Both `d` and `Tr`, on every material, opaque ones included
+    Both,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum MtllibStyle {
+    /// Just the MTL's file name, for an MTL beside the OBJ
+    #[default]
+    Filename,
+    /// The path from the OBJ's directory to the MTL, with `..` where needed
+    RelativeToObj,
+    /// The MTL's absolute path
+    Absolute,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum DoubleSided {
+    /// Faces only face outwards
+    #[default]
+    Off,
+    /// Every part's faces get a flipped copy, so they show from both sides
+    All,
+    /// Only parts thinner than --double-sided-threshold on some axis, like panes and flags
+    Thin,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum Normals {
+    /// One normal per face, so everything shades faceted
+    Flat,
+    /// Smooth around balls and cylinder sides, following the part's shape exactly, and flat everywhere else
+    #[default]
+    Smooth,
+    /// Smooth across edges where faces meet at less than --crease-angle, and hard at the rest, going by the mesh alone
+    Auto,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum Pose {
+    /// As the joints' C0 and C1 have it, the T-pose for most rigs
+    #[default]
+    Bind,
+    /// With each Motor6D's Transform on top, as an animation left it when the place was saved
+    Current,
+}
+
+#[derive(Clone, Copy, Default)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum TrussStyle {
+    /// A plain block
+    #[default]
+    Box,
+    /// Three rails with zigzag braces, like Studio's
+    Triangle,
+    /// An L-shaped angle beam
+    #[cfg_attr(feature = "cli", value(name = "lshape"))]
+    LShape,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> OptionValue {
+        OptionValue::List(
+            items
+                .iter()
+                .map(|item| OptionValue::String(item.to_string()))
+                .collect(),
+        )
+    }
+
+    fn numbers(items: &[f64]) -> OptionValue {
+        OptionValue::List(items.iter().map(|&n| OptionValue::Number(n)).collect())
+    }
+
+    #[test]
+    fn every_field_but_the_closures_can_be_set_by_name() {
+        use OptionValue::{Bool, Null, Number, String as Text};
+        let text = |s: &str| Text(s.to_string());
+        let values = [
+            ("export_constraints", Bool(true)),
+            ("export_selection_effects", Bool(true)),
+            ("export_surface_details", Bool(true)),
+            ("respect_archivable", Bool(true)),
+            ("inherit_color", Bool(true)),
+            ("services", strings(&["Workspace", "Lighting"])),
+            ("part_info_csv", text("parts.csv")),
+            ("exclude_tags", strings(&["Hidden"])),
+            ("include_tags", strings(&["Map", "Props"])),
+            ("region", numbers(&[-1.0, -2.0, -3.0, 1.0, 2.0, 3.0])),
+            (
+                "mirror",
+                OptionValue::List(vec![Bool(false), Bool(true), Bool(false)]),
+            ),
+            ("mirror_and_append", Bool(true)),
+            ("right_handed", Bool(true)),
+            ("cframe_scale", Bool(true)),
+            ("solve_joints", Bool(true)),
+            ("pose", text("current")),
+            ("include_cameras", Bool(true)),
+            ("include_hidden", Bool(true)),
+            ("no_lighting", Bool(true)),
+            ("no_normals", Bool(true)),
+            ("normals", text("auto")),
+            ("crease_angle", Number(45.0)),
+            ("uv_studs_per_tile", Number(4.0)),
+            ("unknown_as_box", Bool(true)),
+            ("print_unknown_classes", Bool(true)),
+            ("weld", Number(0.01)),
+            ("weld_per_material", Bool(true)),
+            ("group_anchored", Bool(true)),
+            ("annotate_faces", Bool(true)),
+            ("object_names", Bool(true)),
+            ("name_separator", text(".")),
+            ("remove_hidden_faces", Number(0.0)),
+            ("fix_tjunctions", Number(0.001)),
+            ("omit_interior", Bool(true)),
+            ("merge_boxes", Bool(true)),
+            ("voxelize", Number(2.0)),
+            ("resample_size", Number(8.0)),
+            ("bake_material_id_texture", Bool(true)),
+            ("blender_materials", Bool(true)),
+            ("mtl_transparency", text("both")),
+            ("mtl_dir", text("materials")),
+            ("mtllib_style", text("relative-to-obj")),
+            ("spatial_sort", Bool(true)),
+            ("truss_style", text("lshape")),
+            (
+                "tag_materials",
+                OptionValue::List(vec![OptionValue::List(vec![
+                    text("Lava"),
+                    numbers(&[255.0, 64.0, 0.0]),
+                ])]),
+            ),
+            ("wireframe", text("edges-and-faces")),
+            ("double_sided", text("thin")),
+            ("double_sided_threshold", Number(0.5)),
+            ("stream", Bool(true)),
+            ("max_vertices_per_file", Number(1000.0)),
+            ("list_textures", Bool(true)),
+            ("dry_run", Bool(true)),
+            ("list_materials", Bool(true)),
+            ("json", Bool(true)),
+            ("preview", numbers(&[80.0, 40.0])),
+            ("preview_in_obj", Bool(true)),
+            ("lods", Number(3.0)),
+            ("max_triangles", Number(5000.0)),
+            ("format", text("mitsuba")),
+            ("json_binary", Bool(true)),
+            ("max_depth", Number(8.0)),
+            ("timings", text("json")),
+            ("quiet", Bool(true)),
+            ("summary_json", text("summary.jsonl")),
+            ("warnings_jsonl", text("-")),
+            ("fail_on", strings(&["missing_size", "broken_tree"])),
+            ("no_partial_output", Bool(true)),
+            ("best_effort", Bool(true)),
+        ];
+        let mut opts = ExportOptions::default();
+        for (name, value) in values {
+            opts.set(name, value)
+                .unwrap_or_else(|e| panic!("{}: {}", name, e));
+        }
+        assert_eq!(
+            opts.services,
+            Some(vec!["Workspace".to_string(), "Lighting".to_string()])
+        );
+        assert_eq!(opts.part_info_csv, Some(PathBuf::from("parts.csv")));
+        assert_eq!(opts.region.unwrap().max, Vector3::new(1.0, 2.0, 3.0));
+        assert_eq!(opts.mirror, [false, true, false]);
+        assert!(opts.pose == Pose::Current && opts.normals == Normals::Auto);
+        assert_eq!(opts.name_separator, '.');
+        assert_eq!(opts.remove_hidden_faces, Some(0.0));
+        assert!(opts.mtllib_style == MtllibStyle::RelativeToObj);
+        assert!(matches!(opts.truss_style, TrussStyle::LShape));
+        assert_eq!(opts.tag_materials, [("Lava".to_string(), (255, 64, 0))]);
+        assert!(opts.wireframe == Wireframe::EdgesAndFaces);
+        assert_eq!(opts.preview, Some((80, 40)));
+        assert_eq!((opts.lods, opts.max_depth), (Some(3), 8));
+        assert!(opts.timings == Some(TimingsFormat::Json));
+        assert_eq!(
+            opts.fail_on,
+            [WarningCode::MissingSize, WarningCode::BrokenTree]
+        );
+
+        // Back to nothing.
+        for name in [
+            "services", "region", "weld", "preview", "timings", "mtl_dir",
+        ] {
+            opts.set(name, Null).unwrap();
+        }
+        assert!(opts.services.is_none() && opts.region.is_none() && opts.weld.is_none());
+        assert!(opts.preview.is_none() && opts.timings.is_none() && opts.mtl_dir.is_none());
+    }
+
+    #[test]
+    fn set_turns_away_values_of_the_wrong_kind() {
+        let mut opts = ExportOptions::default();
+        let rejected = [
+            ("merge_boxes", OptionValue::Number(1.0)),
+            ("weld", OptionValue::Bool(true)),
+            ("crease_angle", OptionValue::Null),
+            ("max_depth", OptionValue::Number(2.5)),
+            ("lods", OptionValue::Number(-1.0)),
+            ("normals", OptionValue::String("shiny".to_string())),
+            ("mirror", OptionValue::List(vec![OptionValue::Bool(true)])),
+            ("region", numbers(&[0.0, 0.0, 0.0])),
+            ("name_separator", OptionValue::String("::".to_string())),
+            ("fail_on", strings(&["no_such_code"])),
+            ("tag_materials", strings(&["Lava=#ff0000"])),
+            ("postprocessors", OptionValue::Null),
+            ("no_such_option", OptionValue::Bool(true)),
+        ];
+        for (name, value) in rejected {
+            let error = opts.set(name, value.clone()).unwrap_err();
+            assert!(
+                matches!(error, ExportError::Unsupported(_)),
+                "{} = {:?}",
+                name,
+                value
+            );
+        }
+        assert_eq!(
+            opts.set("normals", OptionValue::String("shiny".to_string()))
+                .unwrap_err()
+                .to_string(),
+            "normals should be one of flat, smooth, auto"
+        );
+    }
+
+    fn rejection(builder: ExportOptionsBuilder) -> String {
+        match builder.build() {
+            Err(ExportError::Unsupported(message)) => message,
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("built options that should have been rejected"),
+        }
+    }
+
+    #[test]
+    fn validate_rejects_distances_that_arent_finite_or_are_negative() {
+        assert_eq!(
+            rejection(ExportOptions::builder().weld(Some(-0.5))),
+            "weld should be a finite distance of 0 or more, not -0.5"
+        );
+        assert_eq!(
+            rejection(ExportOptions::builder().remove_hidden_faces(Some(f32::NAN))),
+            "remove_hidden_faces should be a finite distance of 0 or more, not NaN"
+        );
+        assert_eq!(
+            rejection(ExportOptions::builder().voxelize(Some(0.0))),
+            "voxelize should be a finite size above 0, not 0"
+        );
+    }
+
+    #[test]
+    fn validate_rejects_options_that_dont_go_together() {
+        assert_eq!(
+            rejection(ExportOptions::builder().stream(true).weld(Some(0.01))),
+            "stream can't be combined with weld"
+        );
+        assert_eq!(
+            rejection(ExportOptions::builder().fix_tjunctions(Some(0.01))),
+            "fix_tjunctions only works with weld"
+        );
+    }
+
+    #[test]
+    fn validate_accepts_options_that_go_together() {
+        let opts = ExportOptions::builder()
+            .weld(Some(0.0))
+            .fix_tjunctions(Some(0.01))
+            .lods(Some(3))
+            .remove_hidden_faces(Some(0.001))
+            .build()
+            .unwrap();
+        assert_eq!(opts.weld, Some(0.0));
+        assert_eq!(opts.lods, Some(3));
+        assert!(ExportOptions::default().validate().is_ok());
+    }
+}
@@ -0,0 +1,49 @@
+//! `--bake-material-id-texture`: every material's color as one pixel of a
+//! small square texture, so the whole OBJ can use a single material and
+//! pick colors by texture coordinates instead.
+
+use std::{error::Error, fs, path::Path};
+
+use crate::ExportError;
+
+/// The texture, one pixel per material in the order they were added, filled
+/// row by row from the top left.
+pub struct Palette {
+    colors: Vec<(u8, u8, u8, u8)>,
+    /// Pixels across and down: the smallest square holding every color.
+    width: usize,
+}
+
+impl Palette {
+    pub fn new(colors: Vec<(u8, u8, u8, u8)>) -> Self {
+        let width = (colors.len() as f64).sqrt().ceil().max(1.0) as usize;
+        Palette { colors, width }
+    }
+
+    /// The center of the `index`th color's pixel. OBJ's V counts up from
+    /// the bottom of the image, while rows count down from the top.
+    pub fn texcoord(&self, index: usize) -> (f32, f32) {
+        let width = self.width as f32;
+        let (x, y) = (index % self.width, index / self.width);
+        ((x as f32 + 0.5) / width, 1.0 - (y as f32 + 0.5) / width)
+    }
+
+    /// Writes the texture as an RGBA PNG. Pixels past the last color are
+    /// left transparent black.
+    pub fn write_png(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut pixels = vec![0; self.width * self.width * 4];
+        for (pixel, &(r, g, b, a)) in pixels.chunks_exact_mut(4).zip(&self.colors) {
+            pixel.copy_from_slice(&[r, g, b, a]);
+        }
+
+        let mut encoded = Vec::new();
+        let mut encoder = png::Encoder::new(&mut encoded, self.width as u32, self.width as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&pixels)?;
+        writer.finish()?;
+        fs::write(path, &encoded).map_err(|e| ExportError::io(path, e))?;
+        Ok(())
+    }
+}
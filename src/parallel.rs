@@ -0,0 +1,23 @@
+//! rayon's parallel iterators, except on `wasm32-unknown-unknown`, which
+//! can't spawn threads. There `par_iter` and `par_iter_mut` hand back the
+//! plain slice iterators, so the same code runs on the calling thread.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use rayon::prelude::*;
+
+#[cfg(target_arch = "wasm32")]
+pub trait ParallelSlice<T> {
+    fn par_iter(&self) -> std::slice::Iter<'_, T>;
+    fn par_iter_mut(&mut self) -> std::slice::IterMut<'_, T>;
+}
+
+#[cfg(target_arch = "wasm32")]
+impl<T> ParallelSlice<T> for [T] {
+    fn par_iter(&self) -> std::slice::Iter<'_, T> {
+        self.iter()
+    }
+
+    fn par_iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.iter_mut()
+    }
+}
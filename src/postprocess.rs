@@ -0,0 +1,40 @@
+//! Hooks for running your own processing on each part's mesh before it's
+//! written, plus a couple of built-in ones behind cargo features.
+
+use crate::mesh::Mesh;
+
+/// Something that rewrites part meshes, set in
+/// [`ExportOptions::postprocessors`](crate::ExportOptions::postprocessors).
+/// It gets each part's mesh in the part's own space, before its CFrame is
+/// applied, along with the part's name and class. Parts are meshed on
+/// several threads at once, hence `Send + Sync`.
+pub trait MeshPostProcessor: Send + Sync {
+    fn process(&self, name: &str, class: &str, mesh: Mesh) -> Mesh;
+}
+
+/// Replaces each mesh with its convex hull. Flat meshes, which have no hull
+/// with any volume, are left alone.
+#[cfg(feature = "convex-hull")]
+pub struct ConvexHullProcessor;
+
+#[cfg(feature = "convex-hull")]
+impl MeshPostProcessor for ConvexHullProcessor {
+    fn process(&self, _name: &str, _class: &str, mesh: Mesh) -> Mesh {
+        crate::hull::convex_hull(&mesh.0).unwrap_or(mesh)
+    }
+}
+
+/// Decimates each mesh down to at most `target_faces` triangles with
+/// quadric error metrics. Meshes already at or under it are left alone, so
+/// on most places this only touches balls and cylinders.
+#[cfg(feature = "simplify")]
+pub struct SimplifyProcessor {
+    pub target_faces: usize,
+}
+
+#[cfg(feature = "simplify")]
+impl MeshPostProcessor for SimplifyProcessor {
+    fn process(&self, _name: &str, _class: &str, mesh: Mesh) -> Mesh {
+        crate::simplify::simplify(mesh, self.target_faces)
+    }
+}
@@ -0,0 +1,60 @@
+//! A top-down ASCII sketch of the scene, for checking it's roughly where it
+//! should be before opening the export in anything.
+
+use rbx_types::Vector3;
+
+use crate::{bvh::Aabb, scene::PartRecord};
+
+const FILLED: char = '█';
+const EMPTY: char = ' ';
+const EDGE: char = '·';
+
+/// Draws each part's bounding box from above, X across and Z down, fitted
+/// into at most `width` by `height` characters. Characters are about twice
+/// as tall as they're wide, so a cell covers twice as much Z as X. Empty
+/// cells along the border show where the scene's bounds are.
+pub fn render(parts: &[PartRecord], (width, height): (usize, usize)) -> Vec<String> {
+    let boxes: Vec<Aabb> = parts.iter().map(PartRecord::bounds).collect();
+    let Some(bounds) = boxes.iter().copied().reduce(|a, b| Aabb {
+        min: Vector3::new(a.min.x.min(b.min.x), 0.0, a.min.z.min(b.min.z)),
+        max: Vector3::new(a.max.x.max(b.max.x), 0.0, a.max.z.max(b.max.z)),
+    }) else {
+        return Vec::new();
+    };
+
+    let extent_x = bounds.max.x - bounds.min.x;
+    let extent_z = bounds.max.z - bounds.min.z;
+    let cell = (extent_x / width as f32)
+        .max(extent_z / (2.0 * height as f32))
+        .max(f32::EPSILON);
+    let columns = ((extent_x / cell).ceil() as usize).clamp(1, width);
+    let rows = ((extent_z / (2.0 * cell)).ceil() as usize).clamp(1, height);
+
+    let column = |x: f32| (((x - bounds.min.x) / cell) as usize).min(columns - 1);
+    let row = |z: f32| (((z - bounds.min.z) / (2.0 * cell)) as usize).min(rows - 1);
+    let mut grid = vec![vec![false; columns]; rows];
+    for b in &boxes {
+        for cells in &mut grid[row(b.min.z)..=row(b.max.z)] {
+            cells[column(b.min.x)..=column(b.max.x)].fill(true);
+        }
+    }
+
+    grid.iter()
+        .enumerate()
+        .map(|(r, cells)| {
+            cells
+                .iter()
+                .enumerate()
+                .map(|(c, &filled)| {
+                    if filled {
+                        FILLED
+                    } else if r == 0 || r == rows - 1 || c == 0 || c == columns - 1 {
+                        EDGE
+                    } else {
+                        EMPTY
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
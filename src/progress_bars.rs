@@ -0,0 +1,57 @@
+//! Progress display on stderr, so long conversions don't look hung, drawn
+//! from the export's progress callback.
+
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use rbx_obj::{ProgressInfo, ProgressPhase};
+
+/// Parts between updates of the bar's message, which costs a path lookup.
+const MESSAGE_INTERVAL: usize = 1024;
+
+/// A progress callback drawing a spinner while parsing and a bar counting
+/// parts while writing.
+pub fn progress_bars() -> impl FnMut(&ProgressInfo) + Send + 'static {
+    let mut bar: Option<ProgressBar> = None;
+    move |info| {
+        if info.finished {
+            if let Some(bar) = bar.take() {
+                bar.finish_and_clear();
+            }
+            return;
+        }
+        let Some(bar) = &bar else {
+            bar = Some(start(info));
+            return;
+        };
+        if info.done % MESSAGE_INTERVAL == 1
+            && let Some(path) = info.part_path()
+        {
+            bar.set_message(path);
+        }
+        bar.set_position(info.done as u64);
+    }
+}
+
+fn start(info: &ProgressInfo) -> ProgressBar {
+    match (info.phase, info.total) {
+        (ProgressPhase::Writing, Some(total)) => {
+            let bar = ProgressBar::new(total as u64).with_prefix("writing");
+            bar.set_style(
+                ProgressStyle::with_template("{prefix} [{bar:30}] {pos}/{len} {wide_msg}")
+                    .unwrap()
+                    .progress_chars("=> "),
+            );
+            bar
+        }
+        _ => {
+            let bar = ProgressBar::new_spinner();
+            bar.set_message(match info.phase {
+                ProgressPhase::Parsing => format!("parsing {}", info.input.display()),
+                ProgressPhase::Writing => "writing".to_string(),
+            });
+            bar.enable_steady_tick(Duration::from_millis(100));
+            bar
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! Prints what a conversion hands back: listings on stdout, the preview,
+//! timings and summary on stderr.
+
+use std::{collections::BTreeMap, fmt::Display, fs, path::Path, time::Duration};
+
+use rbx_obj::{
+    ExportOptions, ExportReport, TimingsFormat,
+    assets::{AssetError, CachedAsset},
+    json_escape,
+};
+
+/// Prints everything in `report` that `opts` asked to see.
+pub fn print(input: &Path, opts: &ExportOptions, report: &ExportReport) {
+    if opts.list_textures {
+        for id in &report.asset_ids {
+            println!("{}", id);
+        }
+        return;
+    }
+    if opts.list_materials {
+        print_materials(input, opts.json, report);
+        return;
+    }
+    for line in &report.preview {
+        eprintln!("{}", line);
+    }
+    if opts.max_vertices_per_file.is_some() {
+        let objs = report
+            .outputs
+            .iter()
+            .filter(|path| path.extension().is_some_and(|e| e == "obj"));
+        for path in objs {
+            println!("{}", path.display());
+        }
+    }
+    if let Some(format) = opts.timings {
+        print_timings(format, report);
+    }
+    if !opts.quiet {
+        print_summary(input, report);
+    }
+}
+
+/// `--asset-cache`: each asset's id and where it is in the cache, or why
+/// it couldn't be had.
+pub fn print_assets(assets: &[Result<CachedAsset, AssetError>]) {
+    let mut downloaded = 0;
+    for asset in assets {
+        match asset {
+            Ok(asset) => {
+                println!("{}\t{}", asset.key.id, asset.path.display());
+                downloaded += usize::from(asset.downloaded);
+            }
+            Err(error) => log::error!("{}", error),
+        }
+    }
+    let cached = assets.iter().filter(|asset| asset.is_ok()).count();
+    log::info!(
+        "{} assets in the cache, {} of them downloaded just now",
+        cached,
+        downloaded
+    );
+}
+
+/// What was written and what was left out, with each output's size.
+fn print_summary(input: &Path, report: &ExportReport) {
+    if report.dry_run {
+        eprintln!("{} (dry run):", input.display());
+    } else {
+        eprintln!("{}:", input.display());
+    }
+    eprintln!(
+        "  {} {} parts ({})",
+        if report.dry_run {
+            "would export"
+        } else {
+            "exported"
+        },
+        report.parts(),
+        counts_text(&report.parts_by_class)
+    );
+    if !report.skipped.is_empty() {
+        eprintln!("  skipped parts: {}", counts_text(&report.skipped));
+    }
+    for (kind, count) in &report.not_exported {
+        eprintln!("  not exported: {} {}", count, kind.describe());
+    }
+    if !report.errors.is_empty() {
+        eprintln!("  skipped after errors: {}", report.errors.len());
+        for error in &report.errors {
+            eprintln!("    {}", error);
+        }
+    }
+    eprintln!(
+        "  {}{} vertices, {} faces, {} materials",
+        if report.dry_run { "~" } else { "" },
+        report.vertices,
+        report.faces,
+        report.materials
+    );
+    for path in &report.outputs {
+        if report.dry_run {
+            eprintln!("  would write {}", path.display());
+        } else {
+            let bytes = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+            eprintln!("  wrote {} ({} bytes)", path.display(), bytes);
+        }
+    }
+    eprintln!("  took {:.2}s", report.elapsed.as_secs_f64());
+}
+
+fn print_materials(input: &Path, json: bool, report: &ExportReport) {
+    if json {
+        let entries: Vec<String> = report
+            .material_usage
+            .iter()
+            .map(|usage| {
+                let (r, g, b, a) = usage.rgba;
+                format!(
+                    "{{\"name\":\"{}\",\"rgba\":[{},{},{},{}],\"parts\":{},\"faces\":{}}}",
+                    json_escape(&usage.name),
+                    r,
+                    g,
+                    b,
+                    a,
+                    usage.parts,
+                    usage.faces
+                )
+            })
+            .collect();
+        println!(
+            "{{\"input\":\"{}\",\"materials\":[{}]}}",
+            json_escape(&input.display().to_string()),
+            entries.join(",")
+        );
+    } else {
+        println!("{}", input.display());
+        println!(
+            "  {:<24} {:<16} {:>8} {:>10}",
+            "name", "rgba", "parts", "faces"
+        );
+        for usage in &report.material_usage {
+            let (r, g, b, a) = usage.rgba;
+            let rgba = format!("{},{},{},{}", r, g, b, a);
+            println!(
+                "  {:<24} {:<16} {:>8} {:>10}",
+                usage.name, rgba, usage.parts, usage.faces
+            );
+        }
+    }
+}
+
+/// Prints the phases and counts to stderr.
+fn print_timings(format: TimingsFormat, report: &ExportReport) {
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let counts = [
+        ("instances_visited", report.instances_visited),
+        ("parts_exported", report.parts()),
+        ("vertices", report.vertices),
+        ("faces", report.faces),
+        ("materials", report.materials),
+    ];
+    match format {
+        TimingsFormat::Text => {
+            eprintln!("timings:");
+            for phase in &report.phases {
+                match phase.cpu {
+                    Some(cpu) => eprintln!(
+                        "  {:<42} {:>10.1} ms  (cpu {:.1} ms)",
+                        phase.name,
+                        ms(phase.wall),
+                        ms(cpu)
+                    ),
+                    None => eprintln!("  {:<42} {:>10.1} ms", phase.name, ms(phase.wall)),
+                }
+            }
+            let counts: Vec<String> = counts
+                .iter()
+                .map(|(name, count)| format!("{} {}", count, name.replace('_', " ")))
+                .collect();
+            eprintln!("counts: {}", counts.join(", "));
+        }
+        TimingsFormat::Json => {
+            let phases: Vec<String> = report
+                .phases
+                .iter()
+                .map(|phase| match phase.cpu {
+                    Some(cpu) => format!(
+                        "{{\"name\":\"{}\",\"wall_ms\":{},\"cpu_ms\":{}}}",
+                        json_escape(phase.name),
+                        ms(phase.wall),
+                        ms(cpu)
+                    ),
+                    None => format!(
+                        "{{\"name\":\"{}\",\"wall_ms\":{}}}",
+                        json_escape(phase.name),
+                        ms(phase.wall)
+                    ),
+                })
+                .collect();
+            let counts: Vec<String> = counts
+                .iter()
+                .map(|(name, count)| format!("\"{}\":{}", name, count))
+                .collect();
+            eprintln!(
+                "{{\"phases\":[{}],\"counts\":{{{}}}}}",
+                phases.join(","),
+                counts.join(",")
+            );
+        }
+    }
+}
+
+fn counts_text<K: Display>(counts: &BTreeMap<K, usize>) -> String {
+    counts
+        .iter()
+        .map(|(key, count)| format!("{} {}", count, key))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,1194 @@
+//! Walking the DOM to pick out what gets exported, and reading the
+//! properties that decide how.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::File,
+    io::BufReader,
+    path::Path,
+};
+
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use rbx_dom_weak::{Instance, Ustr, WeakDom};
+use rbx_types::{CFrame, Color3, Matrix3, Ref, UniqueId, Variant, Vector3};
+
+use crate::{
+    ExportError, InstanceError,
+    bvh::Aabb,
+    callbacks::{ExportCallbacks, WarningEvent},
+    cancel::CancellationToken,
+    joints::solve_joints,
+    mesh::{FaceId, apply_cframe, apply_matrix3},
+    options::ExportOptions,
+    transform::{
+        matrix3_column_magnitudes, matrix3_from_orientation, matrix3_from_rotation,
+        matrix3_normalize_columns,
+    },
+    warning::{Warning, WarningCode},
+};
+
+/// Everything picked out of the DOM for export, in traversal order.
+#[derive(Default)]
+pub struct Scene {
+    pub constraints: Vec<Ref>,
+    /// `SelectionBox`es and `SelectionSphere`s, with `export_selection_effects`.
+    pub selection_effects: Vec<Ref>,
+    /// Instances skipped along with their descendants, counted by class.
+    pub pruned: BTreeMap<&'static str, usize>,
+    /// Instances whose children were cut off by `--max-depth`.
+    pub depth_limited: usize,
+    /// The parent of every child referent that was skipped because it isn't
+    /// in the DOM or its `Parent` is some other instance.
+    pub broken_children: Vec<Ref>,
+    pub visited: usize,
+    /// Parts left out, counted by why.
+    pub skipped: BTreeMap<&'static str, usize>,
+    /// Classes that look like BaseParts but aren't meshed, counted by name.
+    pub unknown_classes: BTreeMap<String, usize>,
+    /// Content that won't come out right, counted with a few examples each.
+    pub unsupported: BTreeMap<Unsupported, (usize, Vec<Ref>)>,
+    /// Keep every warning in `warnings`, not just log it.
+    pub keep_warnings: bool,
+    pub warnings: Vec<Warning>,
+    /// Warnings to report as errors, see `--fail-on`.
+    pub fail_on: Vec<WarningCode>,
+    /// How many warnings came up that `fail_on` turned into errors.
+    pub failures: usize,
+    /// Instances skipped over an error, with `best_effort`.
+    pub errors: Vec<InstanceError>,
+    /// Told about every warning.
+    pub callbacks: ExportCallbacks,
+    /// Where `solve_joints` puts the parts joints reach, used instead of
+    /// their own `CFrame`s.
+    pub joint_cframes: HashMap<Ref, CFrame>,
+}
+
+impl Scene {
+    /// Passes the warning to the `on_warning` callback, counts it if it's in
+    /// `fail_on`, and keeps it if asked to. `make` only runs when something
+    /// will use it, since building a path isn't free.
+    pub fn warn(&mut self, make: impl FnOnce() -> Warning) {
+        if !self.keep_warnings && self.fail_on.is_empty() && !self.callbacks.wants_warnings() {
+            return;
+        }
+        let warning = make();
+        let fails = self.fail_on.contains(&warning.code);
+        if fails {
+            self.failures += 1;
+        }
+        self.callbacks.warning(&WarningEvent {
+            warning: &warning,
+            fails,
+        });
+        if self.keep_warnings {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Counts a part left out for `reason` and warns about it.
+    fn skip(&mut self, reason: &'static str, make: impl FnOnce() -> Warning) {
+        *self.skipped.entry(reason).or_default() += 1;
+        self.warn(make);
+    }
+}
+
+/// Kinds of content the export drops or gets wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+pub enum Unsupported {
+    /// MeshParts
+    #[cfg_attr(feature = "cli", value(name = "meshparts"))]
+    MeshParts,
+    /// Unions, negates and intersections
+    Unions,
+    /// Terrain with voxels in it
+    Terrain,
+    /// Part shapes other than ball, block and cylinder
+    Shapes,
+    /// Parts with a NaN or infinite CFrame or Size
+    NanTransforms,
+    /// SpecialMeshes and BlockMeshes, of which only the Offset is used
+    SpecialMeshes,
+    /// Decals, textures and SurfaceAppearances
+    Decals,
+}
+
+impl Unsupported {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Unsupported::MeshParts => "MeshParts not exported",
+            Unsupported::Unions => "unions, negates and intersections not exported",
+            Unsupported::Terrain => "terrain not exported",
+            Unsupported::Shapes => "Part shapes exported as blocks",
+            Unsupported::NanTransforms => "parts with a NaN or infinite CFrame or Size",
+            Unsupported::SpecialMeshes => {
+                "SpecialMesh and BlockMesh shapes and scales ignored (Offset is applied)"
+            }
+            Unsupported::Decals => "decals, textures and SurfaceAppearances ignored",
+        }
+    }
+}
+
+/// Examples kept per kind of unsupported content.
+const UNSUPPORTED_EXAMPLES: usize = 3;
+
+/// Works out whether an instance is something the export can't reproduce.
+fn unsupported_content(inst: &Instance) -> Option<Unsupported> {
+    match inst.class.as_str() {
+        "MeshPart" => Some(Unsupported::MeshParts),
+        "UnionOperation" | "NegateOperation" | "IntersectOperation" => Some(Unsupported::Unions),
+        // Every place has a Terrain; only the ones with voxels in them matter.
+        "Terrain" => match inst.properties.get(&Ustr::from("SmoothGrid")) {
+            Some(Variant::BinaryString(grid)) if !AsRef::<[u8]>::as_ref(grid).is_empty() => {
+                Some(Unsupported::Terrain)
+            }
+            _ => None,
+        },
+        "SpecialMesh" | "BlockMesh" => Some(Unsupported::SpecialMeshes),
+        "Decal" | "Texture" | "SurfaceAppearance" => Some(Unsupported::Decals),
+        class if is_part_class(class) => {
+            let cframe = get_cframe(inst);
+            let size = match inst.properties.get(&Ustr::from("Size")) {
+                Some(Variant::Vector3(v)) => *v,
+                _ => Vector3::new(1.0, 1.0, 1.0),
+            };
+            let shape = match inst.properties.get(&Ustr::from("Shape")) {
+                Some(Variant::Enum(e)) => e.to_u32(),
+                _ => 1,
+            };
+            if !is_finite_transform(&cframe, size) {
+                Some(Unsupported::NanTransforms)
+            } else if class == "Part" && shape > 2 {
+                Some(Unsupported::Shapes)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn is_finite_transform(cframe: &CFrame, size: Vector3) -> bool {
+    let m = &cframe.orientation;
+    [cframe.position, m.x, m.y, m.z, size]
+        .iter()
+        .all(|v| v.x.is_finite() && v.y.is_finite() && v.z.is_finite())
+}
+
+/// The `Offset` of a SpecialMesh or BlockMesh under a part, which moves
+/// where the part is drawn without moving the part.
+fn mesh_offset(dom: &WeakDom, inst: &Instance) -> Option<Vector3> {
+    inst.children()
+        .iter()
+        .filter_map(|&child| dom.get_by_ref(child))
+        .filter(|child| matches!(child.class.as_str(), "SpecialMesh" | "BlockMesh"))
+        .find_map(|child| match child.properties.get(&Ustr::from("Offset")) {
+            Some(Variant::Vector3(offset)) => Some(*offset),
+            _ => None,
+        })
+}
+
+/// The instance's names from its top-level ancestor down, joined by dots.
+pub fn instance_path(dom: &WeakDom, inst_ref: Ref) -> String {
+    let mut names: Vec<&str> = ancestors(dom, inst_ref)
+        .take_while(|inst| inst.referent() != dom.root_ref())
+        .map(|inst| inst.name.as_str())
+        .collect();
+    names.reverse();
+    names.join(".")
+}
+
+/// `instance_path` for an OBJ `o` line: the names joined by `separator`,
+/// each through `object_name_component`.
+pub fn object_path(dom: &WeakDom, inst_ref: Ref, separator: char) -> String {
+    let mut names: Vec<String> = ancestors(dom, inst_ref)
+        .take_while(|inst| inst.referent() != dom.root_ref())
+        .map(|inst| object_name_component(&inst.name, separator))
+        .collect();
+    names.reverse();
+    names.join(separator.encode_utf8(&mut [0; 4]))
+}
+
+/// A name with slashes, `separator`, whitespace and control characters
+/// turned into underscores, so it reads as one word and one component.
+pub fn object_name_component(name: &str, separator: char) -> String {
+    if name.is_empty() {
+        return "_".to_string();
+    }
+    name.chars()
+        .map(|c| {
+            if c == '/' || c == separator || c.is_whitespace() || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+pub struct PartRecord {
+    /// The instance the part was read from.
+    pub referent: Ref,
+    pub name: String,
+    /// With `object_names`, the `object_path` its `o` line names.
+    pub object_name: Option<String>,
+    pub class: String,
+    pub shape: u32,
+    /// `SurfaceType` of each face in `FaceId::ALL` order, all 0 (smooth)
+    /// unless `export_surface_details` is on.
+    pub surfaces: [u32; 6],
+    pub size: Vector3,
+    pub cframe: CFrame,
+    pub color: (u8, u8, u8, u8),
+    /// `Enum.Material` value, see `roblox_material_name`.
+    pub material: u32,
+    pub anchored: bool,
+    /// The instance's `UniqueId`, which unlike its referent stays the same
+    /// from one save of the place to the next. Files from before Roblox had
+    /// them don't have one.
+    pub unique_id: Option<UniqueId>,
+}
+
+impl PartRecord {
+    /// The world-space box around the part's `Size` box.
+    pub fn bounds(&self) -> Aabb {
+        let half = Vector3::new(self.size.x / 2.0, self.size.y / 2.0, self.size.z / 2.0);
+        let corners: Vec<Vector3> = (0..8)
+            .map(|corner| {
+                let sign = |bit: usize| if corner & bit == 0 { -1.0 } else { 1.0 };
+                let local = Vector3::new(sign(1) * half.x, sign(2) * half.y, sign(4) * half.z);
+                apply_cframe(local, &self.cframe)
+            })
+            .collect();
+        Aabb::from_points(&corners)
+    }
+}
+
+#[cfg(test)]
+impl PartRecord {
+    /// A plain grey block of `size` at `position`, for tests to start from.
+    pub(crate) fn block(size: Vector3, position: Vector3) -> Self {
+        PartRecord {
+            referent: Ref::none(),
+            name: "Part".to_string(),
+            object_name: None,
+            class: "Part".to_string(),
+            shape: 1,
+            surfaces: [0; 6],
+            size,
+            cframe: CFrame::new(position, Matrix3::identity()),
+            color: (163, 162, 165, 255),
+            material: MATERIAL_PLASTIC,
+            anchored: true,
+            unique_id: None,
+        }
+    }
+}
+
+/// Reads a binary place or model file.
+pub fn read_dom(path: &Path) -> Result<WeakDom, ExportError> {
+    let file = BufReader::new(File::open(path).map_err(|e| ExportError::io(path, e))?);
+    rbx_binary::from_reader(file).map_err(|e| ExportError::Parse {
+        path: path.to_path_buf(),
+        source: e.into(),
+    })
+}
+
+/// Picks the top-level instances to traverse, along with the names of
+/// services left out by `--services` that had parts in them.
+pub fn select_top_level(dom: &WeakDom, opts: &ExportOptions) -> (Vec<Ref>, Vec<String>) {
+    // Model files have no services at the top level, so only filter places.
+    let is_place = dom
+        .root()
+        .children()
+        .iter()
+        .any(|&r| dom.get_by_ref(r).is_some_and(|i| i.class == "Workspace"));
+    let mut skipped_services = Vec::new();
+    let mut top_level = Vec::new();
+
+    for &child_ref in dom.root().children() {
+        if is_place && let Some(services) = &opts.services {
+            let Some(service) = dom.get_by_ref(child_ref) else {
+                continue;
+            };
+            if !services.iter().any(|s| s == service.class.as_str()) {
+                if descendants(dom, child_ref).any(|d| is_part_class(d.class.as_str())) {
+                    skipped_services.push(service.class.to_string());
+                }
+                continue;
+            }
+        }
+        top_level.push(child_ref);
+    }
+
+    (top_level, skipped_services)
+}
+
+/// Counts the known part classes under `roots`, as an upper bound on how
+/// many parts a traversal will find.
+pub fn count_parts(dom: &WeakDom, roots: &[Ref]) -> usize {
+    roots
+        .iter()
+        .flat_map(|&root| descendants(dom, root))
+        .filter(|inst| is_part_class(inst.class.as_str()))
+        .count()
+}
+
+/// Walks the subtrees under `roots` depth-first, handing every exported part
+/// to `emit` and noting constraints and pruned instances in `scene`. Stops
+/// early if `cancel` gets triggered along the way. Children that aren't in
+/// the DOM, or whose `Parent` isn't the instance listing them because a
+/// broken file put an instance under two parents or under itself, are
+/// skipped with a warning.
+pub fn collect_scene(
+    dom: &WeakDom,
+    roots: &[Ref],
+    opts: &ExportOptions,
+    scene: &mut Scene,
+    cancel: Option<&CancellationToken>,
+    emit: &mut dyn FnMut(PartRecord),
+) -> Result<(), ExportError> {
+    // An explicit stack rather than recursion, so absurdly deep trees can't
+    // overflow it. Each entry is an instance, the children it has left to
+    // visit and their depth, so the stack grows with the depth of the tree
+    // rather than its size.
+    let mut stack: Vec<(Ref, &[Ref], usize)> = vec![(dom.root_ref(), roots, 0)];
+    if opts.solve_joints {
+        scene.joint_cframes = solve_joints(dom, roots, opts.pose);
+    }
+    while let Some((parent_ref, children, depth)) = stack.last_mut() {
+        let Some((&inst_ref, rest)) = children.split_first() else {
+            stack.pop();
+            continue;
+        };
+        *children = rest;
+        let (parent_ref, depth) = (*parent_ref, *depth);
+        if let Some(cancel) = cancel {
+            cancel.check()?;
+        }
+        // Every instance has one parent, so only following children that
+        // point back at the instance listing them visits each one once; the
+        // only way round again is through a root.
+        let inst = match dom.get_by_ref(inst_ref) {
+            Some(inst)
+                if depth == 0 || (inst.parent() == parent_ref && !roots.contains(&inst_ref)) =>
+            {
+                inst
+            }
+            found => {
+                let detail = match found {
+                    None => format!("child {} isn't in the file, skipped", inst_ref),
+                    Some(_) => format!("child {} belongs elsewhere in the tree, skipped", inst_ref),
+                };
+                scene.broken_children.push(parent_ref);
+                scene.warn(|| Warning::new(dom, parent_ref, WarningCode::BrokenTree, detail));
+                continue;
+            }
+        };
+        scene.visited += 1;
+        if !collect_instance(dom, inst, opts, scene, emit) {
+            continue;
+        }
+
+        let children = inst.children();
+        if children.is_empty() {
+            continue;
+        }
+        if depth >= opts.max_depth {
+            scene.depth_limited += 1;
+            scene.warn(|| {
+                Warning::new(
+                    dom,
+                    inst_ref,
+                    WarningCode::DepthLimited,
+                    format!("children cut off by --max-depth {}", opts.max_depth),
+                )
+            });
+            continue;
+        }
+        stack.push((inst_ref, children, depth + 1));
+    }
+
+    Ok(())
+}
+
+/// Handles a single instance, returning whether its children should be
+/// visited too.
+fn collect_instance(
+    dom: &WeakDom,
+    inst: &Instance,
+    opts: &ExportOptions,
+    scene: &mut Scene,
+    emit: &mut dyn FnMut(PartRecord),
+) -> bool {
+    let inst_ref = inst.referent();
+
+    // Studio never saves non-archivable instances or anything beneath them.
+    if opts.respect_archivable
+        && let Some(Variant::Bool(false)) = inst.properties.get(&Ustr::from("Archivable"))
+    {
+        scene.warn(|| Warning::new(dom, inst_ref, WarningCode::Pruned, "not archivable, pruned"));
+        return false;
+    }
+
+    // Tags usually sit on models, so an excluded tag prunes the whole subtree.
+    if !opts.exclude_tags.is_empty()
+        && instance_tags(inst)
+            .iter()
+            .any(|tag| opts.exclude_tags.contains(tag))
+    {
+        scene.warn(|| Warning::new(dom, inst_ref, WarningCode::Pruned, "excluded tag, pruned"));
+        return false;
+    }
+
+    if let Some(kind) = unsupported_content(inst) {
+        let (count, examples) = scene.unsupported.entry(kind).or_default();
+        *count += 1;
+        if examples.len() < UNSUPPORTED_EXAMPLES {
+            examples.push(inst_ref);
+        }
+        scene.warn(|| {
+            Warning::new(
+                dom,
+                inst_ref,
+                WarningCode::UnsupportedContent,
+                kind.describe(),
+            )
+        });
+    }
+
+    let pruned_class = match inst.class.as_str() {
+        "Camera" if !opts.include_cameras => Some("Camera"),
+        // Terrain isn't exported yet, so don't let anything parented to it leak in.
+        "Terrain" => Some("Terrain"),
+        _ => None,
+    };
+    if let Some(class) = pruned_class {
+        scene.warn(|| {
+            Warning::new(
+                dom,
+                inst_ref,
+                WarningCode::Pruned,
+                format!("{}, pruned", class),
+            )
+        });
+        *scene.pruned.entry(class).or_default() += 1;
+        return false;
+    }
+
+    match inst.class.as_str() {
+        class if is_part_class(class) || is_base_part(inst) => {
+            if !is_part_class(class) {
+                *scene.unknown_classes.entry(class.to_string()).or_default() += 1;
+                if !opts.unknown_as_box {
+                    scene.skip("unsupported class", || {
+                        Warning::new(
+                            dom,
+                            inst_ref,
+                            WarningCode::UnsupportedClass,
+                            format!("skipped, unsupported class {}", class),
+                        )
+                    });
+                    return true;
+                }
+            }
+            if !opts.include_tags.is_empty()
+                && !has_tag_in_ancestry(dom, inst_ref, &opts.include_tags)
+            {
+                scene.skip("filtered", || {
+                    Warning::new(
+                        dom,
+                        inst_ref,
+                        WarningCode::Filtered,
+                        "skipped, no included tag",
+                    )
+                });
+                return true;
+            }
+            if let Some(part) = part_record(dom, inst, opts, scene) {
+                log::trace!("{}: exporting {}", instance_path(dom, inst_ref), class);
+                emit(part);
+            }
+        }
+        "RodConstraint"
+        | "RopeConstraint"
+        | "SpringConstraint"
+        | "BallSocketConstraint"
+        | "HingeConstraint"
+        | "CylindricalConstraint"
+        | "PrismaticConstraint"
+            if opts.export_constraints =>
+        {
+            scene.constraints.push(inst_ref);
+        }
+        "SelectionBox" | "SelectionSphere" if opts.export_selection_effects => {
+            scene.selection_effects.push(inst_ref);
+        }
+        _ => {}
+    }
+
+    true
+}
+
+/// Reads everything about a part that the export needs, or counts it as
+/// skipped in `scene` if it shouldn't be exported.
+fn part_record(
+    dom: &WeakDom,
+    inst: &Instance,
+    opts: &ExportOptions,
+    scene: &mut Scene,
+) -> Option<PartRecord> {
+    let inst_ref = inst.referent();
+    if !opts.include_hidden
+        && let Some(Variant::Bool(false)) = inst.properties.get(&Ustr::from("Visible"))
+    {
+        scene.skip("invisible", || {
+            Warning::new(dom, inst_ref, WarningCode::Invisible, "skipped, invisible")
+        });
+        return None;
+    }
+
+    let size = match inst.properties.get(&Ustr::from("Size")) {
+        Some(Variant::Vector3(v)) => *v,
+        _ => {
+            scene.warn(|| {
+                Warning::new(
+                    dom,
+                    inst_ref,
+                    WarningCode::MissingSize,
+                    "no Size, using 1x1x1",
+                )
+            });
+            Vector3::new(1.0, 1.0, 1.0)
+        }
+    };
+
+    let joint_cframe = scene.joint_cframes.get(&inst_ref).copied();
+    let mut cframe = joint_cframe
+        .or_else(|| find_cframe(inst))
+        .unwrap_or_else(|| {
+            scene.warn(|| {
+                Warning::new(
+                    dom,
+                    inst_ref,
+                    WarningCode::MissingCFrame,
+                    "no CFrame or Position, placed at the origin",
+                )
+            });
+            get_cframe(inst)
+        });
+    let size = if opts.cframe_scale {
+        let scale = matrix3_column_magnitudes(&cframe.orientation);
+        cframe.orientation = matrix3_normalize_columns(&cframe.orientation);
+        scale
+    } else {
+        size
+    };
+    if let Some(offset) = mesh_offset(dom, inst) {
+        // The offset is in the part's own space, so it turns with the part.
+        let shift = apply_matrix3(&cframe.orientation, offset);
+        cframe.position = Vector3::new(
+            cframe.position.x + shift.x,
+            cframe.position.y + shift.y,
+            cframe.position.z + shift.z,
+        );
+    }
+    if !is_finite_transform(&cframe, size) {
+        scene.skip("degenerate", || {
+            Warning::new(
+                dom,
+                inst_ref,
+                WarningCode::Degenerate,
+                "skipped, NaN or infinite CFrame or Size",
+            )
+        });
+        return None;
+    }
+    if let Some(region) = &opts.region
+        && !region.contains(cframe.position)
+    {
+        scene.skip("outside region", || {
+            Warning::new(
+                dom,
+                inst_ref,
+                WarningCode::Filtered,
+                "skipped, outside --region",
+            )
+        });
+        return None;
+    }
+
+    let (r, g, b) = match inst.properties.get(&Ustr::from("Color")) {
+        Some(Variant::Color3uint8(c)) => (c.r, c.g, c.b),
+        Some(Variant::Color3(c)) => color3_to_rgb(c),
+        color => {
+            let fallback = if opts.inherit_color {
+                color_attribute(inst, "Color").or_else(|| find_ancestor_color(dom, inst_ref))
+            } else {
+                None
+            };
+            scene.warn(|| {
+                Warning::new(
+                    dom,
+                    inst_ref,
+                    WarningCode::UnreadableColor,
+                    format!(
+                        "{} Color, using {}",
+                        if color.is_some() { "unreadable" } else { "no" },
+                        if fallback.is_some() {
+                            "an inherited color"
+                        } else {
+                            "white"
+                        }
+                    ),
+                )
+            });
+            fallback.unwrap_or((255, 255, 255))
+        }
+    };
+    let (r, g, b) = tag_material_color(dom, inst_ref, &opts.tag_materials).unwrap_or((r, g, b));
+
+    let transparency = match inst.properties.get(&Ustr::from("Transparency")) {
+        Some(Variant::Float32(t)) => *t,
+        _ => 0.0,
+    };
+    // Scripts hide parts client-side through this, on top of Transparency.
+    let transparency = match inst
+        .properties
+        .get(&Ustr::from("LocalTransparencyModifier"))
+    {
+        Some(Variant::Float32(modifier)) => 1.0 - (1.0 - transparency) * (1.0 - modifier),
+        _ => transparency,
+    };
+    let a = ((1.0 - transparency) * 255.0) as u8;
+
+    let shape = match inst.properties.get(&Ustr::from("Shape")) {
+        Some(Variant::Enum(e)) => e.to_u32(),
+        _ => 1,
+    };
+    if inst.class == "Part" && shape > 2 {
+        scene.warn(|| {
+            Warning::new(
+                dom,
+                inst_ref,
+                WarningCode::UnmeshedShape,
+                format!("Shape {} isn't meshed, exporting a block", shape),
+            )
+        });
+    }
+
+    let surfaces = if opts.export_surface_details {
+        FaceId::ALL.map(
+            |face| match inst.properties.get(&Ustr::from(face.surface_property())) {
+                Some(Variant::Enum(e)) => e.to_u32(),
+                _ => 0,
+            },
+        )
+    } else {
+        [0; 6]
+    };
+
+    let material = match inst.properties.get(&Ustr::from("Material")) {
+        Some(Variant::Enum(e)) => e.to_u32(),
+        _ => MATERIAL_PLASTIC,
+    };
+
+    // Unanchored unless it says otherwise, like a new part in Studio.
+    let anchored = matches!(
+        inst.properties.get(&Ustr::from("Anchored")),
+        Some(Variant::Bool(true))
+    );
+
+    let unique_id = match inst.properties.get(&Ustr::from("UniqueId")) {
+        Some(Variant::UniqueId(id)) if !id.is_nil() => Some(*id),
+        _ => None,
+    };
+
+    Some(PartRecord {
+        referent: inst_ref,
+        name: inst.name.clone(),
+        object_name: opts
+            .object_names
+            .then(|| object_path(dom, inst_ref, opts.name_separator)),
+        class: inst.class.to_string(),
+        shape,
+        surfaces,
+        size,
+        cframe,
+        color: (r, g, b, a),
+        material,
+        anchored,
+        unique_id,
+    })
+}
+
+/// What parts without a `Material` are made of.
+pub const MATERIAL_PLASTIC: u32 = 256;
+
+/// The name of an `Enum.Material` value, or `"Unknown"` for one this table
+/// doesn't have. Custom MaterialService materials aren't in here: those keep
+/// their base material's value and name themselves in `MaterialVariant`.
+pub fn roblox_material_name(id: u32) -> &'static str {
+    match id {
+        256 => "Plastic",
+        272 => "SmoothPlastic",
+        288 => "Neon",
+        512 => "Wood",
+        528 => "WoodPlanks",
+        784 => "Marble",
+        788 => "Basalt",
+        800 => "Slate",
+        804 => "CrackedLava",
+        816 => "Concrete",
+        820 => "Limestone",
+        832 => "Granite",
+        836 => "Pavement",
+        848 => "Brick",
+        864 => "Pebble",
+        880 => "Cobblestone",
+        896 => "Rock",
+        912 => "Sandstone",
+        1040 => "CorrodedMetal",
+        1056 => "DiamondPlate",
+        1072 => "Foil",
+        1088 => "Metal",
+        1280 => "Grass",
+        1284 => "LeafyGrass",
+        1296 => "Sand",
+        1312 => "Fabric",
+        1328 => "Snow",
+        1344 => "Mud",
+        1360 => "Ground",
+        1376 => "Asphalt",
+        1392 => "Salt",
+        1536 => "Ice",
+        1552 => "Glacier",
+        1568 => "Glass",
+        1584 => "ForceField",
+        1792 => "Air",
+        2048 => "Water",
+        2304 => "Cardboard",
+        2305 => "Carpet",
+        2306 => "CeramicTiles",
+        2307 => "ClayRoofTiles",
+        2308 => "RoofShingles",
+        2309 => "Leather",
+        2310 => "Plaster",
+        2311 => "Rubber",
+        _ => "Unknown",
+    }
+}
+
+/// Decodes the CollectionService tags stored in an instance's `Tags` property.
+fn instance_tags(inst: &Instance) -> Vec<String> {
+    match inst.properties.get(&Ustr::from("Tags")) {
+        Some(Variant::Tags(tags)) => tags.iter().map(|tag| tag.to_string()).collect(),
+        Some(Variant::BinaryString(bytes)) => String::from_utf8_lossy(bytes.as_ref())
+            .split('\0')
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| tag.to_string())
+            .collect(),
+        // Some tools keep tags as a space-separated string instead.
+        Some(Variant::String(tags)) => tags.split_whitespace().map(|tag| tag.to_string()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Finds the `--tag-material` color for the nearest tagged instance at or
+/// above a part.
+fn tag_material_color(
+    dom: &WeakDom,
+    inst_ref: Ref,
+    tag_materials: &[(String, (u8, u8, u8))],
+) -> Option<(u8, u8, u8)> {
+    if tag_materials.is_empty() {
+        return None;
+    }
+    ancestors(dom, inst_ref).find_map(|inst| {
+        let tags = instance_tags(inst);
+        tag_materials
+            .iter()
+            .find(|(tag, _)| tags.contains(tag))
+            .map(|&(_, color)| color)
+    })
+}
+
+fn has_tag_in_ancestry(dom: &WeakDom, inst_ref: Ref, tags: &[String]) -> bool {
+    ancestors(dom, inst_ref).any(|inst| instance_tags(inst).iter().any(|tag| tags.contains(tag)))
+}
+
+/// `root` and everything under it, like `dom.descendants_of` but safe on a
+/// broken file: children missing from the DOM are skipped rather than
+/// ending the walk, and so are children whose `Parent` is some other
+/// instance, so a child list leading back up the tree can't go round
+/// forever. Only the path down to the current instance is kept, not
+/// everything seen so far.
+pub fn descendants(dom: &WeakDom, root: Ref) -> impl Iterator<Item = &Instance> {
+    let mut first = dom.get_by_ref(root);
+    let mut stack: Vec<(Ref, &[Ref])> = Vec::new();
+    std::iter::from_fn(move || {
+        if let Some(inst) = first.take() {
+            stack.push((root, inst.children()));
+            return Some(inst);
+        }
+        while let Some((parent_ref, children)) = stack.last_mut() {
+            let Some((&child_ref, rest)) = children.split_first() else {
+                stack.pop();
+                continue;
+            };
+            *children = rest;
+            let parent_ref = *parent_ref;
+            if let Some(inst) = dom.get_by_ref(child_ref)
+                && inst.parent() == parent_ref
+                && child_ref != root
+            {
+                stack.push((child_ref, inst.children()));
+                return Some(inst);
+            }
+        }
+        None
+    })
+}
+
+/// An instance and its ancestors up to the root, like `dom.ancestors_of`
+/// but stopping if the parents loop back on themselves rather than going
+/// round forever, and empty rather than panicking if `inst_ref` is missing.
+pub fn ancestors(dom: &WeakDom, inst_ref: Ref) -> impl Iterator<Item = &Instance> {
+    let mut seen = HashSet::new();
+    std::iter::successors(dom.get_by_ref(inst_ref), |inst| {
+        dom.get_by_ref(inst.parent())
+    })
+    .take_while(move |inst| seen.insert(inst.referent()))
+}
+
+/// Anything with a `Size` and a `CFrame` is geometry, whatever its class.
+fn is_base_part(inst: &Instance) -> bool {
+    matches!(
+        inst.properties.get(&Ustr::from("Size")),
+        Some(Variant::Vector3(_))
+    ) && matches!(
+        inst.properties.get(&Ustr::from("CFrame")),
+        Some(Variant::CFrame(_))
+    )
+}
+
+fn is_part_class(class: &str) -> bool {
+    matches!(
+        class,
+        "Part" | "WedgePart" | "CornerWedgePart" | "TrussPart"
+    )
+}
+
+pub fn find_service<'a>(dom: &'a WeakDom, class: &str) -> Option<&'a Instance> {
+    dom.root()
+        .children()
+        .iter()
+        .filter_map(|&r| dom.get_by_ref(r))
+        .find(|inst| inst.class == class)
+}
+
+/// `find_cframe`, or the origin for an instance without a position at all.
+pub fn get_cframe(inst: &Instance) -> CFrame {
+    find_cframe(inst).unwrap_or(CFrame {
+        position: Vector3::new(0.0, 0.0, 0.0),
+        orientation: Matrix3::identity(),
+    })
+}
+
+/// An instance's `CFrame`, or for files that don't save one, what its
+/// `Position` and `Orientation` (or the older `Rotation`) make up. Without
+/// either angle it's only moved, and without a `Position` there's nothing
+/// to go on.
+pub fn find_cframe(inst: &Instance) -> Option<CFrame> {
+    let property = |name: &str| inst.properties.get(&Ustr::from(name));
+    if let Some(Variant::CFrame(cf)) = property("CFrame") {
+        return Some(*cf);
+    }
+    let Some(Variant::Vector3(position)) = property("Position") else {
+        return None;
+    };
+    let orientation = match (property("Orientation"), property("Rotation")) {
+        (Some(Variant::Vector3(degrees)), _) => matrix3_from_orientation(*degrees),
+        (_, Some(Variant::Vector3(degrees))) => matrix3_from_rotation(*degrees),
+        _ => Matrix3::identity(),
+    };
+    Some(CFrame {
+        position: *position,
+        orientation,
+    })
+}
+
+fn color_attribute(inst: &Instance, name: &str) -> Option<(u8, u8, u8)> {
+    let attributes = match inst.properties.get(&Ustr::from("Attributes")) {
+        Some(Variant::Attributes(attributes)) => attributes,
+        _ => return None,
+    };
+    match attributes.get(name) {
+        Some(Variant::Color3(c)) => Some(color3_to_rgb(c)),
+        _ => None,
+    }
+}
+
+/// Scales a float color to bytes. `Color3` and `Color3uint8` are both
+/// sRGB-encoded (`Color3.fromRGB(r, g, b)` is just `Color3.new(r/255, ...)`),
+/// so there's no gamma to apply.
+pub(crate) fn color3_to_rgb(c: &Color3) -> (u8, u8, u8) {
+    let channel = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    (channel(c.r), channel(c.g), channel(c.b))
+}
+
+/// Climbs from a part towards Workspace looking for a Model carrying a
+/// `PrimaryColor` attribute, for models that get colored by scripts at runtime.
+fn find_ancestor_color(dom: &WeakDom, inst_ref: Ref) -> Option<(u8, u8, u8)> {
+    ancestors(dom, inst_ref)
+        .skip(1)
+        .take_while(|inst| inst.class != "Workspace" && inst.referent() != dom.root_ref())
+        .filter(|inst| inst.class == "Model")
+        .find_map(|inst| color_attribute(inst, "PrimaryColor"))
+}
+
+#[cfg(test)]
+mod tests {
+    use rbx_dom_weak::InstanceBuilder;
+    use rbx_types::{BinaryString, Tags};
+
+    use super::*;
+
+    /// A place with `parts` under its Workspace, and the Workspace.
+    fn workspace(parts: impl IntoIterator<Item = InstanceBuilder>) -> (WeakDom, Ref) {
+        let mut dom = WeakDom::new(InstanceBuilder::new("DataModel"));
+        let workspace = dom.insert(dom.root_ref(), InstanceBuilder::new("Workspace"));
+        for part in parts {
+            dom.insert(workspace, part);
+        }
+        (dom, workspace)
+    }
+
+    /// Walks `roots`, keeping every warning.
+    fn collect(dom: &WeakDom, roots: &[Ref], opts: &ExportOptions) -> (Scene, Vec<PartRecord>) {
+        let mut scene = Scene {
+            keep_warnings: true,
+            ..Default::default()
+        };
+        let mut parts = Vec::new();
+        collect_scene(dom, roots, opts, &mut scene, None, &mut |part| {
+            parts.push(part)
+        })
+        .unwrap();
+        (scene, parts)
+    }
+
+    fn codes(scene: &Scene) -> Vec<WarningCode> {
+        scene.warnings.iter().map(|warning| warning.code).collect()
+    }
+
+    #[test]
+    fn part_without_a_size_is_a_unit_block() {
+        let (dom, workspace) = workspace([InstanceBuilder::new("Part")]);
+        let (scene, parts) = collect(&dom, &[workspace], &ExportOptions::default());
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].size, Vector3::new(1.0, 1.0, 1.0));
+        assert!(codes(&scene).contains(&WarningCode::MissingSize));
+    }
+
+    #[test]
+    fn part_with_a_nan_cframe_is_skipped() {
+        let nan = CFrame::new(Vector3::new(f32::NAN, 0.0, 0.0), Matrix3::identity());
+        let (dom, workspace) = workspace([
+            InstanceBuilder::new("Part").with_property("CFrame", nan),
+            InstanceBuilder::new("Part"),
+        ]);
+        let (scene, parts) = collect(&dom, &[workspace], &ExportOptions::default());
+        assert_eq!(parts.len(), 1);
+        assert_eq!(scene.skipped.get("degenerate"), Some(&1));
+        assert!(codes(&scene).contains(&WarningCode::Degenerate));
+    }
+
+    #[test]
+    fn part_without_a_cframe_is_placed_by_position_and_orientation() {
+        let (dom, workspace) = workspace([
+            InstanceBuilder::new("Part")
+                .with_property("Position", Vector3::new(1.0, 2.0, 3.0))
+                .with_property("Orientation", Vector3::new(0.0, 90.0, 0.0)),
+            InstanceBuilder::new("Part"),
+        ]);
+        let (scene, parts) = collect(&dom, &[workspace], &ExportOptions::default());
+        let cframe = parts[0].cframe;
+        assert_eq!(cframe.position, Vector3::new(1.0, 2.0, 3.0));
+        let m = cframe.orientation;
+        assert!((m.x.z - 1.0).abs() < 1e-6 && (m.z.x + 1.0).abs() < 1e-6);
+        // Only the part with neither gets warned about.
+        assert_eq!(parts[1].cframe.position, Vector3::new(0.0, 0.0, 0.0));
+        let missing = codes(&scene)
+            .into_iter()
+            .filter(|&code| code == WarningCode::MissingCFrame)
+            .count();
+        assert_eq!(missing, 1);
+    }
+
+    #[test]
+    fn cframe_scale_takes_the_size_from_the_columns() {
+        let stretched = CFrame::new(
+            Vector3::new(0.0, 0.0, 0.0),
+            Matrix3::new(
+                Vector3::new(2.0, 0.0, 0.0),
+                Vector3::new(0.0, 3.0, 0.0),
+                Vector3::new(0.0, 0.0, 4.0),
+            ),
+        );
+        let (dom, workspace) = workspace([InstanceBuilder::new("Part")
+            .with_property("Size", Vector3::new(1.0, 1.0, 1.0))
+            .with_property("CFrame", stretched)]);
+        let opts = ExportOptions::builder().cframe_scale(true).build().unwrap();
+        let (_, parts) = collect(&dom, &[workspace], &opts);
+        assert_eq!(parts[0].size, Vector3::new(2.0, 3.0, 4.0));
+        assert_eq!(parts[0].cframe.orientation, Matrix3::identity());
+
+        let (_, parts) = collect(&dom, &[workspace], &ExportOptions::default());
+        assert_eq!(parts[0].size, Vector3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn parts_are_unanchored_unless_they_say_so() {
+        let (dom, workspace) = workspace([
+            InstanceBuilder::new("Part"),
+            InstanceBuilder::new("Part").with_property("Anchored", true),
+            InstanceBuilder::new("Part").with_property("Anchored", false),
+        ]);
+        let (_, parts) = collect(&dom, &[workspace], &ExportOptions::default());
+        let anchored: Vec<bool> = parts.iter().map(|part| part.anchored).collect();
+        assert_eq!(anchored, [false, true, false]);
+    }
+
+    #[test]
+    fn parent_loop_is_walked_once() {
+        // Moving a folder into its own child leaves the two parenting each
+        // other, cut off from the Workspace.
+        let (mut dom, workspace) = workspace([]);
+        let outer = dom.insert(workspace, InstanceBuilder::new("Folder"));
+        let inner = dom.insert(outer, InstanceBuilder::new("Folder"));
+        dom.insert(inner, InstanceBuilder::new("Part"));
+        dom.transfer_within(outer, inner);
+
+        let (scene, parts) = collect(&dom, &[outer], &ExportOptions::default());
+        assert_eq!(parts.len(), 1);
+        assert_eq!(scene.visited, 3);
+        assert_eq!(scene.broken_children, [inner]);
+        assert!(codes(&scene).contains(&WarningCode::BrokenTree));
+        assert_eq!(descendants(&dom, outer).count(), 3);
+        assert_eq!(ancestors(&dom, inner).count(), 2);
+    }
+
+    #[test]
+    fn deep_nesting_doesnt_overflow_the_stack() {
+        let (mut dom, workspace) = workspace([]);
+        let mut parent = workspace;
+        for _ in 0..30_000 {
+            parent = dom.insert(parent, InstanceBuilder::new("Folder"));
+        }
+        dom.insert(parent, InstanceBuilder::new("Part"));
+
+        let opts = ExportOptions::builder()
+            .max_depth(usize::MAX)
+            .build()
+            .unwrap();
+        let (scene, parts) = collect(&dom, &[workspace], &opts);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(scene.visited, 30_002);
+        assert_eq!(descendants(&dom, workspace).count(), 30_002);
+
+        let (scene, parts) = collect(&dom, &[workspace], &ExportOptions::default());
+        assert!(parts.is_empty());
+        assert_eq!(scene.depth_limited, 1);
+    }
+
+    #[test]
+    fn names_every_material_roblox_has() {
+        let database = rbx_reflection_database::get().unwrap();
+        let materials = &database.enums["Material"].items;
+        assert!(materials.len() >= 45);
+        for (name, &id) in materials {
+            assert_eq!(roblox_material_name(id), name, "Enum.Material value {}", id);
+        }
+    }
+
+    #[test]
+    fn names_material_ids() {
+        let cases = [
+            (MATERIAL_PLASTIC, "Plastic"),
+            (1568, "Glass"),
+            (2304, "Cardboard"),
+            (2305, "Carpet"),
+            (2306, "CeramicTiles"),
+            (2307, "ClayRoofTiles"),
+            (2308, "RoofShingles"),
+            (2309, "Leather"),
+            (2310, "Plaster"),
+            (2311, "Rubber"),
+            (0, "Unknown"),
+            (2312, "Unknown"),
+        ];
+        for (id, name) in cases {
+            assert_eq!(roblox_material_name(id), name, "Enum.Material value {}", id);
+        }
+    }
+
+    fn tags(inst: InstanceBuilder) -> Vec<String> {
+        let dom = WeakDom::new(inst);
+        instance_tags(dom.root())
+    }
+
+    #[test]
+    fn reads_every_tag_in_each_encoding() {
+        let expected = ["EditorOnly", "Décor", "建物", "🌲 Trees"];
+        let names: Vec<String> = expected.iter().map(|tag| tag.to_string()).collect();
+        let tagged = InstanceBuilder::new("Model").with_property("Tags", Tags::from(names));
+        assert_eq!(tags(tagged), expected);
+
+        // How the binary format stores them: NUL between the names.
+        let raw = BinaryString::from(expected.join("\0").into_bytes());
+        let tagged = InstanceBuilder::new("Model").with_property("Tags", raw);
+        assert_eq!(tags(tagged), expected);
+
+        let spaced = InstanceBuilder::new("Model").with_property("Tags", "EditorOnly Décor 建物");
+        assert_eq!(tags(spaced), ["EditorOnly", "Décor", "建物"]);
+
+        assert!(tags(InstanceBuilder::new("Model")).is_empty());
+    }
+
+    #[test]
+    fn filters_by_any_of_several_tags() {
+        let model = |tags: &[&str]| {
+            let names: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+            InstanceBuilder::new("Model")
+                .with_property("Tags", Tags::from(names))
+                .with_child(InstanceBuilder::new("Part").with_name("Inside"))
+        };
+        let (dom, workspace) = workspace([
+            model(&["Décor", "EditorOnly"]),
+            model(&["Export", "建物"]),
+            model(&[]),
+            InstanceBuilder::new("Part"),
+        ]);
+        let count = |opts: ExportOptions| collect(&dom, &[workspace], &opts).1.len();
+
+        let exclude = |tags: &[&str]| {
+            let tags = tags.iter().map(|tag| tag.to_string()).collect();
+            ExportOptions::builder().exclude_tags(tags).build().unwrap()
+        };
+        let include = |tags: &[&str]| {
+            let tags = tags.iter().map(|tag| tag.to_string()).collect();
+            ExportOptions::builder().include_tags(tags).build().unwrap()
+        };
+        // Either of a model's tags is enough to prune it.
+        assert_eq!(count(exclude(&["EditorOnly"])), 3);
+        assert_eq!(count(exclude(&["Décor"])), 3);
+        assert_eq!(count(exclude(&["建物", "EditorOnly"])), 2);
+        // Tags match whole and exactly.
+        assert_eq!(count(exclude(&["Decor", "Editor"])), 4);
+        // Including keeps parts under a model with any of the tags.
+        assert_eq!(count(include(&["建物"])), 1);
+        assert_eq!(count(include(&["Export", "Décor"])), 2);
+    }
+}
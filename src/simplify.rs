@@ -0,0 +1,260 @@
+//! Mesh decimation with quadric error metrics (Garland and Heckbert, 1997),
+//! for `SimplifyProcessor`: keep collapsing whichever edge moves the surface
+//! least until the mesh is down to the triangles asked for.
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashSet},
+};
+
+use rbx_types::Vector3;
+
+use crate::mesh::{Mesh, cross, dot, normalize, sub};
+
+/// The symmetric 4x4 matrix summing the squared distances to a set of
+/// planes, upper triangle only.
+type Quadric = [f64; 10];
+
+/// Collapses edges of `mesh` until it has at most `target_faces` triangles,
+/// or until every remaining collapse would flip a triangle over. Each edge
+/// collapses onto whichever of its ends or its midpoint adds the least
+/// error. Vertices no triangle uses any more are dropped.
+pub fn simplify(mesh: Mesh, target_faces: usize) -> Mesh {
+    let (mut vertices, faces) = mesh;
+    if faces.len() <= target_faces {
+        return (vertices, faces);
+    }
+
+    let mut faces: Vec<Option<[usize; 3]>> =
+        faces.into_iter().map(|(a, b, c)| Some([a, b, c])).collect();
+    let mut quadrics = vec![[0.0; 10]; vertices.len()];
+    let mut vertex_faces = vec![Vec::new(); vertices.len()];
+    for (index, face) in faces.iter().enumerate() {
+        let Some(face) = face else { continue };
+        let plane = plane_quadric(face.map(|i| vertices[i]));
+        for &corner in face {
+            add(&mut quadrics[corner], &plane);
+            vertex_faces[corner].push(index);
+        }
+    }
+
+    let mut versions = vec![0u32; vertices.len()];
+    let mut removed = vec![false; vertices.len()];
+    let mut heap = BinaryHeap::new();
+    let mut edges = HashSet::new();
+    for face in faces.iter().flatten() {
+        for (a, b) in [(face[0], face[1]), (face[1], face[2]), (face[2], face[0])] {
+            if edges.insert((a.min(b), a.max(b))) {
+                heap.push(Reverse(candidate(&vertices, &quadrics, &versions, a, b)));
+            }
+        }
+    }
+
+    let mut live_faces = faces.iter().flatten().count();
+    while live_faces > target_faces {
+        let Some(Reverse(collapse)) = heap.pop() else {
+            break;
+        };
+        let Collapse { a, b, position, .. } = collapse;
+        if removed[a] || removed[b] || collapse.versions != (versions[a], versions[b]) {
+            continue;
+        }
+        if flips_a_face(&vertices, &faces, &vertex_faces, a, b, position) {
+            continue;
+        }
+
+        vertices[a] = position;
+        let absorbed = quadrics[b];
+        add(&mut quadrics[a], &absorbed);
+        removed[b] = true;
+        versions[a] += 1;
+        for index in std::mem::take(&mut vertex_faces[b]) {
+            let Some(face) = &mut faces[index] else {
+                continue;
+            };
+            for corner in face.iter_mut() {
+                if *corner == b {
+                    *corner = a;
+                }
+            }
+            if face[0] == face[1] || face[1] == face[2] || face[2] == face[0] {
+                faces[index] = None;
+                live_faces -= 1;
+            } else {
+                vertex_faces[a].push(index);
+            }
+        }
+        vertex_faces[a].retain(|&index| faces[index].is_some());
+        vertex_faces[a].sort_unstable();
+        vertex_faces[a].dedup();
+
+        let mut neighbours: Vec<usize> = vertex_faces[a]
+            .iter()
+            .filter_map(|&index| faces[index])
+            .flatten()
+            .filter(|&corner| corner != a)
+            .collect();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        for neighbour in neighbours {
+            heap.push(Reverse(candidate(
+                &vertices, &quadrics, &versions, a, neighbour,
+            )));
+        }
+    }
+
+    let mut remap = vec![usize::MAX; vertices.len()];
+    let mut kept = Vec::new();
+    let faces = faces
+        .iter()
+        .flatten()
+        .map(|face| {
+            let [a, b, c] = face.map(|index| {
+                if remap[index] == usize::MAX {
+                    remap[index] = kept.len();
+                    kept.push(vertices[index]);
+                }
+                remap[index]
+            });
+            (a, b, c)
+        })
+        .collect();
+    (kept, faces)
+}
+
+/// Collapsing edge `a`-`b` into `a`, placed at `position`. Stale once either
+/// end has changed since, which `versions` catches.
+struct Collapse {
+    cost: f64,
+    a: usize,
+    b: usize,
+    position: Vector3,
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    /// Cheapest first once reversed, ties broken by index so the result
+    /// doesn't depend on the heap's internals.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cost
+            .total_cmp(&other.cost)
+            .then((self.a, self.b).cmp(&(other.a, other.b)))
+    }
+}
+
+fn candidate(
+    vertices: &[Vector3],
+    quadrics: &[Quadric],
+    versions: &[u32],
+    a: usize,
+    b: usize,
+) -> Collapse {
+    let mut quadric = quadrics[a];
+    add(&mut quadric, &quadrics[b]);
+    let (pa, pb) = (vertices[a], vertices[b]);
+    let midpoint = Vector3::new(
+        (pa.x + pb.x) / 2.0,
+        (pa.y + pb.y) / 2.0,
+        (pa.z + pb.z) / 2.0,
+    );
+    let (cost, position) = [pa, pb, midpoint]
+        .into_iter()
+        .map(|p| (error(&quadric, p), p))
+        .min_by(|x, y| x.0.total_cmp(&y.0))
+        .unwrap();
+    Collapse {
+        cost,
+        a,
+        b,
+        position,
+        versions: (versions[a], versions[b]),
+    }
+}
+
+/// Whether moving `a` and `b` to `position` turns any triangle that
+/// survives the collapse to face the other way.
+fn flips_a_face(
+    vertices: &[Vector3],
+    faces: &[Option<[usize; 3]>],
+    vertex_faces: &[Vec<usize>],
+    a: usize,
+    b: usize,
+    position: Vector3,
+) -> bool {
+    vertex_faces[a]
+        .iter()
+        .chain(&vertex_faces[b])
+        .filter_map(|&index| faces[index])
+        .filter(|face| !(face.contains(&a) && face.contains(&b)))
+        .any(|face| {
+            let corners = face.map(|i| vertices[i]);
+            let moved = face.map(|i| {
+                if i == a || i == b {
+                    position
+                } else {
+                    vertices[i]
+                }
+            });
+            let normal = |[p, q, r]: [Vector3; 3]| cross(sub(q, p), sub(r, p));
+            dot(normal(corners), normal(moved)) <= 0.0
+        })
+}
+
+/// The quadric of the plane through a triangle. Degenerate triangles have
+/// no plane and add nothing.
+fn plane_quadric([p, q, r]: [Vector3; 3]) -> Quadric {
+    let n = cross(sub(q, p), sub(r, p));
+    if dot(n, n) == 0.0 {
+        return [0.0; 10];
+    }
+    let n = normalize(n);
+    let [a, b, c] = [n.x, n.y, n.z].map(f64::from);
+    let d = -(a * p.x as f64 + b * p.y as f64 + c * p.z as f64);
+    [
+        a * a,
+        a * b,
+        a * c,
+        a * d,
+        b * b,
+        b * c,
+        b * d,
+        c * c,
+        c * d,
+        d * d,
+    ]
+}
+
+fn add(quadric: &mut Quadric, other: &Quadric) {
+    for (q, o) in quadric.iter_mut().zip(other) {
+        *q += o;
+    }
+}
+
+/// The summed squared distance from `p` to the quadric's planes.
+fn error(q: &Quadric, p: Vector3) -> f64 {
+    let [x, y, z] = [p.x, p.y, p.z].map(f64::from);
+    q[0] * x * x
+        + 2.0 * q[1] * x * y
+        + 2.0 * q[2] * x * z
+        + 2.0 * q[3] * x
+        + q[4] * y * y
+        + 2.0 * q[5] * y * z
+        + 2.0 * q[6] * y
+        + q[7] * z * z
+        + 2.0 * q[8] * z
+        + q[9]
+}
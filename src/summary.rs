@@ -0,0 +1,104 @@
+//! What a conversion wrote and what it left out, appended to
+//! `--summary-json` once it's done.
+
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{json_escape, scene::Scene};
+
+pub struct RunSummary<'a> {
+    pub input: &'a Path,
+    pub scene: &'a Scene,
+    pub parts_by_class: &'a BTreeMap<String, usize>,
+    pub vertices: usize,
+    pub faces: usize,
+    pub materials: usize,
+    /// Every file written, in the order they were created.
+    pub outputs: &'a [PathBuf],
+    pub elapsed: Duration,
+    /// Nothing was written; the counts are estimates and `outputs` are the
+    /// files a real run would write.
+    pub dry_run: bool,
+}
+
+impl RunSummary<'_> {
+    /// Every output with its size, which is 0 on a dry run.
+    fn output_sizes(&self) -> Vec<(&Path, u64)> {
+        self.outputs
+            .iter()
+            .map(|path| {
+                let bytes = if self.dry_run {
+                    0
+                } else {
+                    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+                };
+                (path.as_path(), bytes)
+            })
+            .collect()
+    }
+
+    /// Appends the summary to `path` as one line of JSON.
+    pub fn append_json(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let not_exported: BTreeMap<&str, usize> = self
+            .scene
+            .unsupported
+            .iter()
+            .map(|(kind, (count, _))| (kind.describe(), *count))
+            .collect();
+        let outputs: Vec<String> = self
+            .output_sizes()
+            .iter()
+            .map(|(path, bytes)| {
+                format!(
+                    "{{\"path\":\"{}\",\"bytes\":{}}}",
+                    json_escape(&path.display().to_string()),
+                    bytes
+                )
+            })
+            .collect();
+        let errors: Vec<String> = self
+            .scene
+            .errors
+            .iter()
+            .map(|e| {
+                format!(
+                    "{{\"path\":\"{}\",\"error\":\"{}\"}}",
+                    json_escape(&e.path),
+                    json_escape(&e.error.to_string())
+                )
+            })
+            .collect();
+
+        let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+        writeln!(
+            file,
+            "{{\"input\":\"{}\",\"parts\":{},\"skipped\":{},\"not_exported\":{},\"vertices\":{},\"faces\":{},\"materials\":{},\"outputs\":[{}],\"errors\":[{}],\"elapsed_seconds\":{},\"dry_run\":{}}}",
+            json_escape(&self.input.display().to_string()),
+            counts_json(self.parts_by_class),
+            counts_json(&self.scene.skipped),
+            counts_json(&not_exported),
+            self.vertices,
+            self.faces,
+            self.materials,
+            outputs.join(","),
+            errors.join(","),
+            self.elapsed.as_secs_f64(),
+            self.dry_run
+        )?;
+        Ok(())
+    }
+}
+
+fn counts_json<K: std::fmt::Display>(counts: &BTreeMap<K, usize>) -> String {
+    let fields: Vec<String> = counts
+        .iter()
+        .map(|(key, count)| format!("\"{}\":{}", json_escape(&key.to_string()), count))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
@@ -0,0 +1,246 @@
+//! Finding and splitting T-junctions: vertices sitting on another triangle's
+//! edge without being one of its corners, which show up as hairline cracks
+//! in real-time renderers. Abutting parts never share vertices, so welded
+//! output is full of them.
+
+use std::collections::HashSet;
+
+use rbx_types::Vector3;
+
+use crate::{
+    bvh::{Aabb, Bvh},
+    mesh::{dot, sub},
+};
+
+type Face = (usize, usize, usize);
+
+/// A vertex lying on the edge between two others.
+pub struct TJunction {
+    pub vertex: usize,
+    /// The edge's endpoints, lower index first.
+    pub edge: (usize, usize),
+}
+
+/// Finds every vertex within `eps` of the inside of an edge of `faces`. The
+/// worst case is still O(V·E), but a BVH over the vertices keeps each edge
+/// from testing the ones nowhere near it.
+pub fn find_tjunctions(verts: &[Vector3], faces: &[Face], eps: f32) -> Vec<TJunction> {
+    let mut edges = HashSet::new();
+    for &(a, b, c) in faces {
+        for (from, to) in [(a, b), (b, c), (c, a)] {
+            edges.insert((from.min(to), from.max(to)));
+        }
+    }
+    let mut edges: Vec<_> = edges.into_iter().collect();
+    edges.sort_unstable();
+
+    let boxes = verts
+        .iter()
+        .map(|&v| Aabb {
+            min: Vector3::new(v.x - eps, v.y - eps, v.z - eps),
+            max: Vector3::new(v.x + eps, v.y + eps, v.z + eps),
+        })
+        .collect();
+    let bvh = Bvh::build(boxes);
+
+    let mut junctions = Vec::new();
+    for (a, b) in edges {
+        let (from, to) = (verts[a], verts[b]);
+        let along = sub(to, from);
+        let length_squared = dot(along, along);
+        if length_squared <= eps * eps {
+            continue;
+        }
+        bvh.query_segment(from, to, &mut |vertex| {
+            if vertex == a || vertex == b {
+                return;
+            }
+            let offset = sub(verts[vertex], from);
+            let t = dot(offset, along) / length_squared;
+            // Close to either end it's a near-duplicate corner, not a T.
+            let margin = eps / length_squared.sqrt();
+            if t <= margin || t >= 1.0 - margin {
+                return;
+            }
+            let closest = Vector3::new(along.x * t, along.y * t, along.z * t);
+            let gap = sub(offset, closest);
+            if dot(gap, gap) <= eps * eps {
+                junctions.push(TJunction {
+                    vertex,
+                    edge: (a, b),
+                });
+            }
+        });
+    }
+    junctions
+}
+
+/// Splits each triangle along `tj.edge` in two at `tj.vertex`, keeping the
+/// winding. With the usual two triangles on an edge, that makes four.
+pub fn fix_tjunction(faces: &mut Vec<Face>, tj: &TJunction) {
+    split_edge(faces, None, tj);
+}
+
+/// `fix_tjunction` for faces with a normal index per corner in `normals`,
+/// which get split alongside them. The new corner takes the normal of the
+/// edge end it stands in for, exact on flat faces and close on smooth ones.
+pub(crate) fn fix_tjunction_with_normals(
+    faces: &mut Vec<Face>,
+    normals: &mut Vec<[usize; 3]>,
+    tj: &TJunction,
+) {
+    split_edge(faces, Some(normals), tj);
+}
+
+fn split_edge(faces: &mut Vec<Face>, mut normals: Option<&mut Vec<[usize; 3]>>, tj: &TJunction) {
+    let (a, b) = tj.edge;
+    let v = tj.vertex;
+    let mut split = Vec::new();
+    let mut kept = 0;
+    for i in 0..faces.len() {
+        let (x, y, z) = faces[i];
+        let corners = [x, y, z];
+        let n = normals.as_ref().map_or([0; 3], |normals| normals[i]);
+        // Rotate the corners so the shared edge runs from the first to the second.
+        let start = (0..3).find(|&k| {
+            let (p, q) = (corners[k], corners[(k + 1) % 3]);
+            (p, q) == (a, b) || (p, q) == (b, a)
+        });
+        match start {
+            Some(k) if corners[(k + 2) % 3] != v => {
+                let (p, q, r) = (corners[k], corners[(k + 1) % 3], corners[(k + 2) % 3]);
+                let (np, nq, nr) = (n[k], n[(k + 1) % 3], n[(k + 2) % 3]);
+                split.push(((p, v, r), [np, np, nr]));
+                split.push(((v, q, r), [nq, nq, nr]));
+            }
+            _ => {
+                faces[kept] = faces[i];
+                if let Some(normals) = normals.as_mut() {
+                    normals[kept] = n;
+                }
+                kept += 1;
+            }
+        }
+    }
+    faces.truncate(kept);
+    faces.extend(split.iter().map(|&(face, _)| face));
+    if let Some(normals) = normals {
+        normals.truncate(kept);
+        normals.extend(split.iter().map(|&(_, n)| n));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::cube_mesh;
+
+    /// A 2-stud cube and a half-height block beside it, welded into one
+    /// mesh, so the block's top corners sit halfway up two of the cube's
+    /// edges.
+    fn two_blocks() -> (Vec<Vector3>, Vec<Face>) {
+        let mut verts: Vec<Vector3> = Vec::new();
+        let mut faces = Vec::new();
+        let blocks = [
+            (Vector3::new(2.0, 2.0, 2.0), Vector3::new(0.0, 0.0, 0.0)),
+            (Vector3::new(2.0, 1.0, 2.0), Vector3::new(2.0, -0.5, 0.0)),
+        ];
+        for (size, offset) in blocks {
+            let (vertices, block_faces) = cube_mesh(size);
+            let remap: Vec<usize> = vertices
+                .iter()
+                .map(|v| {
+                    let v = Vector3::new(v.x + offset.x, v.y + offset.y, v.z + offset.z);
+                    verts.iter().position(|&w| w == v).unwrap_or_else(|| {
+                        verts.push(v);
+                        verts.len() - 1
+                    })
+                })
+                .collect();
+            faces.extend(
+                block_faces
+                    .iter()
+                    .map(|&(a, b, c)| (remap[a], remap[b], remap[c])),
+            );
+        }
+        (verts, faces)
+    }
+
+    fn has_edge(&(a, b, c): &Face, (p, q): (usize, usize)) -> bool {
+        [(a, b), (b, c), (c, a)]
+            .iter()
+            .any(|&edge| edge == (p, q) || edge == (q, p))
+    }
+
+    #[test]
+    fn splits_the_edges_a_smaller_block_meets() {
+        let (verts, mut faces) = two_blocks();
+        let at = |x, y, z| {
+            verts
+                .iter()
+                .position(|&v| v == Vector3::new(x, y, z))
+                .unwrap()
+        };
+        let edge = |a: usize, b: usize| (a.min(b), a.max(b));
+
+        let junctions = find_tjunctions(&verts, &faces, 1e-4);
+        let mut found: Vec<_> = junctions.iter().map(|tj| (tj.vertex, tj.edge)).collect();
+        found.sort_unstable();
+        let mut expected = vec![
+            (
+                at(1.0, 0.0, -1.0),
+                edge(at(1.0, -1.0, -1.0), at(1.0, 1.0, -1.0)),
+            ),
+            (
+                at(1.0, 0.0, 1.0),
+                edge(at(1.0, -1.0, 1.0), at(1.0, 1.0, 1.0)),
+            ),
+        ];
+        expected.sort_unstable();
+        assert_eq!(found, expected);
+
+        let before = faces.len();
+        let touching = |faces: &[Face], v: usize| {
+            faces
+                .iter()
+                .filter(|&&(a, b, c)| [a, b, c].contains(&v))
+                .count()
+        };
+        let touching_before: Vec<_> = junctions
+            .iter()
+            .map(|tj| touching(&faces, tj.vertex))
+            .collect();
+        for tj in &junctions {
+            fix_tjunction(&mut faces, tj);
+        }
+        // Each edge ran between two of the cube's triangles, now four.
+        assert_eq!(faces.len(), before + 4);
+        for (tj, was) in junctions.iter().zip(touching_before) {
+            assert!(!faces.iter().any(|face| has_edge(face, tj.edge)));
+            assert_eq!(touching(&faces, tj.vertex), was + 4);
+        }
+        assert!(find_tjunctions(&verts, &faces, 1e-4).is_empty());
+    }
+
+    #[test]
+    fn split_faces_keep_their_normals() {
+        let (verts, mut faces) = two_blocks();
+        // Every corner of a face gets the same normal, as on a block.
+        let mut normals: Vec<[usize; 3]> = (0..faces.len()).map(|i| [i; 3]).collect();
+        let junctions = find_tjunctions(&verts, &faces, 1e-4);
+        let split_edge = junctions[0].edge;
+        let split: Vec<usize> = faces
+            .iter()
+            .enumerate()
+            .filter(|(_, face)| has_edge(face, split_edge))
+            .map(|(i, _)| i)
+            .collect();
+
+        fix_tjunction_with_normals(&mut faces, &mut normals, &junctions[0]);
+        assert_eq!(normals.len(), faces.len());
+        assert!(normals.iter().all(|n| n[0] == n[1] && n[1] == n[2]));
+        // The halves come last, each pair with the normal of the face it came from.
+        let halves: Vec<usize> = normals[faces.len() - 4..].iter().map(|n| n[0]).collect();
+        assert_eq!(halves, [split[0], split[0], split[1], split[1]]);
+    }
+}
@@ -0,0 +1,248 @@
+//! Helpers for orientation matrices and CFrames: building them from Euler
+//! angles, for parts saved without a `CFrame`, chaining them through joints,
+//! and taking apart ones that aren't pure rotations.
+//!
+//! `Matrix3` stores rows, so a column is one component taken from each row.
+
+use rbx_types::{CFrame, Matrix3, Vector3};
+
+use crate::mesh::{apply_cframe, apply_matrix3};
+
+/// The rotation `Orientation` describes: degrees about each axis, applied
+/// Y first, then X, then Z, the same as `CFrame.fromOrientation`.
+pub fn matrix3_from_orientation(degrees: Vector3) -> Matrix3 {
+    let [x, y, z] = axis_rotations(degrees);
+    matrix3_mul(&matrix3_mul(&y, &x), &z)
+}
+
+/// The rotation the older `Rotation` property describes: degrees about each
+/// axis, applied X first, then Y, then Z, the same as `CFrame.Angles`.
+pub fn matrix3_from_rotation(degrees: Vector3) -> Matrix3 {
+    let [x, y, z] = axis_rotations(degrees);
+    matrix3_mul(&matrix3_mul(&x, &y), &z)
+}
+
+/// Rotations about X, Y and Z by each of `degrees`' components.
+fn axis_rotations(degrees: Vector3) -> [Matrix3; 3] {
+    let (sx, cx) = degrees.x.to_radians().sin_cos();
+    let (sy, cy) = degrees.y.to_radians().sin_cos();
+    let (sz, cz) = degrees.z.to_radians().sin_cos();
+    [
+        Matrix3::new(
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, cx, -sx),
+            Vector3::new(0.0, sx, cx),
+        ),
+        Matrix3::new(
+            Vector3::new(cy, 0.0, sy),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(-sy, 0.0, cy),
+        ),
+        Matrix3::new(
+            Vector3::new(cz, -sz, 0.0),
+            Vector3::new(sz, cz, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+        ),
+    ]
+}
+
+/// `a * b`, which rotates by `b` and then by `a`.
+fn matrix3_mul(a: &Matrix3, b: &Matrix3) -> Matrix3 {
+    let row = |r: Vector3| {
+        Vector3::new(
+            r.x * b.x.x + r.y * b.y.x + r.z * b.z.x,
+            r.x * b.x.y + r.y * b.y.y + r.z * b.z.y,
+            r.x * b.x.z + r.y * b.y.z + r.z * b.z.z,
+        )
+    };
+    Matrix3::new(row(a.x), row(a.y), row(a.z))
+}
+
+/// `a * b`: `b`, taken from `a`'s space into the one `a` is relative to.
+pub fn cframe_mul(a: &CFrame, b: &CFrame) -> CFrame {
+    CFrame {
+        position: apply_cframe(b.position, a),
+        orientation: matrix3_mul(&a.orientation, &b.orientation),
+    }
+}
+
+/// Undoes `cf`, assuming its orientation is a pure rotation.
+pub fn cframe_inverse(cf: &CFrame) -> CFrame {
+    let m = &cf.orientation;
+    let transposed = Matrix3::new(
+        Vector3::new(m.x.x, m.y.x, m.z.x),
+        Vector3::new(m.x.y, m.y.y, m.z.y),
+        Vector3::new(m.x.z, m.y.z, m.z.z),
+    );
+    let p = apply_matrix3(&transposed, cf.position);
+    CFrame {
+        position: Vector3::new(-p.x, -p.y, -p.z),
+        orientation: transposed,
+    }
+}
+
+/// Length of each column of `m`, which is the scale it applies along that
+/// local axis.
+pub fn matrix3_column_magnitudes(m: &Matrix3) -> Vector3 {
+    let length = |a: f32, b: f32, c: f32| (a * a + b * b + c * c).sqrt();
+    Vector3::new(
+        length(m.x.x, m.y.x, m.z.x),
+        length(m.x.y, m.y.y, m.z.y),
+        length(m.x.z, m.y.z, m.z.z),
+    )
+}
+
+/// `m` with every column scaled to unit length. Zero columns are left alone.
+pub fn matrix3_normalize_columns(m: &Matrix3) -> Matrix3 {
+    let scale = matrix3_column_magnitudes(m);
+    let inverse = |s: f32| if s > 0.0 { 1.0 / s } else { 1.0 };
+    let (sx, sy, sz) = (inverse(scale.x), inverse(scale.y), inverse(scale.z));
+    let row = |r: Vector3| Vector3::new(r.x * sx, r.y * sy, r.z * sz);
+    Matrix3::new(row(m.x), row(m.y), row(m.z))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    use super::*;
+
+    fn rows(m: &Matrix3) -> [f32; 9] {
+        [
+            m.x.x, m.x.y, m.x.z, m.y.x, m.y.y, m.y.z, m.z.x, m.z.y, m.z.z,
+        ]
+    }
+
+    fn assert_close(m: &Matrix3, expected: [f32; 9]) {
+        let actual = rows(m);
+        assert!(
+            actual
+                .iter()
+                .zip(expected)
+                .all(|(a, e)| (a - e).abs() < 1e-5),
+            "{:?} isn't {:?}",
+            actual,
+            expected
+        );
+    }
+
+    /// What `CFrame.fromOrientation` gives in Studio, as the rotation part of
+    /// `GetComponents()` with the -4.37e-8 noise rounded off.
+    #[test]
+    fn orientation_matches_studio() {
+        let cases = [
+            (
+                Vector3::new(0.0, 0.0, 0.0),
+                [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            ),
+            (
+                Vector3::new(90.0, 0.0, 0.0),
+                [1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 1.0, 0.0],
+            ),
+            (
+                Vector3::new(0.0, 90.0, 0.0),
+                [0.0, 0.0, 1.0, 0.0, 1.0, 0.0, -1.0, 0.0, 0.0],
+            ),
+            (
+                Vector3::new(0.0, 0.0, 90.0),
+                [0.0, -1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            ),
+            (
+                Vector3::new(90.0, 90.0, 0.0),
+                [0.0, 1.0, 0.0, 0.0, 0.0, -1.0, -1.0, 0.0, 0.0],
+            ),
+            (
+                Vector3::new(30.0, 45.0, 0.0),
+                [
+                    FRAC_1_SQRT_2,
+                    0.353553,
+                    0.612372,
+                    0.0,
+                    0.866025,
+                    -0.5,
+                    -FRAC_1_SQRT_2,
+                    0.353553,
+                    0.612372,
+                ],
+            ),
+        ];
+        for (degrees, expected) in cases {
+            assert_close(&matrix3_from_orientation(degrees), expected);
+        }
+    }
+
+    /// `Rotation` is `CFrame.Angles`, which turns about X first, so the same
+    /// angles land somewhere else than `Orientation`'s.
+    #[test]
+    fn rotation_matches_studio() {
+        let cases = [
+            (
+                Vector3::new(90.0, 0.0, 0.0),
+                [1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 1.0, 0.0],
+            ),
+            (
+                Vector3::new(90.0, 90.0, 0.0),
+                [0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+            ),
+        ];
+        for (degrees, expected) in cases {
+            assert_close(&matrix3_from_rotation(degrees), expected);
+        }
+    }
+
+    /// A part turned to Orientation (0, 90, 0) in Studio faces down -X, with
+    /// its `LookVector` being the orientation's third column negated.
+    #[test]
+    fn quarter_turn_looks_down_negative_x() {
+        let m = matrix3_from_orientation(Vector3::new(0.0, 90.0, 0.0));
+        let look = Vector3::new(-m.x.z, -m.y.z, -m.z.z);
+        assert!((look.x + 1.0).abs() < 1e-6 && look.y.abs() < 1e-6 && look.z.abs() < 1e-6);
+    }
+
+    #[test]
+    fn inverse_undoes_a_cframe() {
+        let cf = CFrame::new(
+            Vector3::new(3.0, -2.0, 5.0),
+            matrix3_from_orientation(Vector3::new(30.0, 45.0, 60.0)),
+        );
+        let identity = cframe_mul(&cf, &cframe_inverse(&cf));
+        assert_close(
+            &identity.orientation,
+            [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+        );
+        let p = identity.position;
+        assert!(p.x.abs() < 1e-5 && p.y.abs() < 1e-5 && p.z.abs() < 1e-5);
+    }
+
+    #[test]
+    fn scaled_columns_come_apart_into_size_and_rotation() {
+        // A quarter turn about Y with its columns stretched by 2, 3 and 4.
+        let turn = matrix3_from_orientation(Vector3::new(0.0, 90.0, 0.0));
+        let scaled = Matrix3::new(
+            Vector3::new(turn.x.x * 2.0, turn.x.y * 3.0, turn.x.z * 4.0),
+            Vector3::new(turn.y.x * 2.0, turn.y.y * 3.0, turn.y.z * 4.0),
+            Vector3::new(turn.z.x * 2.0, turn.z.y * 3.0, turn.z.z * 4.0),
+        );
+        let size = matrix3_column_magnitudes(&scaled);
+        assert!((size.x - 2.0).abs() < 1e-5 && (size.y - 3.0).abs() < 1e-5);
+        assert!((size.z - 4.0).abs() < 1e-5);
+        assert_close(&matrix3_normalize_columns(&scaled), rows(&turn));
+    }
+
+    #[test]
+    fn zero_columns_stay_zero() {
+        let flat = Matrix3::new(
+            Vector3::new(2.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, 0.5),
+        );
+        assert_eq!(
+            matrix3_column_magnitudes(&flat),
+            Vector3::new(2.0, 0.0, 0.5)
+        );
+        assert_close(
+            &matrix3_normalize_columns(&flat),
+            [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+        );
+    }
+}
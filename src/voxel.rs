@@ -0,0 +1,184 @@
+//! `--voxelize`: the whole scene as a grid of cubes, for voxel engines that
+//! can't take arbitrary triangles.
+
+use rbx_types::Vector3;
+
+use crate::{ExportError, mesh::Mesh, scene::PartRecord};
+
+/// Most cells a grid may have, a bit over 100 MB of them. A voxel size
+/// small enough to go past this on a real place is a typo more often than
+/// not.
+const MAX_VOXELS: usize = 1 << 27;
+
+/// Which cells of a uniform grid have something in them.
+pub struct VoxelGrid {
+    /// The corner of cell `[0, 0, 0]` with the lowest coordinates.
+    pub origin: Vector3,
+    pub voxel_size: f32,
+    /// Cells along X, Y and Z.
+    pub dims: [usize; 3],
+    /// Indexed by `x + dims[0] * (y + dims[1] * z)`.
+    cells: Vec<bool>,
+}
+
+impl VoxelGrid {
+    /// Whether the cell at `[x, y, z]` is filled. Anything outside the grid
+    /// is empty.
+    pub fn get(&self, cell: [isize; 3]) -> bool {
+        if (0..3).any(|axis| cell[axis] < 0 || cell[axis] as usize >= self.dims[axis]) {
+            return false;
+        }
+        let [x, y, z] = cell.map(|i| i as usize);
+        self.cells[x + self.dims[0] * (y + self.dims[1] * z)]
+    }
+
+    fn fill(&mut self, min: [usize; 3], max: [usize; 3]) {
+        for z in min[2]..=max[2] {
+            for y in min[1]..=max[1] {
+                let row = self.dims[0] * (y + self.dims[1] * z);
+                self.cells[row + min[0]..=row + max[0]].fill(true);
+            }
+        }
+    }
+}
+
+/// Fills every cell that each part's world-space bounding box touches, on a
+/// grid of `voxel_size` cubes covering the whole scene. Rotated parts fill
+/// the box around them, so they come out a bit fat, and a part thinner than
+/// a cell still fills a layer of them. Fails when the grid would be too big
+/// to hold.
+pub fn voxelize_scene(parts: &[PartRecord], voxel_size: f32) -> Result<VoxelGrid, ExportError> {
+    let boxes: Vec<_> = parts.iter().map(PartRecord::bounds).collect();
+    let Some((min, max)) = boxes
+        .iter()
+        .map(|b| (b.min, b.max))
+        .reduce(|(min, max), (lo, hi)| {
+            (
+                Vector3::new(min.x.min(lo.x), min.y.min(lo.y), min.z.min(lo.z)),
+                Vector3::new(max.x.max(hi.x), max.y.max(hi.y), max.z.max(hi.z)),
+            )
+        })
+    else {
+        return Ok(VoxelGrid {
+            origin: Vector3::new(0.0, 0.0, 0.0),
+            voxel_size,
+            dims: [0; 3],
+            cells: Vec::new(),
+        });
+    };
+
+    let extent = [max.x - min.x, max.y - min.y, max.z - min.z];
+    let dims = extent.map(|e| ((e / voxel_size).ceil() as usize).max(1));
+    let count = dims
+        .iter()
+        .try_fold(1usize, |count, &d| count.checked_mul(d))
+        .filter(|&count| count <= MAX_VOXELS)
+        .ok_or_else(|| {
+            ExportError::Unsupported(format!(
+                "--voxelize {} needs a {}x{}x{} grid, over the limit of {} voxels; try a bigger size",
+                voxel_size, dims[0], dims[1], dims[2], MAX_VOXELS
+            ))
+        })?;
+
+    let mut grid = VoxelGrid {
+        origin: min,
+        voxel_size,
+        dims,
+        cells: vec![false; count],
+    };
+    let cell_range = |lo: f32, hi: f32, origin: f32, dim: usize| {
+        let first = (((lo - origin) / voxel_size).floor().max(0.0) as usize).min(dim - 1);
+        let last = ((((hi - origin) / voxel_size).ceil() as usize).saturating_sub(1)).min(dim - 1);
+        (first, last.max(first))
+    };
+    for b in &boxes {
+        let (x0, x1) = cell_range(b.min.x, b.max.x, min.x, dims[0]);
+        let (y0, y1) = cell_range(b.min.y, b.max.y, min.y, dims[1]);
+        let (z0, z1) = cell_range(b.min.z, b.max.z, min.z, dims[2]);
+        grid.fill([x0, y0, z0], [x1, y1, z1]);
+    }
+    Ok(grid)
+}
+
+/// The outer surface of the filled cells, with the faces between two filled
+/// cells left out and each plane's faces merged greedily into as few
+/// rectangles as it takes: grow along one axis as far as the faces go, then
+/// along the other for as long as whole rows match. Triangles wind
+/// counter-clockwise seen from outside. Rectangles don't share vertices, and
+/// where a big one meets several small ones there are T-junctions, which
+/// `--weld` with `--fix-tjunctions` takes care of.
+pub fn greedy_mesh_voxels(grid: &VoxelGrid) -> Mesh {
+    let mut vertices = Vec::new();
+    let mut faces = Vec::new();
+    let corner = |cell: [usize; 3]| {
+        Vector3::new(
+            grid.origin.x + cell[0] as f32 * grid.voxel_size,
+            grid.origin.y + cell[1] as f32 * grid.voxel_size,
+            grid.origin.z + cell[2] as f32 * grid.voxel_size,
+        )
+    };
+
+    for d in 0..3 {
+        let (u, v) = ((d + 1) % 3, (d + 2) % 3);
+        let (width, height) = (grid.dims[u], grid.dims[v]);
+        // 1 for a face looking along +d, -1 for one looking back, 0 for none.
+        let mut mask = vec![0i8; width * height];
+        for layer in 0..=grid.dims[d] {
+            for j in 0..height {
+                for i in 0..width {
+                    let mut cell = [0isize; 3];
+                    cell[d] = layer as isize;
+                    cell[u] = i as isize;
+                    cell[v] = j as isize;
+                    let ahead = grid.get(cell);
+                    cell[d] -= 1;
+                    let behind = grid.get(cell);
+                    mask[i + j * width] = match (behind, ahead) {
+                        (true, false) => 1,
+                        (false, true) => -1,
+                        _ => 0,
+                    };
+                }
+            }
+
+            for j in 0..height {
+                let mut i = 0;
+                while i < width {
+                    let facing = mask[i + j * width];
+                    if facing == 0 {
+                        i += 1;
+                        continue;
+                    }
+                    let w = (i..width)
+                        .take_while(|&k| mask[k + j * width] == facing)
+                        .count();
+                    let h = 1
+                        + (j + 1..height)
+                            .take_while(|&row| (i..i + w).all(|k| mask[k + row * width] == facing))
+                            .count();
+                    for row in j..j + h {
+                        mask[i + row * width..i + w + row * width].fill(0);
+                    }
+
+                    let at = |du: usize, dv: usize| {
+                        let mut cell = [0; 3];
+                        cell[d] = layer;
+                        cell[u] = i + du;
+                        cell[v] = j + dv;
+                        corner(cell)
+                    };
+                    let base = vertices.len();
+                    vertices.extend([at(0, 0), at(w, 0), at(w, h), at(0, h)]);
+                    // u cross v points along +d.
+                    if facing > 0 {
+                        faces.extend([(base, base + 1, base + 2), (base, base + 2, base + 3)]);
+                    } else {
+                        faces.extend([(base, base + 2, base + 1), (base, base + 3, base + 2)]);
+                    }
+                    i += w;
+                }
+            }
+        }
+    }
+    (vertices, faces)
+}
@@ -0,0 +1,184 @@
+//! Everything the export skipped or guessed at, one event per instance.
+//!
+//! The `-v` lines and `--warnings-jsonl` both come from these, so the codes
+//! below are stable: tooling can match on them.
+
+use std::{
+    error::Error,
+    fmt,
+    fs::OpenOptions,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use rbx_dom_weak::WeakDom;
+use rbx_types::Ref;
+
+use crate::{json_escape, scene::instance_path};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[cfg_attr(feature = "cli", value(rename_all = "snake_case"))]
+pub enum WarningCode {
+    /// Left out along with everything beneath it (non-archivable, excluded
+    /// tag, Camera or Terrain).
+    Pruned,
+    /// Has a `Size` and `CFrame` but isn't a class the export meshes.
+    UnsupportedClass,
+    /// Outside every `--include-tag`, or outside `--region`.
+    Filtered,
+    /// `Visible` is false.
+    Invisible,
+    /// The `CFrame` or `Size` isn't finite.
+    Degenerate,
+    /// No `Size`, so the part came out 1x1x1.
+    MissingSize,
+    /// No `CFrame`, nor a `Position` to build one from, so the part was
+    /// placed at the origin.
+    #[cfg_attr(feature = "cli", value(name = "missing_cframe"))]
+    MissingCFrame,
+    /// `Color` is missing or isn't a color, so a fallback was used.
+    UnreadableColor,
+    /// A `Shape` the export doesn't mesh, written as a block.
+    UnmeshedShape,
+    /// Content the export drops or gets wrong, see `Unsupported`.
+    UnsupportedContent,
+    /// Children cut off by `--max-depth`.
+    DepthLimited,
+    /// A service with parts in it that `--services` left out.
+    SkippedService,
+    /// A child referent missing from the file, or leading back to an
+    /// instance already visited, skipped. Reported on the parent.
+    BrokenTree,
+}
+
+impl WarningCode {
+    pub const ALL: [WarningCode; 13] = [
+        WarningCode::Pruned,
+        WarningCode::UnsupportedClass,
+        WarningCode::Filtered,
+        WarningCode::Invisible,
+        WarningCode::Degenerate,
+        WarningCode::MissingSize,
+        WarningCode::MissingCFrame,
+        WarningCode::UnreadableColor,
+        WarningCode::UnmeshedShape,
+        WarningCode::UnsupportedContent,
+        WarningCode::DepthLimited,
+        WarningCode::SkippedService,
+        WarningCode::BrokenTree,
+    ];
+
+    /// The code `as_str` gives `name`.
+    pub fn from_name(name: &str) -> Option<WarningCode> {
+        WarningCode::ALL
+            .into_iter()
+            .find(|code| code.as_str() == name)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WarningCode::Pruned => "pruned",
+            WarningCode::UnsupportedClass => "unsupported_class",
+            WarningCode::Filtered => "filtered",
+            WarningCode::Invisible => "invisible",
+            WarningCode::Degenerate => "degenerate",
+            WarningCode::MissingSize => "missing_size",
+            WarningCode::MissingCFrame => "missing_cframe",
+            WarningCode::UnreadableColor => "unreadable_color",
+            WarningCode::UnmeshedShape => "unmeshed_shape",
+            WarningCode::UnsupportedContent => "unsupported_content",
+            WarningCode::DepthLimited => "depth_limited",
+            WarningCode::SkippedService => "skipped_service",
+            WarningCode::BrokenTree => "broken_tree",
+        }
+    }
+}
+
+pub struct Warning {
+    pub code: WarningCode,
+    pub class: String,
+    pub path: String,
+    pub detail: String,
+}
+
+impl Warning {
+    pub fn new(dom: &WeakDom, inst_ref: Ref, code: WarningCode, detail: impl Into<String>) -> Self {
+        Warning {
+            code,
+            class: dom
+                .get_by_ref(inst_ref)
+                .map(|inst| inst.class.to_string())
+                .unwrap_or_default(),
+            path: instance_path(dom, inst_ref),
+            detail: detail.into(),
+        }
+    }
+
+    fn json(&self, input: &Path) -> String {
+        format!(
+            "{{\"input\":\"{}\",\"code\":\"{}\",\"class\":\"{}\",\"path\":\"{}\",\"detail\":\"{}\"}}",
+            json_escape(&input.display().to_string()),
+            self.code.as_str(),
+            json_escape(&self.class),
+            json_escape(&self.path),
+            json_escape(&self.detail)
+        )
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.detail)
+    }
+}
+
+/// Appends `warnings` to `path` as JSON lines, or to stderr if it's `-`.
+pub fn append_jsonl(path: &Path, input: &Path, warnings: &[Warning]) -> Result<(), Box<dyn Error>> {
+    let mut out: Box<dyn Write> = if path == Path::new("-") {
+        Box::new(io::stderr().lock())
+    } else {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        Box::new(BufWriter::new(file))
+    };
+    for warning in warnings {
+        writeln!(out, "{}", warning.json(input))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rbx_dom_weak::InstanceBuilder;
+
+    use super::*;
+
+    #[test]
+    fn escapes_control_characters_and_quotes() {
+        assert_eq!(
+            json_escape("a \"b\"\\c\nd\re\tf\u{1}g\u{1f}"),
+            "a \\\"b\\\"\\\\c\\nd\\re\\tf\\u0001g\\u001f"
+        );
+        assert_eq!(json_escape("Bläh ✓"), "Bläh ✓");
+    }
+
+    #[test]
+    fn json_line_stays_one_line() {
+        let mut dom = WeakDom::new(InstanceBuilder::new("DataModel"));
+        let part = dom.insert(
+            dom.root_ref(),
+            InstanceBuilder::new("Part").with_name("Door\n\"Front\""),
+        );
+        let warning = Warning::new(&dom, part, WarningCode::MissingSize, "no\tSize");
+        let json = warning.json(Path::new("C:\\maps\\town.rbxl"));
+        assert_eq!(json.lines().count(), 1);
+        assert_eq!(
+            json,
+            "{\"input\":\"C:\\\\maps\\\\town.rbxl\",\"code\":\"missing_size\",\"class\":\"Part\",\
+             \"path\":\"Door\\n\\\"Front\\\"\",\"detail\":\"no\\tSize\"}"
+        );
+    }
+}
@@ -0,0 +1,49 @@
+//! The browser entry point, for `wasm-pack build --target web`. See
+//! `examples/wasm/index.html` for a page using it.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{ExportOptions, export_dom};
+
+/// The OBJ and MTL a conversion wrote.
+#[wasm_bindgen]
+pub struct Converted {
+    obj: Vec<u8>,
+    mtl: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl Converted {
+    /// The OBJ, whose `mtllib` line names `scene.mtl`.
+    #[wasm_bindgen(getter)]
+    pub fn obj(&self) -> String {
+        String::from_utf8_lossy(&self.obj).into_owned()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn mtl(&self) -> String {
+        String::from_utf8_lossy(&self.mtl).into_owned()
+    }
+
+    #[wasm_bindgen(getter, js_name = objBytes)]
+    pub fn obj_bytes(&self) -> Vec<u8> {
+        self.obj.clone()
+    }
+
+    #[wasm_bindgen(getter, js_name = mtlBytes)]
+    pub fn mtl_bytes(&self) -> Vec<u8> {
+        self.mtl.clone()
+    }
+}
+
+/// Converts the bytes of a binary place or model (a `Uint8Array` on the JS
+/// side) with the default options, throwing if they don't decode.
+#[wasm_bindgen(js_name = convertPlace)]
+pub fn convert_place(place: &[u8]) -> Result<Converted, JsError> {
+    let dom = rbx_binary::from_reader(place)
+        .map_err(|e| JsError::new(&format!("couldn't decode the file: {}", e)))?;
+    let opts = ExportOptions::default();
+    let (mut obj, mut mtl) = (Vec::new(), Vec::new());
+    export_dom(&dom, &mut obj, Some(&mut mtl), &opts).map_err(|e| JsError::new(&e.to_string()))?;
+    Ok(Converted { obj, mtl })
+}
@@ -0,0 +1,69 @@
+//! `export_dom` on a DOM built in memory, writing into buffers: what comes
+//! back in the report matches what ends up in the OBJ and MTL.
+
+use rbx_dom_weak::{InstanceBuilder, WeakDom};
+use rbx_obj::ExportOptions;
+use rbx_types::{CFrame, Color3uint8, Enum, Matrix3, Vector3};
+
+/// Two grey blocks and a red ball under the Workspace.
+fn place() -> WeakDom {
+    let mut dom = WeakDom::new(InstanceBuilder::new("DataModel"));
+    let workspace = dom.insert(dom.root_ref(), InstanceBuilder::new("Workspace"));
+    let parts = [
+        (1, Color3uint8::new(163, 162, 165), 0.0),
+        (1, Color3uint8::new(163, 162, 165), 4.0),
+        (0, Color3uint8::new(255, 0, 0), 8.0),
+    ];
+    for (shape, color, x) in parts {
+        dom.insert(
+            workspace,
+            InstanceBuilder::new("Part")
+                .with_property("Size", Vector3::new(2.0, 2.0, 2.0))
+                .with_property(
+                    "CFrame",
+                    CFrame::new(Vector3::new(x, 0.0, 0.0), Matrix3::identity()),
+                )
+                .with_property("Color", color)
+                .with_property("Shape", Enum::from_u32(shape)),
+        );
+    }
+    dom
+}
+
+fn count_lines(text: &str, prefix: &str) -> usize {
+    text.lines().filter(|line| line.starts_with(prefix)).count()
+}
+
+#[test]
+fn writes_what_the_report_says() {
+    let (mut obj, mut mtl) = (Vec::new(), Vec::new());
+    let opts = ExportOptions::default();
+    let report = rbx_obj::export_dom(&place(), &mut obj, Some(&mut mtl), &opts).unwrap();
+    let (obj, mtl) = (
+        String::from_utf8(obj).unwrap(),
+        String::from_utf8(mtl).unwrap(),
+    );
+
+    assert_eq!(report.parts(), 3);
+    assert!(report.outputs.is_empty());
+    assert_eq!(count_lines(&obj, "v "), report.vertices);
+    assert_eq!(count_lines(&obj, "f "), report.faces);
+    // Two blocks' 8 corners and 12 triangles each, plus the ball.
+    assert!(report.vertices > 16 && report.faces > 24);
+    assert_eq!(count_lines(&mtl, "newmtl "), report.materials);
+    assert_eq!(report.materials, 2);
+    for line in obj.lines().filter(|line| line.starts_with("usemtl ")) {
+        let name = &line["usemtl ".len()..];
+        assert!(mtl.lines().any(|m| m == format!("newmtl {}", name)));
+    }
+}
+
+#[test]
+fn exports_the_same_with_or_without_an_mtl() {
+    let opts = ExportOptions::default();
+    let dom = place();
+    let (mut with, mut without, mut mtl) = (Vec::new(), Vec::new(), Vec::new());
+    rbx_obj::export_dom(&dom, &mut with, Some(&mut mtl), &opts).unwrap();
+    rbx_obj::export_dom(&dom, &mut without, None, &opts).unwrap();
+    assert_eq!(with, without);
+}
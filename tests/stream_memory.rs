@@ -0,0 +1,106 @@
+//! `stream` keeps memory flat however big the place is: hundreds of
+//! thousands of parts, or a million in release builds, convert with the
+//! export itself allocating no more at its peak than a few thousand would
+//! need.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use rbx_dom_weak::{InstanceBuilder, WeakDom};
+use rbx_obj::ExportOptions;
+use rbx_types::{CFrame, Color3uint8, Enum, Matrix3, Vector3};
+
+/// The system allocator, keeping track of how much is allocated and the
+/// most there has been since `reset_peak`.
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// The counts are for the whole process, so only one test measures at a time.
+static MEASURING: Mutex<()> = Mutex::new(());
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let now = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(now, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// A place with `count` parts in a grid under its Workspace.
+fn place(count: usize) -> WeakDom {
+    let mut dom = WeakDom::new(InstanceBuilder::new("DataModel"));
+    let workspace = dom.insert(dom.root_ref(), InstanceBuilder::new("Workspace"));
+    for i in 0..count {
+        let position = Vector3::new((i % 1000) as f32 * 4.0, 0.0, (i / 1000) as f32 * 4.0);
+        dom.insert(
+            workspace,
+            InstanceBuilder::new("Part")
+                .with_property("Size", Vector3::new(2.0, 1.0, 2.0))
+                .with_property("CFrame", CFrame::new(position, Matrix3::identity()))
+                .with_property("Color", Color3uint8::new(163, 162, 165))
+                .with_property("Shape", Enum::from_u32(1)),
+        );
+    }
+    dom
+}
+
+/// The most the export allocated on top of what was already allocated.
+fn export_peak(dom: &WeakDom) -> usize {
+    let opts = ExportOptions::builder().stream(true).build().unwrap();
+    let before = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    let report = rbx_obj::export_dom(dom, &mut io::sink(), None, &opts).unwrap();
+    assert!(report.parts() > 0);
+    PEAK.load(Ordering::Relaxed) - before
+}
+
+/// Checks streaming `huge` parts peaks at about what 10k do.
+fn assert_stays_flat(huge: usize) {
+    let _measuring = MEASURING
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let small_peak = export_peak(&place(10_000));
+    let huge_peak = export_peak(&place(huge));
+    eprintln!(
+        "peak allocated: {} bytes for 10k parts, {} for {}",
+        small_peak, huge_peak, huge
+    );
+    assert!(
+        huge_peak < small_peak * 2 + (1 << 20),
+        "{} bytes for {} parts against {} for 10k",
+        huge_peak,
+        huge,
+        small_peak
+    );
+}
+
+#[test]
+fn streaming_200k_parts_stays_flat() {
+    assert_stays_flat(200_000);
+}
+
+/// CI runs this one in release.
+#[test]
+#[ignore = "builds a million-part DOM, which takes a while and a few GB"]
+fn streaming_a_million_parts_stays_flat() {
+    assert_stays_flat(1_000_000);
+}